@@ -26,11 +26,7 @@ fn bench(pir_file: &str) {
     let inst2 = Instant::now();
     let unparsed_file = fs::read_to_string(pir_file).expect("cannot read file");
     let module = Module::parse(&unparsed_file).unwrap();
-    let module_3ac = compile(
-        module,
-        &PrimeFieldOps::<Fp>::default(),
-        &Config { quiet: false },
-    ); // Failed to compile
+    let module_3ac = compile(module, &PrimeFieldOps::<Fp>::default(), &Config::default()).unwrap(); // Failed to compile
     println!("* Compiling constraints (3AC)...");
     let inst3 = Instant::now();
     file.write_all(
@@ -69,8 +69,9 @@ fn bench(pir_file: &str, max_degree: u32) {
     let module_3ac = compile(
         module,
         &PrimeFieldOps::<BlsScalar>::default(),
-        &Config { quiet: false },
-    );
+        &Config::default(),
+    )
+    .unwrap();
     let inst3 = Instant::now();
     file.write_all(
         format!(
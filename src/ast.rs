@@ -9,6 +9,9 @@ use pest::iterators::Pair;
 use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 #[derive(Parser)]
 #[grammar = "vampir.pest"]
 pub struct VampirParser;
@@ -21,34 +24,259 @@ pub struct Module {
 }
 
 impl Module {
-    pub fn parse(unparsed_file: &str) -> Result<Self, Box<pest::error::Error<Rule>>> {
-        let pairs = VampirParser::parse(Rule::moduleItems, unparsed_file)?;
-        let mut defs = vec![];
-        let mut exprs = vec![];
-        let mut pubs = vec![];
+    /* Parse the given source text into a module. Since the source does not
+     * belong to a file, it may not contain imports. */
+    pub fn parse(unparsed_file: &str) -> Result<Self, Error> {
+        let source = Arc::new(Source {
+            name: "<input>".to_string(),
+            text: unparsed_file.to_string(),
+        });
+        Self::parse_relative(source, None, &mut vec![], &mut HashSet::new())
+    }
+
+    /* Read and parse the module at the given path. Imports occurring in the
+     * file are resolved relative to the directory containing it. */
+    pub fn parse_file(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let canonical = path.canonicalize().map_err(|_| Error::MissingImport {
+            path: path.display().to_string(),
+        })?;
+        Self::parse_path(&canonical, &mut vec![], &mut HashSet::new())
+    }
+
+    /* Parse the file at the given canonical path whilst keeping track of the
+     * chain of files currently being imported. */
+    fn parse_path(
+        path: &Path,
+        importing: &mut Vec<PathBuf>,
+        loaded: &mut HashSet<(PathBuf, Option<String>)>,
+    ) -> Result<Self, Error> {
+        let unparsed_file = fs::read_to_string(path).map_err(|_| Error::MissingImport {
+            path: path.display().to_string(),
+        })?;
+        // Prefer paths relative to the working directory in diagnostics
+        let name = std::env::current_dir()
+            .ok()
+            .and_then(|cwd| path.strip_prefix(cwd).ok())
+            .unwrap_or(path);
+        let source = Arc::new(Source {
+            name: name.display().to_string(),
+            text: unparsed_file,
+        });
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        importing.push(path.to_path_buf());
+        let module = Self::parse_relative(source, Some(dir), importing, loaded);
+        importing.pop();
+        module
+    }
+
+    /* Parse the given source text into a module, resolving the imports it
+     * contains relative to the given directory. Each file is only imported
+     * once under each namespace so that diamond-shaped imports do not
+     * duplicate definitions. */
+    fn parse_relative(
+        source: Arc<Source>,
+        dir: Option<&Path>,
+        importing: &mut Vec<PathBuf>,
+        loaded: &mut HashSet<(PathBuf, Option<String>)>,
+    ) -> Result<Self, Error> {
+        let pairs = VampirParser::parse(Rule::moduleItems, &source.text).map_err(|err| {
+            Error::ParseError {
+                e: err.with_path(&source.name).to_string(),
+            }
+        })?;
+        let mut module = Self::default();
         for pair in pairs {
             match pair.as_rule() {
+                Rule::import => {
+                    let mut pairs = pair.into_inner();
+                    let path = pairs.next().expect("import should have a path");
+                    let path = path.as_str().trim_matches('"');
+                    let Some(dir) = dir else {
+                        return Err(Error::UnresolvedImport {
+                            path: path.to_string(),
+                        });
+                    };
+                    let path = dir.join(path);
+                    let namespace = pairs.next().map(|pair| pair.as_str().to_string());
+                    let canonical = path.canonicalize().map_err(|_| Error::MissingImport {
+                        path: path.display().to_string(),
+                    })?;
+                    if importing.contains(&canonical) {
+                        let mut cycle: Vec<_> = importing
+                            .iter()
+                            .skip_while(|x| **x != canonical)
+                            .map(|x| x.display().to_string())
+                            .collect();
+                        cycle.push(canonical.display().to_string());
+                        return Err(Error::CyclicImport { cycle });
+                    }
+                    if !loaded.insert((canonical.clone(), namespace.clone())) {
+                        continue;
+                    }
+                    // Namespaced imports get their own copies of transitive
+                    // imports since their definitions will be renamed. Only
+                    // definitions can be namespaced, so public inputs and
+                    // constraints would otherwise leak into the importer.
+                    let imported = if let Some(namespace) = namespace {
+                        let mut imported =
+                            Self::parse_path(&canonical, importing, &mut HashSet::new())?;
+                        if !imported.pubs.is_empty() || !imported.exprs.is_empty() {
+                            return Err(Error::NamespacedImportItems {
+                                path: path.display().to_string(),
+                            });
+                        }
+                        imported.qualify_definitions(&namespace);
+                        imported
+                    } else {
+                        Self::parse_path(&canonical, importing, loaded)?
+                    };
+                    module.pubs.extend(imported.pubs);
+                    module.defs.extend(imported.defs);
+                    module.exprs.extend(imported.exprs);
+                }
                 Rule::expr => {
-                    let expr = TExpr::parse(pair).expect("expected expression");
-                    exprs.push(expr);
+                    let expr = TExpr::parse(pair, &source).expect("expected expression");
+                    module.exprs.push(expr);
                 }
                 Rule::definition => {
-                    let definition = Definition::parse(pair).expect("expected definition");
-                    defs.push(definition);
+                    let definition = Definition::parse(pair, &source).expect("expected definition");
+                    module.defs.push(definition);
                 }
                 Rule::declaration => {
                     let pairs = pair.into_inner();
                     for pair in pairs {
                         let var = Variable::parse(pair).expect("expected variable");
-                        pubs.push(var);
+                        module.pubs.push(var);
                     }
                 }
-                Rule::EOI => return Ok(Self { pubs, defs, exprs }),
+                Rule::EOI => return Ok(module),
                 _ => unreachable!("module item should either be expression, definition, or EOI"),
             }
         }
         unreachable!("EOI should have been encountered")
     }
+
+    /* Prefix the names of this module's top-level definitions with the given
+     * namespace, along with every reference to them that is not shadowed by a
+     * local binding. Free variables are left untouched as they denote circuit
+     * inputs. */
+    fn qualify_definitions(&mut self, namespace: &str) {
+        let mut renames = HashMap::new();
+        for def in &mut self.defs {
+            qualify_expr_variables(&mut def.0 .1, &HashSet::new(), &renames);
+            qualify_pattern_variables(&mut def.0 .0, namespace, &mut renames);
+        }
+    }
+}
+
+/* Prefix each variable occurring in the given pattern with the given namespace
+ * and record the renaming. */
+fn qualify_pattern_variables(
+    pat: &mut TPat,
+    namespace: &str,
+    renames: &mut HashMap<String, String>,
+) {
+    match &mut pat.v {
+        Pat::As(pat, var) => {
+            qualify_pattern_variables(pat, namespace, renames);
+            qualify_variable_name(var, namespace, renames);
+        }
+        Pat::Product(pat1, pat2) | Pat::Cons(pat1, pat2) => {
+            qualify_pattern_variables(pat1, namespace, renames);
+            qualify_pattern_variables(pat2, namespace, renames);
+        }
+        Pat::Variable(var) => qualify_variable_name(var, namespace, renames),
+        Pat::Constant(_) | Pat::Unit | Pat::Nil => {}
+    }
+}
+
+/* Prefix the given variable with the given namespace and record the renaming.
+ */
+fn qualify_variable_name(
+    var: &mut Variable,
+    namespace: &str,
+    renames: &mut HashMap<String, String>,
+) {
+    if let Some(name) = &var.name {
+        let qualified = format!("{namespace}::{name}");
+        renames.insert(name.clone(), qualified.clone());
+        var.name = Some(qualified);
+    }
+}
+
+/* Collect the names of the variables bound by the given pattern. */
+fn collect_pattern_names(pat: &TPat, names: &mut HashSet<String>) {
+    match &pat.v {
+        Pat::As(pat, var) => {
+            collect_pattern_names(pat, names);
+            names.extend(var.name.clone());
+        }
+        Pat::Product(pat1, pat2) | Pat::Cons(pat1, pat2) => {
+            collect_pattern_names(pat1, names);
+            collect_pattern_names(pat2, names);
+        }
+        Pat::Variable(var) => names.extend(var.name.clone()),
+        Pat::Constant(_) | Pat::Unit | Pat::Nil => {}
+    }
+}
+
+/* Rename the variables occurring in the given expression according to the
+ * given map unless they are shadowed by a local binding. */
+fn qualify_expr_variables(
+    expr: &mut TExpr,
+    locals: &HashSet<String>,
+    renames: &HashMap<String, String>,
+) {
+    match &mut expr.v {
+        Expr::Sequence(exprs) => {
+            for expr in exprs {
+                qualify_expr_variables(expr, locals, renames);
+            }
+        }
+        Expr::Infix(_, expr1, expr2)
+        | Expr::Application(expr1, expr2)
+        | Expr::Product(expr1, expr2)
+        | Expr::Cons(expr1, expr2) => {
+            qualify_expr_variables(expr1, locals, renames);
+            qualify_expr_variables(expr2, locals, renames);
+        }
+        Expr::If(cond, expr1, expr2) => {
+            qualify_expr_variables(cond, locals, renames);
+            qualify_expr_variables(expr1, locals, renames);
+            qualify_expr_variables(expr2, locals, renames);
+        }
+        Expr::Negate(expr) => qualify_expr_variables(expr, locals, renames),
+        Expr::Constant(_) | Expr::Unit | Expr::Nil | Expr::Intrinsic(_) => {}
+        Expr::Variable(var) => {
+            if let Some(name) = &var.name {
+                if let Some(qualified) = renames.get(name).filter(|_| !locals.contains(name)) {
+                    var.name = Some(qualified.clone());
+                }
+            }
+        }
+        Expr::Function(fun) => {
+            let mut locals = locals.clone();
+            for param in &fun.params {
+                collect_pattern_names(param, &mut locals);
+            }
+            qualify_expr_variables(&mut fun.body, &locals, renames);
+        }
+        Expr::LetBinding(binding, expr) => {
+            qualify_expr_variables(&mut binding.1, locals, renames);
+            let mut locals = locals.clone();
+            collect_pattern_names(&binding.0, &mut locals);
+            qualify_expr_variables(expr, &locals, renames);
+        }
+        Expr::Match(matche) => {
+            qualify_expr_variables(&mut matche.0, locals, renames);
+            for (pat, expr2) in matche.1.iter().zip(matche.2.iter_mut()) {
+                let mut locals = locals.clone();
+                collect_pattern_names(pat, &mut locals);
+                qualify_expr_variables(expr2, &locals, renames);
+            }
+        }
+    }
 }
 
 impl fmt::Display for Module {
@@ -69,20 +297,127 @@ impl fmt::Display for Module {
     }
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
-pub struct Definition(pub LetBinding);
+/* A named piece of source text from which modules are parsed. */
+#[derive(Debug)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+/* A region of source text delimited by byte offsets. */
+#[derive(Clone)]
+pub struct Span {
+    pub source: Arc<Source>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(span: pest::Span, source: &Arc<Source>) -> Self {
+        Self {
+            source: source.clone(),
+            start: span.start(),
+            end: span.end(),
+        }
+    }
+
+    /* Make the smallest span enclosing both of the given spans. */
+    pub fn join(a: &Option<Self>, b: &Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) if Arc::ptr_eq(&a.source, &b.source) => Some(Self {
+                source: a.source.clone(),
+                start: a.start.min(b.start),
+                end: a.end.max(b.end),
+            }),
+            (Some(a), _) => Some(a.clone()),
+            (_, b) => b.clone(),
+        }
+    }
+
+    /* Compute the one-based line and column at which this span starts. */
+    pub fn line_col(&self) -> (usize, usize) {
+        let prefix = &self.source.text[..self.start];
+        let line = prefix.matches('\n').count() + 1;
+        let line_start = prefix.rfind('\n').map_or(0, |x| x + 1);
+        let col = prefix[line_start..].chars().count() + 1;
+        (line, col)
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line, col) = self.line_col();
+        write!(f, "{}:{}:{}", self.source.name, line, col)
+    }
+}
+
+/* Render the location of the span followed by the first line of source text
+ * that it covers, with the covered region underlined. */
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line, col) = self.line_col();
+        let text = &self.source.text;
+        let line_start = text[..self.start].rfind('\n').map_or(0, |x| x + 1);
+        let line_end = text[line_start..]
+            .find('\n')
+            .map_or(text.len(), |x| line_start + x);
+        let line_text = text[line_start..line_end].trim_end();
+        let underline_len = text[self.start..self.end.clamp(self.start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+        writeln!(f, "{gutter}--> {}:{}:{}", self.source.name, line, col)?;
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{line} | {line_text}")?;
+        write!(
+            f,
+            "{gutter} | {}{}",
+            " ".repeat(col - 1),
+            "^".repeat(underline_len)
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition(pub LetBinding, pub Option<Span>);
+
+// Encode is manually implemented for Definition because source spans do not
+// implement Encode. Spans are not persisted.
+impl ::bincode::Encode for Definition {
+    fn encode<E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), ::bincode::error::EncodeError> {
+        ::bincode::Encode::encode(&self.0, encoder)
+    }
+}
+
+// Decode is manually implemented for Definition because source spans do not
+// implement Decode. Decoded definitions have no span.
+impl ::bincode::Decode for Definition {
+    fn decode<D: ::bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> core::result::Result<Self, ::bincode::error::DecodeError> {
+        Ok(Self(::bincode::Decode::decode(decoder)?, None))
+    }
+}
+
+impl_borrow_decode!(Definition);
 
 impl Definition {
-    pub fn parse(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::definition {
             return None;
         }
+        let span = Span::new(pair.as_span(), source);
         let mut pairs = pair.into_inner();
         let pair = pairs
             .next()
             .expect("definition should have a single let binding");
-        let binding = LetBinding::parse(pair).expect("definition should contain single binding");
-        Some(Self(binding))
+        let binding =
+            LetBinding::parse(pair, source).expect("definition should contain single binding");
+        Some(Self(binding, Some(span)))
     }
 }
 
@@ -96,36 +431,52 @@ impl fmt::Display for Definition {
 pub struct LetBinding(pub TPat, pub Box<TExpr>);
 
 impl LetBinding {
-    pub fn parse(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::letBinding {
             return None;
         }
+        let span = Span::new(pair.as_span(), source);
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("let binding should not be empty");
-        let expr = TExpr::parse(pair).expect("expression should end with expression");
+        let expr = TExpr::parse(pair, source).expect("expression should end with expression");
         let pair = pairs
             .next()
             .expect("let binding should have at least two parts");
         match pair.as_rule() {
             Rule::valueName => {
+                let name_span = Span::new(pair.as_span(), source);
                 let name = Variable::parse(pair).expect("expression should be value name");
                 let mut pats = vec![];
+                let mut ret = None;
                 for pair in pairs {
-                    let rhs = TPat::parse(pair).expect("expected RHS to be a product");
-                    pats.push(rhs);
+                    if pair.as_rule() == Rule::typeExpr {
+                        ret = Type::parse(pair);
+                    } else {
+                        let rhs = TPat::parse(pair, source).expect("expected RHS to be a product");
+                        pats.push(rhs);
+                    }
                 }
                 let expr = Box::new(
                     Expr::Function(Function {
                         params: pats,
                         body: Box::new(expr),
                         env: HashMap::new(),
+                        ret,
                     })
-                    .type_expr(None),
+                    .type_expr(None)
+                    .with_span(Some(span)),
                 );
-                Some(Self(Pat::Variable(name).type_pat(None), expr))
+                let pat = Pat::Variable(name)
+                    .type_pat(None)
+                    .with_span(Some(name_span));
+                Some(Self(pat, expr))
             }
             Rule::pattern => {
-                let pat = TPat::parse(pair).expect("pattern should start with pattern");
+                let pat = TPat::parse(pair, source).expect("pattern should start with pattern");
+                let pat = match pairs.next() {
+                    Some(pair) => pat.with_annotation(Type::parse(pair)),
+                    None => pat,
+                };
                 Some(Self(pat, Box::new(expr)))
             }
             _ => unreachable!("let binding is of unknown form"),
@@ -136,11 +487,16 @@ impl LetBinding {
 impl fmt::Display for LetBinding {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self.1.v {
-            Expr::Function(Function { params, body, .. }) => {
+            Expr::Function(Function {
+                params, body, ret, ..
+            }) => {
                 write!(f, "{}", self.0)?;
                 for pat in params {
                     write!(f, " {pat}")?;
                 }
+                if let Some(ret) = ret {
+                    write!(f, " : {ret}")?;
+                }
                 write!(f, " =")?;
                 let mut body_str = String::new();
                 write!(body_str, "{body}")?;
@@ -284,12 +640,46 @@ impl ::bincode::Decode for Pat {
 
 impl_borrow_decode!(Pat);
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone)]
 pub struct TPat {
     pub v: Pat,
     pub t: Option<Type>,
+    pub s: Option<Span>,
+    // Annotations are ordered from the innermost to the outermost
+    pub a: Vec<Type>,
 }
 
+// Encode is manually implemented for TPat because source spans do not
+// implement Encode. Spans are not persisted.
+impl ::bincode::Encode for TPat {
+    fn encode<E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), ::bincode::error::EncodeError> {
+        ::bincode::Encode::encode(&self.v, encoder)?;
+        ::bincode::Encode::encode(&self.t, encoder)?;
+        ::bincode::Encode::encode(&self.a, encoder)?;
+        Ok(())
+    }
+}
+
+// Decode is manually implemented for TPat because source spans do not
+// implement Decode. Decoded patterns have no span.
+impl ::bincode::Decode for TPat {
+    fn decode<D: ::bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> core::result::Result<Self, ::bincode::error::DecodeError> {
+        Ok(Self {
+            v: ::bincode::Decode::decode(decoder)?,
+            t: ::bincode::Decode::decode(decoder)?,
+            s: None,
+            a: ::bincode::Decode::decode(decoder)?,
+        })
+    }
+}
+
+impl_borrow_decode!(TPat);
+
 impl Pat {
     pub fn type_pat(self, t: Option<Type>) -> TPat {
         let t = t.or_else(|| match &self {
@@ -307,76 +697,113 @@ impl Pat {
             Self::Constant(_) => Some(Type::Int),
             Self::Variable(_) | Self::Nil => None,
         });
-        TPat { v: self, t }
+        TPat {
+            v: self,
+            t,
+            s: None,
+            a: Vec::new(),
+        }
     }
 }
 
 impl TPat {
-    pub fn parse(pair: Pair<Rule>) -> Option<Self> {
+    /* Attach the given source location to this pattern. */
+    pub fn with_span(mut self, s: Option<Span>) -> Self {
+        self.s = s;
+        self
+    }
+
+    /* Attach the given type annotation to this pattern. Annotations that the
+     * pattern already has are kept so that all of them are checked. */
+    pub fn with_annotation(mut self, a: Option<Type>) -> Self {
+        self.a.extend(a);
+        self
+    }
+
+    pub fn parse(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::pattern {
             return None;
         }
+        let span = Span::new(pair.as_span(), source);
         let mut pairs = pair.into_inner();
         let pair = pairs.next().expect("pattern should not be empty");
-        let mut pat = Self::parse_pat1(pair).expect("pattern should start with pattern");
+        let mut pat = Self::parse_pat1(pair, source).expect("pattern should start with pattern");
         for pair in pairs {
             let name = Variable::parse(pair).expect("expected pattern name");
-            pat = Pat::As(Box::new(pat), name).type_pat(None);
+            pat = Pat::As(Box::new(pat), name)
+                .type_pat(None)
+                .with_span(Some(span.clone()));
         }
         Some(pat)
     }
 
-    pub fn parse_pat1(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_pat1(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::pattern1 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("pattern should not be empty");
-        let mut pats = Self::parse_pat2(pair).expect("expression should start with product");
+        let mut pats =
+            Self::parse_pat2(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next_back() {
-            let rhs = Self::parse_pat2(pair).expect("expected RHS to be a product");
-            pats = Pat::Product(Box::new(rhs), Box::new(pats)).type_pat(None);
+            let rhs = Self::parse_pat2(pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&rhs.s, &pats.s);
+            pats = Pat::Product(Box::new(rhs), Box::new(pats))
+                .type_pat(None)
+                .with_span(span);
         }
         Some(pats)
     }
 
-    pub fn parse_pat2(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_pat2(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::pattern2 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("pattern should not be empty");
-        let mut pats = Self::parse_pat3(pair).expect("expression should start with product");
+        let mut pats =
+            Self::parse_pat3(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next_back() {
-            let rhs = Self::parse_pat3(pair).expect("expected RHS to be a product");
-            pats = Pat::Cons(Box::new(rhs), Box::new(pats)).type_pat(None);
+            let rhs = Self::parse_pat3(pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&rhs.s, &pats.s);
+            pats = Pat::Cons(Box::new(rhs), Box::new(pats))
+                .type_pat(None)
+                .with_span(span);
         }
         Some(pats)
     }
 
-    pub fn parse_pat3(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_pat3(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::pattern3 {
             return None;
         }
+        let span = Some(Span::new(pair.as_span(), source));
         let mut pairs = pair.into_inner();
-        let pair = pairs.next_back().expect("expression should not be empty");
-        match pair.as_rule() {
-            Rule::constant if pair.as_str().starts_with('(') => Some(Pat::Unit.type_pat(None)),
-            Rule::constant if pair.as_str().starts_with('[') => Some(Pat::Nil.type_pat(None)),
+        let pair = pairs.next().expect("expression should not be empty");
+        let pat = match pair.as_rule() {
+            Rule::constant if pair.as_str().starts_with('(') => Pat::Unit.type_pat(None),
+            Rule::constant if pair.as_str().starts_with('[') => Pat::Nil.type_pat(None),
             Rule::constant => {
                 let value = pair
                     .as_str()
                     .parse()
                     .expect("constant should be an integer");
-                Some(Pat::Constant(value).type_pat(None))
+                Pat::Constant(value).type_pat(None)
             }
             Rule::valueName => {
                 let name = Variable::parse(pair).expect("pattern should be value name");
-                Some(Pat::Variable(name).type_pat(None))
+                Pat::Variable(name).type_pat(None)
+            }
+            Rule::pattern => {
+                let pat = Self::parse(pair, source).expect("expected parenthesized pattern");
+                match pairs.next() {
+                    Some(pair) => pat.with_annotation(Type::parse(pair)),
+                    None => return Some(pat),
+                }
             }
-            Rule::pattern => Self::parse(pair),
             _ => unreachable!("pattern is of unknown form"),
-        }
+        };
+        Some(pat.with_span(span))
     }
 
     pub fn to_expr(&self) -> TExpr {
@@ -394,12 +821,18 @@ impl TPat {
         TExpr {
             v,
             t: self.t.clone(),
+            s: self.s.clone(),
         }
     }
 }
 
 impl fmt::Display for TPat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((a, inner)) = self.a.split_last() {
+            let mut pat = self.clone();
+            pat.a = inner.to_vec();
+            return write!(f, "({pat}: {a})");
+        }
         match &self.v {
             Pat::Unit => write!(f, "()")?,
             Pat::Nil => write!(f, "[]")?,
@@ -413,12 +846,42 @@ impl fmt::Display for TPat {
     }
 }
 
-#[derive(Debug, Clone, Encode, Decode)]
+#[derive(Debug, Clone)]
 pub struct TExpr {
     pub v: Expr,
     pub t: Option<Type>,
+    pub s: Option<Span>,
+}
+
+// Encode is manually implemented for TExpr because source spans do not
+// implement Encode. Spans are not persisted.
+impl ::bincode::Encode for TExpr {
+    fn encode<E: ::bincode::enc::Encoder>(
+        &self,
+        encoder: &mut E,
+    ) -> core::result::Result<(), ::bincode::error::EncodeError> {
+        ::bincode::Encode::encode(&self.v, encoder)?;
+        ::bincode::Encode::encode(&self.t, encoder)?;
+        Ok(())
+    }
+}
+
+// Decode is manually implemented for TExpr because source spans do not
+// implement Decode. Decoded expressions have no span.
+impl ::bincode::Decode for TExpr {
+    fn decode<D: ::bincode::de::Decoder>(
+        decoder: &mut D,
+    ) -> core::result::Result<Self, ::bincode::error::DecodeError> {
+        Ok(Self {
+            v: ::bincode::Decode::decode(decoder)?,
+            t: ::bincode::Decode::decode(decoder)?,
+            s: None,
+        })
+    }
 }
 
+impl_borrow_decode!(TExpr);
+
 #[derive(Debug, Clone)]
 pub enum Expr {
     Unit,
@@ -435,6 +898,7 @@ pub enum Expr {
     Intrinsic(Intrinsic),
     LetBinding(LetBinding, Box<TExpr>),
     Match(Match),
+    If(Box<TExpr>, Box<TExpr>, Box<TExpr>),
 }
 
 impl Expr {
@@ -453,6 +917,7 @@ impl Expr {
             Self::Negate(_) => Some(Type::Int),
             Self::Constant(_) => Some(Type::Int),
             Self::LetBinding(_, expr) => expr.t.clone(),
+            Self::If(_, expr1, expr2) => expr1.t.clone().or_else(|| expr2.t.clone()),
             Self::Application(_, _)
             | Self::Match(_)
             | Self::Variable(_)
@@ -460,7 +925,11 @@ impl Expr {
             | Self::Intrinsic(_)
             | Self::Nil => None,
         });
-        TExpr { v: self, t }
+        TExpr {
+            v: self,
+            t,
+            s: None,
+        }
     }
 }
 
@@ -547,6 +1016,13 @@ impl ::bincode::Encode for Expr {
                 ::bincode::Encode::encode(field_1, encoder)?;
                 Ok(())
             }
+            Self::If(field_0, field_1, field_2) => {
+                <u32 as ::bincode::Encode>::encode(&(14u32), encoder)?;
+                ::bincode::Encode::encode(field_0, encoder)?;
+                ::bincode::Encode::encode(field_1, encoder)?;
+                ::bincode::Encode::encode(field_2, encoder)?;
+                Ok(())
+            }
         }
     }
 }
@@ -592,10 +1068,15 @@ impl ::bincode::Decode for Expr {
                 ::bincode::Decode::decode(decoder)?,
                 ::bincode::Decode::decode(decoder)?,
             )),
+            14u32 => Ok(Self::If(
+                ::bincode::Decode::decode(decoder)?,
+                ::bincode::Decode::decode(decoder)?,
+                ::bincode::Decode::decode(decoder)?,
+            )),
             variant => Err(::bincode::error::DecodeError::UnexpectedVariant {
                 found: variant,
                 type_name: "Expr",
-                allowed: &::bincode::error::AllowedEnumVariants::Range { min: 0, max: 11 },
+                allowed: &::bincode::error::AllowedEnumVariants::Range { min: 0, max: 14 },
             }),
         }
     }
@@ -604,158 +1085,243 @@ impl ::bincode::Decode for Expr {
 impl_borrow_decode!(Expr);
 
 impl TExpr {
-    pub fn parse(pair: Pair<Rule>) -> Option<Self> {
+    /* Attach the given source location to this expression. */
+    pub fn with_span(mut self, s: Option<Span>) -> Self {
+        self.s = s;
+        self
+    }
+
+    pub fn parse(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr {
             return None;
         }
+        let span = Some(Span::new(pair.as_span(), source));
         let string = pair.as_str();
         let mut pairs = pair.into_inner();
-        if string.starts_with("fun") {
+        if pairs.peek().map(|pair| pair.as_rule()) == Some(Rule::matchExpr) {
+            let pair = pairs.next().expect("expression should not be empty");
+            Match::parse(pair, source).map(|x| Expr::Match(x).type_expr(None).with_span(span))
+        } else if pairs.peek().map(|pair| pair.as_rule()) == Some(Rule::ifExpr) {
+            let pair = pairs.next().expect("expression should not be empty");
+            let mut exprs = pair.into_inner().map(|pair| {
+                Self::parse(pair, source).expect("conditional should consist of expressions")
+            });
+            let cond = exprs.next().expect("conditional should have a condition");
+            let expr1 = exprs.next().expect("conditional should have a then branch");
+            let expr2 = exprs
+                .next()
+                .expect("conditional should have an else branch");
+            Some(
+                Expr::If(Box::new(cond), Box::new(expr1), Box::new(expr2))
+                    .type_expr(None)
+                    .with_span(span),
+            )
+        } else if string.starts_with("fun") {
             let pair = pairs.next().expect("expression should not be empty");
-            Function::parse(pair).map(|x| Expr::Function(x).type_expr(None))
+            Function::parse(pair, source).map(|x| Expr::Function(x).type_expr(None).with_span(span))
         } else if string.starts_with("def") {
             let pair = pairs
                 .next()
                 .expect("body expression should be prefixed by binding");
-            let binding = LetBinding::parse(pair).expect("expression should start with binding");
+            let binding =
+                LetBinding::parse(pair, source).expect("expression should start with binding");
             let mut body = vec![];
             for pair in pairs.by_ref() {
-                body.push(Self::parse(pair).expect("expression should end with expression"));
+                body.push(
+                    Self::parse(pair, source).expect("expression should end with expression"),
+                );
             }
             if body.is_empty() {
                 panic!("expression should not be empty")
             }
+            let body_span = Span::join(&body[0].s, &body[body.len() - 1].s);
             Some(
-                Expr::LetBinding(binding, Box::new(Expr::Sequence(body).type_expr(None)))
-                    .type_expr(None),
+                Expr::LetBinding(
+                    binding,
+                    Box::new(Expr::Sequence(body).type_expr(None).with_span(body_span)),
+                )
+                .type_expr(None)
+                .with_span(span),
             )
         } else {
             let pair = pairs.next().expect("expression should not be empty");
-            Self::parse_expr1(pair)
+            Self::parse_expr1(pair, source)
         }
     }
 
-    pub fn parse_expr1(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr1(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr1 {
             return None;
         }
+        let span = Some(Span::new(pair.as_span(), source));
         let mut pairs = pair.into_inner();
         let pair = pairs.next().expect("expression should not be empty");
         let mut exprs =
-            vec![Self::parse_expr2(pair).expect("expression should start with product")];
+            vec![Self::parse_expr2(pair, source).expect("expression should start with product")];
         for pair in pairs {
-            let rhs = Self::parse_expr2(pair).expect("expected RHS to be a product");
+            let rhs = Self::parse_expr2(pair, source).expect("expected RHS to be a product");
             exprs.push(rhs);
         }
         Some(if exprs.len() == 1 {
             exprs[0].clone()
         } else {
-            Expr::Sequence(exprs).type_expr(None)
+            Expr::Sequence(exprs).type_expr(None).with_span(span)
         })
     }
 
-    pub fn parse_expr2(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr2(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr2 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("expression should not be empty");
-        let mut exprs = Self::parse_expr3(pair).expect("expression should start with product");
+        let mut exprs =
+            Self::parse_expr3(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next_back() {
-            let rhs = Self::parse_expr3(pair).expect("expected RHS to be a product");
-            exprs = Expr::Product(Box::new(rhs), Box::new(exprs)).type_expr(None);
+            let rhs = Self::parse_expr3(pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&rhs.s, &exprs.s);
+            exprs = Expr::Product(Box::new(rhs), Box::new(exprs))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(exprs)
     }
 
-    pub fn parse_expr3(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr3(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr3 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next().expect("expression should not be empty");
-        let mut expr = Self::parse_expr4(pair).expect("expression should start with product");
+        let mut expr =
+            Self::parse_comparison(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next() {
             let op = InfixOp::parse(pair).expect("expected arithmetic operator");
             let rhs_pair = pairs.next().expect("expected RHS product");
-            let rhs = Self::parse_expr4(rhs_pair).expect("expected RHS to be a product");
-            expr = Expr::Infix(op, Box::new(expr), Box::new(rhs)).type_expr(None);
+            let rhs =
+                Self::parse_comparison(rhs_pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&expr.s, &rhs.s);
+            expr = Expr::Infix(op, Box::new(expr), Box::new(rhs))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(expr)
     }
 
-    pub fn parse_expr4(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_comparison(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
+        if pair.as_rule() != Rule::comparison {
+            return None;
+        }
+        let mut pairs = pair.into_inner();
+        let pair = pairs.next().expect("expression should not be empty");
+        let expr = Self::parse_expr4(pair, source).expect("expression should start with product");
+        if let Some(pair) = pairs.next() {
+            let op = InfixOp::parse(pair).expect("expected comparison operator");
+            let rhs_pair = pairs.next().expect("expected RHS product");
+            let rhs = Self::parse_expr4(rhs_pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&expr.s, &rhs.s);
+            Some(
+                Expr::Infix(op, Box::new(expr), Box::new(rhs))
+                    .type_expr(None)
+                    .with_span(span),
+            )
+        } else {
+            Some(expr)
+        }
+    }
+
+    pub fn parse_expr4(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr4 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("expression should not be empty");
-        let mut exprs = Self::parse_expr5(pair).expect("expression should start with product");
+        let mut exprs =
+            Self::parse_expr5(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next_back() {
-            let rhs = Self::parse_expr5(pair).expect("expected RHS to be a product");
-            exprs = Expr::Cons(Box::new(rhs), Box::new(exprs)).type_expr(None);
+            let rhs = Self::parse_expr5(pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&rhs.s, &exprs.s);
+            exprs = Expr::Cons(Box::new(rhs), Box::new(exprs))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(exprs)
     }
 
-    pub fn parse_expr5(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr5(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr5 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next().expect("expression should not be empty");
-        let mut expr = Self::parse_expr6(pair).expect("expression should start with product");
+        let mut expr =
+            Self::parse_expr6(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next() {
             let op = InfixOp::parse(pair).expect("expected arithmetic operator");
             let rhs_pair = pairs.next().expect("expected RHS product");
-            let rhs = Self::parse_expr6(rhs_pair).expect("expected RHS to be a product");
-            expr = Expr::Infix(op, Box::new(expr), Box::new(rhs)).type_expr(None);
+            let rhs = Self::parse_expr6(rhs_pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&expr.s, &rhs.s);
+            expr = Expr::Infix(op, Box::new(expr), Box::new(rhs))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(expr)
     }
 
-    pub fn parse_expr6(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr6(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr6 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next().expect("expression should not be empty");
-        let mut expr = Self::parse_expr7(pair).expect("expression should start with product");
+        let mut expr =
+            Self::parse_expr7(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next() {
             let op = InfixOp::parse(pair).expect("expected arithmetic operator");
             let rhs_pair = pairs.next().expect("expected RHS product");
-            let rhs = Self::parse_expr7(rhs_pair).expect("expected RHS to be a product");
-            expr = Expr::Infix(op, Box::new(expr), Box::new(rhs)).type_expr(None);
+            let rhs = Self::parse_expr7(rhs_pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&expr.s, &rhs.s);
+            expr = Expr::Infix(op, Box::new(expr), Box::new(rhs))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(expr)
     }
 
-    pub fn parse_expr7(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr7(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr7 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("expression should not be empty");
-        let mut expr = Self::parse_expr8(pair).expect("expression should start with product");
+        let mut expr =
+            Self::parse_expr8(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next_back() {
             let op = InfixOp::parse(pair).expect("expected arithmetic operator");
             let lhs_pair = pairs.next_back().expect("expected RHS product");
-            let lhs = Self::parse_expr8(lhs_pair).expect("expected RHS to be a product");
-            expr = Expr::Infix(op, Box::new(lhs), Box::new(expr)).type_expr(None);
+            let lhs = Self::parse_expr8(lhs_pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&lhs.s, &expr.s);
+            expr = Expr::Infix(op, Box::new(lhs), Box::new(expr))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(expr)
     }
 
-    pub fn parse_expr8(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr8(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr8 {
             return None;
         }
+        let span = Some(Span::new(pair.as_span(), source));
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("expression should not be empty");
-        let mut expr = Self::parse_expr9(pair).expect("expression should start with product");
+        let mut expr =
+            Self::parse_expr9(pair, source).expect("expression should start with product");
         while let Some(pair) = pairs.next_back() {
             if pair.as_rule() == Rule::negate {
-                expr = Expr::Negate(Box::new(expr)).type_expr(None);
+                expr = Expr::Negate(Box::new(expr))
+                    .type_expr(None)
+                    .with_span(span.clone());
             } else {
                 unreachable!("only negative signs should occur here");
             }
@@ -763,43 +1329,49 @@ impl TExpr {
         Some(expr)
     }
 
-    pub fn parse_expr9(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr9(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr9 {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next().expect("expression should not be empty");
-        let mut expr = Self::parse_expr10(pair).expect("expression should start with product");
+        let mut expr =
+            Self::parse_expr10(pair, source).expect("expression should start with product");
         for pair in pairs {
-            let rhs = Self::parse_expr10(pair).expect("expected RHS to be a product");
-            expr = Expr::Application(Box::new(expr), Box::new(rhs)).type_expr(None);
+            let rhs = Self::parse_expr10(pair, source).expect("expected RHS to be a product");
+            let span = Span::join(&expr.s, &rhs.s);
+            expr = Expr::Application(Box::new(expr), Box::new(rhs))
+                .type_expr(None)
+                .with_span(span);
         }
         Some(expr)
     }
 
-    pub fn parse_expr10(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse_expr10(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::expr10 {
             return None;
         }
+        let span = Some(Span::new(pair.as_span(), source));
         let string = pair.as_str();
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("expression should not be empty");
         if pair.as_rule() == Rule::constant && string.starts_with('(') {
-            Some(Expr::Unit.type_expr(None))
+            Some(Expr::Unit.type_expr(None).with_span(span))
         } else if pair.as_rule() == Rule::constant && string.starts_with('[') {
-            Some(Expr::Nil.type_expr(None))
+            Some(Expr::Nil.type_expr(None).with_span(span))
         } else if pair.as_rule() == Rule::constant {
             let value = parse_prefixed_num(pair.as_str()).expect("constant should be an integer");
-            Some(Expr::Constant(value).type_expr(None))
-        } else if pair.as_rule() == Rule::valueName {
+            Some(Expr::Constant(value).type_expr(None).with_span(span))
+        } else if pair.as_rule() == Rule::valueName || pair.as_rule() == Rule::qualifiedName {
             let name = Variable::parse(pair).expect("expression should be value name");
-            Some(Expr::Variable(name).type_expr(None))
+            Some(Expr::Variable(name).type_expr(None).with_span(span))
         } else if string.starts_with('(')
             || string.starts_with("fun")
             || string.starts_with("def")
             || string.starts_with("match")
+            || string.starts_with("if")
         {
-            Self::parse(pair)
+            Self::parse(pair, source)
         } else {
             unreachable!("expression is of unknown form")
         }
@@ -847,6 +1419,7 @@ impl fmt::Display for TExpr {
                 }
             }
             Expr::Match(matche) => write!(f, "{matche}")?,
+            Expr::If(cond, expr1, expr2) => write!(f, "(if {cond} then {expr1} else {expr2})")?,
         }
         Ok(())
     }
@@ -855,15 +1428,42 @@ impl fmt::Display for TExpr {
 #[derive(Debug, Clone, Encode, Decode)]
 pub struct Match(pub Box<TExpr>, pub Vec<TPat>, pub Vec<TExpr>);
 
+impl Match {
+    pub fn parse(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
+        if pair.as_rule() != Rule::matchExpr {
+            return None;
+        }
+        let mut pairs = pair.into_inner();
+        let pair = pairs.next().expect("match should not be empty");
+        let expr = TExpr::parse(pair, source).expect("match should start with expression");
+        let mut pats = vec![];
+        let mut exprs = vec![];
+        for pair in pairs {
+            let mut pairs = pair.into_inner();
+            let pair = pairs.next().expect("match arm should not be empty");
+            let pat = TPat::parse(pair, source).expect("match arm should start with pattern");
+            let pair = pairs.next().expect("match arm should have a body");
+            let expr = match pair.as_rule() {
+                Rule::expr1 => TExpr::parse_expr1(pair, source),
+                _ => TExpr::parse_expr3(pair, source),
+            }
+            .expect("match arm should end with expression");
+            pats.push(pat);
+            exprs.push(expr);
+        }
+        Some(Self(Box::new(expr), pats, exprs))
+    }
+}
+
 impl fmt::Display for Match {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "match {} {{", self.0)?;
         for (pat, expr2) in self.1.iter().zip(self.2.iter()) {
             let mut body = String::new();
-            writeln!(body, "{expr2}")?;
+            write!(body, "{expr2}")?;
             if body.contains('\n') {
                 body = body.replace('\n', "\n    ");
-                writeln!(f, "  {pat} => {{\n    {body}}},")?;
+                writeln!(f, "  {pat} => {{\n    {body}\n  }},")?;
             } else {
                 writeln!(f, "  {pat} => {expr2},")?;
             }
@@ -873,7 +1473,7 @@ impl fmt::Display for Match {
     }
 }
 
-#[derive(Debug, Clone, Copy, Encode, Decode, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Encode, Decode, Eq, PartialEq, Hash)]
 pub enum InfixOp {
     Divide,
     DivideZ,
@@ -884,6 +1484,56 @@ pub enum InfixOp {
     Exponentiate,
     IntDivide,
     Modulo,
+    Less,
+    LessEqual,
+    NotEqual,
+}
+
+impl Type {
+    pub fn parse(pair: Pair<Rule>) -> Option<Self> {
+        if pair.as_rule() != Rule::typeExpr {
+            return None;
+        }
+        let mut pairs = pair.into_inner();
+        let pair = pairs.next_back().expect("type should not be empty");
+        let mut typ = Self::parse_type1(pair).expect("type should end with type");
+        while let Some(pair) = pairs.next_back() {
+            let lhs = Self::parse_type1(pair).expect("expected LHS to be a type");
+            typ = Type::Function(Box::new(lhs), Box::new(typ));
+        }
+        Some(typ)
+    }
+
+    pub fn parse_type1(pair: Pair<Rule>) -> Option<Self> {
+        if pair.as_rule() != Rule::typeExpr1 {
+            return None;
+        }
+        let string = pair.as_str();
+        let mut pairs = pair.into_inner();
+        match pairs.peek().map(|pair| pair.as_rule()) {
+            None => Some(Type::Unit),
+            Some(Rule::intType) => Some(Type::Int),
+            Some(Rule::listType) => {
+                let pair = pairs.nth(1).expect("list type should have an element type");
+                let elt = Self::parse_type1(pair).expect("expected element type");
+                Some(Type::List(Box::new(elt)))
+            }
+            Some(_) if string.starts_with('[') => {
+                let pair = pairs.next().expect("list type should have an element type");
+                let elt = Self::parse(pair).expect("expected element type");
+                Some(Type::List(Box::new(elt)))
+            }
+            Some(_) => {
+                let pair = pairs.next_back().expect("type should not be empty");
+                let mut typ = Self::parse(pair).expect("expected tuple element type");
+                while let Some(pair) = pairs.next_back() {
+                    let lhs = Self::parse(pair).expect("expected tuple element type");
+                    typ = Type::Product(Box::new(lhs), Box::new(typ));
+                }
+                Some(typ)
+            }
+        }
+    }
 }
 
 impl InfixOp {
@@ -901,6 +1551,9 @@ impl InfixOp {
             "^" => Some(Self::Exponentiate),
             "\\" => Some(Self::IntDivide),
             "%" => Some(Self::Modulo),
+            "<" => Some(Self::Less),
+            "<=" => Some(Self::LessEqual),
+            "!=" => Some(Self::NotEqual),
             _ => unreachable!("Encountered unknown infix operator"),
         }
     }
@@ -918,6 +1571,9 @@ impl fmt::Display for InfixOp {
             Self::Exponentiate => write!(f, "^"),
             Self::IntDivide => write!(f, "\\"),
             Self::Modulo => write!(f, "%"),
+            Self::Less => write!(f, "<"),
+            Self::LessEqual => write!(f, "<="),
+            Self::NotEqual => write!(f, "!="),
         }
     }
 }
@@ -936,7 +1592,7 @@ impl Variable {
     }
 
     pub fn parse(pair: Pair<Rule>) -> Option<Self> {
-        if pair.as_rule() != Rule::valueName {
+        if pair.as_rule() != Rule::valueName && pair.as_rule() != Rule::qualifiedName {
             return None;
         }
         Some(Self {
@@ -961,25 +1617,33 @@ pub struct Function {
     pub params: Vec<TPat>,
     pub body: Box<TExpr>,
     pub env: HashMap<VariableId, TExpr>,
+    pub ret: Option<Type>,
 }
 
 impl Function {
-    pub fn parse(pair: Pair<Rule>) -> Option<Self> {
+    pub fn parse(pair: Pair<Rule>, source: &Arc<Source>) -> Option<Self> {
         if pair.as_rule() != Rule::function {
             return None;
         }
         let mut pairs = pair.into_inner();
         let pair = pairs.next_back().expect("function should not be empty");
-        let body = TExpr::parse(pair).expect("function should end with expression");
+        let body = TExpr::parse(pair, source).expect("function should end with expression");
         let mut params = vec![];
+        let mut ret = None;
         for pair in pairs {
-            let param = TPat::parse(pair).expect("all prefixes to function should be patterns");
-            params.push(param);
+            if pair.as_rule() == Rule::typeExpr {
+                ret = Type::parse(pair);
+            } else {
+                let param =
+                    TPat::parse(pair, source).expect("all prefixes to function should be patterns");
+                params.push(param);
+            }
         }
         Some(Self {
             params,
             body: Box::new(body),
             env: HashMap::default(),
+            ret,
         })
     }
 }
@@ -990,6 +1654,9 @@ impl fmt::Display for Function {
         for pat in &self.params[1..] {
             write!(f, " {pat}")?;
         }
+        if let Some(ret) = &self.ret {
+            write!(f, " : {ret}")?;
+        }
 
         let mut body = String::new();
         write!(body, "{}", self.body)?;
@@ -1005,7 +1672,7 @@ impl fmt::Display for Function {
 
 /* The underlying function that expands an intrinsic call. */
 type IntrinsicImp = fn(
-    &Vec<TPat>,
+    &[TPat],
     &HashMap<VariableId, TExpr>,
     &mut HashSet<VariableId>,
     &mut VarGen,
@@ -1076,3 +1743,196 @@ impl Intrinsic {
         (self.imp)(&self.params, bindings, prover_defs, gen)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo2::synth::{make_constant, Halo2Module, PrimeFieldOps};
+    use crate::transform::compile;
+    use crate::util::{get_circuit_assignments, Config};
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::pasta::Fp;
+    use std::rc::Rc;
+
+    /* A directory of source files that is removed when dropped. */
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        /* Write the given files into a fresh directory for the given test. */
+        fn new(test: &str, files: &[(&str, &str)]) -> Self {
+            let dir = std::env::temp_dir().join(format!("vamp-ir-{}-{test}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            for (name, text) in files {
+                fs::write(dir.join(name), text).unwrap();
+            }
+            Self(dir)
+        }
+
+        fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_span_after_non_ascii_text() {
+        let source = Arc::new(Source {
+            name: "test.pir".to_string(),
+            text: "x = 1;\ndef é = ü + y;".to_string(),
+        });
+        let start = source.text.find('y').unwrap();
+        let span = Span {
+            source,
+            start,
+            end: start + 1,
+        };
+        assert_eq!(
+            span.to_string(),
+            format!(
+                " --> test.pir:2:13\n  |\n2 | def é = ü + y;\n  | {}^",
+                " ".repeat(12)
+            )
+        );
+    }
+
+    #[test]
+    fn test_annotations_with_type_names_as_variables() {
+        let module = Module::parse(
+            "def int = 1; def list = int:[]; def f x : list int = x:list; \
+             def (a:b) : list int = f int; a = int;",
+        )
+        .unwrap();
+        // The result annotation follows all the parameter patterns
+        match &module.defs[2].0 .1.v {
+            Expr::Function(Function { params, ret, .. }) => {
+                assert!(matches!(
+                    params.as_slice(),
+                    [TPat {
+                        v: Pat::Variable(_),
+                        ..
+                    }]
+                ));
+                assert_eq!(ret.as_ref().map(Type::to_string), Some("[int]".to_string()));
+            }
+            _ => panic!("expected function definition"),
+        }
+        // A cons pattern can still precede a definition annotation
+        let pat = &module.defs[3].0 .0;
+        assert!(matches!(pat.v, Pat::Cons(_, _)));
+        assert_eq!(
+            pat.a.iter().map(Type::to_string).collect::<Vec<_>>(),
+            ["[int]"]
+        );
+    }
+
+    #[test]
+    fn test_self_import_rejected() {
+        let dir = Fixture::new(
+            "self-import",
+            &[("main.pir", "import \"main.pir\"; x = 1;")],
+        );
+        let result = Module::parse_file(dir.join("main.pir"));
+        assert!(matches!(result, Err(Error::CyclicImport { cycle }) if cycle.len() == 2));
+    }
+
+    #[test]
+    fn test_import_cycle_rejected() {
+        let dir = Fixture::new(
+            "import-cycle",
+            &[
+                ("a.pir", "import \"b.pir\"; def a = 1;"),
+                ("b.pir", "import \"a.pir\"; def b = 2;"),
+            ],
+        );
+        let result = Module::parse_file(dir.join("a.pir"));
+        assert!(matches!(result, Err(Error::CyclicImport { cycle }) if cycle.len() == 3));
+    }
+
+    #[test]
+    fn test_missing_import_rejected() {
+        let dir = Fixture::new(
+            "missing-import",
+            &[("main.pir", "import \"absent.pir\"; x = 1;")],
+        );
+        let result = Module::parse_file(dir.join("main.pir"));
+        assert!(
+            matches!(result, Err(Error::MissingImport { path }) if path.ends_with("absent.pir"))
+        );
+    }
+
+    #[test]
+    fn test_import_outside_file_rejected() {
+        let result = Module::parse("import \"lib.pir\"; x = 1;");
+        assert!(matches!(result, Err(Error::UnresolvedImport { path }) if path == "lib.pir"));
+    }
+
+    #[test]
+    fn test_namespaced_import_constraints_rejected() {
+        let dir = Fixture::new(
+            "namespaced-import-constraints",
+            &[
+                ("lib.pir", "pub x; def double x = 2*x; x = 1;"),
+                ("main.pir", "import \"lib.pir\" as lib; lib::double 3 = 6;"),
+            ],
+        );
+        let result = Module::parse_file(dir.join("main.pir"));
+        assert!(
+            matches!(result, Err(Error::NamespacedImportItems { path }) if path.ends_with("lib.pir"))
+        );
+    }
+
+    #[test]
+    fn test_namespaced_import() {
+        let dir = Fixture::new(
+            "namespaced-import",
+            &[
+                ("lib.pir", "def double x = 2*x;"),
+                (
+                    "main.pir",
+                    "import \"lib.pir\" as lib; pub y; def double x = x; lib::double 3 = y;",
+                ),
+            ],
+        );
+        let module = Module::parse_file(dir.join("main.pir")).unwrap();
+        let names: HashSet<_> = module
+            .defs
+            .iter()
+            .filter_map(|def| match &def.0 .0.v {
+                Pat::Variable(var) => var.name.clone(),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            names,
+            HashSet::from(["lib::double".to_string(), "double".to_string()])
+        );
+        // The qualified reference resolves to the imported definition
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module_3ac =
+            Rc::new(compile(module, &PrimeFieldOps::<Fp>::default(), &config).unwrap());
+        let satisfied = |y: u64| {
+            let named = HashMap::from([("y".to_string(), make_constant(BigInt::from(y)))]);
+            let assignments = get_circuit_assignments(&module_3ac, &named).unwrap();
+            let instances = module_3ac
+                .pubs
+                .iter()
+                .map(|var| assignments[&var.id])
+                .collect();
+            let mut circuit = Halo2Module::<Fp>::new(module_3ac.clone());
+            circuit.populate_variables(assignments).unwrap();
+            let prover = MockProver::run(circuit.k, &circuit, vec![instances]).unwrap();
+            prover.verify().is_ok()
+        };
+        assert!(satisfied(6));
+        assert!(!satisfied(3));
+    }
+}
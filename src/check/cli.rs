@@ -0,0 +1,104 @@
+use crate::ast::Module;
+use crate::error::Error;
+use crate::proof_system::cli::read_inputs;
+use crate::qprintln;
+use crate::transform::compile;
+use crate::util::{Config, UnsatisfiedEquality};
+
+use crate::plonk::synth::{
+    make_constant as plonk_make_constant, PlonkModule, PrimeFieldOps as PlonkPrimeFieldOps,
+};
+use ark_bls12_381::Fr as BlsScalar;
+use ark_ed_on_bls12_381::EdwardsParameters as JubJubParameters;
+
+use crate::halo2::synth::{
+    make_constant as halo2_make_constant, Halo2Module, PrimeFieldOps as Halo2PrimeFieldOps,
+};
+use halo2_proofs::pasta::Fp;
+
+use clap::{Args, ValueEnum};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Args)]
+pub struct Check {
+    /// Path to source file to be checked
+    #[arg(short, long)]
+    source: PathBuf,
+    /// Path to prover's input file, next to the source file by default
+    #[arg(short, long)]
+    inputs: Option<PathBuf>,
+    /// Field in which the circuit is evaluated
+    #[arg(long, value_enum)]
+    field: CheckField,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum CheckField {
+    /// Scalar field of BLS12-381, as used by the PLONK backend
+    #[value(name = "bls12-381")]
+    Bls12381,
+    /// Pallas base field, as used by the Halo2 backend
+    Pallas,
+}
+
+/* Implements the subcommand that solves for the witnesses of a circuit and
+ * reports the equalities that they do not satisfy, without proving. */
+pub fn check(
+    Check {
+        source,
+        inputs,
+        field,
+    }: &Check,
+    config: &Config,
+) -> Result<(), Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(source)?;
+    let unsatisfied = match field {
+        CheckField::Bls12381 => {
+            let module_3ac = compile(module, &PlonkPrimeFieldOps::<BlsScalar>::default(), config)?;
+            let circuit = PlonkModule::<BlsScalar, JubJubParameters>::new(Rc::new(module_3ac));
+            let assignments = read_inputs(inputs, source, &circuit.module, config)?
+                .into_iter()
+                .map(|(id, val)| (id, plonk_make_constant(&val)))
+                .collect();
+            qprintln!(config, "* Checking witnesses...");
+            circuit.check_variables(assignments)?
+        }
+        CheckField::Pallas => {
+            let module_3ac = compile(module, &Halo2PrimeFieldOps::<Fp>::default(), config)?;
+            let circuit = Halo2Module::<Fp>::new(Rc::new(module_3ac));
+            let assignments = read_inputs(inputs, source, &circuit.module, config)?
+                .into_iter()
+                .map(|(id, val)| (id, halo2_make_constant(val)))
+                .collect();
+            qprintln!(config, "* Checking witnesses...");
+            circuit.check_variables(assignments)?
+        }
+    };
+    report_unsatisfied(&unsatisfied, config)
+}
+
+/* Print out each unsatisfied equality along with the values involved in it. */
+fn report_unsatisfied(unsatisfied: &[UnsatisfiedEquality], config: &Config) -> Result<(), Error> {
+    for UnsatisfiedEquality {
+        expr,
+        lhs,
+        rhs,
+        variables,
+    } in unsatisfied
+    {
+        println!("* Equality {expr} does not hold: {lhs} != {rhs}");
+        for (var, val) in variables {
+            println!("    {var} = {val}");
+        }
+    }
+    if unsatisfied.is_empty() {
+        qprintln!(config, "* All equalities hold");
+        Ok(())
+    } else {
+        Err(Error::UnsatisfiedEqualities {
+            count: unsatisfied.len(),
+        })
+    }
+}
@@ -1,16 +1,47 @@
 use crate::{
-    ast::{TExpr, TPat, Variable},
+    ast::{Span, TExpr, TPat, Variable},
     typecheck::Type,
 };
+use num_bigint::BigInt;
 
-#[derive(Debug)]
 pub enum Error {
-    ParseError { e: String },
+    ParseError {
+        e: String,
+    },
+
+    // {} followed by the offending source location
+    Located {
+        e: Box<Error>,
+        s: Span,
+    },
+
+    // unable to read imported file {}
+    MissingImport {
+        path: String,
+    },
+
+    // import cycle detected: {}
+    CyclicImport {
+        cycle: Vec<String>,
+    },
+
+    // import of {} outside of a file
+    UnresolvedImport {
+        path: String,
+    },
+
+    // namespaced import {} declares public inputs or constraints
+    NamespacedImportItems {
+        path: String,
+    },
 
     // Compilation errors
 
     // cannot apply {} to {}
-    ApplicationError { e2: TExpr, e1: TExpr },
+    ApplicationError {
+        e2: Box<TExpr>,
+        e1: Box<TExpr>,
+    },
 
     // encountered empty sequence
     EmptySequenceError,
@@ -21,26 +52,89 @@ pub enum Error {
     // only constant arguments to iter supported
     NonConstantIterArgumentError,
 
+    // iteration count {} is too large
+    IterationCountError {
+        c: BigInt,
+    },
+
+    // only constant bit widths to range supported
+    NonConstantRangeWidthError,
+
+    // bit width {} is too large
+    BitWidthError {
+        w: BigInt,
+    },
+
+    // {} does not fit into {} bits
+    RangeError {
+        c: BigInt,
+        w: usize,
+    },
+
     // cannot statically match {} against {}
-    StaticMatchError { e: TExpr, p: TPat },
+    StaticMatchError {
+        e: Box<TExpr>,
+        p: Box<TPat>,
+    },
 
     // cannot match {} to any pattern in {}
-    MatchError { e1: TExpr, e2: TExpr },
+    MatchError {
+        e1: Box<TExpr>,
+        e2: Box<TExpr>,
+    },
 
     // only list arguments to fold supported
     NonListArgumentsInFoldError,
 
     // encountered unexpected expression: {}
-    UnexpectedExpression { e: TExpr },
+    UnexpectedExpression {
+        e: Box<TExpr>,
+    },
+
+    // cannot constrain {} to equal {}
+    EqualityError {
+        e1: Box<TExpr>,
+        e2: Box<TExpr>,
+    },
+
+    // encountered unexpected pattern: {}
+    UnexpectedPattern {
+        p: Box<TPat>,
+    },
+
+    // condition {} is neither 0 nor 1
+    NonBooleanCondition {
+        c: BigInt,
+    },
+
+    // cannot select between {} and {} using a variable condition
+    ConditionalBranchError {
+        e1: Box<TExpr>,
+        e2: Box<TExpr>,
+    },
+
+    // branches selected by a variable condition cannot contain constraints
+    ConditionalConstraintError,
 
     // unexpected parameters for fresh: {:?}
-    UnexpectedFreshParameters { params: Vec<TPat> },
+    UnexpectedFreshParameters {
+        params: Vec<TPat>,
+    },
 
     // unexpected arguments to iter: {:?}
-    UnexpectedIterArguments { params: Vec<TPat> },
+    UnexpectedIterArguments {
+        params: Vec<TPat>,
+    },
 
     // unexpected arguments to fold: {:?}
-    UnexpectedArgumentsInFold { params: Vec<TPat> },
+    UnexpectedArgumentsInFold {
+        params: Vec<TPat>,
+    },
+
+    // unexpected arguments to range: {:?}
+    UnexpectedRangeArguments {
+        params: Vec<TPat>,
+    },
 
     // functions should have at least one parameter
     NoParameterInFunction,
@@ -51,51 +145,173 @@ pub enum Error {
     OccursCheckError,
 
     // unable to match {:?} with {}
-    VariableTypeError { v: Variable, t: Type },
+    VariableTypeError {
+        v: Variable,
+        t: Type,
+    },
 
     // unable to match {} with {}
-    TypeError { t1: Type, t2: Type },
+    TypeError {
+        t1: Type,
+        t2: Type,
+    },
+
+    // annotated type {} does not match inferred type {}
+    AnnotationError {
+        a: Type,
+        t: Type,
+    },
 
     // pattern {} cannot match {}
-    PatternMatchError { p: TPat, e: TExpr },
+    PatternMatchError {
+        p: Box<TPat>,
+        e: Box<TExpr>,
+    },
 
     // pattern cannot use the variable {} more than once
-    DuplicatePatternVariable { v: Variable },
+    DuplicatePatternVariable {
+        v: Variable,
+    },
 
     // the global function {} is undefined
-    UndefinedGlobalFunction { v: Variable },
+    UndefinedGlobalFunction {
+        v: Variable,
+    },
 
     // unable to determine type of global variable {}
-    UnableDetermineType { v: Variable },
+    UnableDetermineType {
+        v: Variable,
+    },
 
     // expression {} cannot have type {}
-    ImpossibleType { e: TExpr, t: Type },
+    ImpossibleType {
+        e: Box<TExpr>,
+        t: Type,
+    },
 
     // the global list {} is undefined
-    UndefinedGlobalList { v: Variable },
+    UndefinedGlobalList {
+        v: Variable,
+    },
 
     // not enough parameters are available for this circuit
     InsufficientParameters,
 
     // general error from backend
-    BackendError { e: String },
+    BackendError {
+        e: String,
+    },
 
     // The user did not provide an assignment for a variable during proving
-    MissingVariableAssignment { var_name: String },
+    MissingVariableAssignment {
+        var_name: String,
+    },
 
     // A variable assignment has an invalid value
-    InvalidVariableAssignmentValue { var_name: String },
+    InvalidVariableAssignmentValue {
+        var_name: String,
+    },
+
+    // unable to read inputs file {}: {}
+    MissingInputsFile {
+        path: String,
+        e: String,
+    },
+
+    // inputs file {} is not valid JSON5: {}
+    InvalidInputsFile {
+        path: String,
+        e: String,
+    },
+
+    // the witness solver could not determine the values of variables {}
+    UnsolvedVariables {
+        var_names: Vec<String>,
+    },
 
     // proof fails to verify
     ProofVerificationFailure,
 
+    // public parameters support circuits of size 2^{} but the circuit needs 2^{}
+    InsufficientParams {
+        k: u32,
+        required: u32,
+    },
+
+    // the regenerated verifying key does not match the digest recorded with it
+    VerifyingKeyMismatch,
+
+    // unable to read or write {}: {}
+    ArtifactError {
+        artifact: String,
+        e: String,
+    },
+
+    // public input {} is {} in the proof but {} was expected
+    PublicInputMismatch {
+        var_name: String,
+        actual: BigInt,
+        expected: BigInt,
+    },
+
+    // the backend requires public parameters but none were given
+    MissingParams,
+
+    // proof has a public input at gate {} which is not a public input of the circuit
+    UnexpectedPublicInput {
+        pos: usize,
+    },
+
+    // proof has {} public inputs but the circuit has {}
+    PublicInputCountMismatch {
+        actual: usize,
+        expected: usize,
+    },
+
+    // public variable {} has no name to assign it by
+    UnnamedPublicVariable {
+        v: Variable,
+    },
+
+    // {} equalities do not hold under the given inputs
+    UnsatisfiedEqualities {
+        count: usize,
+    },
+
+    // constraint {} cannot be expressed as a rank-1 constraint
+    NonQuadraticConstraint {
+        e: Box<TExpr>,
+    },
+
+    // cannot convert three-address module to or from JSON: {}
+    ModuleJsonError {
+        e: String,
+    },
+
     // invalid field at repl
     InvalidField,
 }
 
+impl Error {
+    /* Attribute this error to the given source location unless it has already
+     * been attributed to a more specific one. */
+    pub fn locate(self, s: &Option<Span>) -> Self {
+        match (self, s) {
+            (e @ Self::Located { .. }, _) | (e, None) => e,
+            (e, Some(s)) => Self::Located {
+                e: Box::new(e),
+                s: s.clone(),
+            },
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            // {} followed by the offending source location
+            Self::Located { e, s } => write!(f, "{e}\n{s}"),
+
             // cannot apply {} to {}
             Self::ApplicationError { e2, e1 } => write!(f, "Cannot apply {e2} to {e1}"),
 
@@ -112,9 +328,27 @@ impl std::fmt::Display for Error {
                 write!(f, "Only constant arguments to iter supported")
             }
 
+            // iteration count {} is too large
+            Self::IterationCountError { c } => write!(f, "Iteration count {c} is too large"),
+
+            // only constant bit widths to range supported
+            Self::NonConstantRangeWidthError => {
+                write!(f, "Only constant bit widths to range supported")
+            }
+
+            // bit width {} is too large
+            Self::BitWidthError { w } => write!(f, "Bit width {w} is too large"),
+
+            // {} does not fit into {} bits
+            Self::RangeError { c, w } => write!(f, "{c} does not fit into {w} bits"),
+
             // cannot statically match {} against {}
             Self::StaticMatchError { e, p } => {
-                write!(f, "Cannot statically match {e} against {p}")
+                write!(
+                    f,
+                    "Cannot statically match {e} against {p}: match conditions must be \
+                    known at compile time"
+                )
             }
 
             // cannot match {} to any pattern in {}
@@ -130,6 +364,28 @@ impl std::fmt::Display for Error {
                 write!(f, "Encountered unexpected expression: {e}")
             }
 
+            // cannot constrain {} to equal {}
+            Self::EqualityError { e1, e2 } => write!(f, "Cannot constrain {e1} to equal {e2}"),
+
+            // encountered unexpected pattern: {}
+            Self::UnexpectedPattern { p } => write!(f, "Encountered unexpected pattern: {p}"),
+
+            // condition {} is neither 0 nor 1
+            Self::NonBooleanCondition { c } => write!(f, "Condition {c} is neither 0 nor 1"),
+
+            // cannot select between {} and {} using a variable condition
+            Self::ConditionalBranchError { e1, e2 } => write!(
+                f,
+                "Cannot select between {e1} and {e2} using a variable condition"
+            ),
+
+            // branches selected by a variable condition cannot contain constraints
+            Self::ConditionalConstraintError => write!(
+                f,
+                "Branches selected by a variable condition cannot contain constraints \
+                since these would hold whichever branch is selected"
+            ),
+
             // unexpected parameters for fresh: {:?}
             Self::UnexpectedFreshParameters { params } => {
                 write!(f, "unexpected parameters for fresh: {params:?}")
@@ -145,6 +401,11 @@ impl std::fmt::Display for Error {
                 write!(f, "Unexpected arguments to fold: {params:?}")
             }
 
+            // unexpected arguments to range: {:?}
+            Self::UnexpectedRangeArguments { params } => {
+                write!(f, "Unexpected arguments to range: {params:?}")
+            }
+
             // functions should have at least one parameter
             Self::NoParameterInFunction => {
                 write!(f, "Functions should have at least one parameter")
@@ -163,6 +424,11 @@ impl std::fmt::Display for Error {
             // unable to match {} with {}
             Self::TypeError { t1, t2 } => write!(f, "Unable to match {t1} with {t2}"),
 
+            // annotated type {} does not match inferred type {}
+            Self::AnnotationError { a, t } => {
+                write!(f, "Annotated type {a} does not match inferred type {t}")
+            }
+
             // pattern {} cannot match {}
             Self::PatternMatchError { p, e } => write!(f, "Pattern {p} cannot match {e}"),
 
@@ -197,11 +463,104 @@ impl std::fmt::Display for Error {
             // proof fails to verify
             Self::ProofVerificationFailure => write!(f, "Proof failed to verify"),
 
+            // the regenerated verifying key does not match the digest recorded with it
+            Self::VerifyingKeyMismatch => write!(
+                f,
+                "The regenerated verifying key does not match the digest recorded with it"
+            ),
+
+            // public parameters support circuits of size 2^{} but the circuit needs 2^{}
+            Self::InsufficientParams { k, required } => write!(
+                f,
+                "Public parameters support circuits of size 2^{k} but the circuit needs 2^{required}"
+            ),
+
+            // unable to read or write {}: {}
+            Self::ArtifactError { artifact, e } => {
+                write!(f, "Unable to read or write {artifact}: {e}")
+            }
+
+            // the backend requires public parameters but none were given
+            Self::MissingParams => write!(
+                f,
+                "Public parameters are required by this backend, supply them with -u"
+            ),
+
+            // public input {} is {} in the proof but {} was expected
+            Self::PublicInputMismatch {
+                var_name,
+                actual,
+                expected,
+            } => write!(
+                f,
+                "Public input {var_name} is {actual} in the proof but {expected} was expected"
+            ),
+
+            // proof has a public input at gate {} which is not a public input of the circuit
+            Self::UnexpectedPublicInput { pos } => write!(
+                f,
+                "Proof has a public input at gate {pos} which is not a public input of the circuit"
+            ),
+
+            // proof has {} public inputs but the circuit has {}
+            Self::PublicInputCountMismatch { actual, expected } => write!(
+                f,
+                "Proof has {actual} public inputs but the circuit has {expected}"
+            ),
+
+            // public variable {} has no name to assign it by
+            Self::UnnamedPublicVariable { v } => {
+                write!(f, "Public variable {v} has no name to assign it by")
+            }
+
+            // {} equalities do not hold under the given inputs
+            Self::UnsatisfiedEqualities { count } => {
+                write!(f, "{count} equalities do not hold under the given inputs")
+            }
+
+            // constraint {} cannot be expressed as a rank-1 constraint
+            Self::NonQuadraticConstraint { e } => {
+                write!(
+                    f,
+                    "Constraint {e} cannot be expressed as a rank-1 constraint"
+                )
+            }
+
+            // cannot convert three-address module to or from JSON: {}
+            Self::ModuleJsonError { e } => {
+                write!(
+                    f,
+                    "Cannot convert three-address module to or from JSON: {e}"
+                )
+            }
+
             // invalid field at repl
             Self::InvalidField => write!(f, "Invalid field value"),
 
             Self::ParseError { e } => write!(f, "Error while parsing file: {e}"),
 
+            // unable to read imported file {}
+            Self::MissingImport { path } => write!(f, "Unable to read imported file {path}"),
+
+            // import cycle detected: {}
+            Self::CyclicImport { cycle } => {
+                write!(f, "Import cycle detected: {}", cycle.join(" -> "))
+            }
+
+            // import of {} outside of a file
+            Self::UnresolvedImport { path } => write!(
+                f,
+                "Unable to resolve import of {path} outside of a file; \
+                compile the file containing it instead"
+            ),
+
+            // namespaced import {} declares public inputs or constraints
+            Self::NamespacedImportItems { path } => write!(
+                f,
+                "Namespaced import {path} declares public inputs or constraints, \
+                which may only be imported without a namespace"
+            ),
+
             Self::MissingVariableAssignment { var_name } => {
                 write!(f, "Missing assignment for variable: {var_name}")
             }
@@ -210,6 +569,31 @@ impl std::fmt::Display for Error {
                 f,
                 "The assignment for variable: {var_name} has an invalid value"
             ),
+
+            // unable to read inputs file {}: {}
+            Self::MissingInputsFile { path, e } => {
+                write!(f, "Unable to read inputs file {path}: {e}")
+            }
+
+            // inputs file {} is not valid JSON5: {}
+            Self::InvalidInputsFile { path, e } => {
+                write!(f, "Inputs file {path} is not valid JSON5: {e}")
+            }
+
+            // the witness solver could not determine the values of variables {}
+            Self::UnsolvedVariables { var_names } => write!(
+                f,
+                "The witness solver could not determine the values of variables: {}",
+                var_names.join(", ")
+            ),
         }
     }
 }
+
+// Debug is implemented in terms of Display so that unwrapped errors are
+// reported as rendered diagnostics.
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
@@ -0,0 +1,342 @@
+use crate::ast::Module;
+use crate::error::Error;
+use crate::export::json::{
+    halo2_layout_to_json, module_to_json, plonk_layout_to_json, read_module_json, witness_to_json,
+};
+use crate::export::r1cs::{write_witness, ModularFieldOps, R1cs};
+use crate::halo2::synth::{Halo2Module, PrimeFieldOps as Halo2PrimeFieldOps};
+use crate::plonk::synth::{PlonkModule, PrimeFieldOps as PlonkPrimeFieldOps};
+use crate::proof_system::artifact_error;
+use crate::proof_system::cli::{create_file, read_inputs, ProofSystems};
+use crate::qprintln;
+use crate::solver::solve_witnesses;
+use crate::transform::{compile, FieldOps};
+use crate::util::Config;
+
+use ark_bls12_381::{Fr as BlsScalar, FrParameters};
+use ark_ed_on_bls12_381::EdwardsParameters as JubJubParameters;
+use ark_ff::{BigInteger, FpParameters};
+use ff::PrimeField;
+use halo2_proofs::pasta::Fp;
+
+use clap::{Args, Subcommand, ValueEnum};
+use num_bigint::{BigInt, BigUint};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Subcommand)]
+pub enum ExportCommands {
+    /// Export a circuit as a rank-1 constraint system in the iden3 format
+    R1cs(R1csExport),
+    /// Export the witness of a rank-1 constraint system in the iden3 format
+    Wtns(WtnsExport),
+    /// Export the three-address constraints of a circuit as JSON
+    Json(JsonExport),
+    /// Export the gates of a circuit and the variables wired into them as JSON
+    Layout(LayoutExport),
+    /// Export the values of the variables of a circuit as JSON
+    Witness(WitnessExport),
+}
+
+#[derive(Args)]
+pub struct R1csExport {
+    /// Path to source file to be exported
+    #[arg(short, long)]
+    source: PathBuf,
+    /// Field over which the constraint system is expressed
+    #[arg(long, value_enum)]
+    field: ExportField,
+    /// Path to which the constraint system is written
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Path to which the wire names are written, next to the output by default
+    #[arg(long)]
+    symbols: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct WtnsExport {
+    /// Path to source file whose witness is exported
+    #[arg(short, long)]
+    source: PathBuf,
+    /// Path to prover's input file, next to the source file by default
+    #[arg(short, long)]
+    inputs: Option<PathBuf>,
+    /// Field over which the constraint system is expressed
+    #[arg(long, value_enum)]
+    field: ExportField,
+    /// Path to which the witness is written
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct JsonExport {
+    /// Path to source file to be exported
+    #[arg(short, long)]
+    source: PathBuf,
+    /// Field over which the constraints are simplified
+    #[arg(long, value_enum)]
+    field: ExportField,
+    /// Path to which the three-address constraints are written
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct LayoutExport {
+    /// Proof system whose gates are exported
+    #[arg(short, long, value_enum)]
+    backend: ProofSystems,
+    /// Path to source file to be exported
+    #[arg(short, long, required_unless_present = "module")]
+    source: Option<PathBuf>,
+    /// Path to three-address constraints exported as JSON
+    #[arg(long, conflicts_with = "source")]
+    module: Option<PathBuf>,
+    /// Path to which the gates are written
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct WitnessExport {
+    /// Path to source file whose witness is exported
+    #[arg(short, long, required_unless_present = "module")]
+    source: Option<PathBuf>,
+    /// Path to three-address constraints exported as JSON
+    #[arg(long, conflicts_with = "source")]
+    module: Option<PathBuf>,
+    /// Path to prover's input file, next to the source or module file by default
+    #[arg(short, long)]
+    inputs: Option<PathBuf>,
+    /// Field over which the witness is solved
+    #[arg(long, value_enum)]
+    field: ExportField,
+    /// Path to which the witness is written
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum ExportField {
+    /// Scalar field of BLS12-381, as used by the PLONK backend
+    #[value(name = "bls12-381")]
+    Bls12381,
+    /// Scalar field of BN254, as used by circom and snarkjs by default
+    Bn254,
+    /// Pallas base field, as used by the Halo2 backend
+    Pallas,
+}
+
+impl ExportField {
+    /* The characteristic of this field. */
+    fn modulus(&self) -> BigInt {
+        match self {
+            Self::Bls12381 => BigUint::from_bytes_le(&FrParameters::MODULUS.to_bytes_le()).into(),
+            // None of the backends work over BN254, so its modulus is spelled out
+            Self::Bn254 => {
+                "21888242871839275222246405745257275088548364400416034343698204186575808495617"
+                    .parse()
+                    .unwrap()
+            }
+            Self::Pallas => {
+                BigInt::parse_bytes(Fp::MODULUS.trim_start_matches("0x").as_bytes(), 16).unwrap()
+            }
+        }
+    }
+}
+
+pub fn export(export_commands: &ExportCommands, config: &Config) -> Result<(), Error> {
+    match export_commands {
+        ExportCommands::R1cs(args) => export_r1cs_cmd(args, config),
+        ExportCommands::Wtns(args) => export_wtns_cmd(args, config),
+        ExportCommands::Json(args) => export_json_cmd(args, config),
+        ExportCommands::Layout(args) => export_layout_cmd(args, config),
+        ExportCommands::Witness(args) => export_witness_cmd(args, config),
+    }
+}
+
+/* Write the given JSON value describing the given artifact to the given
+ * path. */
+fn write_json(value: &Value, output: &PathBuf, artifact: &str) -> Result<(), Error> {
+    let file = create_file(output, artifact)?;
+    serde_json::to_writer_pretty(file, value).map_err(|e| artifact_error(artifact, e))
+}
+
+/* Compile the given source file into a rank-1 constraint system over the
+ * given field. */
+fn compile_r1cs(source: &PathBuf, field: ExportField, config: &Config) -> Result<R1cs, Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(source)?;
+    let module_3ac = compile(module, &ModularFieldOps::new(field.modulus()), config)?;
+    qprintln!(config, "* Synthesizing rank-1 constraint system...");
+    R1cs::new(module_3ac, ModularFieldOps::new(field.modulus()))
+}
+
+/* Implements the subcommand that writes out a rank-1 constraint system along
+ * with the name of the variable carried by each of its wires. */
+fn export_r1cs_cmd(
+    R1csExport {
+        source,
+        field,
+        output,
+        symbols,
+    }: &R1csExport,
+    config: &Config,
+) -> Result<(), Error> {
+    let r1cs = compile_r1cs(source, *field, config)?;
+    qprintln!(
+        config,
+        "* Constraint system has {} constraints over {} wires",
+        r1cs.constraints.len(),
+        r1cs.wires.len() + 1
+    );
+
+    qprintln!(config, "* Serializing constraint system to storage...");
+    let r1cs_file = create_file(output, "constraint system")?;
+    r1cs.write(r1cs_file)
+        .map_err(|e| artifact_error("constraint system", e))?;
+
+    let symbols = symbols
+        .clone()
+        .unwrap_or_else(|| output.with_extension("sym"));
+    qprintln!(
+        config,
+        "* Writing wire names to {}...",
+        symbols.to_string_lossy()
+    );
+    let symbols_file = create_file(&symbols, "wire names")?;
+    r1cs.write_symbols(symbols_file)
+        .map_err(|e| artifact_error("wire names", e))?;
+
+    qprintln!(config, "* Constraint system export success!");
+    Ok(())
+}
+
+/* Implements the subcommand that solves for the wires of a rank-1 constraint
+ * system and writes them out. */
+fn export_wtns_cmd(
+    WtnsExport {
+        source,
+        inputs,
+        field,
+        output,
+    }: &WtnsExport,
+    config: &Config,
+) -> Result<(), Error> {
+    let r1cs = compile_r1cs(source, *field, config)?;
+    let assignments = read_inputs(inputs, source, &r1cs.module, config)?;
+    qprintln!(config, "* Solving for witnesses...");
+    let witness = r1cs.witness(assignments)?;
+
+    qprintln!(config, "* Serializing witness to storage...");
+    let wtns_file = create_file(output, "witness")?;
+    write_witness(r1cs.field_ops.modulus(), &witness, wtns_file)
+        .map_err(|e| artifact_error("witness", e))?;
+    qprintln!(config, "* Witness export success!");
+    Ok(())
+}
+
+/* Implements the subcommand that writes out the three-address constraints of
+ * a circuit as JSON. */
+fn export_json_cmd(
+    JsonExport {
+        source,
+        field,
+        output,
+    }: &JsonExport,
+    config: &Config,
+) -> Result<(), Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(source)?;
+    let field_ops = ModularFieldOps::new(field.modulus());
+    let module_3ac = compile(module, &field_ops, config)?;
+    qprintln!(config, "* Writing three-address constraints to storage...");
+    write_json(
+        &module_to_json(&module_3ac, &field_ops)?,
+        output,
+        "three-address constraints",
+    )?;
+    qprintln!(config, "* Three-address constraint export success!");
+    Ok(())
+}
+
+/* Implements the subcommand that writes out the gates of a circuit as JSON. */
+fn export_layout_cmd(
+    LayoutExport {
+        backend,
+        source,
+        module,
+        output,
+    }: &LayoutExport,
+    config: &Config,
+) -> Result<(), Error> {
+    let field_ops: Box<dyn FieldOps> = match backend {
+        ProofSystems::Plonk => Box::new(PlonkPrimeFieldOps::<BlsScalar>::default()),
+        ProofSystems::Halo2 => Box::new(Halo2PrimeFieldOps::<Fp>::default()),
+    };
+    let module_3ac = load_module(source, module, field_ops.as_ref(), config)?;
+    qprintln!(config, "* Laying out gates...");
+    let layout = match backend {
+        ProofSystems::Plonk => plonk_layout_to_json(
+            &PlonkModule::<BlsScalar, JubJubParameters>::new(Rc::new(module_3ac)),
+        ),
+        ProofSystems::Halo2 => halo2_layout_to_json(&Halo2Module::<Fp>::new(Rc::new(module_3ac))),
+    };
+    qprintln!(config, "* Writing gates to storage...");
+    write_json(&layout, output, "gate layout")?;
+    qprintln!(config, "* Gate layout export success!");
+    Ok(())
+}
+
+/* Read the three-address constraints of a circuit over the given field from
+ * JSON, or compile them from the given source file if there is no such JSON
+ * file. */
+fn load_module(
+    source: &Option<PathBuf>,
+    module: &Option<PathBuf>,
+    field_ops: &dyn FieldOps,
+    config: &Config,
+) -> Result<Module, Error> {
+    match (source, module) {
+        (_, Some(module)) => {
+            qprintln!(config, "* Reading three-address constraints...");
+            read_module_json(module, field_ops)
+        }
+        (Some(source), None) => {
+            qprintln!(config, "* Compiling constraints...");
+            let module = Module::parse_file(source)?;
+            compile(module, field_ops, config)
+        }
+        (None, None) => unreachable!("source or module required"),
+    }
+}
+
+/* Implements the subcommand that solves for the values of the variables of a
+ * circuit and writes them out as JSON. */
+fn export_witness_cmd(
+    WitnessExport {
+        source,
+        module,
+        inputs,
+        field,
+        output,
+    }: &WitnessExport,
+    config: &Config,
+) -> Result<(), Error> {
+    let field_ops = ModularFieldOps::new(field.modulus());
+    let module_3ac = load_module(source, module, &field_ops, config)?;
+    let inputs_next_to = module
+        .as_ref()
+        .or(source.as_ref())
+        .expect("source or module required");
+    let assignments = read_inputs(inputs, inputs_next_to, &module_3ac, config)?;
+    qprintln!(config, "* Solving for witnesses...");
+    let values = solve_witnesses(&module_3ac, assignments, &field_ops)?;
+    qprintln!(config, "* Writing witness to storage...");
+    write_json(&witness_to_json(&module_3ac, &values), output, "witness")?;
+    qprintln!(config, "* Witness export success!");
+    Ok(())
+}
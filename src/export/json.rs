@@ -0,0 +1,428 @@
+use crate::ast::{Definition, Expr, InfixOp, LetBinding, Module, Pat, TExpr, Variable, VariableId};
+use crate::error::Error;
+use crate::halo2::synth::Halo2Module;
+use crate::plonk::synth::PlonkModule;
+use crate::transform::{collect_module_variables, weighted_sum, FieldOps};
+use crate::typecheck::Type;
+
+use ark_ec::TEModelParameters;
+use num_bigint::{BigInt, BigUint};
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+const INFIX_OPS: [InfixOp; 12] = [
+    InfixOp::Divide,
+    InfixOp::DivideZ,
+    InfixOp::Multiply,
+    InfixOp::Add,
+    InfixOp::Subtract,
+    InfixOp::Equal,
+    InfixOp::Exponentiate,
+    InfixOp::IntDivide,
+    InfixOp::Modulo,
+    InfixOp::Less,
+    InfixOp::LessEqual,
+    InfixOp::NotEqual,
+];
+
+/* Make an error describing why a module cannot be converted to or from JSON. */
+fn json_error(e: impl ToString) -> Error {
+    Error::ModuleJsonError { e: e.to_string() }
+}
+
+/* Describe the given three-address module over the given field in JSON.
+ * Variables are given by their id along with their name if they have one, and
+ * the modulus of the field is recorded so that the module is not imported
+ * into a different one. */
+pub fn module_to_json(module: &Module, field_ops: &dyn FieldOps) -> Result<Value, Error> {
+    let defs = module
+        .defs
+        .iter()
+        .map(|Definition(LetBinding(pat, expr), _)| match &pat.v {
+            Pat::Variable(var) => Ok(json!({
+                "var": variable_to_json(var),
+                "expr": expr_to_json(expr)?,
+            })),
+            _ => Err(json_error(format!(
+                "definition of {pat} is not of a variable"
+            ))),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({
+        "modulus": field_modulus(field_ops).to_string(),
+        "pubs": module.pubs.iter().map(variable_to_json).collect::<Vec<_>>(),
+        "defs": defs,
+        "exprs": module.exprs.iter().map(expr_to_json).collect::<Result<Vec<_>, _>>()?,
+    }))
+}
+
+/* Reconstruct a three-address module over the given field from its JSON
+ * description. Fails if the description was made for a different field, or
+ * if any of its constraints does not have a shape that the synthesizers
+ * accept. */
+pub fn module_from_json(value: &Value, field_ops: &dyn FieldOps) -> Result<Module, Error> {
+    let modulus: BigInt = json_field(value, "modulus")?
+        .as_str()
+        .and_then(|modulus| modulus.parse().ok())
+        .ok_or_else(|| json_error("invalid field modulus"))?;
+    let expected = field_modulus(field_ops);
+    if modulus != expected {
+        return Err(json_error(format!(
+            "constraints are over the field of modulus {modulus} instead of {expected}"
+        )));
+    }
+    let pubs = json_array(value, "pubs")?
+        .iter()
+        .map(variable_from_json)
+        .collect::<Result<_, _>>()?;
+    let defs = json_array(value, "defs")?
+        .iter()
+        .map(|def| {
+            let var = variable_from_json(json_field(def, "var")?)?;
+            let expr = expr_from_json(json_field(def, "expr")?)?;
+            check_3ac_definition(&expr)?;
+            let pat = Pat::Variable(var).type_pat(Some(Type::Int));
+            Ok(Definition(LetBinding(pat, Box::new(expr)), None))
+        })
+        .collect::<Result<_, Error>>()?;
+    let exprs = json_array(value, "exprs")?
+        .iter()
+        .map(|expr| {
+            let expr = expr_from_json(expr)?;
+            check_3ac_equality(&expr)?;
+            Ok(expr)
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok(Module { pubs, defs, exprs })
+}
+
+/* The modulus of the field that the given operations work in. */
+fn field_modulus(field_ops: &dyn FieldOps) -> BigInt {
+    field_ops.canonical(BigInt::from(-1)) + 1
+}
+
+/* Check that the given definition body can be evaluated by the witness
+ * solver, which handles every operator besides equality. */
+fn check_3ac_definition(expr: &TExpr) -> Result<(), Error> {
+    match &expr.v {
+        Expr::Infix(InfixOp::Equal, _, _) => Err(json_error(format!(
+            "definition body {expr} contains an equality"
+        ))),
+        Expr::Infix(_, a, b) => {
+            check_3ac_definition(a)?;
+            check_3ac_definition(b)
+        }
+        Expr::Negate(e) => check_3ac_definition(e),
+        _ => Ok(()),
+    }
+}
+
+/* Check that the given constraint has one of the three-address shapes that
+ * the synthesizers lay out as gates. */
+fn check_3ac_equality(expr: &TExpr) -> Result<(), Error> {
+    let is_atom = |e: &TExpr| matches!(e.v, Expr::Variable(_) | Expr::Constant(_));
+    let supported = match &expr.v {
+        Expr::Infix(InfixOp::Equal, lhs, rhs) if is_atom(lhs) => match &rhs.v {
+            Expr::Variable(_) | Expr::Constant(_) => true,
+            Expr::Negate(e) if is_atom(e) => true,
+            Expr::Infix(
+                InfixOp::Add | InfixOp::Subtract | InfixOp::Multiply | InfixOp::Divide,
+                a,
+                b,
+            ) if is_atom(a) && is_atom(b) => true,
+            _ => {
+                matches!(lhs.v, Expr::Variable(_))
+                    && weighted_sum(rhs).is_some_and(|(terms, _)| terms.len() < 3)
+            }
+        },
+        _ => false,
+    };
+    if supported {
+        Ok(())
+    } else {
+        Err(json_error(format!(
+            "constraint {expr} is not in three-address form"
+        )))
+    }
+}
+
+/* Read a three-address module over the given field from the JSON file at the
+ * given path. */
+pub fn read_module_json(path: &PathBuf, field_ops: &dyn FieldOps) -> Result<Module, Error> {
+    let contents = std::fs::read_to_string(path).map_err(json_error)?;
+    let value: Value = serde_json::from_str(&contents).map_err(json_error)?;
+    module_from_json(&value, field_ops)
+}
+
+/* Describe the given values of the variables of the given module in JSON,
+ * listing each variable by its id and name in the same way as module_to_json
+ * does. */
+pub fn witness_to_json(module: &Module, values: &HashMap<VariableId, BigInt>) -> Value {
+    let mut vars = HashMap::new();
+    collect_module_variables(module, &mut vars);
+    let mut vars: Vec<_> = vars.into_values().collect();
+    vars.sort_by_key(|var| var.id);
+    let values: Vec<Value> = vars
+        .iter()
+        .filter_map(|var| {
+            let value = values.get(&var.id)?;
+            let mut entry = variable_to_json(var);
+            entry["value"] = Value::String(value.to_string());
+            Some(entry)
+        })
+        .collect();
+    json!({ "values": values })
+}
+
+fn variable_to_json(var: &Variable) -> Value {
+    match &var.name {
+        Some(name) => json!({ "id": var.id, "name": name }),
+        None => json!({ "id": var.id }),
+    }
+}
+
+fn variable_from_json(value: &Value) -> Result<Variable, Error> {
+    let id = json_field(value, "id")?
+        .as_u64()
+        .and_then(|id| VariableId::try_from(id).ok())
+        .ok_or_else(|| json_error(format!("invalid variable id in {value}")))?;
+    let name = match value.get("name") {
+        None => None,
+        Some(name) => Some(
+            name.as_str()
+                .ok_or_else(|| json_error(format!("invalid variable name in {value}")))?
+                .to_string(),
+        ),
+    };
+    Ok(Variable { name, id })
+}
+
+fn expr_to_json(expr: &TExpr) -> Result<Value, Error> {
+    match &expr.v {
+        Expr::Constant(c) => Ok(json!({ "constant": c.to_string() })),
+        Expr::Variable(var) => Ok(json!({ "variable": variable_to_json(var) })),
+        Expr::Negate(e) => Ok(json!({ "negate": expr_to_json(e)? })),
+        Expr::Infix(op, a, b) => Ok(json!({
+            "op": op.to_string(),
+            "lhs": expr_to_json(a)?,
+            "rhs": expr_to_json(b)?,
+        })),
+        _ => Err(Error::UnexpectedExpression {
+            e: Box::new(expr.clone()),
+        }),
+    }
+}
+
+fn expr_from_json(value: &Value) -> Result<TExpr, Error> {
+    if let Some(c) = value.get("constant") {
+        let c: BigInt = c
+            .as_str()
+            .and_then(|c| c.parse().ok())
+            .ok_or_else(|| json_error(format!("invalid constant in {value}")))?;
+        Ok(Expr::Constant(c).type_expr(Some(Type::Int)))
+    } else if let Some(var) = value.get("variable") {
+        Ok(Expr::Variable(variable_from_json(var)?).type_expr(Some(Type::Int)))
+    } else if let Some(e) = value.get("negate") {
+        Ok(Expr::Negate(Box::new(expr_from_json(e)?)).type_expr(Some(Type::Int)))
+    } else if let Some(op) = value.get("op") {
+        let op = INFIX_OPS
+            .into_iter()
+            .find(|known| op.as_str() == Some(&known.to_string()))
+            .ok_or_else(|| json_error(format!("unknown operator {op}")))?;
+        let a = expr_from_json(json_field(value, "lhs")?)?;
+        let b = expr_from_json(json_field(value, "rhs")?)?;
+        Ok(Expr::Infix(op, Box::new(a), Box::new(b)).type_expr(None))
+    } else {
+        Err(json_error(format!("unrecognized expression {value}")))
+    }
+}
+
+fn json_field<'a>(value: &'a Value, field: &str) -> Result<&'a Value, Error> {
+    value
+        .get(field)
+        .ok_or_else(|| json_error(format!("missing field {field} in {value}")))
+}
+
+fn json_array<'a>(value: &'a Value, field: &str) -> Result<&'a Vec<Value>, Error> {
+    json_field(value, field)?
+        .as_array()
+        .ok_or_else(|| json_error(format!("field {field} is not an array")))
+}
+
+/* Render a field element given the canonical forms of it and of its
+ * negation, preferring whichever is shorter. */
+fn field_to_json(pos: BigUint, neg: BigUint) -> Value {
+    if neg < pos {
+        Value::String(format!("-{neg}"))
+    } else {
+        Value::String(pos.to_string())
+    }
+}
+
+/* Describe the variable on a wire, with null standing for zero. */
+fn wire_to_json(wire: Option<VariableId>, vars: &HashMap<VariableId, Variable>) -> Value {
+    match wire {
+        Some(id) => variable_to_json(&vars[&id]),
+        None => Value::Null,
+    }
+}
+
+/* Describe the arithmetic gates of the given PLONK circuit in the order that
+ * they are laid out, along with the variables wired into each one. */
+pub fn plonk_layout_to_json<F, P>(circuit: &PlonkModule<F, P>) -> Value
+where
+    F: ark_ff::PrimeField,
+    P: TEModelParameters<BaseField = F>,
+{
+    let mut vars = HashMap::new();
+    collect_module_variables(&circuit.module, &mut vars);
+    let field = |x: F| field_to_json(x.into(), (-x).into());
+    let gates: Vec<Value> = circuit
+        .gates()
+        .into_iter()
+        .map(|gate| {
+            let (a, b, c) = gate.witness;
+            json!({
+                "a": wire_to_json(a, &vars),
+                "b": wire_to_json(b, &vars),
+                "c": wire_to_json(c, &vars),
+                "q_m": field(gate.q_m),
+                "q_l": field(gate.q_l),
+                "q_r": field(gate.q_r),
+                "q_o": field(gate.q_o),
+                "q_c": field(gate.q_c),
+                "public": gate.pi.is_some(),
+            })
+        })
+        .collect();
+    json!({ "backend": "plonk", "gates": gates })
+}
+
+/* Describe the gates of the given Halo2 circuit in the order that they are
+ * laid out, along with the variables wired into each one. The public inputs
+ * and the zero cell that unused wires are copied from come first. */
+pub fn halo2_layout_to_json<F>(circuit: &Halo2Module<F>) -> Value
+where
+    F: ff::FromUniformBytes<64> + std::cmp::Ord,
+{
+    let mut vars = HashMap::new();
+    collect_module_variables(&circuit.module, &mut vars);
+    let to_uint = |x: F| BigUint::from_bytes_le(x.to_repr().as_ref());
+    let field = |x: F| field_to_json(to_uint(x), to_uint(-x));
+    let mut gates: Vec<Value> = circuit
+        .module
+        .pubs
+        .iter()
+        .map(|var| json!({ "a": variable_to_json(var), "sp": "1" }))
+        .collect();
+    gates.push(json!({ "b": Value::Null, "sr": "1" }));
+    gates.extend(circuit.gates().into_iter().map(|gate| {
+        json!({
+            "constraint": gate.constraint,
+            "a": wire_to_json(gate.a, &vars),
+            "b": wire_to_json(gate.b, &vars),
+            "c": wire_to_json(gate.c, &vars),
+            "sl": field(gate.sl),
+            "sr": field(gate.sr),
+            "so": field(gate.so),
+            "sm": field(gate.sm),
+            "sc": field(gate.sc),
+        })
+    }));
+    json!({ "backend": "halo2", "k": circuit.k, "gates": gates })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo2::synth::PrimeFieldOps;
+    use crate::transform::compile;
+    use crate::util::Config;
+    use halo2_proofs::pasta::Fp;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_module_round_trip() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse("pub z; def w = x / y; w*y + 3 = z; x != y = 1;").unwrap();
+        let field_ops = PrimeFieldOps::<Fp>::default();
+        let module_3ac = compile(module, &field_ops, &config).unwrap();
+        let value = module_to_json(&module_3ac, &field_ops).unwrap();
+        let imported = module_from_json(&value, &field_ops).unwrap();
+        assert_eq!(module_to_json(&imported, &field_ops).unwrap(), value);
+
+        let original = Halo2Module::<Fp>::new(Rc::new(module_3ac));
+        let imported = Halo2Module::<Fp>::new(Rc::new(imported));
+        assert_eq!(
+            halo2_layout_to_json(&original),
+            halo2_layout_to_json(&imported)
+        );
+    }
+
+    #[test]
+    fn test_invalid_module_rejected() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let field_ops = PrimeFieldOps::<Fp>::default();
+        let module = Module::parse("x*y = z;").unwrap();
+        let module_3ac = compile(module, &field_ops, &config).unwrap();
+        let value = module_to_json(&module_3ac, &field_ops).unwrap();
+        // Constraints exported over another field are not imported
+        let bls_ops = crate::plonk::synth::PrimeFieldOps::<ark_bls12_381::Fr>::default();
+        assert!(matches!(
+            module_from_json(&value, &bls_ops),
+            Err(Error::ModuleJsonError { .. })
+        ));
+        // Nor are constraints that no gate can express
+        let mut value = value;
+        let x = json!({ "variable": { "id": 0 } });
+        value["exprs"] = json!([{
+            "op": "=",
+            "lhs": x,
+            "rhs": { "op": "*", "lhs": { "op": "*", "lhs": x, "rhs": x }, "rhs": x },
+        }]);
+        assert!(matches!(
+            module_from_json(&value, &field_ops),
+            Err(Error::ModuleJsonError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_witness_export() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let field_ops = PrimeFieldOps::<Fp>::default();
+        let module = Module::parse("def y = x * x; y = 9;").unwrap();
+        let module_3ac = compile(module, &field_ops, &config).unwrap();
+        let mut vars = HashMap::new();
+        collect_module_variables(&module_3ac, &mut vars);
+        let x = vars
+            .values()
+            .find(|var| var.name.as_deref() == Some("x"))
+            .unwrap();
+        let assigns = HashMap::from([(x.id, BigInt::from(-3))]);
+        let values = crate::solver::solve_witnesses(&module_3ac, assigns, &field_ops).unwrap();
+        let witness = witness_to_json(&module_3ac, &values);
+        let entries = witness["values"].as_array().unwrap();
+        assert_eq!(entries.len(), vars.len());
+        let value_of = |name: &str| {
+            entries
+                .iter()
+                .find(|entry| entry["name"] == name)
+                .map(|entry| entry["value"].clone())
+        };
+        assert_eq!(
+            value_of("x"),
+            Some(json!(field_ops.canonical(BigInt::from(-3)).to_string()))
+        );
+    }
+}
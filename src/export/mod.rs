@@ -0,0 +1,3 @@
+pub mod cli;
+pub mod json;
+pub mod r1cs;
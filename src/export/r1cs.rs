@@ -0,0 +1,416 @@
+use crate::ast::{Expr, InfixOp, Module, Pat, TExpr, Variable, VariableId};
+use crate::error::Error;
+use crate::solver::solve_witnesses;
+use crate::transform::{collect_expr_variables, FieldOps};
+
+use num_bigint::{BigInt, Sign};
+use num_traits::{One, Zero};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+
+/* Arithmetic modulo an arbitrary prime. This allows circuits to be compiled
+ * over fields that none of the proving backends support. */
+pub struct ModularFieldOps {
+    modulus: BigInt,
+}
+
+impl ModularFieldOps {
+    pub fn new(modulus: BigInt) -> Self {
+        Self { modulus }
+    }
+
+    pub fn modulus(&self) -> &BigInt {
+        &self.modulus
+    }
+
+    /* Compute the multiplicative inverse of the given canonical element using
+     * Fermat's little theorem. Zero is mapped to zero. */
+    fn invert(&self, a: &BigInt) -> BigInt {
+        a.modpow(&(&self.modulus - 2), &self.modulus)
+    }
+}
+
+impl FieldOps for ModularFieldOps {
+    /* Reduce the given integer into the range [0, modulus). */
+    fn canonical(&self, a: BigInt) -> BigInt {
+        let r = a % &self.modulus;
+        if r.sign() == Sign::Minus {
+            r + &self.modulus
+        } else {
+            r
+        }
+    }
+    /* Evaluate the given negation expression in the given prime field. */
+    fn negate(&self, a: BigInt) -> BigInt {
+        self.canonical(-a)
+    }
+    /* Evaluate the given infix expression in the given prime field. */
+    fn infix(&self, op: InfixOp, a: BigInt, b: BigInt) -> BigInt {
+        let c = self.canonical(a.clone());
+        let d = self.canonical(b.clone());
+        match op {
+            InfixOp::Add => self.canonical(c + d),
+            InfixOp::Subtract => self.canonical(c - d),
+            InfixOp::Multiply => self.canonical(c * d),
+            InfixOp::Divide => self.canonical(c * self.invert(&d)),
+            InfixOp::DivideZ => {
+                if d.is_zero() {
+                    BigInt::zero()
+                } else {
+                    self.canonical(c * self.invert(&d))
+                }
+            }
+            InfixOp::IntDivide => a / b,
+            InfixOp::Modulo => a % b,
+            InfixOp::Exponentiate => {
+                let e = c.modpow(&BigInt::from(b.magnitude().clone()), &self.modulus);
+                if b.sign() == Sign::Minus {
+                    self.invert(&e)
+                } else {
+                    e
+                }
+            }
+            InfixOp::Less => BigInt::from(u8::from(c < d)),
+            InfixOp::LessEqual => BigInt::from(u8::from(c <= d)),
+            InfixOp::NotEqual => BigInt::from(u8::from(c != d)),
+            InfixOp::Equal => panic!("cannot evaluate equals expression"),
+        }
+    }
+}
+
+/* A linear combination of wires. Wire 0 always holds the constant 1. */
+pub type LinearCombination = BTreeMap<usize, BigInt>;
+
+/* A rank-1 constraint system derived from a three-address module. Each
+ * constraint (A, B, C) requires that A*B - C = 0. Wires are ordered as the
+ * iden3 format expects: the constant 1, then the public inputs, then the
+ * private inputs, then the variables computed from them. */
+pub struct R1cs {
+    pub module: Module,
+    pub field_ops: ModularFieldOps,
+    // The variable carried by each wire after the constant wire
+    pub wires: Vec<Variable>,
+    pub public_inputs: usize,
+    pub private_inputs: usize,
+    pub constraints: Vec<(LinearCombination, LinearCombination, LinearCombination)>,
+}
+
+impl R1cs {
+    /* Derive a rank-1 constraint system from the given three-address module,
+     * which must have been compiled using the given field operations. */
+    pub fn new(module: Module, field_ops: ModularFieldOps) -> Result<Self, Error> {
+        let defined: HashSet<VariableId> = module
+            .defs
+            .iter()
+            .filter_map(|def| match &def.0 .0.v {
+                Pat::Variable(var) => Some(var.id),
+                _ => None,
+            })
+            .collect();
+        // Public variables come first in declaration order, followed by the
+        // remaining variables occurring in constraints in order of their ids
+        let mut wires = module.pubs.clone();
+        let mut seen: HashSet<VariableId> = wires.iter().map(|var| var.id).collect();
+        let mut vars = HashMap::new();
+        for expr in &module.exprs {
+            collect_expr_variables(expr, &mut vars);
+        }
+        let mut vars: Vec<Variable> = vars
+            .into_values()
+            .filter(|var| seen.insert(var.id))
+            .collect();
+        vars.sort_by_key(|var| (defined.contains(&var.id), var.id));
+        let private_inputs = vars.iter().filter(|var| !defined.contains(&var.id)).count();
+        let public_inputs = wires.len();
+        wires.extend(vars);
+
+        let mut r1cs = Self {
+            module,
+            field_ops,
+            wires,
+            public_inputs,
+            private_inputs,
+            constraints: vec![],
+        };
+        let wire_map: HashMap<VariableId, usize> = r1cs
+            .wires
+            .iter()
+            .enumerate()
+            .map(|(idx, var)| (var.id, idx + 1))
+            .collect();
+        r1cs.constraints = r1cs
+            .module
+            .exprs
+            .iter()
+            .map(|expr| r1cs.constraint(expr, &wire_map))
+            .collect::<Result<_, _>>()?;
+        Ok(r1cs)
+    }
+
+    /* Express the given equality as a rank-1 constraint. */
+    fn constraint(
+        &self,
+        expr: &TExpr,
+        wire_map: &HashMap<VariableId, usize>,
+    ) -> Result<(LinearCombination, LinearCombination, LinearCombination), Error> {
+        let (lhs, rhs) = match &expr.v {
+            Expr::Infix(InfixOp::Equal, lhs, rhs) => (lhs, rhs),
+            _ => {
+                return Err(Error::UnexpectedExpression {
+                    e: Box::new(expr.clone()),
+                })
+            }
+        };
+        let lc = |e: &TExpr| self.linear_combination(e, wire_map);
+        if let (Some(l), Some(r)) = (lc(lhs), lc(rhs)) {
+            // Linear constraints are multiplied by nothing
+            return Ok((
+                LinearCombination::new(),
+                LinearCombination::new(),
+                self.add(l, self.scale(r, &BigInt::from(-1))),
+            ));
+        }
+        let quadratic = |out: &TExpr, e: &TExpr| match &e.v {
+            Expr::Infix(InfixOp::Multiply, a, b) => Some((lc(a)?, lc(b)?, lc(out)?)),
+            Expr::Infix(InfixOp::Divide, a, b) => Some((lc(out)?, lc(b)?, lc(a)?)),
+            _ => None,
+        };
+        quadratic(lhs, rhs)
+            .or_else(|| quadratic(rhs, lhs))
+            .ok_or_else(|| Error::NonQuadraticConstraint {
+                e: Box::new(expr.clone()),
+            })
+    }
+
+    /* Express the given expression as a linear combination of wires if it is
+     * linear. */
+    fn linear_combination(
+        &self,
+        expr: &TExpr,
+        wire_map: &HashMap<VariableId, usize>,
+    ) -> Option<LinearCombination> {
+        let lc = |e: &TExpr| self.linear_combination(e, wire_map);
+        match &expr.v {
+            Expr::Constant(c) => Some(self.add(
+                LinearCombination::new(),
+                LinearCombination::from([(0, c.clone())]),
+            )),
+            Expr::Variable(var) => Some(LinearCombination::from([(
+                wire_map[&var.id],
+                BigInt::one(),
+            )])),
+            Expr::Negate(e) => Some(self.scale(lc(e)?, &BigInt::from(-1))),
+            Expr::Infix(InfixOp::Add, a, b) => Some(self.add(lc(a)?, lc(b)?)),
+            Expr::Infix(InfixOp::Subtract, a, b) => {
+                Some(self.add(lc(a)?, self.scale(lc(b)?, &BigInt::from(-1))))
+            }
+            Expr::Infix(InfixOp::Multiply, a, b) => {
+                let (a, b) = (lc(a)?, lc(b)?);
+                if let Some(k) = constant_value(&a) {
+                    Some(self.scale(b, &k))
+                } else {
+                    constant_value(&b).map(|k| self.scale(a, &k))
+                }
+            }
+            Expr::Infix(InfixOp::Divide, a, b) => {
+                let k = constant_value(&lc(b)?).filter(|k| !k.is_zero())?;
+                Some(self.scale(lc(a)?, &self.field_ops.invert(&k)))
+            }
+            _ => None,
+        }
+    }
+
+    /* Add the given linear combinations, dropping vanishing terms. */
+    fn add(&self, mut a: LinearCombination, b: LinearCombination) -> LinearCombination {
+        for (wire, coeff) in b {
+            let sum = a.remove(&wire).unwrap_or_default() + coeff;
+            let sum = self.field_ops.canonical(sum);
+            if !sum.is_zero() {
+                a.insert(wire, sum);
+            }
+        }
+        a
+    }
+
+    /* Multiply the given linear combination by the given constant. */
+    fn scale(&self, a: LinearCombination, k: &BigInt) -> LinearCombination {
+        a.into_iter()
+            .map(|(wire, coeff)| (wire, self.field_ops.canonical(coeff * k)))
+            .filter(|(_, coeff)| !coeff.is_zero())
+            .collect()
+    }
+
+    /* Solve for the values of all wires given assignments to the inputs. */
+    pub fn witness(&self, assigns: HashMap<VariableId, BigInt>) -> Result<Vec<BigInt>, Error> {
+        let values = solve_witnesses(&self.module, assigns, &self.field_ops)?;
+        let mut witness = vec![BigInt::one()];
+        witness.extend(self.wires.iter().map(|var| values[&var.id].clone()));
+        Ok(witness)
+    }
+
+    /* Write this constraint system out in the iden3 binary format, with the
+     * label of each wire being its own index. */
+    pub fn write<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let n8 = field_size(self.field_ops.modulus());
+        let n_wires = self.wires.len() + 1;
+
+        let mut header = vec![];
+        write_u32(&mut header, n8 as u32)?;
+        write_field(&mut header, self.field_ops.modulus(), n8)?;
+        write_u32(&mut header, n_wires as u32)?;
+        // Outputs are indistinguishable from public inputs in vamp-ir
+        write_u32(&mut header, 0)?;
+        write_u32(&mut header, self.public_inputs as u32)?;
+        write_u32(&mut header, self.private_inputs as u32)?;
+        write_u64(&mut header, n_wires as u64)?;
+        write_u32(&mut header, self.constraints.len() as u32)?;
+
+        let mut constraints = vec![];
+        for (a, b, c) in &self.constraints {
+            for lc in [a, b, c] {
+                write_u32(&mut constraints, lc.len() as u32)?;
+                for (wire, coeff) in lc {
+                    write_u32(&mut constraints, *wire as u32)?;
+                    write_field(&mut constraints, coeff, n8)?;
+                }
+            }
+        }
+
+        let mut wire_labels = vec![];
+        for wire in 0..n_wires {
+            write_u64(&mut wire_labels, wire as u64)?;
+        }
+
+        writer.write_all(b"r1cs")?;
+        write_u32(&mut writer, 1)?;
+        write_u32(&mut writer, 3)?;
+        write_section(&mut writer, 1, &header)?;
+        write_section(&mut writer, 2, &constraints)?;
+        write_section(&mut writer, 3, &wire_labels)
+    }
+
+    /* Write out the name of the variable carried by each wire in the format of
+     * circom's symbol files: label, wire, component, name. */
+    pub fn write_symbols<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "0,0,0,one")?;
+        for (idx, var) in self.wires.iter().enumerate() {
+            writeln!(writer, "{},{},0,{}", idx + 1, idx + 1, var)?;
+        }
+        Ok(())
+    }
+}
+
+/* Write the given wire values out in the iden3 binary witness format. */
+pub fn write_witness<W: Write>(
+    modulus: &BigInt,
+    witness: &[BigInt],
+    mut writer: W,
+) -> io::Result<()> {
+    let n8 = field_size(modulus);
+
+    let mut header = vec![];
+    write_u32(&mut header, n8 as u32)?;
+    write_field(&mut header, modulus, n8)?;
+    write_u32(&mut header, witness.len() as u32)?;
+
+    let mut values = vec![];
+    for val in witness {
+        write_field(&mut values, val, n8)?;
+    }
+
+    writer.write_all(b"wtns")?;
+    write_u32(&mut writer, 2)?;
+    write_u32(&mut writer, 2)?;
+    write_section(&mut writer, 1, &header)?;
+    write_section(&mut writer, 2, &values)
+}
+
+/* Get the value of the given linear combination if it is constant. */
+fn constant_value(lc: &LinearCombination) -> Option<BigInt> {
+    match lc.iter().next() {
+        None => Some(BigInt::zero()),
+        Some((0, k)) if lc.len() == 1 => Some(k.clone()),
+        _ => None,
+    }
+}
+
+/* The number of bytes used to store an element of the field with the given
+ * modulus, rounded up to whole 64-bit words. */
+fn field_size(modulus: &BigInt) -> usize {
+    (modulus.bits() as usize).div_ceil(64) * 8
+}
+
+fn write_u32<W: Write>(writer: &mut W, n: u32) -> io::Result<()> {
+    writer.write_all(&n.to_le_bytes())
+}
+
+fn write_u64<W: Write>(writer: &mut W, n: u64) -> io::Result<()> {
+    writer.write_all(&n.to_le_bytes())
+}
+
+/* Write the given canonical field element as n8 little-endian bytes. */
+fn write_field<W: Write>(writer: &mut W, val: &BigInt, n8: usize) -> io::Result<()> {
+    let (_, mut bytes) = val.to_bytes_le();
+    bytes.resize(n8, 0);
+    writer.write_all(&bytes)
+}
+
+fn write_section<W: Write>(writer: &mut W, section_type: u32, contents: &[u8]) -> io::Result<()> {
+    write_u32(writer, section_type)?;
+    write_u64(writer, contents.len() as u64)?;
+    writer.write_all(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transform::compile;
+    use crate::util::Config;
+
+    use ff::PrimeField;
+    use halo2_proofs::pasta::Fp;
+
+    fn pallas() -> ModularFieldOps {
+        ModularFieldOps::new(
+            BigInt::parse_bytes(Fp::MODULUS.trim_start_matches("0x").as_bytes(), 16).unwrap(),
+        )
+    }
+
+    /* Evaluate the given linear combination over the given witness. */
+    fn evaluate(lc: &LinearCombination, witness: &[BigInt], field_ops: &ModularFieldOps) -> BigInt {
+        let sum: BigInt = lc.iter().map(|(wire, coeff)| coeff * &witness[*wire]).sum();
+        field_ops.canonical(sum)
+    }
+
+    #[test]
+    fn test_witness_satisfies_constraints() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse("pub z; def w = x / y; w*y + 3 = z; x - 2*y = 0;").unwrap();
+        // x = 8 and y = 4 make w = 2 and hence z = 11
+        let module_3ac = compile(module, &pallas(), &config).unwrap();
+        let r1cs = R1cs::new(module_3ac, pallas()).unwrap();
+        assert_eq!(r1cs.public_inputs, 1);
+        assert_eq!(r1cs.private_inputs, 2);
+
+        let mut assigns = HashMap::new();
+        for var in &r1cs.wires {
+            match var.name.as_deref() {
+                Some("x") => assigns.insert(var.id, BigInt::from(8)),
+                Some("y") => assigns.insert(var.id, BigInt::from(4)),
+                Some("z") => assigns.insert(var.id, BigInt::from(11)),
+                _ => None,
+            };
+        }
+        let witness = r1cs.witness(assigns).unwrap();
+        let field_ops = pallas();
+        for (a, b, c) in &r1cs.constraints {
+            let ab = field_ops
+                .canonical(evaluate(a, &witness, &field_ops) * evaluate(b, &witness, &field_ops));
+            assert_eq!(ab, evaluate(c, &witness, &field_ops));
+        }
+    }
+}
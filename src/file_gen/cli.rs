@@ -5,8 +5,6 @@ use crate::transform::{collect_module_variables, compile, FieldOps};
 use serde_json::Map;
 use std::collections::HashMap;
 
-use std::fs;
-
 use clap::{Args, Subcommand};
 use std::path::PathBuf;
 
@@ -50,9 +48,8 @@ pub fn witness_file_cmd(
     config: &Config,
 ) -> Result<(), Error> {
     qprintln!(config, "** Reading file...");
-    let unparsed_file = fs::read_to_string(source).expect("cannot read file");
-    let module = Module::parse(&unparsed_file).unwrap();
-    let module_3ac = compile(module.clone(), &(), config);
+    let module = Module::parse_file(source)?;
+    let module_3ac = compile(module.clone(), &(), config)?;
 
     qprintln!(config, "** Collecting variables...");
     // Collect unbound variables from module
@@ -1,33 +1,90 @@
-use crate::ast::{Module, VariableId};
+use crate::ast::{Module, TExpr, Variable, VariableId};
 use crate::error::Error;
-use crate::error::Error::{
-    BackendError, MissingVariableAssignment, ParseError, ProofVerificationFailure,
-};
-use crate::halo2::synth::{keygen, prover, Halo2Module, PrimeFieldOps};
-use crate::halo2::synth::{make_constant, verifier};
+use crate::error::Error::{BackendError, MissingVariableAssignment, ProofVerificationFailure};
+use crate::halo2::synth::{downsize_params, make_constant, verifier, verifying_key_digest};
+use crate::halo2::synth::{failed_constraint, keygen, prover, Halo2Module, PrimeFieldOps};
+use crate::proof_system::{artifact_error, public_variable_name, ProofSystem};
 use crate::qprintln;
-use crate::util::{get_circuit_assignments, Config};
+use crate::transform::collect_expr_variables;
+use crate::util::{get_circuit_assignments, Config, UnsatisfiedEquality};
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use ark_serialize::{Read, SerializationError};
 use bincode::error::{DecodeError, EncodeError};
 use ff::PrimeField;
+use halo2_proofs::dev::{MockProver, VerifyFailure};
 use halo2_proofs::pasta::{EqAffine, Fp};
-use halo2_proofs::plonk::{keygen_vk, VerifyingKey};
+use halo2_proofs::plonk::{keygen_vk, ProvingKey, VerifyingKey};
 use halo2_proofs::poly::commitment::Params;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, BigUint, ToBigInt};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::Write;
+use std::path::Path;
 use std::rc::Rc;
 
+/* Generate the public parameters for circuits with up to 2^k rows. */
+pub fn setup(k: u32) -> Params<EqAffine> {
+    Params::new(k)
+}
+
+/* Shrink the given public parameters to the size of a circuit with 2^k rows. */
+pub fn fit_params(params: &Params<EqAffine>, k: u32) -> Result<Params<EqAffine>, Error> {
+    downsize_params(params, k)
+}
+
 pub fn compile(source: impl AsRef<str>, config: &Config) -> Result<HaloCircuitData, Error> {
     qprintln!(config, "* Compiling constraints...");
-    let module = Module::parse(source.as_ref()).map_err(|err| ParseError { e: err.to_string() })?;
-    let module_3ac = crate::transform::compile(module, &PrimeFieldOps::<Fp>::default(), config);
+    let module = Module::parse(source.as_ref())?;
+    compile_module(module, None, config)
+}
+
+/* Compile the vamp-ir file at the given path. Imports are resolved relative to
+ * the directory containing the file. */
+pub fn compile_file(path: impl AsRef<Path>, config: &Config) -> Result<HaloCircuitData, Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(path)?;
+    compile_module(module, None, config)
+}
+
+/* Compile the vamp-ir file at the given path against the given public
+ * parameters instead of generating new ones. */
+pub fn compile_file_with_params(
+    path: impl AsRef<Path>,
+    params: &Params<EqAffine>,
+    config: &Config,
+) -> Result<HaloCircuitData, Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(path)?;
+    compile_module(module, Some(params), config)
+}
+
+fn compile_module(
+    module: Module,
+    params: Option<&Params<EqAffine>>,
+    config: &Config,
+) -> Result<HaloCircuitData, Error> {
+    let module_3ac = crate::transform::compile(module, &PrimeFieldOps::<Fp>::default(), config)?;
+    synthesize_module(module_3ac, params, config)
+}
+
+fn synthesize_module(
+    module_3ac: Module,
+    params: Option<&Params<EqAffine>>,
+    config: &Config,
+) -> Result<HaloCircuitData, Error> {
     qprintln!(config, "* Synthesizing arithmetic circuit...");
     let module_rc = Rc::new(module_3ac);
     let circuit = Halo2Module::<Fp>::new(module_rc);
-    let params: Params<EqAffine> = Params::new(circuit.k);
-    Ok(HaloCircuitData { params, circuit })
+    let params = match params {
+        Some(params) => fit_params(params, circuit.k)?,
+        None => Params::new(circuit.k),
+    };
+    Ok(HaloCircuitData {
+        params,
+        circuit,
+        transcript_label: config.transcript_label.clone(),
+        proving_key: RefCell::new(None),
+    })
 }
 
 pub fn prove(
@@ -41,19 +98,20 @@ pub fn prove(
         .map(|(key, value)| (key.as_ref().to_string(), *value))
         .collect();
     let assignments = get_circuit_assignments(module, &named_string_assignments)?;
-    let proof_data_cli = prove_from_variable_assignments(circuit_data, &assignments, config)?;
+    let (proof_data_cli, verifying_key) =
+        prove_with_verifying_key(circuit_data, &assignments, config)?;
     let public_fields: Vec<String> = module
         .pubs
         .clone()
         .into_iter()
         .filter_map(|v| v.name)
         .collect();
-    let verifying_key = keygen_vk(&circuit_data.params, &circuit_data.circuit)?;
     Ok(ProofDataHalo2 {
         proof: proof_data_cli.proof,
         verifying_key,
         public_fields,
         params: circuit_data.params.clone(),
+        transcript_label: circuit_data.transcript_label.clone(),
     })
 }
 
@@ -76,6 +134,77 @@ pub fn verify(
         &proof_data.verifying_key,
         &proof_data.proof,
         public_input.as_slice(),
+        proof_data.transcript_label.as_bytes(),
+    )
+    .map_err(|_| ProofVerificationFailure)
+}
+
+/* Generate the verifying key of the given circuit along with the data that a
+ * verifier needs to regenerate it, leaving out the witness definitions. */
+pub fn export_vk(
+    circuit_data: &HaloCircuitData,
+    config: &Config,
+) -> Result<VerifyingKeyDataHalo2, Error> {
+    qprintln!(config, "* Generating verifying key...");
+    let circuit = circuit_data.circuit.without_definitions();
+    let verifying_key = keygen_vk(&circuit_data.params, &circuit)?;
+    let digest = verifying_key_digest(&verifying_key)?;
+    Ok(VerifyingKeyDataHalo2 {
+        verifying_key: RefCell::new(Some(Rc::new(verifying_key))),
+        params: circuit_data.params.clone(),
+        circuit,
+        transcript_label: circuit_data.transcript_label.clone(),
+        digest,
+    })
+}
+
+/* Solve for the witnesses of the given circuit without generating a proof and
+ * report every equality that they do not satisfy. */
+pub fn check(
+    circuit_data: &HaloCircuitData,
+    named_assignments: &HashMap<impl AsRef<str>, Fp>,
+    config: &Config,
+) -> Result<Vec<UnsatisfiedEquality>, Error> {
+    let module = circuit_data.circuit.module.as_ref();
+    let named_string_assignments: HashMap<String, Fp> = named_assignments
+        .iter()
+        .map(|(key, value)| (key.as_ref().to_string(), *value))
+        .collect();
+    let assignments = get_circuit_assignments(module, &named_string_assignments)?;
+    qprintln!(config, "* Checking witnesses...");
+    circuit_data.circuit.check_variables(assignments)
+}
+
+/* Run the mock prover on the given circuit and report every failure that it
+ * finds, traced back to the constraint that caused it. */
+pub fn debug(
+    circuit_data: &HaloCircuitData,
+    named_assignments: &HashMap<impl AsRef<str>, Fp>,
+    config: &Config,
+) -> Result<Vec<DebugFailure>, Error> {
+    let module = circuit_data.circuit.module.as_ref();
+    let named_string_assignments: HashMap<String, Fp> = named_assignments
+        .iter()
+        .map(|(key, value)| (key.as_ref().to_string(), *value))
+        .collect();
+    let assignments = get_circuit_assignments(module, &named_string_assignments)?;
+    debug_from_variable_assignments(circuit_data, &assignments, config)
+}
+
+/* Check the given proof against the given public inputs, which are in the
+ * order in which the circuit declares its public variables. */
+fn verify_instances(
+    verifier_data: &VerifyingKeyDataHalo2,
+    proof_data: &ProofDataCliHalo2,
+    instances: &[Fp],
+) -> Result<(), Error> {
+    let verifying_key = verifier_data.verifying_key()?;
+    verifier(
+        &verifier_data.params,
+        &verifying_key,
+        &proof_data.proof,
+        instances,
+        verifier_data.transcript_label.as_bytes(),
     )
     .map_err(|_| ProofVerificationFailure)
 }
@@ -91,7 +220,7 @@ fn public_inputs_from_variable_assignments(
         .collect::<Vec<Fp>>()
 }
 
-fn public_inputs_from_assignments(
+pub(crate) fn public_inputs_from_assignments(
     public_fields: &[String],
     assignments: &HashMap<String, Fp>,
 ) -> Result<Vec<Fp>, Error> {
@@ -108,29 +237,27 @@ fn public_inputs_from_assignments(
         .collect()
 }
 
-pub(crate) fn prove_from_int_variable_assignments(
+pub(crate) fn prove_from_variable_assignments(
     circuit_data: &HaloCircuitData,
-    int_assignments: &HashMap<VariableId, BigInt>,
+    assignments: &HashMap<VariableId, Fp>,
     config: &Config,
 ) -> Result<ProofDataCliHalo2, Error> {
-    let assignments: HashMap<VariableId, Fp> = int_assignments
-        .iter()
-        .map(|(id, f)| (*id, make_constant::<Fp>(f.clone())))
-        .collect();
-    prove_from_variable_assignments(circuit_data, &assignments, config)
+    prove_with_verifying_key(circuit_data, assignments, config).map(|(proof, _vk)| proof)
 }
 
-pub(crate) fn prove_from_variable_assignments(
+/* Prove the given assignments and also return the verifying key that was
+ * generated along the way, so that callers need not generate it again. */
+fn prove_with_verifying_key(
     circuit_data: &HaloCircuitData,
     assignments: &HashMap<VariableId, Fp>,
     config: &Config,
-) -> Result<ProofDataCliHalo2, Error> {
+) -> Result<(ProofDataCliHalo2, VerifyingKey<EqAffine>), Error> {
     let params = &circuit_data.params;
     let module = circuit_data.circuit.module.as_ref();
 
     // Populate variable definitions
     let mut circuit = circuit_data.circuit.clone();
-    circuit.populate_variables(assignments.clone());
+    circuit.populate_variables(assignments.clone())?;
 
     // Get public inputs Fp
     let binding = public_inputs_from_variable_assignments(module, assignments);
@@ -144,29 +271,114 @@ pub(crate) fn prove_from_variable_assignments(
         })
         .collect();
 
-    // Generating proving key
-    qprintln!(config, "* Generating proving key...");
-    let (pk, _vk) = keygen(&circuit, params)?;
+    // Reuse the proving key of earlier proofs of this circuit
+    let pk = circuit_data.proving_key(config)?;
+    let vk = pk.get_vk().clone();
 
     // Start proving witnesses
     qprintln!(config, "* Proving knowledge of witnesses...");
-    let proof = prover(circuit.clone(), params, &pk, instances)
-        .map_err(|e| BackendError { e: e.to_string() })?;
-    Ok(ProofDataCliHalo2 {
-        proof,
-        public_inputs,
-    })
+    let proof = prover(
+        circuit.clone(),
+        params,
+        &pk,
+        instances,
+        circuit_data.transcript_label.as_bytes(),
+    )
+    .map_err(|e| BackendError { e: e.to_string() })?;
+    Ok((
+        ProofDataCliHalo2 {
+            proof,
+            public_inputs,
+        },
+        vk,
+    ))
 }
 
-/* Captures all the data required to use a Halo2 circuit. */
+pub(crate) fn debug_from_int_variable_assignments(
+    circuit_data: &HaloCircuitData,
+    int_assignments: &HashMap<VariableId, BigInt>,
+    config: &Config,
+) -> Result<Vec<DebugFailure>, Error> {
+    let assignments: HashMap<VariableId, Fp> = int_assignments
+        .iter()
+        .map(|(id, f)| (*id, make_constant::<Fp>(f.clone())))
+        .collect();
+    debug_from_variable_assignments(circuit_data, &assignments, config)
+}
+
+pub(crate) fn debug_from_variable_assignments(
+    circuit_data: &HaloCircuitData,
+    assignments: &HashMap<VariableId, Fp>,
+    config: &Config,
+) -> Result<Vec<DebugFailure>, Error> {
+    let module = circuit_data.circuit.module.as_ref();
+
+    // Populate variable definitions
+    let mut circuit = circuit_data.circuit.clone();
+    let values = circuit.solve_variables(assignments.clone())?;
+    circuit.populate_variables(values.clone())?;
+    let instances = public_inputs_from_variable_assignments(module, assignments);
+
+    qprintln!(config, "* Running mock prover...");
+    let prover = MockProver::run(circuit.k, &circuit, vec![instances])?;
+    let failures = prover.verify().err().unwrap_or_default();
+
+    // Attribute each failure to the constraint that caused it
+    Ok(failures
+        .into_iter()
+        .map(|failure| {
+            let constraint = failed_constraint(&failure).map(|index| module.exprs[index].clone());
+            let mut vars = HashMap::new();
+            if let Some(expr) = &constraint {
+                collect_expr_variables(expr, &mut vars);
+            }
+            let mut variables: Vec<_> = vars
+                .into_values()
+                .map(|var| {
+                    let val = BigUint::from_bytes_le(values[&var.id].to_repr().as_ref());
+                    (var, val.to_bigint().unwrap())
+                })
+                .collect();
+            variables.sort_by_key(|(var, _)| var.id);
+            DebugFailure {
+                failure,
+                constraint,
+                variables,
+            }
+        })
+        .collect())
+}
+
+/* A failure found by the mock prover, together with the constraint that
+ * caused it and the values of the variables occurring in that constraint. */
+pub struct DebugFailure {
+    pub failure: VerifyFailure,
+    pub constraint: Option<TExpr>,
+    pub variables: Vec<(Variable, BigInt)>,
+}
+
+/* Captures all the data required to use a Halo2 circuit. halo2_proofs
+ * provides no way to write keys out, so the proving key is not stored with the
+ * circuit. It is generated on the first proof made with this value and reused
+ * by later ones. */
 pub struct HaloCircuitData {
     pub params: Params<EqAffine>,
     pub circuit: Halo2Module<Fp>,
+    pub transcript_label: String,
+    proving_key: RefCell<Option<Rc<ProvingKey<EqAffine>>>>,
 }
 
+/* Captures all the data required to verify proofs of a Halo2 circuit.
+ * halo2_proofs provides no way to read keys back, so the verifying key is
+ * regenerated from the public parameters and the constraints of the circuit on
+ * the first verification in each process. The digest of the key that was
+ * exported is kept alongside, and a regenerated key must match it. */
 pub struct VerifyingKeyDataHalo2 {
-    pub verifying_key: VerifyingKey<EqAffine>,
+    verifying_key: RefCell<Option<Rc<VerifyingKey<EqAffine>>>>,
     pub params: Params<EqAffine>,
+    pub circuit: Halo2Module<Fp>,
+    pub transcript_label: String,
+    pub digest: Fp,
 }
 
 pub struct ProofDataHalo2 {
@@ -174,15 +386,177 @@ pub struct ProofDataHalo2 {
     pub verifying_key: VerifyingKey<EqAffine>,
     pub public_fields: Vec<String>,
     pub params: Params<EqAffine>,
+    pub transcript_label: String,
 }
 
+/* Captures a proof along with the encoded public inputs it was made for. */
 #[derive(CanonicalSerialize, CanonicalDeserialize)]
-pub(crate) struct ProofDataCliHalo2 {
-    pub(crate) proof: Vec<u8>,
-    pub(crate) public_inputs: Vec<u8>,
+pub struct ProofDataCliHalo2 {
+    pub proof: Vec<u8>,
+    pub public_inputs: Vec<u8>,
 }
 
+impl ProofDataCliHalo2 {
+    /* Decode the public inputs contained in this proof. */
+    pub fn instances(&self) -> Result<Vec<Fp>, Error> {
+        let chunks = self.public_inputs.chunks_exact(32);
+        if !chunks.remainder().is_empty() {
+            return Err(artifact_error(
+                "proof",
+                "public inputs are not a whole number of field elements",
+            ));
+        }
+        chunks
+            .map(|chunk| {
+                let mut array = [0u8; 32];
+                array.copy_from_slice(chunk);
+                Option::from(Fp::from_repr(array)).ok_or_else(|| {
+                    artifact_error("proof", "public input is not a canonical field element")
+                })
+            })
+            .collect()
+    }
+
+    /* Decode the public inputs contained in this proof and check that there
+     * is one for each public variable of the given circuit. */
+    fn circuit_instances(&self, module: &Module) -> Result<Vec<Fp>, Error> {
+        let instances = self.instances()?;
+        if instances.len() != module.pubs.len() {
+            return Err(Error::PublicInputCountMismatch {
+                actual: instances.len(),
+                expected: module.pubs.len(),
+            });
+        }
+        Ok(instances)
+    }
+}
+
+/* The contents of a circuit file: the public parameters if they are embedded,
+ * the circuit, and its transcript label. */
+type CircuitParts = (Option<Params<EqAffine>>, Halo2Module<Fp>, String);
+
 impl HaloCircuitData {
+    /* Get the proving key of this circuit, generating it on first use. */
+    pub fn proving_key(&self, config: &Config) -> Result<Rc<ProvingKey<EqAffine>>, Error> {
+        if let Some(pk) = self.proving_key.borrow().as_ref() {
+            return Ok(pk.clone());
+        }
+        qprintln!(config, "* Generating proving key...");
+        let (pk, _vk) = keygen(&self.circuit, &self.params)?;
+        let pk = Rc::new(pk);
+        *self.proving_key.borrow_mut() = Some(pk.clone());
+        Ok(pk)
+    }
+
+    /* Read a circuit whose public parameters are embedded in it. */
+    pub fn read<R>(reader: R) -> Result<Self, DecodeError>
+    where
+        R: std::io::Read,
+    {
+        let (params, circuit, transcript_label) = Self::read_parts(reader)?;
+        let params = params.ok_or_else(|| {
+            DecodeError::OtherString("circuit does not contain public parameters".to_string())
+        })?;
+        Ok(Self {
+            params,
+            circuit,
+            transcript_label,
+            proving_key: RefCell::new(None),
+        })
+    }
+
+    /* Read a circuit and use the given public parameters for it, shrunk to the
+     * circuit's size. Any parameters embedded in the circuit are ignored. */
+    pub fn read_with_params<R>(reader: R, params: &Params<EqAffine>) -> Result<Self, DecodeError>
+    where
+        R: std::io::Read,
+    {
+        let (_, circuit, transcript_label) = Self::read_parts(reader)?;
+        let params = fit_params(params, circuit.k)
+            .map_err(|err| DecodeError::OtherString(err.to_string()))?;
+        Ok(Self {
+            params,
+            circuit,
+            transcript_label,
+            proving_key: RefCell::new(None),
+        })
+    }
+
+    fn read_parts<R>(mut reader: R) -> Result<CircuitParts, DecodeError>
+    where
+        R: std::io::Read,
+    {
+        let embedded: bool =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        let params = if embedded {
+            let params = Params::<EqAffine>::read(&mut reader)
+                .map_err(|x| DecodeError::OtherString(x.to_string()))?;
+            Some(params)
+        } else {
+            None
+        };
+        let circuit: Halo2Module<Fp> =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        let transcript_label: String =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        Ok((params, circuit, transcript_label))
+    }
+
+    /* Write the circuit along with its public parameters. */
+    pub fn write<W>(&self, writer: W) -> Result<(), EncodeError>
+    where
+        W: std::io::Write,
+    {
+        self.write_parts(writer, true)
+    }
+
+    /* Write the circuit only. Its public parameters must be supplied when it
+     * is read back. */
+    pub fn write_without_params<W>(&self, writer: W) -> Result<(), EncodeError>
+    where
+        W: std::io::Write,
+    {
+        self.write_parts(writer, false)
+    }
+
+    fn write_parts<W>(&self, mut writer: W, embed_params: bool) -> Result<(), EncodeError>
+    where
+        W: std::io::Write,
+    {
+        bincode::encode_into_std_write(embed_params, &mut writer, bincode::config::standard())?;
+        if embed_params {
+            self.params
+                .write(&mut writer)
+                .map_err(|x| EncodeError::OtherString(x.to_string()))?;
+        }
+        bincode::encode_into_std_write(&self.circuit, &mut writer, bincode::config::standard())?;
+        bincode::encode_into_std_write(
+            &self.transcript_label,
+            &mut writer,
+            bincode::config::standard(),
+        )?;
+        Ok(())
+    }
+}
+
+impl VerifyingKeyDataHalo2 {
+    /* Get the verifying key of this circuit, generating it on first use and
+     * checking it against the recorded digest. */
+    pub fn verifying_key(&self) -> Result<Rc<VerifyingKey<EqAffine>>, Error> {
+        if let Some(vk) = self.verifying_key.borrow().as_ref() {
+            return Ok(vk.clone());
+        }
+        let vk = keygen_vk(&self.params, &self.circuit)?;
+        if verifying_key_digest(&vk)? != self.digest {
+            return Err(Error::VerifyingKeyMismatch);
+        }
+        let vk = Rc::new(vk);
+        *self.verifying_key.borrow_mut() = Some(vk.clone());
+        Ok(vk)
+    }
+
+    /* Read the verifier data. The verifying key is only generated once it is
+     * needed. */
     pub fn read<R>(mut reader: R) -> Result<Self, DecodeError>
     where
         R: std::io::Read,
@@ -191,48 +565,296 @@ impl HaloCircuitData {
             .map_err(|x| DecodeError::OtherString(x.to_string()))?;
         let circuit: Halo2Module<Fp> =
             bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
-        Ok(Self { params, circuit })
+        let transcript_label: String =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        let digest: [u8; 32] =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        let digest = Option::from(Fp::from_repr(digest)).ok_or_else(|| {
+            DecodeError::OtherString("verifying key digest is not a field element".to_string())
+        })?;
+        Ok(Self {
+            verifying_key: RefCell::new(None),
+            params,
+            circuit,
+            transcript_label,
+            digest,
+        })
     }
 
+    /* Write the public parameters and the constraints of the circuit, along
+     * with the digest of its verifying key. */
     pub fn write<W>(&self, mut writer: W) -> Result<(), EncodeError>
     where
         W: std::io::Write,
     {
         self.params
             .write(&mut writer)
-            .expect("unable to create circuit file");
-        bincode::encode_into_std_write(&self.circuit, &mut writer, bincode::config::standard())
-            .expect("unable to create circuit file");
+            .map_err(|x| EncodeError::OtherString(x.to_string()))?;
+        bincode::encode_into_std_write(&self.circuit, &mut writer, bincode::config::standard())?;
+        bincode::encode_into_std_write(
+            &self.transcript_label,
+            &mut writer,
+            bincode::config::standard(),
+        )?;
+        bincode::encode_into_std_write(
+            self.digest.to_repr(),
+            &mut writer,
+            bincode::config::standard(),
+        )?;
         Ok(())
     }
 }
 
+impl ProofSystem for Halo2Module<Fp> {
+    type Field = Fp;
+    type FieldOps = PrimeFieldOps<Fp>;
+    type Params = Params<EqAffine>;
+    type CircuitData = HaloCircuitData;
+    type VerifierData = VerifyingKeyDataHalo2;
+    type ProofData = ProofDataCliHalo2;
+
+    fn make_constant(c: &BigInt) -> Fp {
+        make_constant(c.clone())
+    }
+
+    fn setup(k: u32) -> Result<Params<EqAffine>, Error> {
+        Ok(setup(k))
+    }
+
+    fn synthesize(
+        module_3ac: Module,
+        params: Option<&Params<EqAffine>>,
+        config: &Config,
+    ) -> Result<HaloCircuitData, Error> {
+        synthesize_module(module_3ac, params, config)
+    }
+
+    fn circuit_module(circuit_data: &HaloCircuitData) -> &Module {
+        &circuit_data.circuit.module
+    }
+
+    fn prove(
+        circuit_data: &HaloCircuitData,
+        _params: Option<&Params<EqAffine>>,
+        assignments: HashMap<VariableId, Fp>,
+        config: &Config,
+    ) -> Result<ProofDataCliHalo2, Error> {
+        prove_from_variable_assignments(circuit_data, &assignments, config)
+    }
+
+    fn export_vk(
+        circuit_data: &HaloCircuitData,
+        _params: Option<&Params<EqAffine>>,
+        config: &Config,
+    ) -> Result<VerifyingKeyDataHalo2, Error> {
+        export_vk(circuit_data, config)
+    }
+
+    fn verify(
+        verifier_data: &VerifyingKeyDataHalo2,
+        proof_data: &ProofDataCliHalo2,
+        _config: &Config,
+    ) -> Result<(), Error> {
+        let instances = proof_data.circuit_instances(&verifier_data.circuit.module)?;
+        verify_instances(verifier_data, proof_data, &instances)
+    }
+
+    fn verify_with_public_inputs(
+        verifier_data: &VerifyingKeyDataHalo2,
+        proof_data: &ProofDataCliHalo2,
+        expected: &HashMap<String, BigInt>,
+        _config: &Config,
+    ) -> Result<(), Error> {
+        // Report the first differing value before the proof is rejected
+        Self::check_public_inputs(verifier_data, proof_data, expected)?;
+        let public_fields = verifier_data
+            .circuit
+            .module
+            .pubs
+            .iter()
+            .map(public_variable_name)
+            .collect::<Result<Vec<_>, _>>()?;
+        let assignments: HashMap<String, Fp> = expected
+            .iter()
+            .map(|(name, val)| (name.clone(), make_constant(val.clone())))
+            .collect();
+        let instances = public_inputs_from_assignments(&public_fields, &assignments)?;
+        verify_instances(verifier_data, proof_data, &instances)
+    }
+
+    fn public_inputs(
+        verifier_data: &VerifyingKeyDataHalo2,
+        proof_data: &ProofDataCliHalo2,
+    ) -> Result<Vec<(Variable, BigInt)>, Error> {
+        let module = &verifier_data.circuit.module;
+        let instances = proof_data.circuit_instances(module)?;
+        Ok(module
+            .pubs
+            .iter()
+            .cloned()
+            .zip(instances)
+            .map(|(var, val)| {
+                let val = BigUint::from_bytes_le(val.to_repr().as_ref());
+                (var, val.to_bigint().unwrap())
+            })
+            .collect())
+    }
+
+    fn read_params(
+        mut reader: &mut dyn std::io::Read,
+        _unchecked: bool,
+    ) -> Result<Params<EqAffine>, Error> {
+        Params::read(&mut reader).map_err(|e| artifact_error("public parameters", e))
+    }
+
+    fn write_params(
+        params: &Params<EqAffine>,
+        _unchecked: bool,
+        mut writer: &mut dyn Write,
+    ) -> Result<(), Error> {
+        params
+            .write(&mut writer)
+            .map_err(|e| artifact_error("public parameters", e))
+    }
+
+    fn read_circuit(
+        reader: &mut dyn std::io::Read,
+        params: Option<&Params<EqAffine>>,
+        _unchecked: bool,
+    ) -> Result<HaloCircuitData, Error> {
+        match params {
+            Some(params) => HaloCircuitData::read_with_params(reader, params),
+            None => HaloCircuitData::read(reader),
+        }
+        .map_err(|e| artifact_error("circuit", e))
+    }
+
+    fn write_circuit(
+        circuit_data: &HaloCircuitData,
+        with_params: bool,
+        _unchecked: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error> {
+        if with_params {
+            circuit_data.write(writer)
+        } else {
+            circuit_data.write_without_params(writer)
+        }
+        .map_err(|e| artifact_error("circuit", e))
+    }
+
+    fn read_verifier_data(reader: &mut dyn std::io::Read) -> Result<VerifyingKeyDataHalo2, Error> {
+        VerifyingKeyDataHalo2::read(reader).map_err(|e| artifact_error("verifying key", e))
+    }
+
+    fn write_verifier_data(
+        verifier_data: &VerifyingKeyDataHalo2,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error> {
+        verifier_data
+            .write(writer)
+            .map_err(|e| artifact_error("verifying key", e))
+    }
+
+    fn read_proof(reader: &mut dyn std::io::Read) -> Result<ProofDataCliHalo2, Error> {
+        ProofDataCliHalo2::deserialize(reader).map_err(|e| artifact_error("proof", e))
+    }
+
+    fn write_proof(proof_data: &ProofDataCliHalo2, writer: &mut dyn Write) -> Result<(), Error> {
+        proof_data
+            .serialize(writer)
+            .map_err(|e| artifact_error("proof", e))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_compile_valid_file() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         assert!(compile("x = 1;", &config).is_ok());
     }
 
     #[test]
     fn test_compile_invalid_file() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         assert!(compile("", &config).is_err());
     }
 
     #[test]
-    #[ignore] // This test panics in type checking
     fn test_compile_type_error() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         assert!(compile("(1, 2) = 1;", &config).is_err());
     }
 
+    #[test]
+    fn test_compile_annotation_error() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        assert!(compile("def (y: int) = (1, 2); y = 1;", &config).is_err());
+    }
+
+    #[test]
+    fn test_compile_nested_annotations() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        assert!(compile("def ((y: int): int) = 1; y = 1;", &config).is_ok());
+        assert!(compile("def ((y: [int]): int) = 1; y = 1;", &config).is_err());
+        assert!(compile("def (y: [int]) : int = 1; y = 1;", &config).is_err());
+    }
+
+    #[test]
+    fn test_compile_static_match_error() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let source = "pub a; def sw n = match n { 0 => 1, _ => 2 }; sw a = 1;";
+        assert!(compile(source, &config).is_err());
+    }
+
+    #[test]
+    fn test_compile_conditional_constraint_error() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let source = "def y = if c then { x = 1; x } else 0; y = 1;";
+        assert!(compile(source, &config).is_err());
+        let source = "def y = if 1 then { x = 1; x } else 0; y = 1;";
+        assert!(compile(source, &config).is_ok());
+    }
+
+    #[test]
+    fn test_compile_evaluation_error() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        assert!(compile("def f x = x; f = f;", &config).is_err());
+    }
+
     #[test]
     fn test_prove_valid() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("x = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::one())]);
         assert!(prove(&circuit, &assignments, &config).is_ok());
@@ -240,7 +862,10 @@ mod tests {
 
     #[test]
     fn test_prove_missing_assignment() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("x = 1;", &config).unwrap();
         let assignments: HashMap<String, Fp> = HashMap::new();
         assert!(prove(&circuit, &assignments, &config).is_err());
@@ -248,7 +873,10 @@ mod tests {
 
     #[test]
     fn test_prove_invalid_assignment() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("x = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::zero())]);
         assert!(prove(&circuit, &assignments, &config).is_ok());
@@ -256,7 +884,10 @@ mod tests {
 
     #[test]
     fn test_verify_valid_no_public() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("x = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::one())]);
         let proof_data = prove(&circuit, &assignments, &config).unwrap();
@@ -265,7 +896,10 @@ mod tests {
 
     #[test]
     fn test_verify_valid_with_public() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("pub x; pub y; x + y + z = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::one()), ("y", Fp::zero()), ("z", Fp::zero())]);
         let public_assignments = HashMap::from([("x", Fp::one()), ("y", Fp::zero())]);
@@ -273,9 +907,347 @@ mod tests {
         assert!(verify(&proof_data, &public_assignments, &config).is_ok());
     }
 
+    #[test]
+    fn test_prove_conditional_condition_not_boolean() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub y; (if c then 3 else 5) = y;", &config).unwrap();
+        let assignments = HashMap::from([("c", Fp::one()), ("y", Fp::from(3))]);
+        let public_assignments = HashMap::from([("y", Fp::from(3))]);
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_ok());
+
+        // A condition of 2 satisfies c*(3-5)+5 = 1 but not the boolean constraint
+        let assignments = HashMap::from([("c", Fp::from(2)), ("y", Fp::one())]);
+        let public_assignments = HashMap::from([("y", Fp::one())]);
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_err());
+    }
+
+    #[test]
+    fn test_prove_comparison() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub z; (x < y) = z;", &config).unwrap();
+        let assignments = HashMap::from([("x", Fp::from(3)), ("y", Fp::from(5)), ("z", Fp::one())]);
+        let public_assignments = HashMap::from([("z", Fp::one())]);
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_ok());
+
+        let public_assignments = HashMap::from([("z", Fp::zero())]);
+        assert!(verify(&proof_data, &public_assignments, &config).is_err());
+    }
+
+    #[test]
+    fn test_prove_range_out_of_bounds() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("def _ = range 4 x;", &config).unwrap();
+        let public_assignments: HashMap<String, Fp> = HashMap::new();
+        let assignments = HashMap::from([("x", Fp::from(15))]);
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_ok());
+
+        // 16 does not fit into 4 bits, so its bits cannot recombine into it
+        let assignments = HashMap::from([("x", Fp::from(16))]);
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_err());
+    }
+
+    #[test]
+    fn test_check_reports_unsatisfied_equality() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub z; x*y = z;", &config).unwrap();
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", Fp::from(3)), ("z", Fp::from(6))]);
+        assert!(check(&circuit, &assignments, &config).unwrap().is_empty());
+
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", Fp::from(3)), ("z", Fp::from(7))]);
+        let unsatisfied = check(&circuit, &assignments, &config).unwrap();
+        assert_eq!(unsatisfied.len(), 1);
+        let names: Vec<_> = unsatisfied[0]
+            .variables
+            .iter()
+            .filter_map(|(var, val)| Some((var.name.clone()?, val.clone())))
+            .collect();
+        assert!(names.contains(&("z".to_string(), BigInt::from(7))));
+    }
+
+    #[test]
+    fn test_debug_traces_failure_to_constraint() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub z; x*y = z;", &config).unwrap();
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", Fp::from(3)), ("z", Fp::from(6))]);
+        assert!(debug(&circuit, &assignments, &config).unwrap().is_empty());
+
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", Fp::from(3)), ("z", Fp::from(7))]);
+        let failures = debug(&circuit, &assignments, &config).unwrap();
+        assert!(!failures.is_empty());
+        assert!(failures
+            .iter()
+            .any(|failure| failure
+                .variables
+                .iter()
+                .any(|(var, val)| var.name.as_deref() == Some("z") && *val == BigInt::from(7))));
+    }
+
+    #[test]
+    fn test_failed_constraint_from_failure_location() {
+        use halo2_proofs::dev::{metadata, FailureLocation};
+        let constraint_failure = |location| VerifyFailure::ConstraintNotSatisfied {
+            constraint: metadata::Constraint::from((metadata::Gate::from((0, "poly")), 0, "")),
+            location,
+            cell_values: vec![],
+        };
+        let failure = constraint_failure(FailureLocation::InRegion {
+            region: metadata::Region::from((4, "constraint 12")),
+            offset: 0,
+        });
+        assert_eq!(failed_constraint(&failure), Some(12));
+
+        let failure = constraint_failure(FailureLocation::InRegion {
+            region: metadata::Region::from((4, "raw_poly")),
+            offset: 0,
+        });
+        assert_eq!(failed_constraint(&failure), None);
+
+        let failure = constraint_failure(FailureLocation::OutsideRegion { row: 12 });
+        assert_eq!(failed_constraint(&failure), None);
+    }
+
+    #[test]
+    fn test_prove_with_downsized_params() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let params = setup(8);
+        let circuit = compile("x = 1;", &config).unwrap();
+        let mut circuit_file = Vec::new();
+        circuit.write_without_params(&mut circuit_file).unwrap();
+        assert!(HaloCircuitData::read(circuit_file.as_slice()).is_err());
+
+        // Downsized parameters coincide with ones generated at that size
+        let circuit = HaloCircuitData::read_with_params(circuit_file.as_slice(), &params).unwrap();
+        let mut expected = Vec::new();
+        setup(circuit.circuit.k).write(&mut expected).unwrap();
+        let mut actual = Vec::new();
+        circuit.params.write(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+
+        let assignments = HashMap::from([("x", Fp::one())]);
+        let public_assignments: HashMap<String, Fp> = HashMap::new();
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_ok());
+
+        assert!(fit_params(&setup(1), circuit.circuit.k).is_err());
+    }
+
+    #[test]
+    fn test_verify_with_exported_vk() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub z; x*y = z;", &config).unwrap();
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", Fp::from(3)), ("z", Fp::from(6))]);
+        let proof_data = prove(&circuit, &assignments, &config).unwrap();
+        // Later proofs reuse the proving key generated by the first
+        let pk = circuit.proving_key(&config).unwrap();
+        prove(&circuit, &assignments, &config).unwrap();
+        assert!(Rc::ptr_eq(&pk, &circuit.proving_key(&config).unwrap()));
+
+        let mut vk_file = Vec::new();
+        export_vk(&circuit, &config)
+            .unwrap()
+            .write(&mut vk_file)
+            .unwrap();
+        let vk_data = VerifyingKeyDataHalo2::read(vk_file.as_slice()).unwrap();
+        assert!(vk_data.circuit.module.defs.is_empty());
+
+        // A key regenerated from the file must match the one that was exported
+        let mut tampered = VerifyingKeyDataHalo2::read(vk_file.as_slice()).unwrap();
+        tampered.digest += Fp::one();
+        assert!(matches!(
+            tampered.verifying_key(),
+            Err(Error::VerifyingKeyMismatch)
+        ));
+
+        let proof_data = ProofDataHalo2 {
+            verifying_key: vk_data.verifying_key().unwrap().as_ref().clone(),
+            params: vk_data.params,
+            ..proof_data
+        };
+        let public_assignments = HashMap::from([("z", Fp::from(6))]);
+        assert!(verify(&proof_data, &public_assignments, &config).is_ok());
+        let public_assignments = HashMap::from([("z", Fp::from(7))]);
+        assert!(verify(&proof_data, &public_assignments, &config).is_err());
+    }
+
+    #[test]
+    fn test_prove_through_proof_system() {
+        type S = Halo2Module<Fp>;
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let params = S::setup(8).unwrap();
+        let module = Module::parse("pub z; x*y = z;").unwrap();
+        let circuit = S::compile(module, Some(&params), &config).unwrap();
+        let named_assignments = HashMap::from([
+            ("x".to_string(), BigInt::from(2)),
+            ("y".to_string(), BigInt::from(3)),
+            ("z".to_string(), BigInt::from(6)),
+        ]);
+        let assignments = get_circuit_assignments(S::circuit_module(&circuit), &named_assignments)
+            .unwrap()
+            .into_iter()
+            .map(|(id, val)| (id, S::make_constant(&val)))
+            .collect();
+        let proof_data = S::prove(&circuit, Some(&params), assignments, &config).unwrap();
+
+        let mut vk_file = Vec::new();
+        let verifier_data = S::export_vk(&circuit, Some(&params), &config).unwrap();
+        S::write_verifier_data(&verifier_data, &mut vk_file).unwrap();
+        let verifier_data = S::read_verifier_data(&mut vk_file.as_slice()).unwrap();
+        let mut proof_file = Vec::new();
+        S::write_proof(&proof_data, &mut proof_file).unwrap();
+        let proof_data = S::read_proof(&mut proof_file.as_slice()).unwrap();
+
+        let public_inputs = S::public_inputs(&verifier_data, &proof_data).unwrap();
+        assert_eq!(public_inputs.len(), 1);
+        assert_eq!(public_inputs[0].1, BigInt::from(6));
+        assert!(S::verify(&verifier_data, &proof_data, &config).is_ok());
+    }
+
+    #[test]
+    fn test_check_public_inputs() {
+        type S = Halo2Module<Fp>;
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub z; x*y = z;", &config).unwrap();
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", -Fp::from(3)), ("z", -Fp::from(6))]);
+        let module = circuit.circuit.module.as_ref();
+        let assignments = get_circuit_assignments(
+            module,
+            &assignments
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+        .unwrap();
+        let proof_data = prove_from_variable_assignments(&circuit, &assignments, &config).unwrap();
+        let verifier_data = export_vk(&circuit, &config).unwrap();
+
+        // Expected values are compared modulo the field
+        let expected = HashMap::from([("z".to_string(), BigInt::from(-6))]);
+        assert!(S::check_public_inputs(&verifier_data, &proof_data, &expected).is_ok());
+        let expected = HashMap::from([("z".to_string(), BigInt::from(6))]);
+        assert!(matches!(
+            S::check_public_inputs(&verifier_data, &proof_data, &expected),
+            Err(Error::PublicInputMismatch { .. })
+        ));
+        let expected = HashMap::new();
+        assert!(matches!(
+            S::check_public_inputs(&verifier_data, &proof_data, &expected),
+            Err(MissingVariableAssignment { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_against_expected_public_inputs() {
+        type S = Halo2Module<Fp>;
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let circuit = compile("pub z, w; x*y = z; w = 0;", &config).unwrap();
+        let assignments = HashMap::from([
+            ("x", Fp::from(2)),
+            ("y", Fp::from(3)),
+            ("z", Fp::from(6)),
+            ("w", Fp::zero()),
+        ]);
+        let module = circuit.circuit.module.as_ref();
+        let assignments = get_circuit_assignments(
+            module,
+            &assignments
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+        .unwrap();
+        let mut proof_data =
+            prove_from_variable_assignments(&circuit, &assignments, &config).unwrap();
+        let verifier_data = export_vk(&circuit, &config).unwrap();
+        let expected = HashMap::from([
+            ("z".to_string(), BigInt::from(6)),
+            ("w".to_string(), BigInt::from(0)),
+        ]);
+        assert!(
+            S::verify_with_public_inputs(&verifier_data, &proof_data, &expected, &config).is_ok()
+        );
+
+        // Dropping the trailing zero instance must not let w take any value
+        proof_data.public_inputs.truncate(32);
+        assert!(matches!(
+            S::verify(&verifier_data, &proof_data, &config),
+            Err(Error::PublicInputCountMismatch { .. })
+        ));
+        let expected = HashMap::from([
+            ("z".to_string(), BigInt::from(6)),
+            ("w".to_string(), BigInt::from(5)),
+        ]);
+        assert!(
+            S::verify_with_public_inputs(&verifier_data, &proof_data, &expected, &config).is_err()
+        );
+    }
+
+    #[test]
+    fn test_malformed_public_inputs_rejected() {
+        let proof_data = ProofDataCliHalo2 {
+            proof: Vec::new(),
+            public_inputs: vec![0; 31],
+        };
+        assert!(matches!(
+            proof_data.instances(),
+            Err(Error::ArtifactError { .. })
+        ));
+        let proof_data = ProofDataCliHalo2 {
+            proof: Vec::new(),
+            public_inputs: vec![0xff; 32],
+        };
+        assert!(matches!(
+            proof_data.instances(),
+            Err(Error::ArtifactError { .. })
+        ));
+    }
+
     #[test]
     fn test_verify_invalid_input_no_public() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("x = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::zero())]);
         let public_assignments: HashMap<String, Fp> = HashMap::new();
@@ -285,7 +1257,10 @@ mod tests {
 
     #[test]
     fn test_verify_valid_input_invalid_public_input() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("pub x; x = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::one())]);
         let public_assignments = HashMap::from([("x", Fp::zero())]);
@@ -295,7 +1270,10 @@ mod tests {
 
     #[test]
     fn test_verify_valid_input_missing_public_input() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("pub x; x = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::one())]);
         let public_assignments: HashMap<String, Fp> = HashMap::new();
@@ -305,7 +1283,10 @@ mod tests {
 
     #[test]
     fn test_verify_valid_with_public_and_corrupt_proof_data() {
-        let config = Config { quiet: true };
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
         let circuit = compile("pub x; pub y; x + y + z = 1;", &config).unwrap();
         let assignments = HashMap::from([("x", Fp::one()), ("y", Fp::zero()), ("z", Fp::zero())]);
         let public_assignments = HashMap::from([("x", Fp::one()), ("y", Fp::zero())]);
@@ -313,4 +1294,21 @@ mod tests {
         proof_data.public_fields = vec![];
         assert!(verify(&proof_data, &public_assignments, &config).is_err());
     }
+
+    #[test]
+    fn test_verify_under_other_transcript_label() {
+        let config = Config {
+            quiet: true,
+            transcript_label: "application-a".to_string(),
+            ..Config::default()
+        };
+        let circuit = compile("pub z; x*y = z;", &config).unwrap();
+        let assignments =
+            HashMap::from([("x", Fp::from(2)), ("y", Fp::from(3)), ("z", Fp::from(6))]);
+        let public_assignments = HashMap::from([("z", Fp::from(6))]);
+        let mut proof_data = prove(&circuit, &assignments, &config).unwrap();
+        assert!(verify(&proof_data, &public_assignments, &config).is_ok());
+        proof_data.transcript_label = "application-b".to_string();
+        assert!(verify(&proof_data, &public_assignments, &config).is_err());
+    }
 }
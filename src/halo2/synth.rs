@@ -1,10 +1,16 @@
 use ff::PrimeField;
 use group::ff::{Field, FromUniformBytes};
+use group::prime::PrimeCurveAffine;
+use group::{Curve, GroupEncoding};
+use halo2_proofs::arithmetic::{best_fft, CurveExt};
 use halo2_proofs::circuit::{Cell, Layouter, SimpleFloorPlanner, Value};
-use halo2_proofs::pasta::{EqAffine, Fp};
+use halo2_proofs::dev::{FailureLocation, VerifyFailure};
+use halo2_proofs::pasta::{Eq as EqProjective, EqAffine, Fp};
 use halo2_proofs::plonk::*;
 use halo2_proofs::poly::{commitment::Params, Rotation};
-use halo2_proofs::transcript::{Blake2bRead, Blake2bWrite, Challenge255};
+use halo2_proofs::transcript::{
+    Blake2bRead, Blake2bWrite, Challenge255, EncodedChallenge, Transcript,
+};
 use rand_core::OsRng;
 
 use num_bigint::{BigInt, BigUint, Sign, ToBigInt};
@@ -15,8 +21,10 @@ use std::collections::{BTreeMap, HashMap};
 use std::marker::PhantomData;
 use std::rc::Rc;
 
-use crate::ast::{Expr, InfixOp, Module, Pat, TExpr, VariableId};
-use crate::transform::{collect_module_variables, FieldOps};
+use crate::ast::{Expr, InfixOp, Module, VariableId};
+use crate::solver::{solve_witnesses, unsatisfied_equalities};
+use crate::transform::{collect_module_variables, weighted_sum, FieldOps};
+use crate::util::UnsatisfiedEquality;
 
 struct PrimeFieldBincode<T>(Value<T>)
 where
@@ -67,77 +75,11 @@ pub fn make_constant<F: Field + FromUniformBytes<64> + Ord>(c: BigInt) -> F {
     }
 }
 
-/* Evaluate the given expression sourcing any variables from the given maps. */
-fn evaluate_expr<F>(
-    expr: &TExpr,
-    defs: &mut HashMap<VariableId, TExpr>,
-    assigns: &mut HashMap<VariableId, F>,
-) -> F
-where
-    F: ff::FromUniformBytes<64> + std::cmp::Ord,
-{
-    match &expr.v {
-        Expr::Constant(c) => make_constant(c.clone()),
-        Expr::Variable(v) => {
-            if let Some(val) = assigns.get(&v.id) {
-                // First look for existing variable assignment
-                *val
-            } else {
-                // Otherwise compute variable from first principles
-                let val = evaluate_expr(&defs[&v.id].clone(), defs, assigns);
-                assigns.insert(v.id, val);
-                val
-            }
-        }
-        Expr::Negate(e) => -evaluate_expr(e, defs, assigns),
-        Expr::Infix(InfixOp::Add, a, b) => {
-            evaluate_expr(a, defs, assigns) + evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::Subtract, a, b) => {
-            evaluate_expr(a, defs, assigns) - evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::Multiply, a, b) => {
-            evaluate_expr(a, defs, assigns) * evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::Divide, a, b) => {
-            evaluate_expr(a, defs, assigns) * evaluate_expr(b, defs, assigns).invert().unwrap()
-        }
-        Expr::Infix(InfixOp::DivideZ, a, b) => {
-            let divisor = evaluate_expr(b, defs, assigns);
-            if divisor.is_zero().into() {
-                0.into()
-            } else {
-                evaluate_expr(a, defs, assigns) * divisor.invert().unwrap()
-            }
-        }
-        Expr::Infix(InfixOp::IntDivide, a, b) => {
-            let op1 = BigUint::from_bytes_le(evaluate_expr(a, defs, assigns).to_repr().as_ref());
-            let op2 = BigUint::from_bytes_le(evaluate_expr(b, defs, assigns).to_repr().as_ref());
-            let bytes: Vec<u8> = (op1 / op2).to_bytes_le();
-            let mut byte_array = [0u8; 64];
-            let length = bytes.len();
-            let padding = 64 - bytes.len();
-            byte_array[..length].copy_from_slice(&bytes);
-            byte_array[length..length + padding]
-                .iter_mut()
-                .for_each(|x| *x = 0);
-            F::from_uniform_bytes(&byte_array)
-        }
-        Expr::Infix(InfixOp::Modulo, a, b) => {
-            let op1 = BigUint::from_bytes_le(evaluate_expr(a, defs, assigns).to_repr().as_ref());
-            let op2 = BigUint::from_bytes_le(evaluate_expr(b, defs, assigns).to_repr().as_ref());
-            let bytes: Vec<u8> = (op1 % op2).to_bytes_le();
-            let mut byte_array = [0u8; 64];
-            let length = bytes.len();
-            let padding = 64 - bytes.len();
-            byte_array[..length].copy_from_slice(&bytes);
-            byte_array[length..length + padding]
-                .iter_mut()
-                .for_each(|x| *x = 0);
-            F::from_uniform_bytes(&byte_array)
-        }
-        _ => unreachable!("encountered unexpected expression: {}", expr),
-    }
+/* Get the canonical integer representation of the given field element. */
+fn to_int<F: PrimeField>(x: F) -> BigInt {
+    BigUint::from_bytes_le(x.to_repr().as_ref())
+        .to_bigint()
+        .unwrap()
 }
 
 #[derive(Default)]
@@ -208,6 +150,9 @@ where
                 .to_bigint()
                 .unwrap()
             }
+            InfixOp::Less => BigInt::from(u8::from(self.canonical(a) < self.canonical(b))),
+            InfixOp::LessEqual => BigInt::from(u8::from(self.canonical(a) <= self.canonical(b))),
+            InfixOp::NotEqual => BigInt::from(u8::from(c != d)),
             InfixOp::Equal => panic!("cannot evaluate equals expression"),
         }
     }
@@ -309,8 +254,27 @@ where
     }
 }
 
+/* A gate of the standard PLONK configuration: a*sl + b*sr + a*b*sm + c*so +
+ * sc = 0. The wires carry the given variables, with None standing for a cell
+ * that is copied from a zero. */
+#[derive(Clone, Debug)]
+pub struct Halo2Gate<F> {
+    // Position in module.exprs of the constraint that produced this gate
+    pub constraint: usize,
+    pub a: Option<VariableId>,
+    pub b: Option<VariableId>,
+    pub c: Option<VariableId>,
+    pub sl: F,
+    pub sr: F,
+    pub so: F,
+    pub sm: F,
+    pub sc: F,
+}
+
 struct StandardPlonk<F: Field> {
     config: PlonkConfig,
+    // Position in module.exprs of the constraint currently being laid out
+    constraint: std::cell::Cell<Option<usize>>,
     _marker: PhantomData<F>,
 }
 
@@ -318,9 +282,45 @@ impl<FF: Field> StandardPlonk<FF> {
     fn new(config: PlonkConfig) -> Self {
         StandardPlonk {
             config,
+            constraint: std::cell::Cell::new(None),
             _marker: PhantomData,
         }
     }
+
+    /* Name the regions of gates after the constraint that produced them so
+     * that circuit failures can be traced back to it. */
+    fn region_name(&self) -> String {
+        match self.constraint.get() {
+            Some(index) => format!("{CONSTRAINT_REGION}{index}"),
+            None => "raw_poly".to_string(),
+        }
+    }
+}
+
+// Prefix of the names of the regions that contain the gate of a constraint
+const CONSTRAINT_REGION: &str = "constraint ";
+
+/* Recover the position in module.exprs of the constraint that caused the given
+ * failure from the name of the region that the failure is located in. */
+pub fn failed_constraint(failure: &VerifyFailure) -> Option<usize> {
+    let region = match failure {
+        VerifyFailure::CellNotAssigned { region, .. }
+        | VerifyFailure::InstanceCellNotAssigned { region, .. } => region,
+        VerifyFailure::ConstraintNotSatisfied { location, .. }
+        | VerifyFailure::Lookup { location, .. }
+        | VerifyFailure::Permutation { location, .. } => match location {
+            FailureLocation::InRegion { region, .. } => region,
+            FailureLocation::OutsideRegion { .. } => return None,
+        },
+        VerifyFailure::ConstraintPoisoned { .. } => return None,
+    };
+    // The fields of a region are private, but it is displayed as Region i ('name')
+    let region = region.to_string();
+    let (_, name) = region.split_once(" ('")?;
+    name.strip_suffix("')")?
+        .strip_prefix(CONSTRAINT_REGION)?
+        .parse()
+        .ok()
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -429,7 +429,7 @@ impl<FF: Field> StandardCs<FF> for StandardPlonk<FF> {
         F: FnMut() -> PolyGate<Assigned<FF>>,
     {
         layouter.assign_region(
-            || "raw_poly",
+            || self.region_name(),
             |mut region| {
                 let value = f();
                 let lhs = region.assign_advice(|| "lhs", self.config.a, 0, || value.a)?;
@@ -488,243 +488,109 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Halo2Module<F> {
         }
     }
 
-    /* Populate input and auxiliary variables from the given program inputs. */
-    pub fn populate_variables(&mut self, mut field_assigns: HashMap<VariableId, F>) {
-        // Get the definitions necessary to populate auxiliary variables
-        let mut definitions = HashMap::new();
-        for def in &self.module.defs {
-            if let Pat::Variable(var) = &def.0 .0.v {
-                definitions.insert(var.id, *def.0 .1.clone());
-            }
-        }
-        // Start deriving witnesses
-        for (var, value) in &mut self.variable_map {
-            let var_expr = Expr::Variable(crate::ast::Variable::new(*var)).type_expr(None);
-            *value = Value::known(evaluate_expr(
-                &var_expr,
-                &mut definitions,
-                &mut field_assigns,
-            ));
-        }
+    /* Make a copy of this circuit that keeps only the public inputs and the
+     * constraints. This is all that key generation needs, so the witness
+     * definitions need not be shipped to verifiers. */
+    pub fn without_definitions(&self) -> Self {
+        Self::new(Rc::new(Module {
+            pubs: self.module.pubs.clone(),
+            defs: vec![],
+            exprs: self.module.exprs.clone(),
+        }))
     }
 
-    fn make_gate(
-        &self,
-        a: Option<VariableId>,
-        b: Option<VariableId>,
-        c: Option<VariableId>,
-        sl: F,
-        sr: F,
-        so: F,
-        sm: F,
-        sc: F,
-        cell0: Cell,
-        inputs: &mut BTreeMap<VariableId, Cell>,
-        cs: &impl StandardCs<F>,
-        layouter: &mut impl Layouter<F>,
-    ) -> Result<(), Error> {
-        let (c1, c2, c3) = cs.raw_poly(layouter, || {
-            let a: Value<Assigned<_>> = a
-                .map(|v1| self.variable_map[&v1])
-                .unwrap_or(Value::known(F::ZERO))
-                .into();
-            let b: Value<Assigned<_>> = b
-                .map(|v2| self.variable_map[&v2])
-                .unwrap_or(Value::known(F::ZERO))
-                .into();
-            let c: Value<Assigned<_>> = c
-                .map(|v3| self.variable_map[&v3])
-                .unwrap_or(Value::known(F::ZERO))
-                .into();
-            PolyGate {
-                a,
-                b,
-                c,
-                q_l: sl.into(),
-                q_r: sr.into(),
-                q_o: so.into(),
-                q_m: sm.into(),
-                q_c: sc.into(),
-            }
-        })?;
-        if let Some(v1) = a {
-            copy_variable(v1, c1, inputs, cs, layouter)?;
-        } else {
-            cs.copy(layouter, c1, cell0)?;
-        }
-        if let Some(v2) = b {
-            copy_variable(v2, c2, inputs, cs, layouter)?;
-        } else {
-            cs.copy(layouter, c2, cell0)?;
-        }
-        if let Some(v3) = c {
-            copy_variable(v3, c3, inputs, cs, layouter)?;
-        } else {
-            cs.copy(layouter, c3, cell0)?;
+    /* Populate input and auxiliary variables from the given program inputs. */
+    pub fn populate_variables(
+        &mut self,
+        field_assigns: HashMap<VariableId, F>,
+    ) -> Result<(), crate::error::Error> {
+        let values = self.solve_variables(field_assigns)?;
+        for (var, value) in &mut self.variable_map {
+            *value = Value::known(values[var]);
         }
         Ok(())
     }
-}
 
-fn copy_variable<F: Field>(
-    var: VariableId,
-    cell: Cell,
-    map: &mut BTreeMap<VariableId, Cell>,
-    cs: &impl StandardCs<F>,
-    layouter: &mut impl Layouter<F>,
-) -> Result<(), Error> {
-    match map.entry(var) {
-        Entry::Vacant(vac) => {
-            vac.insert(cell);
-        }
-        Entry::Occupied(occ) => cs.copy(layouter, cell, *occ.get())?,
-    }
-    Ok(())
-}
-
-impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F> {
-    type Config = PlonkConfig;
-    type FloorPlanner = SimpleFloorPlanner;
-
-    fn without_witnesses(&self) -> Self {
-        let mut variable_map = self.variable_map.clone();
-        for val in variable_map.values_mut() {
-            *val = Value::unknown();
-        }
-        Self {
-            variable_map,
-            module: self.module.clone(),
-            k: self.k,
-        }
+    /* Derive the values of all the variables in this module from the given
+     * program inputs. */
+    pub fn solve_variables(
+        &self,
+        field_assigns: HashMap<VariableId, F>,
+    ) -> Result<HashMap<VariableId, F>, crate::error::Error> {
+        let int_assigns = field_assigns
+            .into_iter()
+            .map(|(id, val)| (id, to_int(val)))
+            .collect();
+        let values = solve_witnesses(&self.module, int_assigns, &PrimeFieldOps::<F>::default())?;
+        Ok(values
+            .into_iter()
+            .map(|(id, val)| (id, make_constant(val)))
+            .collect())
     }
 
-    fn configure(meta: &mut ConstraintSystem<F>) -> PlonkConfig {
-        meta.set_minimum_degree(5);
-
-        let a = meta.advice_column();
-        let b = meta.advice_column();
-        let c = meta.advice_column();
-        let p = meta.instance_column();
-
-        meta.enable_equality(a);
-        meta.enable_equality(b);
-        meta.enable_equality(c);
-
-        let sm = meta.fixed_column();
-        let sl = meta.fixed_column();
-        let sr = meta.fixed_column();
-        let so = meta.fixed_column();
-        let sc = meta.fixed_column();
-        let sp = meta.fixed_column();
-
-        meta.create_gate("Combined add-mult", |meta| {
-            let a = meta.query_advice(a, Rotation::cur());
-            let b = meta.query_advice(b, Rotation::cur());
-            let c = meta.query_advice(c, Rotation::cur());
-
-            let sl = meta.query_fixed(sl);
-            let sr = meta.query_fixed(sr);
-            let so = meta.query_fixed(so);
-            let sm = meta.query_fixed(sm);
-            let sc = meta.query_fixed(sc);
-
-            vec![a.clone() * sl + b.clone() * sr + a * b * sm + (c * so) + sc]
-        });
-
-        meta.create_gate("Public input", |meta| {
-            let a = meta.query_advice(a, Rotation::cur());
-            let p = meta.query_instance(p, Rotation::cur());
-            let sp = meta.query_fixed(sp);
-
-            vec![sp * (a - p)]
-        });
-
-        PlonkConfig {
-            a,
-            b,
-            c,
-            sl,
-            sr,
-            so,
-            sm,
-            sc,
-            sp,
-        }
+    /* Populate input and auxiliary variables from the given program inputs
+     * and report every equality that does not hold under them. */
+    pub fn check_variables(
+        &self,
+        field_assigns: HashMap<VariableId, F>,
+    ) -> Result<Vec<UnsatisfiedEquality>, crate::error::Error> {
+        let int_assigns = field_assigns
+            .into_iter()
+            .map(|(id, val)| (id, to_int(val)))
+            .collect();
+        let field_ops = PrimeFieldOps::<F>::default();
+        let values = solve_witnesses(&self.module, int_assigns, &field_ops)?;
+        Ok(unsatisfied_equalities(&self.module, &values, &field_ops))
     }
 
-    fn synthesize(&self, config: PlonkConfig, mut layouter: impl Layouter<F>) -> Result<(), Error> {
-        let cs = StandardPlonk::new(config);
-
-        let mut inputs = BTreeMap::new();
-
-        for pi in &self.module.pubs {
-            let cell_pi = cs.public_input(&mut layouter, || self.variable_map[&pi.id])?;
-            copy_variable(pi.id, cell_pi, &mut inputs, &cs, &mut layouter)?;
-        }
-
-        let val1: Assigned<_> = Assigned::from(F::ONE);
-        let val0: Assigned<_> = Assigned::from(F::ZERO);
-        let (_, cell0, _) = cs.raw_poly(&mut layouter, || PolyGate {
-            a: Value::known(val0),
-            b: Value::known(val0),
-            c: Value::known(val0),
-            q_l: val0,
-            q_r: val1,
-            q_o: val0,
-            q_m: val0,
-            q_c: val0,
-        })?;
-
-        for expr in &self.module.exprs {
+    /* Lay the constraints of this module out as gates of the standard PLONK
+     * configuration, one gate per constraint. */
+    pub fn gates(&self) -> Vec<Halo2Gate<F>> {
+        let mut gates = vec![];
+        for (index, expr) in self.module.exprs.iter().enumerate() {
             if let Expr::Infix(InfixOp::Equal, lhs, rhs) = &expr.v {
                 match (&lhs.v, &rhs.v) {
                     // Variables on the LHS
                     // v1 = v2
                     (Expr::Variable(v1), Expr::Variable(v2)) => {
-                        self.make_gate(
-                            Some(v1.id),
-                            Some(v2.id),
-                            None,
-                            F::ONE,
-                            -F::ONE,
-                            F::ZERO,
-                            F::ZERO,
-                            F::ZERO,
-                            cell0,
-                            &mut inputs,
-                            &cs,
-                            &mut layouter,
-                        )?;
+                        gates.push(Halo2Gate {
+                            constraint: index,
+                            a: Some(v1.id),
+                            b: Some(v2.id),
+                            c: None,
+                            sl: F::ONE,
+                            sr: -F::ONE,
+                            so: F::ZERO,
+                            sm: F::ZERO,
+                            sc: F::ZERO,
+                        });
                     }
                     // v1 = c2
                     (Expr::Variable(v1), Expr::Constant(c2)) => {
                         let op2: F = make_constant::<F>(c2.clone());
-                        self.make_gate(
-                            Some(v1.id),
-                            None,
-                            None,
-                            F::ONE,
-                            F::ZERO,
-                            F::ZERO,
-                            F::ZERO,
-                            -op2,
-                            cell0,
-                            &mut inputs,
-                            &cs,
-                            &mut layouter,
-                        )?;
+                        gates.push(Halo2Gate {
+                            constraint: index,
+                            a: Some(v1.id),
+                            b: None,
+                            c: None,
+                            sl: F::ONE,
+                            sr: F::ZERO,
+                            so: F::ZERO,
+                            sm: F::ZERO,
+                            sc: -op2,
+                        });
                     }
                     // v1 = -c2
                     (Expr::Variable(v1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Constant(c2) if {
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(Some(v1.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op2 });
                             true
                         }) => {}
                     // v1 = -v2
                     (Expr::Variable(v1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Variable(v2) if {
-                            self.make_gate(Some(v1.id), Some(v2.id), None, F::ONE, F::ONE, F::ZERO, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: None, sl: F::ONE, sr: F::ONE, so: F::ZERO, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = c2 + c3
@@ -735,7 +601,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op2: F = make_constant::<F>(c2.clone());
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(Some(v1.id), None, None, F::ONE, F::ONE, F::ZERO, F::ZERO, -op2-op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: None, c: None, sl: F::ONE, sr: F::ONE, so: F::ZERO, sm: F::ZERO, sc: -op2-op3 });
                             true
                         }) => {}
                     // v1 = v2 + c3
@@ -745,7 +611,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Constant(c3),
                         ) if {
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(Some(v1.id), Some(v2.id), None, F::ONE, -F::ONE, F::ZERO, F::ZERO, -op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: None, sl: F::ONE, sr: -F::ONE, so: F::ZERO, sm: F::ZERO, sc: -op3 });
                             true
                         }) => {}
                     // v1 = c2 + v3
@@ -755,7 +621,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(Some(v1.id), Some(v3.id), None, F::ONE, -F::ONE, F::ZERO, F::ZERO, -op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v3.id), c: None, sl: F::ONE, sr: -F::ONE, so: F::ZERO, sm: F::ZERO, sc: -op2 });
                             true
                         }) => {}
                     // v1 = v2 + v3
@@ -764,7 +630,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            self.make_gate(Some(v1.id), Some(v2.id), Some(v3.id), F::ONE, -F::ONE, -F::ONE, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: Some(v3.id), sl: F::ONE, sr: -F::ONE, so: -F::ONE, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = c2 - c3
@@ -775,7 +641,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op2: F = make_constant::<F>(c2.clone());
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(Some(v1.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, op3-op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op3-op2 });
                             true
                         }) => {}
                     // v1 = v2 - c3
@@ -785,7 +651,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Constant(c3),
                         ) if {
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(Some(v1.id), Some(v2.id), None, F::ONE, -F::ONE, F::ZERO, F::ZERO, op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: None, sl: F::ONE, sr: -F::ONE, so: F::ZERO, sm: F::ZERO, sc: op3 });
                             true
                         }) => {}
                     // v1 = c2 - v3
@@ -795,7 +661,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(Some(v1.id), Some(v3.id), None, F::ONE, F::ONE, F::ZERO, F::ZERO, -op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v3.id), c: None, sl: F::ONE, sr: F::ONE, so: F::ZERO, sm: F::ZERO, sc: -op2 });
                             true
                         }) => {}
                     // v1 = v2 - v3
@@ -804,7 +670,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            self.make_gate(Some(v1.id), Some(v2.id), Some(v3.id), F::ONE, -F::ONE, F::ONE, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: Some(v3.id), sl: F::ONE, sr: -F::ONE, so: F::ONE, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = c2 / c3
@@ -815,7 +681,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant(c2.clone());
                             let op2: F = make_constant(c3.clone());
-                            self.make_gate(Some(v1.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, -(op1*op2.invert().unwrap()), cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -(op1*op2.invert().unwrap()) });
                             true
                         }) => {}
                     // v1 = v2 / c3
@@ -825,7 +691,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Constant(c3),
                         ) if {
                             let op2: F = make_constant(c3.clone());
-                            self.make_gate(Some(v1.id), Some(v2.id), None, F::ONE, -op2.invert().unwrap(), F::ZERO, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: None, sl: F::ONE, sr: -op2.invert().unwrap(), so: F::ZERO, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = c2 / v3 ***
@@ -835,7 +701,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(Some(v1.id), Some(v3.id), None, F::ZERO, F::ZERO, F::ZERO, F::ONE, -op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v3.id), c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ONE, sc: -op2 });
                             true
                         }) => {}
                     // v1 = v2 / v3 ***
@@ -844,7 +710,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            self.make_gate(Some(v1.id), Some(v3.id), Some(v2.id), F::ZERO, F::ZERO, -F::ONE, F::ONE, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v3.id), c: Some(v2.id), sl: F::ZERO, sr: F::ZERO, so: -F::ONE, sm: F::ONE, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = c2 * c3
@@ -855,7 +721,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant(c2.clone());
                             let op2: F = make_constant(c3.clone());
-                            self.make_gate(Some(v1.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, -(op1*op2), cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -(op1*op2) });
                             true
                         }) => {}
                     // v1 = v2 * c3
@@ -865,7 +731,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Constant(c3),
                         ) if {
                             let op2: F = make_constant(c3.clone());
-                            self.make_gate(Some(v1.id), Some(v2.id), None, F::ONE, -op2, F::ZERO, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v2.id), c: None, sl: F::ONE, sr: -op2, so: F::ZERO, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = c2 * v3
@@ -875,7 +741,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op2: F = make_constant(c2.clone());
-                            self.make_gate(Some(v1.id), Some(v3.id), None, F::ONE, -op2, F::ZERO, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v1.id), b: Some(v3.id), c: None, sl: F::ONE, sr: -op2, so: F::ZERO, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // v1 = v2 * v3
@@ -884,60 +750,54 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            self.make_gate(Some(v2.id), Some(v3.id), Some(v1.id), F::ZERO, F::ZERO, F::ONE, -F::ONE, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: Some(v3.id), c: Some(v1.id), sl: F::ZERO, sr: F::ZERO, so: F::ONE, sm: -F::ONE, sc: F::ZERO });
                             true
                         }) => {}
                     // Now for constants on the LHS
                     // c1 = v2
                     (Expr::Constant(c1), Expr::Variable(v2)) => {
                         let op1: F = make_constant::<F>(c1.clone());
-                        self.make_gate(
-                            Some(v2.id),
-                            None,
-                            None,
-                            F::ONE,
-                            F::ZERO,
-                            F::ZERO,
-                            F::ZERO,
-                            -op1,
-                            cell0,
-                            &mut inputs,
-                            &cs,
-                            &mut layouter,
-                        )?;
+                        gates.push(Halo2Gate {
+                            constraint: index,
+                            a: Some(v2.id),
+                            b: None,
+                            c: None,
+                            sl: F::ONE,
+                            sr: F::ZERO,
+                            so: F::ZERO,
+                            sm: F::ZERO,
+                            sc: -op1,
+                        });
                     }
                     // c1 = c2
                     (Expr::Constant(c1), Expr::Constant(c2)) => {
                         let op1: F = make_constant::<F>(c1.clone());
                         let op2: F = make_constant::<F>(c2.clone());
-                        self.make_gate(
-                            None,
-                            None,
-                            None,
-                            F::ZERO,
-                            F::ZERO,
-                            F::ZERO,
-                            F::ZERO,
-                            op1 - op2,
-                            cell0,
-                            &mut inputs,
-                            &cs,
-                            &mut layouter,
-                        )?;
+                        gates.push(Halo2Gate {
+                            constraint: index,
+                            a: None,
+                            b: None,
+                            c: None,
+                            sl: F::ZERO,
+                            sr: F::ZERO,
+                            so: F::ZERO,
+                            sm: F::ZERO,
+                            sc: op1 - op2,
+                        });
                     }
                     // c1 = -c2
                     (Expr::Constant(c1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Constant(c2) if {
                             let op1: F = make_constant::<F>(c1.clone());
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(None, None, None, F::ZERO, F::ZERO, F::ZERO, F::ZERO, op1+op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: None, b: None, c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1+op2 });
                             true
                         }) => {}
                     // c1 = -v2
                     (Expr::Constant(c1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Variable(v2) if {
                             let op1: F = make_constant::<F>(c1.clone());
-                            self.make_gate(Some(v2.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1 });
                             true
                         }) => {}
                     // c1 = c2 + c3
@@ -949,7 +809,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             let op1: F = make_constant::<F>(c1.clone());
                             let op2: F = make_constant::<F>(c2.clone());
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(None, None, None, F::ZERO, F::ZERO, F::ZERO, F::ZERO, op1-op2-op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: None, b: None, c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1-op2-op3 });
                             true
                         }) => {}
                     // c1 = v2 + c3
@@ -960,7 +820,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant::<F>(c1.clone());
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(Some(v2.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, op3-op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op3-op1 });
                             true
                         }) => {}
                     // c1 = c2 + v3
@@ -971,7 +831,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant::<F>(c1.clone());
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(Some(v3.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, op2-op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v3.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op2-op1 });
                             true
                         }) => {}
                     // c1 = v2 + v3
@@ -981,7 +841,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant::<F>(c1.clone());
-                            self.make_gate(Some(v2.id), Some(v3.id), None, F::ONE, F::ONE, F::ZERO, F::ZERO, -op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: Some(v3.id), c: None, sl: F::ONE, sr: F::ONE, so: F::ZERO, sm: F::ZERO, sc: -op1 });
                             true
                         }) => {}
                     // c1 = c2 - c3
@@ -993,7 +853,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             let op1: F = make_constant::<F>(c1.clone());
                             let op2: F = make_constant::<F>(c2.clone());
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(None, None, None, F::ZERO, F::ZERO, F::ZERO, F::ZERO, op1-op2+op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: None, b: None, c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1-op2+op3 });
                             true
                         }) => {}
                     // c1 = v2 - c3
@@ -1004,7 +864,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant::<F>(c1.clone());
                             let op3: F = make_constant::<F>(c3.clone());
-                            self.make_gate(Some(v2.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, -op1-op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -op1-op3 });
                             true
                         }) => {}
                     // c1 = c2 - v3
@@ -1015,7 +875,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant::<F>(c1.clone());
                             let op2: F = make_constant::<F>(c2.clone());
-                            self.make_gate(Some(v3.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, op1-op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v3.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1-op2 });
                             true
                         }) => {}
                     // c1 = v2 - v3
@@ -1025,7 +885,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant::<F>(c1.clone());
-                            self.make_gate(Some(v2.id), Some(v3.id), None, F::ONE, -F::ONE, F::ZERO, F::ZERO, -op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: Some(v3.id), c: None, sl: F::ONE, sr: -F::ONE, so: F::ZERO, sm: F::ZERO, sc: -op1 });
                             true
                         }) => {}
                     // c1 = c2 / c3
@@ -1037,7 +897,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             let op1: F = make_constant(c1.clone());
                             let op2: F = make_constant(c2.clone());
                             let op3: F = make_constant(c3.clone());
-                            self.make_gate(None, None, None, F::ZERO, F::ZERO, F::ZERO, F::ZERO, op1*op3-op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: None, b: None, c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1*op3-op2 });
                             true
                         }) => {}
                     // c1 = v2 / c3
@@ -1048,7 +908,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant(c1.clone());
                             let op3: F = make_constant(c3.clone());
-                            self.make_gate(Some(v2.id), None, None, F::ONE, F::ZERO, F::ZERO, F::ZERO, -op1*op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: None, c: None, sl: F::ONE, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -op1*op3 });
                             true
                         }) => {}
                     // c1 = c2 / v3 ***
@@ -1059,7 +919,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant(c1.clone());
                             let op2: F = make_constant(c2.clone());
-                            self.make_gate(Some(v3.id), None, None, op1, F::ZERO, F::ZERO, F::ZERO, -op2, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v3.id), b: None, c: None, sl: op1, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -op2 });
                             true
                         }) => {}
                     // c1 = v2 / v3 ***
@@ -1069,7 +929,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c1.clone());
-                            self.make_gate(Some(v2.id), Some(v3.id), None, F::ONE, -op1, F::ZERO, F::ZERO, F::ZERO, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: Some(v3.id), c: None, sl: F::ONE, sr: -op1, so: F::ZERO, sm: F::ZERO, sc: F::ZERO });
                             true
                         }) => {}
                     // c1 = c2 * c3
@@ -1081,7 +941,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             let op1: F = make_constant(c1.clone());
                             let op2: F = make_constant(c2.clone());
                             let op3: F = make_constant(c3.clone());
-                            self.make_gate(None, None, None, F::ZERO, F::ZERO, F::ZERO, F::ZERO, op1-op2*op3, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: None, b: None, c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: op1-op2*op3 });
                             true
                         }) => {}
                     // c1 = v2 * c3
@@ -1092,7 +952,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant(c1.clone());
                             let op3: F = make_constant(c3.clone());
-                            self.make_gate(Some(v2.id), None, None, op3, F::ZERO, F::ZERO, F::ZERO, -op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: None, c: None, sl: op3, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -op1 });
                             true
                         }) => {}
                     // c1 = c2 * v3
@@ -1103,7 +963,7 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                         ) if {
                             let op1: F = make_constant(c1.clone());
                             let op2: F = make_constant(c2.clone());
-                            self.make_gate(Some(v3.id), None, None, op2, F::ZERO, F::ZERO, F::ZERO, -op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v3.id), b: None, c: None, sl: op2, sr: F::ZERO, so: F::ZERO, sm: F::ZERO, sc: -op1 });
                             true
                         }) => {}
                     // c1 = v2 * v3
@@ -1113,18 +973,279 @@ impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F>
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c1.clone());
-                            self.make_gate(Some(v2.id), Some(v3.id), None, F::ZERO, F::ZERO, F::ZERO, F::ONE, -op1, cell0, &mut inputs, &cs, &mut layouter)?;
+                            gates.push(Halo2Gate { constraint: index, a: Some(v2.id), b: Some(v3.id), c: None, sl: F::ZERO, sr: F::ZERO, so: F::ZERO, sm: F::ONE, sc: -op1 });
                             true
                         }) => {}
+                    // v1 = c2*v2 + c3*v3 + c0, as made by folding linear combinations
+                    (Expr::Variable(v1), _)
+                        if matches!(
+                            weighted_sum(rhs).filter(|(terms, _)| terms.len() < 3),
+                            Some((terms, c0)) if {
+                                let weight = |idx: usize| match terms.get(idx) {
+                                    Some((c, var)) => (Some(var.id), make_constant::<F>(c.clone())),
+                                    None => (None, F::ZERO),
+                                };
+                                let ((b, c2), (c, c3)) = (weight(0), weight(1));
+                                gates.push(Halo2Gate {
+                                    constraint: index,
+                                    a: Some(v1.id),
+                                    b,
+                                    c,
+                                    sl: F::ONE,
+                                    sr: -c2,
+                                    so: -c3,
+                                    sm: F::ZERO,
+                                    sc: -make_constant::<F>(c0.clone()),
+                                });
+                                true
+                            }
+                        ) => {}
                     _ => panic!("unsupported constraint encountered: {expr}"),
                 }
             }
         }
+        gates
+    }
 
+    fn make_gate(
+        &self,
+        a: Option<VariableId>,
+        b: Option<VariableId>,
+        c: Option<VariableId>,
+        sl: F,
+        sr: F,
+        so: F,
+        sm: F,
+        sc: F,
+        cell0: Cell,
+        inputs: &mut BTreeMap<VariableId, Cell>,
+        cs: &impl StandardCs<F>,
+        layouter: &mut impl Layouter<F>,
+    ) -> Result<(), Error> {
+        let (c1, c2, c3) = cs.raw_poly(layouter, || {
+            let a: Value<Assigned<_>> = a
+                .map(|v1| self.variable_map[&v1])
+                .unwrap_or(Value::known(F::ZERO))
+                .into();
+            let b: Value<Assigned<_>> = b
+                .map(|v2| self.variable_map[&v2])
+                .unwrap_or(Value::known(F::ZERO))
+                .into();
+            let c: Value<Assigned<_>> = c
+                .map(|v3| self.variable_map[&v3])
+                .unwrap_or(Value::known(F::ZERO))
+                .into();
+            PolyGate {
+                a,
+                b,
+                c,
+                q_l: sl.into(),
+                q_r: sr.into(),
+                q_o: so.into(),
+                q_m: sm.into(),
+                q_c: sc.into(),
+            }
+        })?;
+        if let Some(v1) = a {
+            copy_variable(v1, c1, inputs, cs, layouter)?;
+        } else {
+            cs.copy(layouter, c1, cell0)?;
+        }
+        if let Some(v2) = b {
+            copy_variable(v2, c2, inputs, cs, layouter)?;
+        } else {
+            cs.copy(layouter, c2, cell0)?;
+        }
+        if let Some(v3) = c {
+            copy_variable(v3, c3, inputs, cs, layouter)?;
+        } else {
+            cs.copy(layouter, c3, cell0)?;
+        }
         Ok(())
     }
 }
 
+fn copy_variable<F: Field>(
+    var: VariableId,
+    cell: Cell,
+    map: &mut BTreeMap<VariableId, Cell>,
+    cs: &impl StandardCs<F>,
+    layouter: &mut impl Layouter<F>,
+) -> Result<(), Error> {
+    match map.entry(var) {
+        Entry::Vacant(vac) => {
+            vac.insert(cell);
+        }
+        Entry::Occupied(occ) => cs.copy(layouter, cell, *occ.get())?,
+    }
+    Ok(())
+}
+
+impl<F: ff::FromUniformBytes<64> + std::cmp::Ord> Circuit<F> for Halo2Module<F> {
+    type Config = PlonkConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self {
+        let mut variable_map = self.variable_map.clone();
+        for val in variable_map.values_mut() {
+            *val = Value::unknown();
+        }
+        Self {
+            variable_map,
+            module: self.module.clone(),
+            k: self.k,
+        }
+    }
+
+    fn configure(meta: &mut ConstraintSystem<F>) -> PlonkConfig {
+        meta.set_minimum_degree(5);
+
+        let a = meta.advice_column();
+        let b = meta.advice_column();
+        let c = meta.advice_column();
+        let p = meta.instance_column();
+
+        meta.enable_equality(a);
+        meta.enable_equality(b);
+        meta.enable_equality(c);
+
+        let sm = meta.fixed_column();
+        let sl = meta.fixed_column();
+        let sr = meta.fixed_column();
+        let so = meta.fixed_column();
+        let sc = meta.fixed_column();
+        let sp = meta.fixed_column();
+
+        meta.create_gate("Combined add-mult", |meta| {
+            let a = meta.query_advice(a, Rotation::cur());
+            let b = meta.query_advice(b, Rotation::cur());
+            let c = meta.query_advice(c, Rotation::cur());
+
+            let sl = meta.query_fixed(sl);
+            let sr = meta.query_fixed(sr);
+            let so = meta.query_fixed(so);
+            let sm = meta.query_fixed(sm);
+            let sc = meta.query_fixed(sc);
+
+            vec![a.clone() * sl + b.clone() * sr + a * b * sm + (c * so) + sc]
+        });
+
+        meta.create_gate("Public input", |meta| {
+            let a = meta.query_advice(a, Rotation::cur());
+            let p = meta.query_instance(p, Rotation::cur());
+            let sp = meta.query_fixed(sp);
+
+            vec![sp * (a - p)]
+        });
+
+        PlonkConfig {
+            a,
+            b,
+            c,
+            sl,
+            sr,
+            so,
+            sm,
+            sc,
+            sp,
+        }
+    }
+
+    fn synthesize(&self, config: PlonkConfig, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        let cs = StandardPlonk::new(config);
+
+        let mut inputs = BTreeMap::new();
+
+        for pi in &self.module.pubs {
+            let cell_pi = cs.public_input(&mut layouter, || self.variable_map[&pi.id])?;
+            copy_variable(pi.id, cell_pi, &mut inputs, &cs, &mut layouter)?;
+        }
+
+        let val1: Assigned<_> = Assigned::from(F::ONE);
+        let val0: Assigned<_> = Assigned::from(F::ZERO);
+        let (_, cell0, _) = cs.raw_poly(&mut layouter, || PolyGate {
+            a: Value::known(val0),
+            b: Value::known(val0),
+            c: Value::known(val0),
+            q_l: val0,
+            q_r: val1,
+            q_o: val0,
+            q_m: val0,
+            q_c: val0,
+        })?;
+
+        for gate in self.gates() {
+            cs.constraint.set(Some(gate.constraint));
+            self.make_gate(
+                gate.a,
+                gate.b,
+                gate.c,
+                gate.sl,
+                gate.sr,
+                gate.so,
+                gate.sm,
+                gate.sc,
+                cell0,
+                &mut inputs,
+                &cs,
+                &mut layouter,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/* The base 2 logarithm of the number of rows that the given parameters
+ * support. */
+pub fn params_k(params: &Params<EqAffine>) -> u32 {
+    params.get_g().len().trailing_zeros()
+}
+
+/* Shrink the given parameters to ones for circuits with 2^k rows. All
+ * parameters are derived from the same sequence of generators, so the smaller
+ * commitment key is a prefix of the larger one and only its Lagrange basis has
+ * to be recomputed. */
+pub fn downsize_params(
+    params: &Params<EqAffine>,
+    k: u32,
+) -> Result<Params<EqAffine>, crate::error::Error> {
+    let params_k = params_k(params);
+    if params_k < k {
+        return Err(crate::error::Error::InsufficientParams {
+            k: params_k,
+            required: k,
+        });
+    }
+    let n = 1usize << k;
+    let g: Vec<EqAffine> = params.get_g().into_iter().take(n).collect();
+    // Evaluate the Lagrange basis polynomials over the smaller domain using an
+    // inverse FFT in the scalar field of EqAffine, as is done when generating
+    // parameters
+    let mut alpha_inv = Fp::ROOT_OF_UNITY_INV;
+    for _ in k..Fp::S {
+        alpha_inv = alpha_inv.square();
+    }
+    let mut g_lagrange_projective: Vec<EqProjective> = g.iter().map(|g| g.to_curve()).collect();
+    best_fft(&mut g_lagrange_projective, alpha_inv, k);
+    let minv = Fp::TWO_INV.pow_vartime([k as u64]);
+    for g in &mut g_lagrange_projective {
+        *g *= minv;
+    }
+    let mut g_lagrange = vec![EqAffine::identity(); n];
+    EqProjective::batch_normalize(&g_lagrange_projective, &mut g_lagrange);
+    let hasher = EqProjective::hash_to_curve("Halo2-Parameters");
+    let w = hasher(&[1]).to_affine();
+    let u = hasher(&[2]).to_affine();
+    // Parameters can only be constructed by deserializing them
+    let mut bytes = k.to_le_bytes().to_vec();
+    for point in g.iter().chain(&g_lagrange).chain([&w, &u]) {
+        bytes.extend_from_slice(point.to_bytes().as_ref());
+    }
+    Params::read(&mut bytes.as_slice())
+        .map_err(|e| crate::error::Error::BackendError { e: e.to_string() })
+}
+
 pub fn keygen(
     circuit: &Halo2Module<Fp>,
     params: &Params<EqAffine>,
@@ -1135,14 +1256,43 @@ pub fn keygen(
     Ok((pk, vk_return))
 }
 
+/* Digest the pinned form of the given verifying key, which covers its domain,
+ * constraint system, fixed commitments and permutation. */
+pub fn verifying_key_digest(vk: &VerifyingKey<EqAffine>) -> Result<Fp, Error> {
+    let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+    vk.hash_into(&mut transcript).map_err(Error::Transcript)?;
+    Ok(transcript.squeeze_challenge().get_scalar())
+}
+
+/* Bind the given transcript to the given label so that a proof made under one
+ * label does not verify under another. The label is absorbed as its length
+ * followed by its 31-byte chunks, each of which is smaller than the modulus. */
+fn absorb_label<T>(transcript: &mut T, label: &[u8]) -> Result<(), Error>
+where
+    T: Transcript<EqAffine, Challenge255<EqAffine>>,
+{
+    transcript
+        .common_scalar(Fp::from(label.len() as u64))
+        .map_err(Error::Transcript)?;
+    for chunk in label.chunks(31) {
+        let mut bytes = [0u8; 64];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        transcript
+            .common_scalar(Fp::from_uniform_bytes(&bytes))
+            .map_err(Error::Transcript)?;
+    }
+    Ok(())
+}
 pub fn prover(
     circuit: Halo2Module<Fp>,
     params: &Params<EqAffine>,
     pk: &ProvingKey<EqAffine>,
     instances: &[Fp],
+    label: &[u8],
 ) -> Result<Vec<u8>, Error> {
     let rng = OsRng;
     let mut transcript = Blake2bWrite::<_, _, Challenge255<_>>::init(vec![]);
+    absorb_label(&mut transcript, label)?;
     create_proof(
         params,
         pk,
@@ -1159,8 +1309,10 @@ pub fn verifier(
     vk: &VerifyingKey<EqAffine>,
     proof: &[u8],
     instances: &[Fp],
+    label: &[u8],
 ) -> Result<(), Error> {
     let strategy = SingleVerifier::new(params);
     let mut transcript = Blake2bRead::<_, _, Challenge255<_>>::init(proof);
+    absorb_label(&mut transcript, label)?;
     verify_proof(params, vk, strategy, &[&[instances]], &mut transcript)
 }
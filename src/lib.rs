@@ -1,9 +1,14 @@
 pub mod ast;
+pub mod check;
 pub mod error;
+pub mod export;
 pub mod file_gen;
 pub mod halo2;
 pub mod plonk;
+pub mod proof_system;
 pub mod repl;
+pub mod solver;
+pub mod stats;
 pub mod transform;
 mod typecheck;
 pub mod util;
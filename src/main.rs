@@ -1,12 +1,17 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Parser, Subcommand};
+use vamp_ir::check::cli::{check, Check};
 use vamp_ir::error::Error;
+use vamp_ir::export::cli::{export, ExportCommands};
 use vamp_ir::file_gen::cli::{generate, GenerateCommands};
 use vamp_ir::halo2::cli::{halo2, Halo2Commands};
 use vamp_ir::plonk::cli::{plonk, PlonkCommands};
+use vamp_ir::proof_system::cli::{proof_system, ProofSystemCommands};
 use vamp_ir::repl::cli::{repl, REPL};
-use vamp_ir::util::Config;
+use vamp_ir::stats::cli::{stats, Stats};
+use vamp_ir::util::{Config, DEFAULT_BIT_WIDTH, DEFAULT_TRANSCRIPT_LABEL};
 
 const VERIF_FAILURE_CODE: i32 = 1;
+const ERROR_CODE: i32 = 2;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -16,6 +21,14 @@ struct Cli {
 
     #[clap(short, long, default_value = "false")]
     quiet: bool,
+
+    /// Number of bits that the operands of < and <= are assumed to fit into
+    #[clap(long, default_value_t = DEFAULT_BIT_WIDTH)]
+    bit_width: usize,
+
+    /// Label that separates the Fiat-Shamir transcripts of compiled circuits
+    #[clap(long, default_value = DEFAULT_TRANSCRIPT_LABEL)]
+    transcript_label: String,
 }
 
 #[derive(Subcommand)]
@@ -27,30 +40,44 @@ enum Backend {
     #[command(subcommand)]
     Halo2(Halo2Commands),
     REPL(REPL),
-}
-
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-enum ProofSystems {
-    /// PLONK general-purpose zero-knowledge proof scheme
-    Plonk,
-    /// Halo 2 zero-knowledge proving system
-    Halo2,
+    Check(Check),
+    Stats(Stats),
+    #[command(subcommand)]
+    Export(ExportCommands),
+    #[command(flatten)]
+    ProofSystem(ProofSystemCommands),
 }
 
 /* Main entry point for vamp-ir compiler, prover, and verifier. */
 fn main() {
     let cli = Cli::parse();
-    let config = Config { quiet: cli.quiet };
+    let config = Config {
+        quiet: cli.quiet,
+        bit_width: cli.bit_width,
+        transcript_label: cli.transcript_label.clone(),
+    };
 
     let res = match &cli.backend {
         Backend::Generate(generate_commands) => generate(generate_commands, &config),
         Backend::Plonk(plonk_commands) => plonk(plonk_commands, &config),
         Backend::Halo2(halo2_commands) => halo2(halo2_commands, &config),
         Backend::REPL(repl_commands) => repl(repl_commands),
+        Backend::Check(check_args) => check(check_args, &config),
+        Backend::Stats(stats_args) => stats(stats_args, &config),
+        Backend::Export(export_commands) => export(export_commands, &config),
+        Backend::ProofSystem(commands) => proof_system(commands, &config),
     };
 
     match res {
         Err(Error::ProofVerificationFailure) => std::process::exit(VERIF_FAILURE_CODE),
-        _ => res.unwrap(),
+        Err(err @ (Error::UnsatisfiedEqualities { .. } | Error::PublicInputMismatch { .. })) => {
+            eprintln!("error: {err}");
+            std::process::exit(VERIF_FAILURE_CODE)
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(ERROR_CODE)
+        }
+        Ok(()) => {}
     }
 }
@@ -0,0 +1,709 @@
+use crate::ast::{Module, Variable, VariableId};
+use crate::error::Error;
+use crate::error::Error::ProofVerificationFailure;
+use crate::plonk::synth::{annotate_public_inputs, make_constant, PlonkModule, PrimeFieldOps};
+use crate::proof_system::{artifact_error, ProofSystem};
+use crate::qprintln;
+use crate::util::{get_circuit_assignments, Config};
+
+use ark_bls12_381::{Bls12_381, Fr as BlsScalar};
+use ark_ec::PairingEngine;
+use ark_ed_on_bls12_381::EdwardsParameters as JubJubParameters;
+use ark_poly::polynomial::univariate::DensePolynomial;
+use ark_poly_commit::{sonic_pc::SonicKZG10, PCUniversalParams, PolynomialCommitment};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError};
+use plonk::error::to_pc_error;
+use plonk_core::circuit::Circuit;
+use plonk_core::proof_system::pi::PublicInputs;
+use plonk_core::proof_system::{Proof, ProverKey, Verifier, VerifierKey};
+
+use bincode::error::{DecodeError, EncodeError};
+use num_bigint::{BigInt, BigUint};
+use rand_core::OsRng;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Mutex, OnceLock};
+
+pub type PC = SonicKZG10<Bls12_381, DensePolynomial<BlsScalar>>;
+pub type UniversalParams = <PC as PolynomialCommitment<
+    <Bls12_381 as PairingEngine>::Fr,
+    DensePolynomial<BlsScalar>,
+>>::UniversalParams;
+pub type PCVerifierKey = <PC as PolynomialCommitment<
+    <Bls12_381 as PairingEngine>::Fr,
+    DensePolynomial<BlsScalar>,
+>>::VerifierKey;
+
+/* Generate the public parameters for circuits with up to 2^max_degree gates. */
+pub fn setup(max_degree: u32) -> Result<UniversalParams, Error> {
+    let pp = PC::setup(1 << max_degree, None, &mut OsRng).map_err(to_pc_error::<BlsScalar, PC>)?;
+    Ok(pp)
+}
+
+/* The base 2 logarithm of the largest circuit size that the given public
+ * parameters support. */
+pub fn params_max_degree(pp: &UniversalParams) -> u32 {
+    usize::BITS - 1 - pp.max_degree().leading_zeros()
+}
+
+/* The smallest max degree that public parameters for the given circuit can
+ * have. Proving trims the public parameters to a few degrees beyond the padded
+ * circuit size, since each wire polynomial is blinded with one extra degree
+ * and the permutation polynomial with two. */
+pub fn required_max_degree(circuit: &PlonkModule<BlsScalar, JubJubParameters>) -> u32 {
+    const WIRE_COUNT: usize = 4;
+    const BLINDING_DEGREE: usize = WIRE_COUNT + 2;
+    (circuit.padded_circuit_size() + BLINDING_DEGREE)
+        .next_power_of_two()
+        .trailing_zeros()
+}
+
+/* The smallest max degree that public parameters for the vamp-ir file at the
+ * given path can have. */
+pub fn required_max_degree_file(path: impl AsRef<Path>, config: &Config) -> Result<u32, Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(path)?;
+    let module_3ac =
+        crate::transform::compile(module, &PrimeFieldOps::<BlsScalar>::default(), config)?;
+    let circuit = PlonkModule::<BlsScalar, JubJubParameters>::new(Rc::new(module_3ac));
+    Ok(required_max_degree(&circuit))
+}
+
+/* Shrink the given public parameters to those for circuits with up to
+ * 2^max_degree gates. The powers kept are the ones that the commitment scheme
+ * trims the parameters to, so circuits compiled with either set have the same
+ * keys. */
+pub fn fit_params(pp: &UniversalParams, max_degree: u32) -> Result<UniversalParams, Error> {
+    let params_max_degree = params_max_degree(pp);
+    if params_max_degree < max_degree {
+        return Err(Error::InsufficientParams {
+            k: params_max_degree,
+            required: max_degree,
+        });
+    }
+    let size = 1usize << max_degree;
+    let (ck, vk) = PC::trim(pp, size, size, None).map_err(to_pc_error::<BlsScalar, PC>)?;
+    Ok(ark_poly_commit::kzg10::UniversalParams {
+        powers_of_g: ck.powers_of_g,
+        powers_of_gamma_g: ck.powers_of_gamma_g.into_iter().enumerate().collect(),
+        h: vk.h,
+        beta_h: vk.beta_h,
+        neg_powers_of_h: pp
+            .neg_powers_of_h
+            .range(..=size)
+            .map(|(i, h)| (*i, *h))
+            .collect(),
+        prepared_h: vk.prepared_h,
+        prepared_beta_h: vk.prepared_beta_h,
+    })
+}
+
+pub fn compile(
+    source: impl AsRef<str>,
+    pp: &UniversalParams,
+    config: &Config,
+) -> Result<PlonkCircuitData, Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse(source.as_ref())?;
+    compile_module(module, pp, config)
+}
+
+/* Compile the vamp-ir file at the given path. Imports are resolved relative to
+ * the directory containing the file. */
+pub fn compile_file(
+    path: impl AsRef<Path>,
+    pp: &UniversalParams,
+    config: &Config,
+) -> Result<PlonkCircuitData, Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(path)?;
+    compile_module(module, pp, config)
+}
+
+fn compile_module(
+    module: Module,
+    pp: &UniversalParams,
+    config: &Config,
+) -> Result<PlonkCircuitData, Error> {
+    let module_3ac =
+        crate::transform::compile(module, &PrimeFieldOps::<BlsScalar>::default(), config)?;
+    synthesize_module(module_3ac, pp, config)
+}
+
+fn synthesize_module(
+    module_3ac: Module,
+    pp: &UniversalParams,
+    config: &Config,
+) -> Result<PlonkCircuitData, Error> {
+    qprintln!(config, "* Synthesizing arithmetic circuit...");
+    let module_rc = Rc::new(module_3ac);
+    let mut circuit = PlonkModule::<BlsScalar, JubJubParameters>::new(module_rc);
+    let max_degree = required_max_degree(&circuit);
+    qprintln!(
+        config,
+        "* Circuit requires public parameters of max degree {max_degree}..."
+    );
+    let params_max_degree = params_max_degree(pp);
+    if params_max_degree < max_degree {
+        return Err(Error::InsufficientParams {
+            k: params_max_degree,
+            required: max_degree,
+        });
+    }
+    let (pk_p, vk) = circuit.compile::<PC>(pp)?;
+    Ok(PlonkCircuitData {
+        pk_p,
+        vk,
+        circuit,
+        transcript_label: config.transcript_label.clone(),
+    })
+}
+
+pub fn prove(
+    circuit_data: &PlonkCircuitData,
+    pp: &UniversalParams,
+    named_assignments: &HashMap<impl AsRef<str>, BlsScalar>,
+    config: &Config,
+) -> Result<ProofDataPlonk, Error> {
+    let module = circuit_data.circuit.module.as_ref();
+    let named_string_assignments: HashMap<String, BlsScalar> = named_assignments
+        .iter()
+        .map(|(key, value)| (key.as_ref().to_string(), *value))
+        .collect();
+    let assignments = get_circuit_assignments(module, &named_string_assignments)?;
+    prove_from_variable_assignments(circuit_data, pp, assignments, config)
+}
+
+/* Verify the given proof against the given public inputs. The public inputs
+ * are named after the public variables of the circuit. */
+pub fn verify(
+    verifier_data: &PlonkVerifierData,
+    proof_data: &ProofDataPlonk,
+    named_public_assignments: &HashMap<impl AsRef<str>, BlsScalar>,
+    _config: &Config,
+) -> Result<(), Error> {
+    let named_public_int_assignments: HashMap<String, BigInt> = named_public_assignments
+        .iter()
+        .map(|(key, value)| {
+            let value: BigUint = (*value).into();
+            (key.as_ref().to_string(), value.into())
+        })
+        .collect();
+    // Ensure that the proof was made for the expected public inputs
+    PlonkModule::<BlsScalar, JubJubParameters>::check_public_inputs(
+        verifier_data,
+        proof_data,
+        &named_public_int_assignments,
+    )?;
+    verifier_data
+        .verify(proof_data)
+        .map_err(|_| ProofVerificationFailure)
+}
+
+/* Extract the data required to verify proofs of the given circuit, trimming
+ * the public parameters down to the circuit's size. */
+pub fn export_vk(
+    circuit_data: &PlonkCircuitData,
+    pp: &UniversalParams,
+    config: &Config,
+) -> Result<PlonkVerifierData, Error> {
+    qprintln!(config, "* Trimming public parameters...");
+    let (_, pc_vk) = PC::trim(pp, circuit_data.vk.0.padded_circuit_size(), 0, None)
+        .map_err(to_pc_error::<BlsScalar, PC>)?;
+    Ok(PlonkVerifierData {
+        vk: circuit_data.vk.clone(),
+        pc_vk,
+        pubs: circuit_data.circuit.module.pubs.clone(),
+        transcript_label: circuit_data.transcript_label.clone(),
+    })
+}
+
+pub(crate) fn prove_from_variable_assignments(
+    circuit_data: &PlonkCircuitData,
+    pp: &UniversalParams,
+    assignments: HashMap<VariableId, BlsScalar>,
+    config: &Config,
+) -> Result<ProofDataPlonk, Error> {
+    // Populate variable definitions
+    let mut circuit = circuit_data.circuit.clone();
+    circuit.populate_variables(assignments)?;
+
+    // Start proving witnesses
+    qprintln!(config, "* Proving knowledge of witnesses...");
+    let (proof, pi) = circuit.gen_proof::<PC>(
+        pp,
+        circuit_data.pk_p.clone(),
+        static_label(&circuit_data.transcript_label),
+    )?;
+    Ok(ProofDataPlonk { proof, pi })
+}
+
+/* Get the given transcript label as bytes that live for the rest of the
+ * program. plonk-core only accepts static transcript labels, so each distinct
+ * label is leaked the first time it is used and shared thereafter. */
+fn static_label(label: &str) -> &'static [u8] {
+    static LABELS: OnceLock<Mutex<HashMap<String, &'static [u8]>>> = OnceLock::new();
+    let mut labels = LABELS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    *labels
+        .entry(label.to_string())
+        .or_insert_with(|| Box::leak(label.as_bytes().to_vec().into_boxed_slice()))
+}
+
+/* Captures all the data required to use a PLONK circuit. */
+pub struct PlonkCircuitData {
+    pub pk_p: ProverKey<BlsScalar>,
+    pub vk: (VerifierKey<BlsScalar, PC>, Vec<usize>),
+    pub circuit: PlonkModule<BlsScalar, JubJubParameters>,
+    pub transcript_label: String,
+}
+
+/* Captures all the data required to verify proofs of a PLONK circuit: the
+ * circuit's verifier key, the positions and names of its public inputs, and
+ * the commitment scheme's verifier key trimmed to the circuit's size. */
+pub struct PlonkVerifierData {
+    pub vk: (VerifierKey<BlsScalar, PC>, Vec<usize>),
+    pub pc_vk: PCVerifierKey,
+    pub pubs: Vec<Variable>,
+    pub transcript_label: String,
+}
+
+/* Captures all the data generated from proving circuit witnesses. */
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub struct ProofDataPlonk {
+    pub proof: Proof<BlsScalar, PC>,
+    pub pi: PublicInputs<BlsScalar>,
+}
+
+impl PlonkCircuitData {
+    /* Read a circuit, skipping the validity checks on its keys if unchecked
+     * is set. */
+    pub fn read<R>(mut reader: R, unchecked: bool) -> Result<Self, DecodeError>
+    where
+        R: std::io::Read,
+    {
+        let keys = if unchecked {
+            ProverKey::<BlsScalar>::deserialize_unchecked(&mut reader).and_then(|pk_p| {
+                let vk = <(VerifierKey<_, _>, Vec<usize>)>::deserialize_unchecked(&mut reader)?;
+                Ok((pk_p, vk))
+            })
+        } else {
+            ProverKey::<BlsScalar>::deserialize(&mut reader).and_then(|pk_p| {
+                let vk = <(VerifierKey<_, _>, Vec<usize>)>::deserialize(&mut reader)?;
+                Ok((pk_p, vk))
+            })
+        };
+        let (pk_p, vk) = keys.map_err(|x| DecodeError::OtherString(x.to_string()))?;
+        let circuit: PlonkModule<BlsScalar, JubJubParameters> =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        let transcript_label: String =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        Ok(Self {
+            pk_p,
+            vk,
+            circuit,
+            transcript_label,
+        })
+    }
+
+    /* Write this circuit, leaving out the validity checks on its keys if
+     * unchecked is set. */
+    pub fn write<W>(&self, mut writer: W, unchecked: bool) -> Result<(), EncodeError>
+    where
+        W: std::io::Write,
+    {
+        if unchecked {
+            self.pk_p
+                .serialize_unchecked(&mut writer)
+                .and_then(|_| self.vk.serialize_unchecked(&mut writer))
+        } else {
+            self.pk_p
+                .serialize(&mut writer)
+                .and_then(|_| self.vk.serialize(&mut writer))
+        }
+        .map_err(|x| EncodeError::OtherString(x.to_string()))?;
+        bincode::encode_into_std_write(&self.circuit, &mut writer, bincode::config::standard())?;
+        bincode::encode_into_std_write(
+            &self.transcript_label,
+            &mut writer,
+            bincode::config::standard(),
+        )?;
+        Ok(())
+    }
+}
+
+impl PlonkVerifierData {
+    pub fn read<R>(mut reader: R) -> Result<Self, DecodeError>
+    where
+        R: std::io::Read,
+    {
+        let vk = <(VerifierKey<_, _>, Vec<usize>)>::deserialize(&mut reader)
+            .map_err(|x| DecodeError::OtherString(x.to_string()))?;
+        let pc_vk = PCVerifierKey::deserialize(&mut reader)
+            .map_err(|x| DecodeError::OtherString(x.to_string()))?;
+        let pubs: Vec<Variable> =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        let transcript_label: String =
+            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
+        Ok(Self {
+            vk,
+            pc_vk,
+            pubs,
+            transcript_label,
+        })
+    }
+
+    pub fn write<W>(&self, mut writer: W) -> Result<(), EncodeError>
+    where
+        W: std::io::Write,
+    {
+        self.vk
+            .serialize(&mut writer)
+            .map_err(|x| EncodeError::OtherString(x.to_string()))?;
+        self.pc_vk
+            .serialize(&mut writer)
+            .map_err(|x| EncodeError::OtherString(x.to_string()))?;
+        bincode::encode_into_std_write(&self.pubs, &mut writer, bincode::config::standard())?;
+        bincode::encode_into_std_write(
+            &self.transcript_label,
+            &mut writer,
+            bincode::config::standard(),
+        )?;
+        Ok(())
+    }
+
+    /* Annotate the public inputs of a proof with the public variables of the
+     * circuit. */
+    pub fn annotate_public_inputs(
+        &self,
+        pi: &PublicInputs<BlsScalar>,
+    ) -> HashMap<VariableId, (Variable, BlsScalar)> {
+        annotate_public_inputs(&self.pubs, &self.vk.1, pi)
+    }
+
+    /* Check the given proof against the public inputs contained in it. These
+     * are only accepted at the gates that the circuit reserves for public
+     * inputs, since a value at any other gate would offset its constraint. */
+    pub fn verify(&self, proof_data: &ProofDataPlonk) -> Result<(), Error> {
+        if let Some(pos) = proof_data.pi.get_pos().find(|pos| !self.vk.1.contains(pos)) {
+            return Err(Error::UnexpectedPublicInput { pos: *pos });
+        }
+        let mut verifier =
+            Verifier::<BlsScalar, JubJubParameters, PC>::new(static_label(&self.transcript_label));
+        verifier.verifier_key = Some(self.vk.0.clone());
+        verifier.verify(&proof_data.proof, &self.pc_vk, &proof_data.pi)?;
+        Ok(())
+    }
+}
+
+impl ProofSystem for PlonkModule<BlsScalar, JubJubParameters> {
+    type Field = BlsScalar;
+    type FieldOps = PrimeFieldOps<BlsScalar>;
+    type Params = UniversalParams;
+    type CircuitData = PlonkCircuitData;
+    type VerifierData = PlonkVerifierData;
+    type ProofData = ProofDataPlonk;
+
+    fn make_constant(c: &BigInt) -> BlsScalar {
+        make_constant(c)
+    }
+
+    fn setup(k: u32) -> Result<UniversalParams, Error> {
+        setup(k)
+    }
+
+    fn synthesize(
+        module_3ac: Module,
+        pp: Option<&UniversalParams>,
+        config: &Config,
+    ) -> Result<PlonkCircuitData, Error> {
+        synthesize_module(module_3ac, pp.ok_or(Error::MissingParams)?, config)
+    }
+
+    fn circuit_module(circuit_data: &PlonkCircuitData) -> &Module {
+        &circuit_data.circuit.module
+    }
+
+    fn prove(
+        circuit_data: &PlonkCircuitData,
+        pp: Option<&UniversalParams>,
+        assignments: HashMap<VariableId, BlsScalar>,
+        config: &Config,
+    ) -> Result<ProofDataPlonk, Error> {
+        let pp = pp.ok_or(Error::MissingParams)?;
+        prove_from_variable_assignments(circuit_data, pp, assignments, config)
+    }
+
+    fn export_vk(
+        circuit_data: &PlonkCircuitData,
+        pp: Option<&UniversalParams>,
+        config: &Config,
+    ) -> Result<PlonkVerifierData, Error> {
+        export_vk(circuit_data, pp.ok_or(Error::MissingParams)?, config)
+    }
+
+    fn verify(
+        verifier_data: &PlonkVerifierData,
+        proof_data: &ProofDataPlonk,
+        _config: &Config,
+    ) -> Result<(), Error> {
+        verifier_data
+            .verify(proof_data)
+            .map_err(|_| ProofVerificationFailure)
+    }
+
+    fn public_inputs(
+        verifier_data: &PlonkVerifierData,
+        proof_data: &ProofDataPlonk,
+    ) -> Result<Vec<(Variable, BigInt)>, Error> {
+        // Public inputs that are absent from a PLONK proof are zero
+        let annotated = verifier_data.annotate_public_inputs(&proof_data.pi);
+        Ok(verifier_data
+            .pubs
+            .iter()
+            .map(|var| {
+                let val: BigUint = annotated[&var.id].1.into();
+                (var.clone(), val.into())
+            })
+            .collect())
+    }
+
+    fn read_params(
+        reader: &mut dyn std::io::Read,
+        unchecked: bool,
+    ) -> Result<UniversalParams, Error> {
+        if unchecked {
+            UniversalParams::deserialize_unchecked(reader)
+        } else {
+            UniversalParams::deserialize(reader)
+        }
+        .map_err(|e| artifact_error("public parameters", e))
+    }
+
+    fn write_params(
+        pp: &UniversalParams,
+        unchecked: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error> {
+        if unchecked {
+            pp.serialize_unchecked(writer)
+        } else {
+            pp.serialize(writer)
+        }
+        .map_err(|e| artifact_error("public parameters", e))
+    }
+
+    fn read_circuit(
+        reader: &mut dyn std::io::Read,
+        _pp: Option<&UniversalParams>,
+        unchecked: bool,
+    ) -> Result<PlonkCircuitData, Error> {
+        PlonkCircuitData::read(reader, unchecked).map_err(|e| artifact_error("circuit", e))
+    }
+
+    fn write_circuit(
+        circuit_data: &PlonkCircuitData,
+        _with_params: bool,
+        unchecked: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error> {
+        circuit_data
+            .write(writer, unchecked)
+            .map_err(|e| artifact_error("circuit", e))
+    }
+
+    fn read_verifier_data(reader: &mut dyn std::io::Read) -> Result<PlonkVerifierData, Error> {
+        PlonkVerifierData::read(reader).map_err(|e| artifact_error("verifying key", e))
+    }
+
+    fn write_verifier_data(
+        verifier_data: &PlonkVerifierData,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error> {
+        verifier_data
+            .write(writer)
+            .map_err(|e| artifact_error("verifying key", e))
+    }
+
+    fn read_proof(reader: &mut dyn std::io::Read) -> Result<ProofDataPlonk, Error> {
+        ProofDataPlonk::deserialize(reader).map_err(|e| artifact_error("proof", e))
+    }
+
+    fn write_proof(proof_data: &ProofDataPlonk, writer: &mut dyn Write) -> Result<(), Error> {
+        proof_data
+            .serialize(writer)
+            .map_err(|e| artifact_error("proof", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prove_valid() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let pp = setup(8).unwrap();
+        let circuit = compile("x = 1;", &pp, &config).unwrap();
+        let assignments = HashMap::from([("x", BlsScalar::from(1))]);
+        assert!(prove(&circuit, &pp, &assignments, &config).is_ok());
+    }
+
+    #[test]
+    fn test_prove_missing_assignment() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let pp = setup(8).unwrap();
+        let circuit = compile("x = 1;", &pp, &config).unwrap();
+        let assignments: HashMap<String, BlsScalar> = HashMap::new();
+        assert!(matches!(
+            prove(&circuit, &pp, &assignments, &config),
+            Err(Error::MissingVariableAssignment { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_valid_with_public() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let pp = setup(8).unwrap();
+        let circuit = compile("pub z; x*y = z;", &pp, &config).unwrap();
+        let assignments = HashMap::from([
+            ("x", BlsScalar::from(2)),
+            ("y", BlsScalar::from(3)),
+            ("z", BlsScalar::from(6)),
+        ]);
+        let proof_data = prove(&circuit, &pp, &assignments, &config).unwrap();
+
+        // Verifier data and proofs survive a round trip through storage
+        let mut vk_file = Vec::new();
+        export_vk(&circuit, &pp, &config)
+            .unwrap()
+            .write(&mut vk_file)
+            .unwrap();
+        let verifier_data = PlonkVerifierData::read(vk_file.as_slice()).unwrap();
+        let mut proof_file = Vec::new();
+        proof_data.serialize(&mut proof_file).unwrap();
+        let proof_data = ProofDataPlonk::deserialize(proof_file.as_slice()).unwrap();
+
+        let public_assignments = HashMap::from([("z", BlsScalar::from(6))]);
+        assert!(verify(&verifier_data, &proof_data, &public_assignments, &config).is_ok());
+        let public_assignments = HashMap::from([("z", BlsScalar::from(7))]);
+        assert!(matches!(
+            verify(&verifier_data, &proof_data, &public_assignments, &config),
+            Err(Error::PublicInputMismatch { .. })
+        ));
+        let public_assignments: HashMap<String, BlsScalar> = HashMap::new();
+        assert!(matches!(
+            verify(&verifier_data, &proof_data, &public_assignments, &config),
+            Err(Error::MissingVariableAssignment { .. })
+        ));
+    }
+
+    #[test]
+    fn test_labels_leaked_once() {
+        let label = static_label("test_labels_leaked_once");
+        assert!(std::ptr::eq(label, static_label("test_labels_leaked_once")));
+        assert_eq!(label, b"test_labels_leaked_once");
+    }
+
+    #[test]
+    fn test_forged_public_input_rejected() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let pp = setup(8).unwrap();
+        let circuit = compile("pub z; x*y = z;", &pp, &config).unwrap();
+        let assignments = HashMap::from([
+            ("x", BlsScalar::from(2)),
+            ("y", BlsScalar::from(3)),
+            ("z", BlsScalar::from(6)),
+        ]);
+        let proof_data = prove(&circuit, &pp, &assignments, &config).unwrap();
+        let verifier_data = export_vk(&circuit, &pp, &config).unwrap();
+
+        // Offset a gate that does not hold a public input
+        let pos = (0..).find(|pos| !circuit.vk.1.contains(pos)).unwrap();
+        let mut pi_file = Vec::new();
+        proof_data.pi.serialize(&mut pi_file).unwrap();
+        let mut values =
+            std::collections::BTreeMap::<usize, BlsScalar>::deserialize(pi_file.as_slice())
+                .unwrap();
+        values.insert(pos, BlsScalar::from(1));
+        let mut pi_file = Vec::new();
+        values.serialize(&mut pi_file).unwrap();
+        let forged = ProofDataPlonk {
+            proof: proof_data.proof.clone(),
+            pi: PublicInputs::deserialize(pi_file.as_slice()).unwrap(),
+        };
+
+        assert!(matches!(
+            verifier_data.verify(&forged),
+            Err(Error::UnexpectedPublicInput { pos: p }) if p == pos
+        ));
+        let public_assignments = HashMap::from([("z", BlsScalar::from(6))]);
+        assert!(verify(&verifier_data, &forged, &public_assignments, &config).is_err());
+        assert!(verify(&verifier_data, &proof_data, &public_assignments, &config).is_ok());
+    }
+
+    #[test]
+    fn test_prove_with_trimmed_params() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let pp = setup(8).unwrap();
+        let circuit = compile("pub z; x*y = z;", &pp, &config).unwrap();
+        let max_degree = required_max_degree(&circuit.circuit);
+        assert!(max_degree < 8);
+
+        // Trimmed parameters yield the same keys as the ones they came from
+        let trimmed = fit_params(&pp, max_degree).unwrap();
+        assert_eq!(params_max_degree(&trimmed), max_degree);
+        let assignments = HashMap::from([
+            ("x", BlsScalar::from(2)),
+            ("y", BlsScalar::from(3)),
+            ("z", BlsScalar::from(6)),
+        ]);
+        let proof_data = prove(&circuit, &trimmed, &assignments, &config).unwrap();
+        let verifier_data = export_vk(&circuit, &trimmed, &config).unwrap();
+        let public_assignments = HashMap::from([("z", BlsScalar::from(6))]);
+        assert!(verify(&verifier_data, &proof_data, &public_assignments, &config).is_ok());
+
+        // Parameters of exactly the required size suffice, and smaller ones
+        // are rejected
+        assert!(compile("pub z; x*y = z;", &trimmed, &config).is_ok());
+        let smaller = fit_params(&pp, max_degree - 1).unwrap();
+        assert!(matches!(
+            compile("pub z; x*y = z;", &smaller, &config),
+            Err(Error::InsufficientParams { .. })
+        ));
+        assert!(fit_params(&trimmed, max_degree + 1).is_err());
+    }
+
+    #[test]
+    fn test_params_required() {
+        type S = PlonkModule<BlsScalar, JubJubParameters>;
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse("x = 1;").unwrap();
+        assert!(matches!(
+            S::compile(module, None, &config),
+            Err(Error::MissingParams)
+        ));
+    }
+}
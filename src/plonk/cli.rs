@@ -1,371 +1,94 @@
-use crate::ast::{Module, VariableId};
 use crate::error::Error;
-use crate::plonk::synth::{make_constant, PlonkModule, PrimeFieldOps};
+use crate::plonk::synth::PlonkModule;
+use crate::proof_system::cli::{create_file, read_params, run, CircuitCommands};
+use crate::proof_system::ProofSystem;
 use crate::qprintln;
-use crate::transform::compile;
-use crate::util::{get_circuit_assignments, prompt_inputs, read_inputs_from_file, Config};
+use crate::util::Config;
 
-use ark_bls12_381::{Bls12_381, Fr as BlsScalar};
-use ark_ec::PairingEngine;
+use ark_bls12_381::Fr as BlsScalar;
 use ark_ed_on_bls12_381::EdwardsParameters as JubJubParameters;
-use ark_poly::polynomial::univariate::DensePolynomial;
-use ark_poly_commit::{sonic_pc::SonicKZG10, PolynomialCommitment};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, Read, SerializationError};
-use plonk::error::to_pc_error;
-use plonk_core::circuit::{verify_proof, Circuit};
-use plonk_core::prelude::VerifierData;
-use plonk_core::proof_system::pi::PublicInputs;
-use plonk_core::proof_system::{Proof, ProverKey, VerifierKey};
-
-use bincode::error::{DecodeError, EncodeError};
-use rand_core::OsRng;
-use std::collections::HashMap;
-use std::fs;
-use std::fs::File;
-use std::io::Write;
 
 use std::path::PathBuf;
-use std::rc::Rc;
 
 use clap::{Args, Subcommand};
-use num_bigint::BigInt;
 
-type PC = SonicKZG10<Bls12_381, DensePolynomial<BlsScalar>>;
-type UniversalParams = <PC as PolynomialCommitment<
-    <Bls12_381 as PairingEngine>::Fr,
-    DensePolynomial<BlsScalar>,
->>::UniversalParams;
+type Plonk = PlonkModule<BlsScalar, JubJubParameters>;
 
 #[derive(Subcommand)]
 pub enum PlonkCommands {
     /// Sets up the public parameters required for proving
     Setup(Setup),
-    /// Compiles a given source file to a circuit
-    Compile(PlonkCompile),
-    /// Proves knowledge of witnesses satisfying a circuit
-    Prove(PlonkProve),
-    /// Verifies that a proof is a correct one
-    Verify(PlonkVerify),
+    #[command(flatten)]
+    Circuit(CircuitCommands),
 }
 
 #[derive(Args)]
 pub struct Setup {
     /// Maximum degree exponent of the polynomial commitment scheme
-    #[arg(short, long, default_value_t = 10)]
+    #[arg(short, long, default_value_t = 10, conflicts_with = "for_circuit")]
     max_degree: u128,
-    /// Path to which the public parameters are written
-    #[arg(short, long)]
-    output: PathBuf,
-    /// Disable validity checks on the generated public parameters
+    /// Path to source file whose circuit the public parameters are sized for
     #[arg(long)]
-    unchecked: bool,
-}
-
-#[derive(Args)]
-pub struct PlonkCompile {
-    /// Path to public parameters
-    #[arg(short, long)]
-    universal_params: PathBuf,
-    /// Path to source file to be compiled
-    #[arg(short, long)]
-    source: PathBuf,
-    /// Path to which circuit is written
+    for_circuit: Option<PathBuf>,
+    /// Path to larger public parameters to trim instead of generating new ones
     #[arg(short, long)]
-    output: PathBuf,
-    /// Do not perform validity checks on public parameters
-    #[arg(long)]
-    unchecked: bool,
-}
-
-#[derive(Args)]
-pub struct PlonkProve {
-    /// Path to public parameters
-    #[arg(short, long)]
-    universal_params: PathBuf,
-    /// Path to circuit on which to construct proof
-    #[arg(short, long)]
-    circuit: PathBuf,
-    /// Path to which the proof is written
+    universal_params: Option<PathBuf>,
+    /// Path to which the public parameters are written
     #[arg(short, long)]
     output: PathBuf,
     /// Do not perform validity checks on public parameters
     #[arg(long)]
     unchecked: bool,
-    /// Path to prover's input file
-    #[arg(short, long)]
-    inputs: Option<PathBuf>,
-}
-
-#[derive(Args)]
-pub struct PlonkVerify {
-    /// Path to public parameters
-    #[arg(short, long)]
-    universal_params: PathBuf,
-    /// Path to circuit on which to construct proof
-    #[arg(short, long)]
-    circuit: PathBuf,
-    /// Path to the proof that is being verified
-    #[arg(short, long)]
-    proof: PathBuf,
-    /// Do not perform validity checks on public parameters
-    #[arg(long)]
-    unchecked: bool,
 }
 
 pub fn plonk(plonk_commands: &PlonkCommands, config: &Config) -> Result<(), Error> {
     match plonk_commands {
         PlonkCommands::Setup(args) => setup_plonk_cmd(args, config),
-        PlonkCommands::Compile(args) => compile_plonk_cmd(args, config),
-        PlonkCommands::Prove(args) => prove_plonk_cmd(args, config),
-        PlonkCommands::Verify(args) => verify_plonk_cmd(args, config),
-    }
-}
-
-/* Captures all the data required to use a PLONK circuit. */
-struct PlonkCircuitData {
-    pk_p: ProverKey<BlsScalar>,
-    vk: (VerifierKey<BlsScalar, PC>, Vec<usize>),
-    circuit: PlonkModule<BlsScalar, JubJubParameters>,
-}
-
-impl PlonkCircuitData {
-    fn read<R>(mut reader: R) -> Result<Self, DecodeError>
-    where
-        R: std::io::Read,
-    {
-        let pk_p = ProverKey::<BlsScalar>::deserialize(&mut reader)
-            .map_err(|x| DecodeError::OtherString(x.to_string()))?;
-        let vk = <(VerifierKey<_, _>, Vec<usize>)>::deserialize(&mut reader)
-            .map_err(|x| DecodeError::OtherString(x.to_string()))?;
-        let circuit: PlonkModule<BlsScalar, JubJubParameters> =
-            bincode::decode_from_std_read(&mut reader, bincode::config::standard())?;
-        Ok(Self { pk_p, vk, circuit })
-    }
-
-    fn write<W>(&self, mut writer: W) -> Result<(), EncodeError>
-    where
-        W: std::io::Write,
-    {
-        self.pk_p
-            .serialize(&mut writer)
-            .map_err(|x| EncodeError::OtherString(x.to_string()))?;
-        self.vk
-            .serialize(&mut writer)
-            .map_err(|x| EncodeError::OtherString(x.to_string()))?;
-        bincode::encode_into_std_write(&self.circuit, &mut writer, bincode::config::standard())?;
-        Ok(())
+        PlonkCommands::Circuit(commands) => run::<Plonk>(commands, config),
     }
 }
 
-/* Captures all the data generated from proving circuit witnesses. */
-#[derive(CanonicalSerialize, CanonicalDeserialize)]
-struct ProofData {
-    proof: Proof<BlsScalar, PC>,
-    pi: PublicInputs<BlsScalar>,
-}
-
-/* Implements the subcommand that generates the public parameters for proofs. */
+/* Implements the subcommand that generates the public parameters for proofs.
+ * The parameters are either sized for a given circuit or for the given max
+ * degree, and are either freshly generated or trimmed from larger ones. */
 fn setup_plonk_cmd(
     Setup {
         max_degree,
-        output,
-        unchecked,
-    }: &Setup,
-    config: &Config,
-) -> Result<(), Error> {
-    // Generate CRS
-    qprintln!(config, "* Setting up public parameters...");
-    let pp = PC::setup(1 << max_degree, None, &mut OsRng)
-        .map_err(to_pc_error::<BlsScalar, PC>)
-        .expect("unable to setup polynomial commitment scheme public parameters");
-    let mut pp_file = File::create(output).expect("unable to create public parameters file");
-    if *unchecked {
-        pp.serialize_unchecked(&mut pp_file)
-    } else {
-        pp.serialize(&mut pp_file)
-    }
-    .unwrap();
-    qprintln!(config, "* Public parameter setup success!");
-
-    Ok(())
-}
-
-/* Implements the subcommand that compiles a vamp-ir file into a PLONK circuit.
- */
-fn compile_plonk_cmd(
-    PlonkCompile {
-        universal_params,
-        source,
-        output,
-        unchecked,
-    }: &PlonkCompile,
-    config: &Config,
-) -> Result<(), Error> {
-    qprintln!(config, "* Compiling constraints...");
-    let unparsed_file = fs::read_to_string(source).expect("cannot read file");
-    let module = Module::parse(&unparsed_file).unwrap();
-    let module_3ac = compile(module, &PrimeFieldOps::<BlsScalar>::default(), config);
-
-    qprintln!(config, "* Reading public parameters...");
-    let mut pp_file = File::open(universal_params).expect("unable to load public parameters file");
-    let pp = if *unchecked {
-        UniversalParams::deserialize_unchecked(&mut pp_file)
-    } else {
-        UniversalParams::deserialize(&mut pp_file)
-    }
-    .unwrap();
-
-    qprintln!(config, "* Synthesizing arithmetic circuit...");
-    //let mut circuit = PlonkModule::<BlsScalar, JubJubParameters>::new(&module_3ac);
-    let module_rc = Rc::new(module_3ac);
-    let mut circuit = PlonkModule::<BlsScalar, JubJubParameters>::new(module_rc);
-
-    // Compile the circuit
-    let (pk_p, vk) = circuit.compile::<PC>(&pp)?;
-    //.expect("unable to compile circuit");
-    qprintln!(config, "* Serializing circuit to storage...");
-    let mut circuit_file = File::create(output).expect("unable to create circuit file");
-    PlonkCircuitData { pk_p, vk, circuit }
-        .write(&mut circuit_file)
-        .unwrap();
-
-    qprintln!(config, "* Constraint compilation success!");
-
-    Ok(())
-}
-
-fn inputs_from_file(
-    path_to_inputs: &PathBuf,
-    circuit_module: &Module,
-    config: &Config,
-) -> Result<HashMap<VariableId, BigInt>, Error> {
-    qprintln!(
-        config,
-        "* Reading inputs from file {}...",
-        path_to_inputs.to_string_lossy()
-    );
-    let raw_inputs: HashMap<String, BigInt> = read_inputs_from_file(path_to_inputs).unwrap();
-    get_circuit_assignments::<BigInt>(circuit_module, &raw_inputs)
-}
-
-/* Implements the subcommand that creates a proof from interactively entered
- * inputs. */
-fn prove_plonk_cmd(
-    PlonkProve {
+        for_circuit,
         universal_params,
-        circuit,
         output,
         unchecked,
-        inputs,
-    }: &PlonkProve,
+    }: &Setup,
     config: &Config,
 ) -> Result<(), Error> {
-    qprintln!(config, "* Reading arithmetic circuit...");
-    let mut circuit_file = File::open(circuit).expect("unable to load circuit file");
-
-    let mut expected_path_to_inputs = circuit.clone();
-    expected_path_to_inputs.set_extension("inputs");
-
-    let PlonkCircuitData {
-        pk_p,
-        vk: _vk,
-        mut circuit,
-    } = PlonkCircuitData::read(&mut circuit_file).unwrap();
-
-    // Prompt for program inputs
-    let var_assignments_ints = match inputs {
-        Some(path_to_inputs) => inputs_from_file(path_to_inputs, &circuit.module, config),
+    let max_degree = match for_circuit {
+        Some(source) => {
+            let max_degree = crate::plonk::api::required_max_degree_file(source, config)?;
+            qprintln!(
+                config,
+                "* Circuit requires public parameters of max degree {max_degree}..."
+            );
+            max_degree
+        }
+        None => *max_degree as u32,
+    };
+    let pp = match universal_params {
+        Some(universal_params) => {
+            let pp = read_params::<Plonk>(universal_params, *unchecked, config)?;
+            qprintln!(config, "* Trimming public parameters...");
+            crate::plonk::api::fit_params(&pp, max_degree)?
+        }
         None => {
-            if expected_path_to_inputs.exists() {
-                inputs_from_file(&expected_path_to_inputs, &circuit.module, config)
-            } else {
-                qprintln!(config, "* Soliciting circuit witnesses...");
-                Ok(prompt_inputs(&circuit.module))
-            }
+            qprintln!(config, "* Setting up public parameters...");
+            Plonk::setup(max_degree)?
         }
-    }?;
-
-    let mut var_assignments = HashMap::new();
-    for (k, v) in var_assignments_ints {
-        var_assignments.insert(k, make_constant(&v));
-    }
-
-    // Populate variable definitions
-    circuit.populate_variables(var_assignments);
-
-    qprintln!(config, "* Reading public parameters...");
-    let mut pp_file = File::open(universal_params).expect("unable to load public parameters file");
-    let pp = if *unchecked {
-        UniversalParams::deserialize_unchecked(&mut pp_file)
-    } else {
-        UniversalParams::deserialize(&mut pp_file)
-    }
-    .unwrap();
-
-    // Start proving witnesses
-    qprintln!(config, "* Proving knowledge of witnesses...");
-    let (proof, pi) = circuit.gen_proof::<PC>(&pp, pk_p, b"Test").unwrap();
-
-    qprintln!(config, "* Serializing proof to storage...");
-    let mut proof_file = File::create(output).expect("unable to create proof file");
-    ProofData { proof, pi }.serialize(&mut proof_file).unwrap();
-
-    qprintln!(config, "* Proof generation success!");
+    };
+    Plonk::write_params(
+        &pp,
+        *unchecked,
+        &mut create_file(output, "public parameters")?,
+    )?;
+    qprintln!(config, "* Public parameter setup success!");
 
     Ok(())
 }
-
-/* Implements the subcommand that verifies that a proof is correct. */
-fn verify_plonk_cmd(
-    PlonkVerify {
-        universal_params,
-        circuit,
-        proof,
-        unchecked,
-    }: &PlonkVerify,
-    config: &Config,
-) -> Result<(), Error> {
-    qprintln!(config, "* Reading arithmetic circuit...");
-    let mut circuit_file = File::open(circuit).expect("unable to load circuit file");
-    let PlonkCircuitData {
-        pk_p: _pk_p,
-        vk,
-        circuit,
-    } = PlonkCircuitData::read(&mut circuit_file).unwrap();
-
-    qprintln!(config, "* Reading zero-knowledge proof...");
-    let mut proof_file = File::open(proof).expect("unable to load proof file");
-    let ProofData { proof, pi } = ProofData::deserialize(&mut proof_file).unwrap();
-
-    qprintln!(config, "* Public inputs:");
-    for (var, val) in circuit.annotate_public_inputs(&vk.1, &pi).values() {
-        qprintln!(config, "{} = {}", var, val);
-    }
-
-    qprintln!(config, "* Reading public parameters...");
-    let mut pp_file = File::open(universal_params).expect("unable to load public parameters file");
-    let pp = if *unchecked {
-        UniversalParams::deserialize_unchecked(&mut pp_file)
-    } else {
-        UniversalParams::deserialize(&mut pp_file)
-    }
-    .unwrap();
-
-    // Verifier POV
-    qprintln!(config, "* Verifying proof validity...");
-    let verifier_data = VerifierData::new(vk.0, pi);
-    let verifier_result = verify_proof::<BlsScalar, JubJubParameters, PC>(
-        &pp,
-        verifier_data.key,
-        &proof,
-        &verifier_data.pi,
-        b"Test",
-    );
-    if let Ok(()) = verifier_result {
-        qprintln!(config, "* Zero-knowledge proof is valid");
-        Ok(())
-    } else {
-        qprintln!(config, "* Result from verifier: {:?}", verifier_result);
-        Err(Error::ProofVerificationFailure)
-    }
-}
@@ -1,6 +1,8 @@
 use crate::ast::Variable;
-use crate::ast::{Expr, InfixOp, Module, Pat, TExpr, VariableId};
-use crate::transform::{collect_module_variables, FieldOps};
+use crate::ast::{Expr, InfixOp, Module, VariableId};
+use crate::solver::{solve_witnesses, unsatisfied_equalities};
+use crate::transform::{collect_module_variables, weighted_sum, FieldOps};
+use crate::util::UnsatisfiedEquality;
 use ark_ec::TEModelParameters;
 use ark_ff::PrimeField;
 use num_bigint::{BigInt, BigUint, Sign, ToBigInt};
@@ -56,61 +58,9 @@ pub fn make_constant<F: PrimeField>(c: &BigInt) -> F {
     }
 }
 
-/* Evaluate the given expression sourcing any variables from the given maps. */
-fn evaluate_expr<F>(
-    expr: &TExpr,
-    defs: &mut HashMap<VariableId, TExpr>,
-    assigns: &mut HashMap<VariableId, F>,
-) -> F
-where
-    F: PrimeField,
-{
-    match &expr.v {
-        Expr::Constant(c) => make_constant(c),
-        Expr::Variable(v) => {
-            if let Some(val) = assigns.get(&v.id) {
-                // First look for existing variable assignment
-                *val
-            } else {
-                // Otherwise compute variable from first principles
-                let val = evaluate_expr(&defs[&v.id].clone(), defs, assigns);
-                assigns.insert(v.id, val);
-                val
-            }
-        }
-        Expr::Negate(e) => -evaluate_expr(e, defs, assigns),
-        Expr::Infix(InfixOp::Add, a, b) => {
-            evaluate_expr(a, defs, assigns) + evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::Subtract, a, b) => {
-            evaluate_expr(a, defs, assigns) - evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::Multiply, a, b) => {
-            evaluate_expr(a, defs, assigns) * evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::Divide, a, b) => {
-            evaluate_expr(a, defs, assigns) / evaluate_expr(b, defs, assigns)
-        }
-        Expr::Infix(InfixOp::DivideZ, a, b) => {
-            let denom = evaluate_expr(b, defs, assigns);
-            if denom == F::zero() {
-                F::zero()
-            } else {
-                evaluate_expr(a, defs, assigns) / denom
-            }
-        }
-        Expr::Infix(InfixOp::IntDivide, a, b) => {
-            (Into::<BigUint>::into(evaluate_expr(a, defs, assigns))
-                / Into::<BigUint>::into(evaluate_expr(b, defs, assigns)))
-            .into()
-        }
-        Expr::Infix(InfixOp::Modulo, a, b) => {
-            (Into::<BigUint>::into(evaluate_expr(a, defs, assigns))
-                % Into::<BigUint>::into(evaluate_expr(b, defs, assigns)))
-            .into()
-        }
-        _ => unreachable!("encountered unexpected expression: {}", expr),
-    }
+/* Get the canonical integer representation of the given field element. */
+fn to_int<F: PrimeField>(x: F) -> BigInt {
+    Into::<BigUint>::into(x).to_bigint().unwrap()
 }
 
 #[derive(Default)]
@@ -161,11 +111,96 @@ where
                 .to_bigint()
                 .unwrap()
             }
+            InfixOp::Less => BigInt::from(u8::from(self.canonical(a) < self.canonical(b))),
+            InfixOp::LessEqual => BigInt::from(u8::from(self.canonical(a) <= self.canonical(b))),
+            InfixOp::NotEqual => BigInt::from(u8::from(c != d)),
             InfixOp::Equal => panic!("cannot evaluate equals expression"),
         }
     }
 }
 
+/* An arithmetic gate of the standard composer: q_m*a*b + q_l*a + q_r*b +
+ * q_o*c + q_c + pi = 0. The wires carry the given variables, with None
+ * standing for the composer's zero variable. */
+#[derive(Clone, Debug)]
+pub struct PlonkGate<F: PrimeField> {
+    pub witness: (Option<VariableId>, Option<VariableId>, Option<VariableId>),
+    pub q_m: F,
+    pub q_l: F,
+    pub q_r: F,
+    pub q_o: F,
+    pub q_c: F,
+    pub pi: Option<F>,
+}
+
+// The selectors of a fresh gate are those of plonk-core's ArithmeticGate
+impl<F: PrimeField> Default for PlonkGate<F> {
+    fn default() -> Self {
+        PlonkGate {
+            witness: (None, None, None),
+            q_m: F::zero(),
+            q_l: F::zero(),
+            q_r: F::zero(),
+            q_o: -F::one(),
+            q_c: F::zero(),
+            pi: None,
+        }
+    }
+}
+
+// The builder methods mirror those of plonk-core's ArithmeticGate
+impl<F: PrimeField> PlonkGate<F> {
+    fn witness(
+        &mut self,
+        a: Option<VariableId>,
+        b: Option<VariableId>,
+        c: Option<Option<VariableId>>,
+    ) -> &mut Self {
+        self.witness = (a, b, c.flatten());
+        self
+    }
+
+    fn mul(&mut self, q_m: F) -> &mut Self {
+        self.q_m = q_m;
+        self
+    }
+
+    fn add(&mut self, q_l: F, q_r: F) -> &mut Self {
+        self.q_l = q_l;
+        self.q_r = q_r;
+        self
+    }
+
+    fn out(&mut self, q_o: F) -> &mut Self {
+        self.q_o = q_o;
+        self
+    }
+
+    fn constant(&mut self, q_c: F) -> &mut Self {
+        self.q_c = q_c;
+        self
+    }
+
+    fn pi(&mut self, pi: F) -> &mut Self {
+        self.pi = Some(pi);
+        self
+    }
+}
+
+/* Collects gates through the same interface as a standard composer. */
+struct PlonkGates<F: PrimeField>(Vec<PlonkGate<F>>);
+
+impl<F: PrimeField> PlonkGates<F> {
+    fn arithmetic_gate<G>(&mut self, func: G)
+    where
+        G: FnOnce(&mut PlonkGate<F>) -> &mut PlonkGate<F>,
+    {
+        let mut gate = PlonkGate::default();
+        func(&mut gate);
+        self.0.push(gate);
+    }
+}
+
 pub struct PlonkModule<F, P>
 where
     F: PrimeField,
@@ -176,6 +211,20 @@ where
     phantom: PhantomData<P>,
 }
 
+impl<F, P> Clone for PlonkModule<F, P>
+where
+    F: PrimeField,
+    P: TEModelParameters<BaseField = F>,
+{
+    fn clone(&self) -> Self {
+        PlonkModule {
+            module: self.module.clone(),
+            variable_map: self.variable_map.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl<F, P> bincode::Encode for PlonkModule<F, P>
 where
     F: PrimeField,
@@ -238,19 +287,39 @@ where
     }
 
     /* Populate input and auxiliary variables from the given program inputs. */
-    pub fn populate_variables(&mut self, mut field_assigns: HashMap<VariableId, F>) {
-        // Get the definitions necessary to populate auxiliary variables
-        let mut definitions = HashMap::new();
-        for def in &self.module.defs {
-            if let Pat::Variable(var) = &def.0 .0.v {
-                definitions.insert(var.id, *def.0 .1.clone());
-            }
-        }
-        // Start deriving witnesses
+    pub fn populate_variables(
+        &mut self,
+        field_assigns: HashMap<VariableId, F>,
+    ) -> Result<(), crate::error::Error> {
+        let values = self.solve_variables(field_assigns)?;
         for (var, value) in &mut self.variable_map {
-            let var_expr = Expr::Variable(crate::ast::Variable::new(*var)).type_expr(None);
-            *value = evaluate_expr(&var_expr, &mut definitions, &mut field_assigns);
+            *value = make_constant(&values[var]);
         }
+        Ok(())
+    }
+
+    /* Derive the values of all the variables in this module from the given
+     * program inputs. */
+    fn solve_variables(
+        &self,
+        field_assigns: HashMap<VariableId, F>,
+    ) -> Result<HashMap<VariableId, BigInt>, crate::error::Error> {
+        let int_assigns = field_assigns
+            .into_iter()
+            .map(|(id, val)| (id, to_int(val)))
+            .collect();
+        solve_witnesses(&self.module, int_assigns, &PrimeFieldOps::<F>::default())
+    }
+
+    /* Populate input and auxiliary variables from the given program inputs
+     * and report every equality that does not hold under them. */
+    pub fn check_variables(
+        &self,
+        field_assigns: HashMap<VariableId, F>,
+    ) -> Result<Vec<UnsatisfiedEquality>, crate::error::Error> {
+        let values = self.solve_variables(field_assigns)?;
+        let field_ops = PrimeFieldOps::<F>::default();
+        Ok(unsatisfied_equalities(&self.module, &values, &field_ops))
     }
 
     /* Annotate the given public inputs with the variable names contained in
@@ -258,41 +327,25 @@ where
      * module and the public inputs in the argument occur in the same order. */
     pub fn annotate_public_inputs(
         &self,
-        intended_pi_pos: &Vec<usize>,
+        intended_pi_pos: &[usize],
         pi: &PublicInputs<F>,
     ) -> HashMap<VariableId, (Variable, F)> {
-        // First map public input positions to values
-        let mut pi_map = BTreeMap::new();
-        for (pos, val) in pi.get_pos().zip(pi.get_vals()) {
-            pi_map.insert(*pos, *val);
-        }
-        // Next, annotate the public inputs with this module's variables
-        let mut annotated = HashMap::new();
-        for (var, pos) in self.module.pubs.iter().zip(intended_pi_pos) {
-            let val = pi_map.get(pos).copied().unwrap_or(F::zero());
-            annotated.insert(var.id, (var.clone(), val));
-        }
-        annotated
+        annotate_public_inputs(&self.module.pubs, intended_pi_pos, pi)
     }
-}
-
-impl<F, P> Circuit<F, P> for PlonkModule<F, P>
-where
-    F: PrimeField,
-    P: TEModelParameters<BaseField = F>,
-{
-    const CIRCUIT_ID: [u8; 32] = [0xff; 32];
 
-    fn gadget(&mut self, composer: &mut StandardComposer<F, P>) -> Result<(), Error> {
-        let mut inputs = BTreeMap::new();
-        for (var, field_elt) in &self.variable_map {
-            inputs.insert(var, composer.add_input(*field_elt));
-        }
-        let zero = composer.zero_var();
+    /* Lay the constraints of this module out as the arithmetic gates of a
+     * standard composer, starting with one gate per public input. */
+    pub fn gates(&self) -> Vec<PlonkGate<F>> {
+        let mut variables = HashMap::new();
+        collect_module_variables(&self.module, &mut variables);
+        let inputs: BTreeMap<VariableId, Option<VariableId>> =
+            variables.into_keys().map(|var| (var, Some(var))).collect();
+        let zero = None;
+        let mut gates = PlonkGates(vec![]);
         // It is assumed that the generated PublicInputs will share the same
         // order as this module's public variables
         for var in &self.module.pubs {
-            composer.arithmetic_gate(|gate| {
+            gates.arithmetic_gate(|gate| {
                 gate.witness(inputs[&var.id], zero, Some(zero))
                     .add(-F::one(), F::zero())
                     .pi(self.variable_map[&var.id])
@@ -304,14 +357,14 @@ where
                     // Variables on the LHS
                     // v1 = v2
                     (Expr::Variable(v1), Expr::Variable(v2)) => {
-                        composer.arithmetic_gate(|gate| {
+                        gates.arithmetic_gate(|gate| {
                             gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                 .add(F::one(), -F::one())
                         });
                     }
                     // v1 = c2
                     (Expr::Variable(v1), Expr::Constant(c2)) => {
-                        composer.arithmetic_gate(|gate| {
+                        gates.arithmetic_gate(|gate| {
                             gate.witness(inputs[&v1.id], zero, Some(zero))
                                 .add(F::one(), F::zero())
                                 .constant(make_constant(&-c2))
@@ -320,7 +373,7 @@ where
                     // v1 = -c2
                     (Expr::Variable(v1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Constant(c2) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(c2))
@@ -330,7 +383,7 @@ where
                     // v1 = -v2
                     (Expr::Variable(v1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Variable(v2) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                     .add(F::one(), F::one())
                             });
@@ -342,7 +395,7 @@ where
                             Expr::Constant(c2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(&(-c2-c3)))
@@ -355,7 +408,7 @@ where
                             Expr::Variable(v2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                     .add(F::one(), -F::one())
                                     .constant(make_constant(&-c3))
@@ -368,7 +421,7 @@ where
                             Expr::Constant(c2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), -F::one())
                                     .constant(make_constant(&-c2))
@@ -381,7 +434,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(inputs[&v3.id]))
                                     .add(F::one(), -F::one())
                                     .out(-F::one())
@@ -394,7 +447,7 @@ where
                             Expr::Constant(c2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(&(-c2+c3)))
@@ -407,7 +460,7 @@ where
                             Expr::Variable(v2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                     .add(F::one(), -F::one())
                                     .constant(make_constant(c3))
@@ -420,7 +473,7 @@ where
                             Expr::Constant(c2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), F::one())
                                     .constant(make_constant(&-c2))
@@ -433,7 +486,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(inputs[&v3.id]))
                                     .add(F::one(), -F::one())
                                     .out(F::one())
@@ -449,7 +502,7 @@ where
                         ) if {
                             let op1: F = make_constant(c2);
                             let op2: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(-(op1/op2))
@@ -463,7 +516,7 @@ where
                             Expr::Constant(c3),
                         ) if {
                             let op2: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                     .add(F::one(), -(F::one()/op2))
                             });
@@ -476,7 +529,7 @@ where
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c2);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(zero))
                                     .mul(F::one())
                                     .constant(-op1)
@@ -489,7 +542,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(inputs[&v2.id]))
                                     .mul(F::one())
                                     .out(-F::one())
@@ -505,7 +558,7 @@ where
                         ) if {
                             let op1: F = make_constant(c2);
                             let op2: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(-(if op2 == F::zero() { F::zero() } else { op1/op2 }))
@@ -519,7 +572,7 @@ where
                             Expr::Constant(c3),
                         ) if {
                             let op2: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                     .add(F::one(), -(if op2 == F::zero() { F::zero() } else { F::one()/op2 }))
                             });
@@ -532,7 +585,7 @@ where
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c2);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(zero))
                                     .mul(F::one())
                                     .constant(-op1)
@@ -545,7 +598,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(inputs[&v2.id]))
                                     .mul(F::one())
                                     .out(-F::one())
@@ -561,7 +614,7 @@ where
                         ) if {
                             let op1: F = make_constant(c2);
                             let op2: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(-(op1*op2))
@@ -575,7 +628,7 @@ where
                             Expr::Constant(c3),
                         ) if {
                             let op2: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v2.id], Some(zero))
                                     .add(F::one(), -op2)
                             });
@@ -588,7 +641,7 @@ where
                             Expr::Variable(v3),
                         ) if {
                             let op2: F = make_constant(c2);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v1.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), -op2)
                             });
@@ -600,7 +653,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], inputs[&v3.id], Some(inputs[&v1.id]))
                                     .mul(F::one())
                                     .out(-F::one())
@@ -610,7 +663,7 @@ where
                     // Now for constants on the LHS
                     // c1 = v2
                     (Expr::Constant(c1), Expr::Variable(v2)) => {
-                        composer.arithmetic_gate(|gate| {
+                        gates.arithmetic_gate(|gate| {
                             gate.witness(inputs[&v2.id], zero, Some(zero))
                                 .add(F::one(), F::zero())
                                 .constant(make_constant(&-c1))
@@ -618,7 +671,7 @@ where
                     }
                     // c1 = c2
                     (Expr::Constant(c1), Expr::Constant(c2)) => {
-                        composer.arithmetic_gate(|gate| {
+                        gates.arithmetic_gate(|gate| {
                             gate.witness(zero, zero, Some(zero))
                                 .add(F::zero(), F::zero())
                                 .constant(make_constant(&(c2 - c1)))
@@ -627,7 +680,7 @@ where
                     // c1 = -c2
                     (Expr::Constant(c1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Constant(c2) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(zero, zero, Some(zero))
                                     .add(F::zero(), F::zero())
                                     .constant(make_constant(&(c1+c2)))
@@ -637,7 +690,7 @@ where
                     // c1 = -v2
                     (Expr::Constant(c1), Expr::Negate(e2))
                         if matches!(&e2.v, Expr::Variable(v2) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(c1))
@@ -650,7 +703,7 @@ where
                             Expr::Constant(c2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(zero, zero, Some(zero))
                                     .add(F::zero(), F::zero())
                                     .constant(make_constant(&(c1-c2-c3)))
@@ -663,7 +716,7 @@ where
                             Expr::Variable(v2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(&(c3-c1)))
@@ -676,7 +729,7 @@ where
                             Expr::Constant(c2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v3.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(&(c2-c1)))
@@ -689,7 +742,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), F::one())
                                     .constant(make_constant(&-c1))
@@ -702,7 +755,7 @@ where
                             Expr::Constant(c2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(zero, zero, Some(zero))
                                     .add(F::zero(), F::zero())
                                     .constant(make_constant(&(c2-c3-c1)))
@@ -715,7 +768,7 @@ where
                             Expr::Variable(v2),
                             Expr::Constant(c3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(&(-c3-c1)))
@@ -728,7 +781,7 @@ where
                             Expr::Constant(c2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v3.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(make_constant(&(c1-c2)))
@@ -741,7 +794,7 @@ where
                             Expr::Variable(v2),
                             Expr::Variable(v3),
                         ) if {
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), -F::one())
                                     .constant(make_constant(&-c1))
@@ -758,7 +811,7 @@ where
                             let op1: F = make_constant(c1);
                             let op2: F = make_constant(c2);
                             let op3: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(zero, zero, Some(zero))
                                     .add(F::zero(), F::zero())
                                     .constant(op1-(op2/op3))
@@ -773,7 +826,7 @@ where
                         ) if {
                             let op1: F = make_constant(c1);
                             let op3: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(-(op1*op3))
@@ -788,7 +841,7 @@ where
                         ) if {
                             let op1: F = make_constant(c1);
                             let op2: F = make_constant(c2);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v3.id], zero, Some(zero))
                                     .constant(-(op2/op1))
                             });
@@ -801,7 +854,7 @@ where
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c1);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), -op1)
                             });
@@ -817,7 +870,7 @@ where
                             let op1: F = make_constant(c1);
                             let op2: F = make_constant(c2);
                             let op3: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(zero, zero, Some(zero))
                                     .add(F::zero(), F::zero())
                                     .constant(op1-(if op3 == F::zero() { F::zero() } else { op2/op3 }))
@@ -832,7 +885,7 @@ where
                         ) if {
                             let op1: F = make_constant(c1);
                             let op3: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], zero, Some(zero))
                                     .add(F::one(), F::zero())
                                     .constant(-(op1*op3))
@@ -847,7 +900,7 @@ where
                         ) if {
                             let op1: F = make_constant(c1);
                             let op2: F = make_constant(c2);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v3.id], zero, Some(zero))
                                     .constant(-(if op1 == F::zero() { F::zero() } else { op2/op1 }))
                             });
@@ -860,7 +913,7 @@ where
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c1);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], inputs[&v3.id], Some(zero))
                                     .add(F::one(), -op1)
                             });
@@ -876,7 +929,7 @@ where
                             let op1: F = make_constant(c1);
                             let op2: F = make_constant(c2);
                             let op3: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(zero, zero, Some(zero))
                                     .add(F::zero(), F::zero())
                                     .constant(op1-(op2*op3))
@@ -891,7 +944,7 @@ where
                         ) if {
                             let op1: F = make_constant(c1);
                             let op3: F = make_constant(c3);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], zero, Some(zero))
                                     .add(op3, F::zero())
                                     .constant(-op1)
@@ -906,7 +959,7 @@ where
                         ) if {
                             let op1: F = make_constant(c1);
                             let op2: F = make_constant(c2);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v3.id], zero, Some(zero))
                                     .add(op2, F::zero())
                                     .constant(-op1)
@@ -920,17 +973,90 @@ where
                             Expr::Variable(v3),
                         ) if {
                             let op1: F = make_constant(c1);
-                            composer.arithmetic_gate(|gate| {
+                            gates.arithmetic_gate(|gate| {
                                 gate.witness(inputs[&v2.id], inputs[&v3.id], Some(zero))
                                     .mul(F::one())
                                     .constant(-op1)
                             });
                             true
                         }) => {}
+                    // v1 = c2*v2 + c3*v3 + c0, as made by folding linear combinations
+                    (Expr::Variable(v1), _)
+                        if matches!(
+                            weighted_sum(rhs).filter(|(terms, _)| terms.len() < 3),
+                            Some((terms, c0)) if {
+                                let weight = |idx: usize| match terms.get(idx) {
+                                    Some((c, var)) => (inputs[&var.id], make_constant::<F>(c)),
+                                    None => (zero, F::zero()),
+                                };
+                                let ((b, c2), (c, c3)) = (weight(0), weight(1));
+                                gates.arithmetic_gate(|gate| {
+                                    gate.witness(inputs[&v1.id], b, Some(c))
+                                        .add(F::one(), -c2)
+                                        .out(-c3)
+                                        .constant(-make_constant::<F>(&c0))
+                                });
+                                true
+                            }
+                        ) => {}
                     _ => panic!("unsupported constraint encountered: {expr}"),
                 }
             }
         }
+        gates.0
+    }
+}
+
+/* Annotate the given public inputs with the given public variables. This
+ * function assumes that both occur in the same order. */
+pub fn annotate_public_inputs<F: PrimeField>(
+    pubs: &[Variable],
+    intended_pi_pos: &[usize],
+    pi: &PublicInputs<F>,
+) -> HashMap<VariableId, (Variable, F)> {
+    // First map public input positions to values
+    let mut pi_map = BTreeMap::new();
+    for (pos, val) in pi.get_pos().zip(pi.get_vals()) {
+        pi_map.insert(*pos, *val);
+    }
+    // Next, annotate the public inputs with the variables
+    let mut annotated = HashMap::new();
+    for (var, pos) in pubs.iter().zip(intended_pi_pos) {
+        let val = pi_map.get(pos).copied().unwrap_or(F::zero());
+        annotated.insert(var.id, (var.clone(), val));
+    }
+    annotated
+}
+
+impl<F, P> Circuit<F, P> for PlonkModule<F, P>
+where
+    F: PrimeField,
+    P: TEModelParameters<BaseField = F>,
+{
+    const CIRCUIT_ID: [u8; 32] = [0xff; 32];
+
+    fn gadget(&mut self, composer: &mut StandardComposer<F, P>) -> Result<(), Error> {
+        let mut inputs = BTreeMap::new();
+        for (var, field_elt) in &self.variable_map {
+            inputs.insert(var, composer.add_input(*field_elt));
+        }
+        let zero = composer.zero_var();
+        for gate in self.gates() {
+            let (a, b, c) = gate.witness;
+            let wire = |var: Option<VariableId>| var.map_or(zero, |var| inputs[&var]);
+            composer.arithmetic_gate(|arith| {
+                arith
+                    .witness(wire(a), wire(b), Some(wire(c)))
+                    .mul(gate.q_m)
+                    .add(gate.q_l, gate.q_r)
+                    .out(gate.q_o)
+                    .constant(gate.q_c);
+                match gate.pi {
+                    Some(pi) => arith.pi(pi),
+                    None => arith,
+                }
+            });
+        }
         Ok(())
     }
 
@@ -0,0 +1,178 @@
+/* The vamp-ir standard prelude, version 0.1.0. These definitions are made
+   available to every program and can be shadowed by definitions of the same
+   name. Bit representations are little-endian lists, so the head of a list
+   returned by range is the least significant bit.
+*/
+
+// Access elements of a list
+
+def hd (h:t) = h;
+
+def tl (h:t) = t;
+
+def nth lst n = hd (iter n tl lst);
+
+// Append two lists together
+
+def cons x y = x:y;
+
+def append xs = fold xs cons;
+
+// Take the given prefix of a given list
+
+def take_base lst = [];
+
+def take_ind take (h:t) = h:(take t);
+
+def take n = iter n take_ind take_base;
+
+// Make function that always returns constant
+
+def const x y = x;
+
+// Extract the first and second elements of any supplied pair
+
+def fst (a,b) = a;
+
+def snd (a,b) = b;
+
+// Pair up corresponding elements of each list
+
+def zip_aux x z (y:ys) = (x, y):(z ys);
+
+def zip xs = fold xs zip_aux (const []);
+
+// Apply function to each element of list
+
+def map_ind f x acc = (f x):acc;
+
+def map f xs = fold xs (map_ind f) [];
+
+// Ensure that the given argument is 1 or 0, and returns it
+
+def bool x = { x*(x-1) = 0; x };
+
+// Multiply each list element by corresponding unit
+
+def combine_aux x y = x + 2*y;
+
+def combine xs = fold xs combine_aux 0;
+
+// Boolean logic on values in the domain {0, 1}
+
+def bit_not a = 1-a;
+
+def bit_and (a,b) = a*b;
+
+def bit_or (a,b) = a + b - a*b;
+
+def bit_xor (a,b) = a*(1-b)+(1-a)*b;
+
+// Apply given function to corresponding bit-pairs in n bit representation
+
+def bitwise n g a b = {
+    def zipped = zip (range n a) (range n b);
+    def new_bits = map g zipped;
+    combine new_bits
+};
+
+// Bitwise logic on n bit values
+
+def xor n = bitwise n bit_xor;
+
+def or n = bitwise n bit_or;
+
+def and n = bitwise n bit_and;
+
+def not n y = combine (map bit_not (range n y));
+
+// Shift right by one bit for n bit values, preserving the sign bit
+
+def ashr n x = {
+    def a = range n x;
+    def msb = nth a (n-1);
+    def new_bits = append (tl a) (msb:[]);
+    combine new_bits
+};
+
+// Shift right by one bit for n bit values
+
+def lshr n x = {
+    def a = range n x;
+    def new_bits = append (tl a) (0:[]);
+    combine new_bits
+};
+
+// Shift left by one bit for n bit values
+
+def shl n x = {
+    def a = range n x;
+    combine (0:(take (n-1) a))
+};
+
+// Rotate right by one bit for n bit values
+
+def ror n x = {
+    def a = range n x;
+    def lsb = hd a;
+    def new_bits = append (tl a) (lsb:[]);
+    combine new_bits
+};
+
+// Rotate left by one bit for n bit values
+
+def rol n x = {
+    def a = range n x;
+    def msb = nth a (n-1);
+    def lsbs = take (n-1) a;
+    combine (msb:lsbs)
+};
+
+// Rotate right and left by k bits for n bit values. The amount k must be a
+// constant
+
+def rotr n k = iter k (ror n);
+
+def rotl n k = iter k (rol n);
+
+// Add two n bit values modulo 2^n
+
+def add n a b = {
+    def bits = range (n+1) (a+b);
+    combine (take n bits)
+};
+
+// Subtract two n bit values modulo 2^n
+
+def sub n a b = {
+    def bits = range (n+1) (a+(2^n)-b);
+    combine (take n bits)
+};
+
+// Unsigned less than or equal to for n bit values. 1 if true, 0 otherwise. Both
+// values are constrained to fit into n bits
+
+def ule n a b = {
+    def a_bits = range n a;
+    def b_bits = range n b;
+    def c = range (n+1) ((2^n)+b-a);
+    nth c n
+};
+
+// Unsigned less than for n bit values
+
+def ult n a b = 1 - ule n b a;
+
+// Signed less than for n bit values in two's complement. Values of the same sign
+// compare like unsigned ones, and otherwise the negative one is smaller
+
+def slt n a b = {
+    def sa = nth (range n a) (n-1);
+    def sb = nth (range n b) (n-1);
+    def d = bit_xor (sa, sb);
+    d*sa + (1-d)*(ult n a b)
+};
+
+// Signed less than or equal to for n bit values in two's complement
+
+def sle n a b = 1 - slt n b a;
@@ -0,0 +1,450 @@
+use crate::ast::{Module, VariableId};
+use crate::error::Error;
+use crate::export::json::read_module_json;
+use crate::halo2::synth::Halo2Module;
+use crate::plonk::synth::PlonkModule;
+use crate::proof_system::{artifact_error, ProofSystem};
+use crate::qprintln;
+use crate::util::{get_given_circuit_assignments, prompt_inputs, read_inputs_from_file, Config};
+
+use ark_bls12_381::Fr as BlsScalar;
+use ark_ed_on_bls12_381::EdwardsParameters as JubJubParameters;
+use halo2_proofs::pasta::Fp;
+
+use clap::{Args, Subcommand, ValueEnum};
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum ProofSystems {
+    /// PLONK general-purpose zero-knowledge proof scheme
+    Plonk,
+    /// Halo 2 zero-knowledge proving system
+    Halo2,
+}
+
+#[derive(Args)]
+pub struct BackendArg {
+    /// Proof system with which the command is carried out
+    #[arg(short, long, value_enum)]
+    backend: ProofSystems,
+}
+
+#[derive(Subcommand)]
+pub enum ProofSystemCommands {
+    /// Sets up the public parameters required for proving with a backend
+    Setup {
+        #[command(flatten)]
+        backend: BackendArg,
+        #[command(flatten)]
+        args: SetupArgs,
+    },
+    /// Compiles a given source file to a circuit of a backend
+    Compile {
+        #[command(flatten)]
+        backend: BackendArg,
+        #[command(flatten)]
+        args: CompileArgs,
+    },
+    /// Proves knowledge of witnesses satisfying a circuit
+    Prove {
+        #[command(flatten)]
+        backend: BackendArg,
+        #[command(flatten)]
+        args: ProveArgs,
+    },
+    /// Verifies that a proof is a correct one
+    Verify {
+        #[command(flatten)]
+        backend: BackendArg,
+        #[command(flatten)]
+        args: VerifyArgs,
+    },
+    /// Exports the data required to verify proofs of a circuit
+    ExportVk {
+        #[command(flatten)]
+        backend: BackendArg,
+        #[command(flatten)]
+        args: ExportVkArgs,
+    },
+}
+
+/* The subcommands on circuits that every proof system supports, which the
+ * backend-specific subcommands offer once their backend is chosen. */
+#[derive(Subcommand)]
+pub enum CircuitCommands {
+    /// Compiles a given source file to a circuit
+    Compile(CompileArgs),
+    /// Proves knowledge of witnesses satisfying a circuit
+    Prove(ProveArgs),
+    /// Verifies that a proof is a correct one
+    Verify(VerifyArgs),
+    /// Exports the data required to verify proofs of a circuit
+    ExportVk(ExportVkArgs),
+}
+
+#[derive(Args)]
+pub struct SetupArgs {
+    /// Base 2 logarithm of the maximum number of constraints in a circuit
+    #[arg(short)]
+    k: u32,
+    /// Path to which the public parameters are written
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Do not perform validity checks on public parameters and circuits
+    #[arg(long)]
+    unchecked: bool,
+}
+
+#[derive(Args)]
+pub struct CompileArgs {
+    /// Path to public parameters, which backends that can size their own
+    /// parameters otherwise embed in the circuit
+    #[arg(short, long)]
+    universal_params: Option<PathBuf>,
+    /// Path to source file to be compiled
+    #[arg(short, long, required_unless_present = "module")]
+    source: Option<PathBuf>,
+    /// Path to three-address constraints exported as JSON, compiled in place
+    /// of a source file
+    #[arg(long, conflicts_with = "source")]
+    module: Option<PathBuf>,
+    /// Path to which circuit is written
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Do not perform validity checks on public parameters and circuits
+    #[arg(long)]
+    unchecked: bool,
+}
+
+#[derive(Args)]
+pub struct ProveArgs {
+    /// Path to public parameters, if they are not embedded in the circuit
+    #[arg(short, long)]
+    universal_params: Option<PathBuf>,
+    /// Path to circuit on which to construct proof
+    #[arg(short, long)]
+    circuit: PathBuf,
+    /// Path to which the proof is written
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Path to prover's input file
+    #[arg(short, long)]
+    inputs: Option<PathBuf>,
+    /// Do not perform validity checks on public parameters and circuits
+    #[arg(long)]
+    unchecked: bool,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Path to a verifying key exported from the circuit
+    #[arg(short = 'k', long, required_unless_present = "circuit")]
+    verifying_key: Option<PathBuf>,
+    /// Path to the circuit, from which the verifying key is then derived
+    #[arg(short, long, conflicts_with = "verifying_key")]
+    circuit: Option<PathBuf>,
+    /// Path to public parameters, if they are not embedded in the circuit
+    #[arg(short, long, requires = "circuit")]
+    universal_params: Option<PathBuf>,
+    /// Path to the proof that is being verified
+    #[arg(short, long)]
+    proof: PathBuf,
+    /// Path to a JSON file with the public inputs that the proof must be for
+    #[arg(long)]
+    public_inputs: Option<PathBuf>,
+    /// Do not perform validity checks on public parameters and circuits
+    #[arg(long)]
+    unchecked: bool,
+}
+
+#[derive(Args)]
+pub struct ExportVkArgs {
+    /// Path to public parameters, if they are not embedded in the circuit
+    #[arg(short, long)]
+    universal_params: Option<PathBuf>,
+    /// Path to circuit whose verifying key is exported
+    #[arg(short, long)]
+    circuit: PathBuf,
+    /// Path to which the verifying key is written
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Do not perform validity checks on public parameters and circuits
+    #[arg(long)]
+    unchecked: bool,
+}
+
+impl ProofSystemCommands {
+    /* The proof system selected for this command. */
+    fn backend(&self) -> ProofSystems {
+        match self {
+            Self::Setup { backend, .. }
+            | Self::Compile { backend, .. }
+            | Self::Prove { backend, .. }
+            | Self::Verify { backend, .. }
+            | Self::ExportVk { backend, .. } => backend.backend,
+        }
+    }
+}
+
+pub fn proof_system(commands: &ProofSystemCommands, config: &Config) -> Result<(), Error> {
+    match commands.backend() {
+        ProofSystems::Plonk => {
+            run_with_backend::<PlonkModule<BlsScalar, JubJubParameters>>(commands, config)
+        }
+        ProofSystems::Halo2 => run_with_backend::<Halo2Module<Fp>>(commands, config),
+    }
+}
+
+/* Run the given backend-agnostic command using the given proof system. */
+fn run_with_backend<S: ProofSystem>(
+    commands: &ProofSystemCommands,
+    config: &Config,
+) -> Result<(), Error> {
+    match commands {
+        ProofSystemCommands::Setup { args, .. } => setup_cmd::<S>(args, config),
+        ProofSystemCommands::Compile { args, .. } => compile_cmd::<S>(args, config),
+        ProofSystemCommands::Prove { args, .. } => prove_cmd::<S>(args, config),
+        ProofSystemCommands::Verify { args, .. } => verify_cmd::<S>(args, config),
+        ProofSystemCommands::ExportVk { args, .. } => export_vk_cmd::<S>(args, config),
+    }
+}
+
+/* Run the given command on circuits of the given proof system. */
+pub fn run<S: ProofSystem>(commands: &CircuitCommands, config: &Config) -> Result<(), Error> {
+    match commands {
+        CircuitCommands::Compile(args) => compile_cmd::<S>(args, config),
+        CircuitCommands::Prove(args) => prove_cmd::<S>(args, config),
+        CircuitCommands::Verify(args) => verify_cmd::<S>(args, config),
+        CircuitCommands::ExportVk(args) => export_vk_cmd::<S>(args, config),
+    }
+}
+
+fn open_file(path: &PathBuf, artifact: &str) -> Result<File, Error> {
+    File::open(path).map_err(|e| artifact_error(artifact, e))
+}
+
+pub(crate) fn create_file(path: &PathBuf, artifact: &str) -> Result<File, Error> {
+    File::create(path).map_err(|e| artifact_error(artifact, e))
+}
+
+pub(crate) fn read_params<S: ProofSystem>(
+    path: &PathBuf,
+    unchecked: bool,
+    config: &Config,
+) -> Result<S::Params, Error> {
+    qprintln!(config, "* Reading public parameters...");
+    S::read_params(&mut open_file(path, "public parameters")?, unchecked)
+}
+
+/* Read the public parameters at the given path if there is one. */
+pub(crate) fn read_optional_params<S: ProofSystem>(
+    path: &Option<PathBuf>,
+    unchecked: bool,
+    config: &Config,
+) -> Result<Option<S::Params>, Error> {
+    path.as_ref()
+        .map(|path| read_params::<S>(path, unchecked, config))
+        .transpose()
+}
+
+pub(crate) fn read_circuit<S: ProofSystem>(
+    path: &PathBuf,
+    params: Option<&S::Params>,
+    unchecked: bool,
+    config: &Config,
+) -> Result<S::CircuitData, Error> {
+    qprintln!(config, "* Reading arithmetic circuit...");
+    S::read_circuit(&mut open_file(path, "circuit")?, params, unchecked)
+}
+
+/* Read the inputs to the given circuit from the given file, from the inputs
+ * file next to the given artifact, or interactively, in that order of
+ * preference. The inputs are left as integers so that each caller can embed
+ * them into the field it works over, and inputs that are not given are left
+ * for the witness solver to determine. */
+pub(crate) fn read_inputs(
+    inputs: &Option<PathBuf>,
+    artifact: &Path,
+    module: &Module,
+    config: &Config,
+) -> Result<HashMap<VariableId, BigInt>, Error> {
+    let expected_path_to_inputs = artifact.with_extension("inputs");
+    let path_to_inputs = inputs
+        .clone()
+        .or_else(|| Some(expected_path_to_inputs).filter(|path| path.exists()));
+    match path_to_inputs {
+        Some(path_to_inputs) => {
+            qprintln!(
+                config,
+                "* Reading inputs from file {}...",
+                path_to_inputs.to_string_lossy()
+            );
+            let raw_inputs: HashMap<String, BigInt> = read_inputs_from_file(&path_to_inputs)?;
+            Ok(get_given_circuit_assignments(module, &raw_inputs))
+        }
+        None => {
+            qprintln!(config, "* Soliciting circuit witnesses...");
+            Ok(prompt_inputs(module))
+        }
+    }
+}
+
+/* Implements the subcommand that generates the public parameters of a proof
+ * system. */
+pub fn setup_cmd<S: ProofSystem>(
+    SetupArgs {
+        k,
+        output,
+        unchecked,
+    }: &SetupArgs,
+    config: &Config,
+) -> Result<(), Error> {
+    qprintln!(config, "* Setting up public parameters...");
+    let params = S::setup(*k)?;
+    S::write_params(
+        &params,
+        *unchecked,
+        &mut create_file(output, "public parameters")?,
+    )?;
+    qprintln!(config, "* Public parameter setup success!");
+    Ok(())
+}
+
+/* Implements the subcommand that compiles a vamp-ir file into a circuit of a
+ * proof system. */
+fn compile_cmd<S: ProofSystem>(
+    CompileArgs {
+        universal_params,
+        source,
+        module,
+        output,
+        unchecked,
+    }: &CompileArgs,
+    config: &Config,
+) -> Result<(), Error> {
+    let params = read_optional_params::<S>(universal_params, *unchecked, config)?;
+    let circuit_data = match (source, module) {
+        (_, Some(module)) => {
+            qprintln!(config, "* Reading three-address constraints...");
+            S::synthesize(
+                read_module_json(module, &S::FieldOps::default())?,
+                params.as_ref(),
+                config,
+            )?
+        }
+        (Some(source), None) => {
+            qprintln!(config, "* Compiling constraints...");
+            let module = Module::parse_file(source)?;
+            S::compile(module, params.as_ref(), config)?
+        }
+        (None, None) => unreachable!("source or module required"),
+    };
+    qprintln!(config, "* Serializing circuit to storage...");
+    S::write_circuit(
+        &circuit_data,
+        params.is_none(),
+        *unchecked,
+        &mut create_file(output, "circuit")?,
+    )?;
+    qprintln!(config, "* Constraint compilation success!");
+    Ok(())
+}
+
+/* Implements the subcommand that creates a proof of knowledge of witnesses. */
+fn prove_cmd<S: ProofSystem>(
+    ProveArgs {
+        universal_params,
+        circuit,
+        output,
+        inputs,
+        unchecked,
+    }: &ProveArgs,
+    config: &Config,
+) -> Result<(), Error> {
+    let params = read_optional_params::<S>(universal_params, *unchecked, config)?;
+    let circuit_data = read_circuit::<S>(circuit, params.as_ref(), *unchecked, config)?;
+    let module = S::circuit_module(&circuit_data);
+    let assignments = read_inputs(inputs, circuit, module, config)?
+        .into_iter()
+        .map(|(id, val)| (id, S::make_constant(&val)))
+        .collect();
+    let proof_data = S::prove(&circuit_data, params.as_ref(), assignments, config)?;
+    qprintln!(config, "* Serializing proof to storage...");
+    S::write_proof(&proof_data, &mut create_file(output, "proof")?)?;
+    qprintln!(config, "* Proof generation success!");
+    Ok(())
+}
+
+/* Implements the subcommand that writes out the data required to verify
+ * proofs of a circuit. */
+fn export_vk_cmd<S: ProofSystem>(
+    ExportVkArgs {
+        universal_params,
+        circuit,
+        output,
+        unchecked,
+    }: &ExportVkArgs,
+    config: &Config,
+) -> Result<(), Error> {
+    let params = read_optional_params::<S>(universal_params, *unchecked, config)?;
+    let circuit_data = read_circuit::<S>(circuit, params.as_ref(), *unchecked, config)?;
+    let verifier_data = S::export_vk(&circuit_data, params.as_ref(), config)?;
+    qprintln!(config, "* Serializing verifying key to storage...");
+    S::write_verifier_data(&verifier_data, &mut create_file(output, "verifying key")?)?;
+    qprintln!(config, "* Verifying key export success!");
+    Ok(())
+}
+
+/* Implements the subcommand that verifies that a proof is correct. */
+fn verify_cmd<S: ProofSystem>(
+    VerifyArgs {
+        verifying_key,
+        circuit,
+        universal_params,
+        proof,
+        public_inputs,
+        unchecked,
+    }: &VerifyArgs,
+    config: &Config,
+) -> Result<(), Error> {
+    let verifier_data = match verifying_key {
+        Some(path) => {
+            qprintln!(config, "* Reading verifying key...");
+            S::read_verifier_data(&mut open_file(path, "verifying key")?)?
+        }
+        None => {
+            let circuit = circuit.as_ref().expect("circuit or verifying key required");
+            let params = read_optional_params::<S>(universal_params, *unchecked, config)?;
+            let circuit_data = read_circuit::<S>(circuit, params.as_ref(), *unchecked, config)?;
+            S::export_vk(&circuit_data, params.as_ref(), config)?
+        }
+    };
+    qprintln!(config, "* Reading zero-knowledge proof...");
+    let proof_data = S::read_proof(&mut open_file(proof, "proof")?)?;
+
+    qprintln!(config, "* Public inputs:");
+    for (var, val) in S::public_inputs(&verifier_data, &proof_data)? {
+        qprintln!(config, "{} = {}", var, val);
+    }
+
+    match public_inputs {
+        Some(path) => {
+            qprintln!(
+                config,
+                "* Verifying proof against public inputs from {}...",
+                path.to_string_lossy()
+            );
+            let expected: HashMap<String, BigInt> = read_inputs_from_file(path)?;
+            S::verify_with_public_inputs(&verifier_data, &proof_data, &expected, config)?;
+        }
+        None => {
+            qprintln!(config, "* Verifying proof validity...");
+            S::verify(&verifier_data, &proof_data, config)?;
+        }
+    }
+    qprintln!(config, "* Zero-knowledge proof is valid");
+    Ok(())
+}
@@ -0,0 +1,170 @@
+pub mod cli;
+
+use crate::ast::{Module, Variable, VariableId};
+use crate::error::Error;
+use crate::transform::{compile, FieldOps};
+use crate::util::Config;
+
+use num_bigint::BigInt;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+/* A proving system that vamp-ir circuits can be compiled to. Implementing this
+ * trait is all that is needed to make a backend available through the
+ * backend-agnostic subcommands. Public parameters are optional wherever a
+ * system can size its own parameters and carry them in its circuits, and
+ * systems that cannot fail with Error::MissingParams when they are absent. */
+pub trait ProofSystem {
+    // The field over which circuits are expressed
+    type Field;
+    // Arithmetic over the field used during compilation
+    type FieldOps: FieldOps + Default;
+    // Public parameters shared by the circuits of this system
+    type Params;
+    // A compiled circuit together with the keys required to prove it
+    type CircuitData;
+    // Everything that is required to verify proofs of a circuit
+    type VerifierData;
+    // A proof together with the public inputs that it was made for
+    type ProofData;
+
+    /* Embed the given integer into the field. */
+    fn make_constant(c: &BigInt) -> Self::Field;
+
+    /* Generate public parameters for circuits with up to 2^k constraints. */
+    fn setup(k: u32) -> Result<Self::Params, Error>;
+
+    /* Turn the given three-address module into a circuit. */
+    fn synthesize(
+        module_3ac: Module,
+        params: Option<&Self::Params>,
+        config: &Config,
+    ) -> Result<Self::CircuitData, Error>;
+
+    /* Get the three-address module underlying the given circuit. */
+    fn circuit_module(circuit_data: &Self::CircuitData) -> &Module;
+
+    /* Prove knowledge of the given assignments to the circuit's inputs. */
+    fn prove(
+        circuit_data: &Self::CircuitData,
+        params: Option<&Self::Params>,
+        assignments: HashMap<VariableId, Self::Field>,
+        config: &Config,
+    ) -> Result<Self::ProofData, Error>;
+
+    /* Extract the data required to verify proofs of the given circuit. */
+    fn export_vk(
+        circuit_data: &Self::CircuitData,
+        params: Option<&Self::Params>,
+        config: &Config,
+    ) -> Result<Self::VerifierData, Error>;
+
+    /* Check the given proof against the public inputs contained in it. */
+    fn verify(
+        verifier_data: &Self::VerifierData,
+        proof_data: &Self::ProofData,
+        config: &Config,
+    ) -> Result<(), Error>;
+
+    /* Annotate the public inputs contained in the given proof with the public
+     * variables of the circuit, in declaration order. Fails if the proof does
+     * not carry a public input for each public variable. */
+    fn public_inputs(
+        verifier_data: &Self::VerifierData,
+        proof_data: &Self::ProofData,
+    ) -> Result<Vec<(Variable, BigInt)>, Error>;
+
+    /* Read and write public parameters and circuits. Systems that check the
+     * validity of these artifacts skip the checks when unchecked is set. */
+    fn read_params(reader: &mut dyn Read, unchecked: bool) -> Result<Self::Params, Error>;
+    fn write_params(
+        params: &Self::Params,
+        unchecked: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error>;
+    fn read_circuit(
+        reader: &mut dyn Read,
+        params: Option<&Self::Params>,
+        unchecked: bool,
+    ) -> Result<Self::CircuitData, Error>;
+    /* Write the given circuit, along with its public parameters if they are
+     * not kept separately. */
+    fn write_circuit(
+        circuit_data: &Self::CircuitData,
+        with_params: bool,
+        unchecked: bool,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error>;
+    fn read_verifier_data(reader: &mut dyn Read) -> Result<Self::VerifierData, Error>;
+    fn write_verifier_data(
+        verifier_data: &Self::VerifierData,
+        writer: &mut dyn Write,
+    ) -> Result<(), Error>;
+    fn read_proof(reader: &mut dyn Read) -> Result<Self::ProofData, Error>;
+    fn write_proof(proof_data: &Self::ProofData, writer: &mut dyn Write) -> Result<(), Error>;
+
+    /* Check that the public inputs contained in the given proof take the given
+     * values, which are named after the public variables of the circuit. */
+    fn check_public_inputs(
+        verifier_data: &Self::VerifierData,
+        proof_data: &Self::ProofData,
+        expected: &HashMap<String, BigInt>,
+    ) -> Result<(), Error> {
+        let field_ops = Self::FieldOps::default();
+        for (var, actual) in Self::public_inputs(verifier_data, proof_data)? {
+            let var_name = public_variable_name(&var)?;
+            let expected = expected.get(&var_name).cloned().ok_or_else(|| {
+                Error::MissingVariableAssignment {
+                    var_name: var_name.clone(),
+                }
+            })?;
+            let expected = field_ops.canonical(expected);
+            if expected != actual {
+                return Err(Error::PublicInputMismatch {
+                    var_name,
+                    actual,
+                    expected,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /* Check the given proof against the given public input values, which are
+     * named after the public variables of the circuit. Systems whose proofs
+     * carry their own public inputs must not verify against those instead. */
+    fn verify_with_public_inputs(
+        verifier_data: &Self::VerifierData,
+        proof_data: &Self::ProofData,
+        expected: &HashMap<String, BigInt>,
+        config: &Config,
+    ) -> Result<(), Error> {
+        Self::check_public_inputs(verifier_data, proof_data, expected)?;
+        Self::verify(verifier_data, proof_data, config)
+    }
+
+    /* Compile the given module into a circuit of this proof system. */
+    fn compile(
+        module: Module,
+        params: Option<&Self::Params>,
+        config: &Config,
+    ) -> Result<Self::CircuitData, Error> {
+        let module_3ac = compile(module, &Self::FieldOps::default(), config)?;
+        Self::synthesize(module_3ac, params, config)
+    }
+}
+
+/* Get the name by which values are assigned to the given public variable. */
+pub(crate) fn public_variable_name(var: &Variable) -> Result<String, Error> {
+    var.name
+        .clone()
+        .ok_or_else(|| Error::UnnamedPublicVariable { v: var.clone() })
+}
+
+/* Make an error describing a failure to read or write the given artifact. */
+pub(crate) fn artifact_error(artifact: &str, e: impl ToString) -> Error {
+    Error::ArtifactError {
+        artifact: artifact.to_string(),
+        e: e.to_string(),
+    }
+}
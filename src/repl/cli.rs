@@ -8,8 +8,6 @@ use ark_bls12_381::Fr;
 use crate::halo2::synth::PrimeFieldOps as Halo2PrimeFieldOps;
 use halo2_proofs::pasta::Fp;
 
-use std::fs;
-
 use clap::Args;
 use std::path::PathBuf;
 
@@ -27,8 +25,7 @@ pub fn repl_cmd(source: &Option<PathBuf>, field_ops: &dyn FieldOps) -> Result<()
     let mut module: Module = Module::default();
 
     if let Some(path) = source {
-        let unparsed_file: String = fs::read_to_string(path).expect("cannot read file");
-        module = Module::parse(&unparsed_file).unwrap();
+        module = Module::parse_file(path)?;
         println!("Entering REPL with module loaded from file.");
         // println!("{}", module);
     } else {
@@ -0,0 +1,346 @@
+use crate::ast::{Expr, InfixOp, Module, Pat, TExpr, VariableId};
+use crate::error::Error;
+use crate::transform::{collect_expr_variables, collect_module_variables, FieldOps};
+use crate::util::UnsatisfiedEquality;
+use num_bigint::BigInt;
+use num_traits::{One, Zero};
+use std::collections::{HashMap, VecDeque};
+
+/* A computation that determines the value of a variable once the values of
+ * the variables that it refers to are known. */
+enum Computation<'a> {
+    // The definition of the given variable
+    Definition(VariableId, &'a TExpr),
+    // An equality that may determine the last of its variables to be unknown
+    Equality(&'a TExpr, &'a TExpr),
+}
+
+impl Computation<'_> {
+    /* The number of unknown variables at which this computation can be
+     * attempted. */
+    fn arity(&self) -> usize {
+        match self {
+            Self::Definition(_, _) => 0,
+            Self::Equality(_, _) => 1,
+        }
+    }
+}
+
+/* Solve for the values of all the variables of the given module from the
+ * given assignments, using the arithmetic of the given field. Since each
+ * backend's PrimeFieldOps implements FieldOps, this serves every prime field
+ * that vamp-ir targets. Definitions are evaluated in dependency order without
+ * recursion, and an equality in which a single variable remains unknown is
+ * solved for that variable when it occurs linearly. Definitions are preferred
+ * to equalities whenever both can determine a variable. Values are returned in
+ * canonical form, and the variables that could not be determined are reported
+ * in the error. */
+pub fn solve_witnesses(
+    module: &Module,
+    assigns: HashMap<VariableId, BigInt>,
+    field_ops: &dyn FieldOps,
+) -> Result<HashMap<VariableId, BigInt>, Error> {
+    let mut values: HashMap<VariableId, BigInt> = assigns
+        .into_iter()
+        .map(|(id, val)| (id, field_ops.canonical(val)))
+        .collect();
+    let mut computations = vec![];
+    for def in &module.defs {
+        if let Pat::Variable(var) = &def.0 .0.v {
+            // Given assignments take precedence over definitions
+            if !values.contains_key(&var.id) {
+                computations.push(Computation::Definition(var.id, &def.0 .1));
+            }
+        }
+    }
+    for expr in &module.exprs {
+        if let Expr::Infix(InfixOp::Equal, lhs, rhs) = &expr.v {
+            computations.push(Computation::Equality(lhs, rhs));
+        }
+    }
+    // Track the unknown variables of each computation, how many of them
+    // remain unknown, and the computations that are waiting on each of them
+    let mut unknowns = vec![];
+    let mut waiting = vec![];
+    let mut dependents: HashMap<VariableId, Vec<usize>> = HashMap::new();
+    let mut ready_defs = VecDeque::new();
+    let mut ready_eqs = VecDeque::new();
+    for (idx, computation) in computations.iter().enumerate() {
+        let mut vars = HashMap::new();
+        match computation {
+            Computation::Definition(_, expr) => collect_expr_variables(expr, &mut vars),
+            Computation::Equality(lhs, rhs) => {
+                collect_expr_variables(lhs, &mut vars);
+                collect_expr_variables(rhs, &mut vars);
+            }
+        }
+        let vars: Vec<_> = vars
+            .into_keys()
+            .filter(|id| !values.contains_key(id))
+            .collect();
+        for id in &vars {
+            dependents.entry(*id).or_default().push(idx);
+        }
+        if vars.len() == computation.arity() {
+            enqueue(computation, idx, &mut ready_defs, &mut ready_eqs);
+        }
+        waiting.push(vars.len());
+        unknowns.push(vars);
+    }
+    // Attempt each computation as soon as enough of its variables are known,
+    // resorting to equalities only when no definition can be evaluated
+    while let Some(idx) = ready_defs.pop_front().or_else(|| ready_eqs.pop_front()) {
+        let solved = match &computations[idx] {
+            // The variable may already have been solved from an equality
+            Computation::Definition(id, _) if values.contains_key(id) => None,
+            Computation::Definition(id, expr) => {
+                Some((*id, evaluate_expr(expr, &values, field_ops)))
+            }
+            Computation::Equality(lhs, rhs) => unknowns[idx]
+                .iter()
+                .find(|id| !values.contains_key(id))
+                .and_then(|id| {
+                    solve_linear(lhs, rhs, *id, &values, field_ops).map(|val| (*id, val))
+                }),
+        };
+        if let Some((id, val)) = solved {
+            values.insert(id, val);
+            for dependent in dependents.remove(&id).unwrap_or_default() {
+                waiting[dependent] -= 1;
+                if waiting[dependent] == computations[dependent].arity() {
+                    let computation = &computations[dependent];
+                    enqueue(computation, dependent, &mut ready_defs, &mut ready_eqs);
+                }
+            }
+        }
+    }
+    // Report the variables that remain unknown
+    let mut variables = HashMap::new();
+    collect_module_variables(module, &mut variables);
+    let mut unsolved: Vec<_> = variables
+        .into_values()
+        .filter(|var| !values.contains_key(&var.id))
+        .collect();
+    if unsolved.is_empty() {
+        Ok(values)
+    } else {
+        unsolved.sort_by_key(|var| var.id);
+        Err(Error::UnsolvedVariables {
+            var_names: unsolved.iter().map(ToString::to_string).collect(),
+        })
+    }
+}
+
+/* Queue the computation with the given index for evaluation alongside the
+ * other computations of its kind. */
+fn enqueue(
+    computation: &Computation,
+    idx: usize,
+    ready_defs: &mut VecDeque<usize>,
+    ready_eqs: &mut VecDeque<usize>,
+) {
+    match computation {
+        Computation::Definition(_, _) => ready_defs.push_back(idx),
+        Computation::Equality(_, _) => ready_eqs.push_back(idx),
+    }
+}
+
+/* Report every equality of the given module that does not hold under the
+ * given values, which must cover all the variables of the module. */
+pub fn unsatisfied_equalities(
+    module: &Module,
+    values: &HashMap<VariableId, BigInt>,
+    field_ops: &dyn FieldOps,
+) -> Vec<UnsatisfiedEquality> {
+    let mut unsatisfied = Vec::new();
+    for expr in &module.exprs {
+        if let Expr::Infix(InfixOp::Equal, lhs, rhs) = &expr.v {
+            let lhs = evaluate_expr(lhs, values, field_ops);
+            let rhs = evaluate_expr(rhs, values, field_ops);
+            if lhs == rhs {
+                continue;
+            }
+            // Report the values of the variables involved in the equality
+            let mut vars = HashMap::new();
+            collect_expr_variables(expr, &mut vars);
+            let mut variables: Vec<_> = vars
+                .into_values()
+                .map(|var| {
+                    let val = values[&var.id].clone();
+                    (var, val)
+                })
+                .collect();
+            variables.sort_by_key(|(var, _)| var.id);
+            unsatisfied.push(UnsatisfiedEquality {
+                expr: expr.clone(),
+                lhs,
+                rhs,
+                variables,
+            });
+        }
+    }
+    unsatisfied
+}
+
+/* Evaluate the given expression in the given field, sourcing its variables
+ * from the given values. */
+fn evaluate_expr(
+    expr: &TExpr,
+    values: &HashMap<VariableId, BigInt>,
+    field_ops: &dyn FieldOps,
+) -> BigInt {
+    match &expr.v {
+        Expr::Constant(c) => field_ops.canonical(c.clone()),
+        Expr::Variable(var) => values[&var.id].clone(),
+        Expr::Negate(e) => field_ops.negate(evaluate_expr(e, values, field_ops)),
+        Expr::Infix(op, a, b) => {
+            let a = evaluate_expr(a, values, field_ops);
+            let b = evaluate_expr(b, values, field_ops);
+            field_ops.infix(*op, a, b)
+        }
+        _ => unreachable!("encountered unexpected expression: {}", expr),
+    }
+}
+
+/* Express the given expression as a*x + b, where x is the given unknown
+ * variable and all the other variables have known values. Fails if the
+ * expression is not linear in the unknown. */
+fn affine_expr(
+    expr: &TExpr,
+    unknown: VariableId,
+    values: &HashMap<VariableId, BigInt>,
+    field_ops: &dyn FieldOps,
+) -> Option<(BigInt, BigInt)> {
+    match &expr.v {
+        Expr::Constant(c) => Some((BigInt::zero(), field_ops.canonical(c.clone()))),
+        Expr::Variable(var) if var.id == unknown => Some((BigInt::one(), BigInt::zero())),
+        Expr::Variable(var) => Some((BigInt::zero(), values.get(&var.id)?.clone())),
+        Expr::Negate(e) => {
+            let (a, b) = affine_expr(e, unknown, values, field_ops)?;
+            Some((field_ops.negate(a), field_ops.negate(b)))
+        }
+        Expr::Infix(op, e1, e2) => {
+            let (a1, b1) = affine_expr(e1, unknown, values, field_ops)?;
+            let (a2, b2) = affine_expr(e2, unknown, values, field_ops)?;
+            match op {
+                InfixOp::Add | InfixOp::Subtract => {
+                    Some((field_ops.infix(*op, a1, a2), field_ops.infix(*op, b1, b2)))
+                }
+                InfixOp::Multiply if a1.is_zero() => Some((
+                    field_ops.infix(InfixOp::Multiply, b1.clone(), a2),
+                    field_ops.infix(InfixOp::Multiply, b1, b2),
+                )),
+                InfixOp::Multiply if a2.is_zero() => Some((
+                    field_ops.infix(InfixOp::Multiply, a1, b2.clone()),
+                    field_ops.infix(InfixOp::Multiply, b1, b2),
+                )),
+                InfixOp::Divide | InfixOp::DivideZ if a2.is_zero() && b2.is_zero() => None,
+                InfixOp::Divide | InfixOp::DivideZ if a2.is_zero() => Some((
+                    field_ops.infix(InfixOp::Divide, a1, b2.clone()),
+                    field_ops.infix(InfixOp::Divide, b1, b2),
+                )),
+                _ if a1.is_zero() && a2.is_zero() => {
+                    Some((BigInt::zero(), field_ops.infix(*op, b1, b2)))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/* Solve the given equality for the given unknown variable if it occurs
+ * linearly with a nonzero coefficient. */
+fn solve_linear(
+    lhs: &TExpr,
+    rhs: &TExpr,
+    unknown: VariableId,
+    values: &HashMap<VariableId, BigInt>,
+    field_ops: &dyn FieldOps,
+) -> Option<BigInt> {
+    let (a1, b1) = affine_expr(lhs, unknown, values, field_ops)?;
+    let (a2, b2) = affine_expr(rhs, unknown, values, field_ops)?;
+    let coeff = field_ops.infix(InfixOp::Subtract, a1, a2);
+    if coeff.is_zero() {
+        return None;
+    }
+    let constant = field_ops.infix(InfixOp::Subtract, b2, b1);
+    Some(field_ops.infix(InfixOp::Divide, constant, coeff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Variable;
+    use crate::halo2::synth::PrimeFieldOps;
+    use crate::transform::compile;
+    use crate::util::Config;
+    use halo2_proofs::pasta::Fp;
+
+    /* Find the variable of the given module that has the given name. */
+    fn find_variable(module: &Module, name: &str) -> Variable {
+        let mut vars = HashMap::new();
+        collect_module_variables(module, &mut vars);
+        vars.into_values()
+            .find(|var| var.name.as_deref() == Some(name))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_linear_equalities_solved() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let field_ops = PrimeFieldOps::<Fp>::default();
+        let module = Module::parse("def y = x * x; z = 2 * y + 3; z * w = 1;").unwrap();
+        let module_3ac = compile(module, &field_ops, &config).unwrap();
+        let (x, z, w) = (
+            find_variable(&module_3ac, "x"),
+            find_variable(&module_3ac, "z"),
+            find_variable(&module_3ac, "w"),
+        );
+        // z follows linearly from x, and then so does w
+        let assigns = HashMap::from([(x.id, BigInt::from(4))]);
+        let values = solve_witnesses(&module_3ac, assigns, &field_ops).unwrap();
+        assert_eq!(values[&z.id], BigInt::from(35));
+        assert_eq!(
+            field_ops.infix(
+                InfixOp::Multiply,
+                values[&z.id].clone(),
+                values[&w.id].clone()
+            ),
+            BigInt::one()
+        );
+        assert!(unsatisfied_equalities(&module_3ac, &values, &field_ops).is_empty());
+        // Nothing determines x
+        assert!(solve_witnesses(&module_3ac, HashMap::new(), &field_ops).is_err());
+    }
+
+    #[test]
+    fn test_definitions_solved_in_order() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let field_ops = PrimeFieldOps::<Fp>::default();
+        let module = Module::parse("def y = x * x; def z = 2 * y + 3; z = w;").unwrap();
+        let module_3ac = compile(module, &field_ops, &config).unwrap();
+        let (x, w) = (
+            find_variable(&module_3ac, "x"),
+            find_variable(&module_3ac, "w"),
+        );
+        let assigns = HashMap::from([(x.id, BigInt::from(4)), (w.id, BigInt::from(35))]);
+        let values = solve_witnesses(&module_3ac, assigns, &field_ops).unwrap();
+        assert!(unsatisfied_equalities(&module_3ac, &values, &field_ops).is_empty());
+        let assigns = HashMap::from([(x.id, BigInt::from(4)), (w.id, BigInt::from(36))]);
+        let values = solve_witnesses(&module_3ac, assigns, &field_ops).unwrap();
+        assert!(!unsatisfied_equalities(&module_3ac, &values, &field_ops).is_empty());
+        // x only occurs nonlinearly
+        let assigns = HashMap::from([(w.id, BigInt::from(35))]);
+        assert!(matches!(
+            solve_witnesses(&module_3ac, assigns, &field_ops),
+            Err(Error::UnsolvedVariables { .. })
+        ));
+    }
+}
@@ -0,0 +1,66 @@
+use crate::ast::Module;
+use crate::error::Error;
+use crate::qprintln;
+use crate::stats::compile_with_breakdown;
+use crate::transform::compile;
+use crate::util::Config;
+
+use crate::plonk::synth::{PlonkModule, PrimeFieldOps as PlonkPrimeFieldOps};
+use ark_bls12_381::Fr as BlsScalar;
+use ark_ed_on_bls12_381::EdwardsParameters as JubJubParameters;
+use plonk_core::circuit::Circuit;
+
+use crate::halo2::synth::{Halo2Module, PrimeFieldOps as Halo2PrimeFieldOps};
+use halo2_proofs::pasta::Fp;
+
+use clap::Args;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+#[derive(Args)]
+pub struct Stats {
+    /// Path to source file whose statistics are reported
+    #[arg(short, long)]
+    source: PathBuf,
+}
+
+/* Implements the subcommand that reports the size of a circuit without
+ * generating any parameters or keys. The three-address statistics are those
+ * of the circuit compiled for the PLONK backend. */
+pub fn stats(Stats { source }: &Stats, config: &Config) -> Result<(), Error> {
+    qprintln!(config, "* Compiling constraints...");
+    let module = Module::parse_file(source)?;
+    let (plonk_3ac, total, breakdown) = compile_with_breakdown(
+        module.clone(),
+        &PlonkPrimeFieldOps::<BlsScalar>::default(),
+        config,
+    )?;
+    let halo2_3ac = compile(module, &Halo2PrimeFieldOps::<Fp>::default(), config)?;
+    let plonk_circuit = PlonkModule::<BlsScalar, JubJubParameters>::new(Rc::new(plonk_3ac));
+    let halo2_circuit = Halo2Module::<Fp>::new(Rc::new(halo2_3ac));
+
+    println!("Constraints: {}", total.constraints);
+    println!("  Multiplications: {}", total.multiplications);
+    println!("  Linear constraints: {}", total.linear);
+    println!("Variables: {}", total.variables);
+    println!("Public inputs: {}", total.public_inputs);
+    println!("Fresh witnesses: {}", total.witnesses);
+    println!(
+        "PLONK gates: {} (padded circuit size {})",
+        plonk_circuit.gates().len(),
+        plonk_circuit.padded_circuit_size()
+    );
+    println!("Halo2 k: {}", halo2_circuit.k);
+    println!("Breakdown by top-level definition:");
+    for (def, growth) in &breakdown {
+        println!(
+            "  {}: {} constraints ({} multiplications), {} variables, {} fresh witnesses",
+            def.as_deref().unwrap_or("top-level constraints"),
+            growth.constraints,
+            growth.multiplications,
+            growth.variables,
+            growth.witnesses
+        );
+    }
+    Ok(())
+}
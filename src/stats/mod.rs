@@ -0,0 +1,191 @@
+pub mod cli;
+
+use crate::ast::{Definition, Expr, InfixOp, Module, Pat, TExpr, VariableId};
+use crate::error::Error;
+use crate::transform::{collect_module_variables, compile_with_provenance, FieldOps};
+use crate::util::Config;
+
+use std::collections::{HashMap, HashSet};
+
+/* Counts describing the size of a three-address module. */
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModuleStats {
+    // Number of three-address equalities
+    pub constraints: usize,
+    // Number of equalities that multiply or divide two variables
+    pub multiplications: usize,
+    // Number of equalities that are linear in their variables
+    pub linear: usize,
+    // Number of distinct variables
+    pub variables: usize,
+    // Number of public inputs
+    pub public_inputs: usize,
+    // Number of definitions that the prover computes without an equality
+    // fixing them, such as those made by fresh
+    pub witnesses: usize,
+}
+
+impl ModuleStats {
+    /* Count the constraints and variables of the given three-address module,
+     * in which the given variables are only computed by the prover. */
+    pub fn new(module: &Module, prover_defs: &HashSet<VariableId>) -> Self {
+        let multiplications = module.exprs.iter().filter(|e| is_multiplication(e)).count();
+        let mut variables = HashMap::new();
+        collect_module_variables(module, &mut variables);
+        let witnesses = module
+            .defs
+            .iter()
+            .filter(|def| is_witness(def, prover_defs))
+            .count();
+        Self {
+            constraints: module.exprs.len(),
+            multiplications,
+            linear: module.exprs.len() - multiplications,
+            variables: variables.len(),
+            public_inputs: module.pubs.len(),
+            witnesses,
+        }
+    }
+}
+
+/* The statistics attributed to each top-level definition, named by its
+ * pattern, or to the top level where there is no name. */
+pub type Breakdown = Vec<(Option<String>, ModuleStats)>;
+
+/* Check whether the given three-address equality multiplies or divides two
+ * variables, as opposed to scaling a variable by a constant. */
+fn is_multiplication(expr: &TExpr) -> bool {
+    match &expr.v {
+        Expr::Infix(InfixOp::Equal, _, rhs) => matches!(
+            &rhs.v,
+            Expr::Infix(InfixOp::Multiply | InfixOp::Divide, a, b)
+                if matches!((&a.v, &b.v), (Expr::Variable(_), Expr::Variable(_)))
+        ),
+        _ => false,
+    }
+}
+
+/* Check whether the given definition is only computed by the prover. */
+fn is_witness(def: &Definition, prover_defs: &HashSet<VariableId>) -> bool {
+    matches!(&def.0 .0.v, Pat::Variable(var) if prover_defs.contains(&var.id))
+}
+
+/* Compile the given module and attribute each part of the result to the
+ * top-level definition that causes it, along with the statistics of the whole
+ * result. Definitions that add nothing, such as those of functions, are left
+ * out. The top-level constraints and public inputs of the module are
+ * attributed to the final entry, which has no definition. The entries sum to
+ * the whole. */
+pub fn compile_with_breakdown(
+    module: Module,
+    field_ops: &dyn FieldOps,
+    config: &Config,
+) -> Result<(Module, ModuleStats, Breakdown), Error> {
+    let names: Vec<String> = module.defs.iter().map(|def| def.0 .0.to_string()).collect();
+    let (module_3ac, provenance) = compile_with_provenance(module, field_ops, config)?;
+    let total = ModuleStats::new(&module_3ac, &provenance.prover_defs);
+    // One entry per definition followed by one for the top level
+    let mut entries = vec![ModuleStats::default(); names.len() + 1];
+    let entry = |origin: Option<usize>| origin.unwrap_or(names.len());
+    for (expr, origin) in module_3ac.exprs.iter().zip(&provenance.expr_origins) {
+        let stats = &mut entries[entry(*origin)];
+        stats.constraints += 1;
+        if is_multiplication(expr) {
+            stats.multiplications += 1;
+        } else {
+            stats.linear += 1;
+        }
+    }
+    for origin in provenance.var_origins.values() {
+        entries[entry(*origin)].variables += 1;
+    }
+    for def in &module_3ac.defs {
+        if let Pat::Variable(var) = &def.0 .0.v {
+            if is_witness(def, &provenance.prover_defs) {
+                entries[entry(provenance.var_origins[&var.id])].witnesses += 1;
+            }
+        }
+    }
+    entries[names.len()].public_inputs = module_3ac.pubs.len();
+    let breakdown = names
+        .into_iter()
+        .map(Some)
+        .chain([None])
+        .zip(entries)
+        .filter(|(name, stats)| name.is_none() || *stats != ModuleStats::default())
+        .collect();
+    Ok((module_3ac, total, breakdown))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use halo2_proofs::pasta::Fp;
+
+    /* Add up the entries of the given breakdown. */
+    fn sum(breakdown: &[(Option<String>, ModuleStats)]) -> ModuleStats {
+        breakdown
+            .iter()
+            .fold(ModuleStats::default(), |acc, (_, s)| ModuleStats {
+                constraints: acc.constraints + s.constraints,
+                multiplications: acc.multiplications + s.multiplications,
+                linear: acc.linear + s.linear,
+                variables: acc.variables + s.variables,
+                public_inputs: acc.public_inputs + s.public_inputs,
+                witnesses: acc.witnesses + s.witnesses,
+            })
+    }
+
+    /* Check that the breakdown of the given source over the given field
+     * accounts for every part of the compiled module. */
+    fn check_breakdown(source: &str, field_ops: &dyn FieldOps) -> Breakdown {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse(source).unwrap();
+        let (module_3ac, total, breakdown) =
+            compile_with_breakdown(module.clone(), field_ops, &config).unwrap();
+        // The breakdown is taken from the same compilation as the module
+        let compiled = crate::transform::compile(module, field_ops, &config).unwrap();
+        assert_eq!(module_3ac.exprs.len(), compiled.exprs.len());
+        assert_eq!(module_3ac.defs.len(), compiled.defs.len());
+        assert_eq!(total.constraints, total.multiplications + total.linear);
+        assert_eq!(sum(&breakdown), total);
+        breakdown
+    }
+
+    #[test]
+    fn test_breakdown_sums_to_total() {
+        let source = "pub x; def bool x = { x*(x-1) = 0; x }; \
+                      def b = bool (fresh (x % 2)); b*x = 1;";
+        let bls_ops = crate::plonk::synth::PrimeFieldOps::<ark_bls12_381::Fr>::default();
+        let pallas_ops = crate::halo2::synth::PrimeFieldOps::<Fp>::default();
+        for field_ops in [&bls_ops as &dyn FieldOps, &pallas_ops] {
+            let breakdown = check_breakdown(source, field_ops);
+            let total = sum(&breakdown);
+            assert_eq!(total.public_inputs, 1);
+            assert!(total.witnesses >= 1);
+
+            // Only the definition of b and the top-level constraint add anything
+            assert_eq!(breakdown.len(), 2);
+            assert!(breakdown[0].0.as_ref().unwrap().starts_with('b'));
+            assert!(breakdown[0].1.multiplications >= 1);
+            assert_eq!(breakdown[0].1.witnesses, total.witnesses);
+            assert_eq!(breakdown[1].0, None);
+            assert_eq!(breakdown[1].1.public_inputs, 1);
+        }
+    }
+
+    #[test]
+    fn test_breakdown_of_gadgets() {
+        // Each decomposition belongs to its own definition, and the equality
+        // between the public inputs to the top level
+        let source = "pub a, b; def c = range 8 a; def d = range 8 b; a + b = 300;";
+        let pallas_ops = crate::halo2::synth::PrimeFieldOps::<Fp>::default();
+        let breakdown = check_breakdown(source, &pallas_ops);
+        assert_eq!(breakdown.len(), 3);
+        assert_eq!(breakdown[0].1.witnesses, breakdown[1].1.witnesses);
+        assert_eq!(breakdown[2].1.public_inputs, 2);
+    }
+}
@@ -1,9 +1,8 @@
 use crate::ast::{
-    Definition, Expr, Function, InfixOp, Intrinsic, LetBinding, Module, Pat, Rule, TExpr, TPat,
-    VampirParser, Variable, VariableId,
+    Definition, Expr, Function, InfixOp, Intrinsic, LetBinding, Match, Module, Pat, TExpr, TPat,
+    Variable, VariableId,
 };
 use crate::error::*;
-use crate::pest::Parser;
 use crate::qprintln;
 use crate::typecheck::{
     expand_expr_variables, expand_pattern_variables, infer_module_types, print_types,
@@ -14,13 +13,20 @@ use ark_ff::{One, Zero};
 use num_bigint::BigInt;
 use num_traits::sign::Signed;
 use num_traits::ToPrimitive;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::hash::Hash;
 use std::io::Write;
+use std::sync::OnceLock;
 
 /* A structure for generating unique variable IDs. */
 pub struct VarGen(VariableId);
 
+impl Default for VarGen {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl VarGen {
     pub fn new() -> Self {
         VarGen(0)
@@ -41,9 +47,13 @@ fn refresh_expr_variables(
     gen: &mut VarGen,
 ) {
     match &mut expr.v {
-        Expr::Intrinsic(Intrinsic { params, .. }) => {
+        Expr::Intrinsic(Intrinsic { params, pos, .. }) => {
             let mut map = map.clone();
-            for param in params.iter_mut() {
+            // Parameters that have already been filled are bound in the
+            // captured environment under their current names, so renaming
+            // them would detach them from their arguments. Only intrinsics
+            // with several parameters are ever refreshed part way through
+            for param in params.iter_mut().skip(*pos) {
                 refresh_pattern_variables(param, &mut map, prover_defs, gen);
             }
         }
@@ -70,6 +80,11 @@ fn refresh_expr_variables(
         Expr::Negate(expr) => {
             refresh_expr_variables(expr, map, prover_defs, gen);
         }
+        Expr::If(cond, expr1, expr2) => {
+            refresh_expr_variables(cond, map, prover_defs, gen);
+            refresh_expr_variables(expr1, map, prover_defs, gen);
+            refresh_expr_variables(expr2, map, prover_defs, gen);
+        }
         Expr::Constant(_) | Expr::Unit | Expr::Nil => {}
         Expr::Variable(var) => {
             if let Some(id) = map.get(&var.id) {
@@ -163,12 +178,16 @@ fn match_pattern_expr(
             match_pattern_expr(pat, expr, env, ext, prover_defs, gen)
         }
         (Pat::Unit, Expr::Unit) | (Pat::Nil, Expr::Nil) => Ok(Tribool::True),
+        (Pat::Nil, Expr::Cons(_, _)) | (Pat::Cons(_, _), Expr::Nil) => Ok(Tribool::False),
         (Pat::Constant(a), Expr::Constant(b)) if a == b => Ok(Tribool::True),
         (Pat::Constant(a), Expr::Constant(b)) if a != b => Ok(Tribool::False),
-        (Pat::Constant(_), Expr::Variable(_) | Expr::Infix(_, _, _)) => Ok(Tribool::Indeterminate),
+        (
+            Pat::Constant(_),
+            Expr::Variable(_) | Expr::Infix(_, _, _) | Expr::Negate(_) | Expr::If(_, _, _),
+        ) => Ok(Tribool::Indeterminate),
         _ => Err(Error::StaticMatchError {
-            e: expr.clone(),
-            p: pat.clone(),
+            e: Box::new(expr.clone()),
+            p: Box::new(pat.clone()),
         }),
     }
 }
@@ -286,6 +305,11 @@ fn number_expr_variables(
         Expr::Negate(expr) => {
             number_expr_variables(expr, locals, globals, gen);
         }
+        Expr::If(cond, expr1, expr2) => {
+            number_expr_variables(cond, locals, globals, gen);
+            number_expr_variables(expr1, locals, globals, gen);
+            number_expr_variables(expr2, locals, globals, gen);
+        }
         Expr::Constant(_) | Expr::Unit | Expr::Nil => {}
         Expr::Variable(var) => {
             number_variable(var, locals, globals, gen);
@@ -386,18 +410,19 @@ fn evaluate_binding(
     prover_defs: &mut HashSet<VariableId>,
     field_ops: &dyn FieldOps,
     gen: &mut VarGen,
-) -> HashMap<VariableId, TExpr> {
+) -> Result<HashMap<VariableId, TExpr>, Error> {
     // Evaluate the binding expression in the current environment
-    let mut val = evaluate(&binding.1, flattened, bindings, prover_defs, field_ops, gen).unwrap();
+    let mut val = evaluate(&binding.1, flattened, bindings, prover_defs, field_ops, gen)?;
     // Allow binding value to carry around its own context
-    capture_env(&mut val, capture).unwrap();
+    capture_env(&mut val, capture)?;
     // Now make a let binding for the expanded value whilst making sure that the
     // pattern is fully expanded
-    let mut new_binding = Definition(LetBinding(binding.0.clone(), Box::new(val)));
+    let mut new_binding = Definition(LetBinding(binding.0.clone(), Box::new(val)), None);
     let mut pat_exps = HashMap::new();
-    expand_pattern_variables(&mut new_binding.0 .0, &new_binding.0 .1, &mut pat_exps, gen).unwrap();
+    expand_pattern_variables(&mut new_binding.0 .0, &new_binding.0 .1, &mut pat_exps, gen)
+        .map_err(|err| err.locate(&binding.0.s))?;
     // Now decompose the let-binding into a flattened form
-    flatten_binding(&new_binding.0 .0, &new_binding.0 .1, flattened);
+    flatten_binding(&new_binding.0 .0, &new_binding.0 .1, flattened)?;
     // Now expand the environment to reflect the binding that has been effected
     let mut new_bindings = HashMap::new();
     for (var, pat) in pat_exps {
@@ -412,9 +437,8 @@ fn evaluate_binding(
         &mut new_bindings,
         prover_defs,
         gen,
-    )
-    .unwrap();
-    new_bindings
+    )?;
+    Ok(new_bindings)
 }
 
 /* Weakly add the given bindings to the environment of the given expression if
@@ -443,17 +467,21 @@ fn capture_env(val: &mut TExpr, new_bindings: HashMap<VariableId, TExpr>) -> Res
         Expr::Unit
         | Expr::Infix(_, _, _)
         | Expr::Negate(_)
+        | Expr::If(_, _, _)
         | Expr::Constant(_)
         | Expr::Variable(_)
         | Expr::Nil => Ok(()),
         Expr::Application(_, _) | Expr::Sequence(_) | Expr::LetBinding(_, _) | Expr::Match(_) => {
-            Err(Error::UnexpectedExpression { e: val.clone() })
+            Err(Error::UnexpectedExpression {
+                e: Box::new(val.clone()),
+            })
         }
     }
 }
 
 /* Evaluate the given expression emitting constraints as necessary. Returns the
- * value that the given expression evaluates to. */
+ * value that the given expression evaluates to. Errors are attributed to the
+ * innermost expression that has a source location. */
 fn evaluate(
     expr: &TExpr,
     flattened: &mut Option<Module>,
@@ -461,269 +489,79 @@ fn evaluate(
     prover_defs: &mut HashSet<VariableId>,
     field_ops: &dyn FieldOps,
     gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    evaluate_unlocated(expr, flattened, bindings, prover_defs, field_ops, gen)
+        .map_err(|err| err.locate(&expr.s))
+}
+
+/* Evaluate the given expression without attributing errors to its source
+ * location. Each kind of expression is evaluated by its own function so that
+ * the stack frame of this function, which is live throughout the recursion,
+ * stays small. */
+fn evaluate_unlocated(
+    expr: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
 ) -> Result<TExpr, Error> {
     match &expr.v {
-        Expr::Application(expr1, expr2) => {
-            let mut expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            refresh_expr_variables(&mut expr1, &HashMap::new(), prover_defs, gen);
-            match &mut expr1.v {
-                Expr::Intrinsic(intr) => {
-                    // Now that the current parameter is filled, move onto the
-                    // next position
-                    let param1 = intr.params[intr.pos].clone();
-                    intr.pos += 1;
-                    // The specific binding that needs to be added to
-                    // environment
-                    let new_bind = LetBinding(param1, Box::new(*expr2.clone()));
-                    // Make sure that the argument's environment does not get
-                    // overridden by that of the function by producing
-                    // alternatives sourced from this context
-                    let mut implicit_env = HashMap::new();
-                    for var in intr.env.keys() {
-                        if let Some(val) = bindings.get(var) {
-                            implicit_env.insert(*var, val.clone());
-                        }
-                    }
-                    // Setup the environment in which to evaluate body
-                    let new_bindings = evaluate_binding(
-                        &new_bind,
-                        implicit_env,
-                        flattened,
-                        bindings,
-                        prover_defs,
-                        field_ops,
-                        gen,
-                    );
-                    // Apply the new environment to the body
-                    intr.env.extend(new_bindings.clone());
-                    // Modify function type to account for the partial
-                    // application that has just happened
-                    expr1.t = None;
-                    // Finally evaluate the body
-                    let mut val =
-                        evaluate(&expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-                    // Enable closures by storing the required environment
-                    // modifications inside the evaluation result
-                    capture_env(&mut val, new_bindings)?;
-                    Ok(val)
-                }
-                Expr::Function(fun) if fun.params.is_empty() => Err(Error::NoParameterInFunction),
-                Expr::Function(fun) => {
-                    // Now that we have an assignment, move the function
-                    // parameter into the environment
-                    let param1 = fun.params.remove(0);
-                    // The specific binding that needs to be added to
-                    // environment
-                    let new_bind = LetBinding(param1, Box::new(*expr2.clone()));
-                    // Make sure that the argument's environment does not get
-                    // overridden by that of the function by producing
-                    // alternatives sourced from this context
-                    let mut implicit_env = HashMap::new();
-                    for var in fun.env.keys() {
-                        if let Some(val) = bindings.get(var) {
-                            implicit_env.insert(*var, val.clone());
-                        }
-                    }
-                    // Setup the environment in which to evaluate body
-                    let new_bindings = evaluate_binding(
-                        &new_bind,
-                        implicit_env,
-                        flattened,
-                        bindings,
-                        prover_defs,
-                        field_ops,
-                        gen,
-                    );
-                    // Apply the new environment to the body
-                    fun.env.extend(new_bindings.clone());
-                    // Modify function type to account for the partial
-                    // application that has just happened
-                    expr1.t = None;
-                    // Finally evaluate the body
-                    let mut val =
-                        evaluate(&expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-                    // Enable closures by storing the required environment
-                    // modifications inside the evaluation result
-                    capture_env(&mut val, new_bindings)?;
-                    Ok(val)
-                }
-                _ => Err(Error::ApplicationError {
-                    e2: *expr2.clone(),
-                    e1: expr1,
-                }),
-            }
-        }
+        Expr::Application(expr1, expr2) => evaluate_application(
+            expr1,
+            expr2,
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        ),
         Expr::LetBinding(_, _) => {
-            let mut acc_bindings = HashMap::new();
-            let mut expr = expr;
-            // Instead of recursively evaluating let-bindings, iteratively
-            // evaluate them to reduce stack usage.
-            while let Expr::LetBinding(binding, body) = &expr.v {
-                // Evaluate binding expression and get new bindings
-                let new_bindings = evaluate_binding(
-                    binding,
-                    HashMap::new(),
-                    flattened,
-                    bindings,
-                    prover_defs,
-                    field_ops,
-                    gen,
-                );
-                let mut new_bindings = new_bindings
-                    .into_iter()
-                    .map(|(k, v)| (k, Some(v)))
-                    .collect();
-                // Insert new bindings into environment and get old bindings
-                exchange_map(bindings, &mut new_bindings);
-                // Store the old bindings, prioritizing the oldest bindings
-                for (k, v) in new_bindings {
-                    acc_bindings.entry(k).or_insert(v);
-                }
-                // Now move onto the body expression
-                if let Expr::Sequence(seq) = &body.v {
-                    // Iteratively evaluate a sequence expression here in order
-                    // to avoid leaving this call frame
-                    for expr in &seq[0..seq.len() - 1] {
-                        evaluate(expr, flattened, bindings, prover_defs, field_ops, gen)?;
-                    }
-                    // Hence the let's body is now effectively this sequence's
-                    // last expression
-                    expr = seq.last().unwrap();
-                } else {
-                    expr = body;
-                }
-            }
-            // Now evaluate the inner-most body
-            let mut val = evaluate(expr, flattened, bindings, prover_defs, field_ops, gen)?;
-            // Now restore the old environment before this entire let expression
-            exchange_map(bindings, &mut acc_bindings);
-            let acc_bindings = acc_bindings
-                .into_iter()
-                .map(|(k, v)| (k, v.unwrap()))
-                .collect();
-            // Capture the environment modifications required to evaluate body
-            // inside the body. Necessary for closures.
-            capture_env(&mut val, acc_bindings)?;
-            Ok(val)
+            evaluate_let_binding(expr, flattened, bindings, prover_defs, field_ops, gen)
         }
         Expr::Sequence(seq) => {
-            let mut val = None;
-            for expr in seq {
-                val = Some(evaluate(
-                    expr,
-                    flattened,
-                    bindings,
-                    prover_defs,
-                    field_ops,
-                    gen,
-                ));
-            }
-            val.expect("encountered empty sequence")
-        }
-        Expr::Product(expr1, expr2) => {
-            let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            let expr2 = evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)?;
-            Ok(Expr::Product(Box::new(expr1), Box::new(expr2)).type_expr(expr.t.clone()))
-        }
-        Expr::Cons(expr1, expr2) => {
-            let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            let expr2 = evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)?;
-            Ok(Expr::Cons(Box::new(expr1), Box::new(expr2)).type_expr(expr.t.clone()))
-        }
-        Expr::Infix(InfixOp::Equal, expr1, expr2) => {
-            let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            let expr2 = evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)?;
-            flatten_equals(&expr1, &expr2, flattened);
-            if flattened.is_none() {
-                // To Do: Some way for repl to see constraint results?
-                Ok(Expr::Unit.type_expr(Some(Type::Unit)))
-            } else {
-                Ok(Expr::Unit.type_expr(Some(Type::Unit)))
-            }
-        }
+            evaluate_sequence(seq, flattened, bindings, prover_defs, field_ops, gen)
+        }
+        Expr::Product(expr1, expr2) => evaluate_pair(
+            expr1,
+            expr2,
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        )
+        .map(|(expr1, expr2)| {
+            Expr::Product(Box::new(expr1), Box::new(expr2)).type_expr(expr.t.clone())
+        }),
+        Expr::Cons(expr1, expr2) => evaluate_pair(
+            expr1,
+            expr2,
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        )
+        .map(|(expr1, expr2)| {
+            Expr::Cons(Box::new(expr1), Box::new(expr2)).type_expr(expr.t.clone())
+        }),
         Expr::Infix(InfixOp::Exponentiate, e1, e2) => {
-            // Compute the base once and for all
-            let e1 = evaluate(e1, flattened, bindings, prover_defs, field_ops, gen)?;
-            let e2 = evaluate(e2, flattened, bindings, prover_defs, field_ops, gen)?;
-            match (&e1.v, &e2.v) {
-                (Expr::Constant(a), Expr::Constant(b)) => Ok(Expr::Constant(field_ops.infix(
-                    InfixOp::Exponentiate,
-                    a.clone(),
-                    b.clone(),
-                ))
-                .type_expr(Some(Type::Int))),
-                (_, Expr::Constant(c)) if c.is_zero() => {
-                    Ok(Expr::Constant(One::one()).type_expr(Some(Type::Int)))
-                }
-                (_, Expr::Constant(c)) if c.is_one() => Ok(e1),
-                (_, Expr::Constant(v2)) if v2.is_positive() => {
-                    // Compute roughly the sqrt of this expression
-                    let sqrt = Expr::Infix(
-                        InfixOp::Exponentiate,
-                        Box::new(e1.clone()),
-                        Box::new(Expr::Constant(v2 / 2i8).type_expr(Some(Type::Int))),
-                    )
-                    .type_expr(Some(Type::Int));
-                    let out2_term =
-                        evaluate(&sqrt, flattened, bindings, prover_defs, field_ops, gen)?;
-                    // Now square the value to obtain roughly this expression
-                    let mut rhs = infix_op(InfixOp::Multiply, out2_term.clone(), out2_term);
-                    // Multiply by the base once more in order to obtain
-                    // original value
-                    if v2 % 2i8 == One::one() {
-                        rhs = infix_op(InfixOp::Multiply, rhs, e1);
-                    }
-                    evaluate(&rhs, flattened, bindings, prover_defs, field_ops, gen)
-                }
-                (_, Expr::Constant(v2)) => {
-                    // Compute the reciprocal of this expression
-                    let recip = Expr::Infix(
-                        InfixOp::Exponentiate,
-                        Box::new(e1),
-                        Box::new(Expr::Constant(-v2).type_expr(Some(Type::Int))),
-                    );
-                    // Now invert the value to obtain this expression
-                    let rhs = infix_op(
-                        InfixOp::Divide,
-                        Expr::Constant(One::one()).type_expr(Some(Type::Int)),
-                        recip.type_expr(Some(Type::Int)),
-                    );
-                    evaluate(&rhs, flattened, bindings, prover_defs, field_ops, gen)
-                }
-                _ => Err(Error::VariableExponentError),
-            }
-        }
-        Expr::Infix(op, expr1, expr2) => {
-            let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            let expr2 = evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)?;
-            match (&expr1.v, &expr2.v) {
-                (Expr::Constant(c1), Expr::Constant(c2)) => {
-                    Ok(Expr::Constant(field_ops.infix(*op, c1.clone(), c2.clone()))
-                        .type_expr(expr.t.clone()))
-                }
-                (_, _) => {
-                    let val = infix_op(*op, expr1, expr2);
-                    let var = Variable::new(gen.generate_id());
-                    let binding = Definition(LetBinding(
-                        Pat::Variable(var.clone()).type_pat(expr.t.clone()),
-                        Box::new(val),
-                    ));
-                    if let Some(flat) = flattened {
-                        flat.defs.push(binding);
-                    }
-                    Ok(Expr::Variable(var).type_expr(expr.t.clone()))
-                }
-            }
+            evaluate_exponentiate(e1, e2, flattened, bindings, prover_defs, field_ops, gen)
         }
-        Expr::Negate(expr1) => {
-            let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            match expr1.v {
-                Expr::Constant(c1) => {
-                    Ok(Expr::Constant(field_ops.negate(c1)).type_expr(expr.t.clone()))
-                }
-                _ => Ok(Expr::Negate(Box::new(expr1)).type_expr(expr.t.clone())),
-            }
+        Expr::Infix(_, _, _) => {
+            evaluate_infix(expr, flattened, bindings, prover_defs, field_ops, gen)
         }
+        Expr::Negate(expr1) => evaluate_negate(
+            expr1,
+            &expr.t,
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        ),
+        Expr::If(_, _, _) => evaluate_if(expr, flattened, bindings, prover_defs, field_ops, gen),
         Expr::Constant(c) => {
             Ok(Expr::Constant(field_ops.canonical(c.clone())).type_expr(expr.t.clone()))
         }
@@ -742,53 +580,537 @@ fn evaluate(
             exchange_map(bindings, &mut ext);
             val
         }
-        Expr::Intrinsic(
-            intr @ Intrinsic {
-                pos, params, env, ..
-            },
-        ) if *pos == params.len() => {
-            let mut ext = env.clone().into_iter().map(|(k, v)| (k, Some(v))).collect();
-            // Supplement the partially captured environment with bindings
-            exchange_map(bindings, &mut ext);
-            let expr1 = intr.execute(bindings, prover_defs, gen)?;
-            let val = evaluate(&expr1, flattened, bindings, prover_defs, field_ops, gen)?;
-            exchange_map(bindings, &mut ext);
-            Ok(val)
+        Expr::Intrinsic(intr) if intr.pos == intr.params.len() => {
+            evaluate_intrinsic(intr, flattened, bindings, prover_defs, field_ops, gen)
         }
         Expr::Function(_) | Expr::Intrinsic(_) => Ok(expr.clone()),
-        Expr::Match(matche) => {
-            let val = evaluate(&matche.0, flattened, bindings, prover_defs, field_ops, gen)?;
-            for (pat, expr2) in matche.1.iter().zip(matche.2.iter()) {
-                let res =
-                    match_pattern_expr(pat, &val, bindings, &mut HashMap::new(), prover_defs, gen)?;
-                match res {
-                    Tribool::True => {
-                        let expr = TExpr {
-                            v: Expr::LetBinding(
-                                LetBinding(pat.clone(), matche.0.clone()),
-                                Box::new(expr2.clone()),
-                            ),
-                            t: expr.t.clone(),
-                        };
-                        return evaluate(&expr, flattened, bindings, prover_defs, field_ops, gen);
-                    }
-                    Tribool::Indeterminate => {
-                        return Err(Error::StaticMatchError {
-                            e: expr.clone(),
-                            p: pat.clone(),
-                        })
-                    }
-                    Tribool::False => continue,
+        Expr::Match(matche) => evaluate_match(
+            matche,
+            expr,
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        ),
+    }
+}
+
+/* Evaluate the given sequence of expressions in order. Returns the value of the
+ * last expression. */
+fn evaluate_sequence(
+    seq: &[TExpr],
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let mut val = None;
+    for expr in seq {
+        val = Some(evaluate(
+            expr,
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        )?);
+    }
+    val.ok_or(Error::EmptySequenceError)
+}
+
+/* Evaluate the two components of a tuple or list. */
+fn evaluate_pair(
+    expr1: &TExpr,
+    expr2: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<(TExpr, TExpr), Error> {
+    let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+    let expr2 = evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)?;
+    Ok((expr1, expr2))
+}
+
+/* Evaluate the given infix operation other than exponentiation. Equalities
+ * are emitted as constraints, and other operations on variables are bound to
+ * fresh variables. */
+fn evaluate_infix(
+    expr: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let Expr::Infix(op, expr1, expr2) = &expr.v else {
+        return Err(Error::UnexpectedExpression {
+            e: Box::new(expr.clone()),
+        });
+    };
+    let (op, t) = (*op, &expr.t);
+    let (expr1, expr2) = evaluate_pair(
+        expr1,
+        expr2,
+        flattened,
+        bindings,
+        prover_defs,
+        field_ops,
+        gen,
+    )?;
+    match (op, &expr1.v, &expr2.v) {
+        (InfixOp::Equal, _, _) => {
+            flatten_equals(&expr1, &expr2, flattened)?;
+            if flattened.is_none() {
+                // To Do: Some way for repl to see constraint results?
+                Ok(Expr::Unit.type_expr(Some(Type::Unit)))
+            } else {
+                Ok(Expr::Unit.type_expr(Some(Type::Unit)))
+            }
+        }
+        (_, Expr::Constant(c1), Expr::Constant(c2)) => {
+            Ok(Expr::Constant(field_ops.infix(op, c1.clone(), c2.clone())).type_expr(t.clone()))
+        }
+        (_, _, _) => {
+            let val = infix_op(op, expr1, expr2);
+            let var = Variable::new(gen.generate_id());
+            let binding = Definition(
+                LetBinding(
+                    Pat::Variable(var.clone()).type_pat(t.clone()),
+                    Box::new(val),
+                ),
+                None,
+            );
+            if let Some(flat) = flattened {
+                flat.defs.push(binding);
+            }
+            Ok(Expr::Variable(var).type_expr(t.clone()))
+        }
+    }
+}
+
+/* Evaluate the negation of the given expression. */
+fn evaluate_negate(
+    expr1: &TExpr,
+    t: &Option<Type>,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+    match expr1.v {
+        Expr::Constant(c1) => Ok(Expr::Constant(field_ops.negate(c1)).type_expr(t.clone())),
+        _ => Ok(Expr::Negate(Box::new(expr1)).type_expr(t.clone())),
+    }
+}
+
+/* Evaluate the given intrinsic now that all its parameters have been
+ * supplied. */
+fn evaluate_intrinsic(
+    intr: &Intrinsic,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let mut ext = intr
+        .env
+        .clone()
+        .into_iter()
+        .map(|(k, v)| (k, Some(v)))
+        .collect();
+    // Supplement the partially captured environment with bindings
+    exchange_map(bindings, &mut ext);
+    let expr1 = intr.execute(bindings, prover_defs, gen)?;
+    let val = evaluate(&expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+    exchange_map(bindings, &mut ext);
+    Ok(val)
+}
+
+/* Evaluate the application of the given function to the given argument. */
+fn evaluate_application(
+    expr1: &TExpr,
+    expr2: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let mut expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+    refresh_expr_variables(&mut expr1, &HashMap::new(), prover_defs, gen);
+    match &mut expr1.v {
+        Expr::Intrinsic(intr) => {
+            // Now that the current parameter is filled, move onto the
+            // next position
+            let param1 = intr.params[intr.pos].clone();
+            intr.pos += 1;
+            // The specific binding that needs to be added to
+            // environment
+            let new_bind = LetBinding(param1, Box::new(expr2.clone()));
+            // Make sure that the argument's environment does not get
+            // overridden by that of the function by producing
+            // alternatives sourced from this context
+            let mut implicit_env = HashMap::new();
+            for var in intr.env.keys() {
+                if let Some(val) = bindings.get(var) {
+                    implicit_env.insert(*var, val.clone());
                 }
             }
-            Err(Error::MatchError {
-                e1: *matche.0.clone(),
-                e2: expr.clone(),
-            })
+            // Setup the environment in which to evaluate body
+            let new_bindings = evaluate_binding(
+                &new_bind,
+                implicit_env,
+                flattened,
+                bindings,
+                prover_defs,
+                field_ops,
+                gen,
+            )?;
+            // Apply the new environment to the body
+            intr.env.extend(new_bindings.clone());
+            // Modify function type to account for the partial
+            // application that has just happened
+            expr1.t = None;
+            // Finally evaluate the body
+            let mut val = evaluate(&expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+            // Enable closures by storing the required environment
+            // modifications inside the evaluation result
+            capture_env(&mut val, new_bindings)?;
+            Ok(val)
+        }
+        Expr::Function(fun) if fun.params.is_empty() => Err(Error::NoParameterInFunction),
+        Expr::Function(fun) => {
+            // Now that we have an assignment, move the function
+            // parameter into the environment
+            let param1 = fun.params.remove(0);
+            // The specific binding that needs to be added to
+            // environment
+            let new_bind = LetBinding(param1, Box::new(expr2.clone()));
+            // Make sure that the argument's environment does not get
+            // overridden by that of the function by producing
+            // alternatives sourced from this context
+            let mut implicit_env = HashMap::new();
+            for var in fun.env.keys() {
+                if let Some(val) = bindings.get(var) {
+                    implicit_env.insert(*var, val.clone());
+                }
+            }
+            // Setup the environment in which to evaluate body
+            let new_bindings = evaluate_binding(
+                &new_bind,
+                implicit_env,
+                flattened,
+                bindings,
+                prover_defs,
+                field_ops,
+                gen,
+            )?;
+            // Apply the new environment to the body
+            fun.env.extend(new_bindings.clone());
+            // Modify function type to account for the partial
+            // application that has just happened
+            expr1.t = None;
+            // Finally evaluate the body
+            let mut val = evaluate(&expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+            // Enable closures by storing the required environment
+            // modifications inside the evaluation result
+            capture_env(&mut val, new_bindings)?;
+            Ok(val)
+        }
+        _ => Err(Error::ApplicationError {
+            e2: Box::new(expr2.clone()),
+            e1: Box::new(expr1),
+        }),
+    }
+}
+
+/* Evaluate the given let-binding and the body that it scopes over. */
+fn evaluate_let_binding(
+    expr: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let mut acc_bindings = HashMap::new();
+    let mut expr = expr;
+    // Instead of recursively evaluating let-bindings, iteratively
+    // evaluate them to reduce stack usage.
+    while let Expr::LetBinding(binding, body) = &expr.v {
+        // Evaluate binding expression and get new bindings
+        let new_bindings = evaluate_binding(
+            binding,
+            HashMap::new(),
+            flattened,
+            bindings,
+            prover_defs,
+            field_ops,
+            gen,
+        )?;
+        let mut new_bindings = new_bindings
+            .into_iter()
+            .map(|(k, v)| (k, Some(v)))
+            .collect();
+        // Insert new bindings into environment and get old bindings
+        exchange_map(bindings, &mut new_bindings);
+        // Store the old bindings, prioritizing the oldest bindings
+        for (k, v) in new_bindings {
+            acc_bindings.entry(k).or_insert(v);
+        }
+        // Now move onto the body expression
+        if let Expr::Sequence(seq) = &body.v {
+            // Iteratively evaluate a sequence expression here in order
+            // to avoid leaving this call frame
+            for expr in &seq[0..seq.len() - 1] {
+                evaluate(expr, flattened, bindings, prover_defs, field_ops, gen)?;
+            }
+            // Hence the let's body is now effectively this sequence's
+            // last expression
+            expr = seq.last().unwrap();
+        } else {
+            expr = body;
+        }
+    }
+    // Now evaluate the inner-most body
+    let mut val = evaluate(expr, flattened, bindings, prover_defs, field_ops, gen)?;
+    // Now restore the old environment before this entire let expression
+    exchange_map(bindings, &mut acc_bindings);
+    let acc_bindings = acc_bindings
+        .into_iter()
+        .map(|(k, v)| (k, v.unwrap()))
+        .collect();
+    // Capture the environment modifications required to evaluate body
+    // inside the body. Necessary for closures.
+    capture_env(&mut val, acc_bindings)?;
+    Ok(val)
+}
+
+/* Evaluate the given base raised to the given constant exponent. */
+fn evaluate_exponentiate(
+    e1: &TExpr,
+    e2: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    // Compute the base once and for all
+    let e1 = evaluate(e1, flattened, bindings, prover_defs, field_ops, gen)?;
+    let e2 = evaluate(e2, flattened, bindings, prover_defs, field_ops, gen)?;
+    match (&e1.v, &e2.v) {
+        (Expr::Constant(a), Expr::Constant(b)) => {
+            Ok(
+                Expr::Constant(field_ops.infix(InfixOp::Exponentiate, a.clone(), b.clone()))
+                    .type_expr(Some(Type::Int)),
+            )
+        }
+        (_, Expr::Constant(c)) if c.is_zero() => {
+            Ok(Expr::Constant(One::one()).type_expr(Some(Type::Int)))
+        }
+        (_, Expr::Constant(c)) if c.is_one() => Ok(e1),
+        (_, Expr::Constant(v2)) if v2.is_positive() => {
+            // Compute roughly the sqrt of this expression
+            let sqrt = Expr::Infix(
+                InfixOp::Exponentiate,
+                Box::new(e1.clone()),
+                Box::new(Expr::Constant(v2 / 2i8).type_expr(Some(Type::Int))),
+            )
+            .type_expr(Some(Type::Int));
+            let out2_term = evaluate(&sqrt, flattened, bindings, prover_defs, field_ops, gen)?;
+            // Now square the value to obtain roughly this expression
+            let mut rhs = infix_op(InfixOp::Multiply, out2_term.clone(), out2_term);
+            // Multiply by the base once more in order to obtain
+            // original value
+            if v2 % 2i8 == One::one() {
+                rhs = infix_op(InfixOp::Multiply, rhs, e1);
+            }
+            evaluate(&rhs, flattened, bindings, prover_defs, field_ops, gen)
+        }
+        (_, Expr::Constant(v2)) => {
+            // Compute the reciprocal of this expression
+            let recip = Expr::Infix(
+                InfixOp::Exponentiate,
+                Box::new(e1),
+                Box::new(Expr::Constant(-v2).type_expr(Some(Type::Int))),
+            );
+            // Now invert the value to obtain this expression
+            let rhs = infix_op(
+                InfixOp::Divide,
+                Expr::Constant(One::one()).type_expr(Some(Type::Int)),
+                recip.type_expr(Some(Type::Int)),
+            );
+            evaluate(&rhs, flattened, bindings, prover_defs, field_ops, gen)
+        }
+        _ => Err(Error::VariableExponentError),
+    }
+}
+
+/* Evaluate the given match expression by finding the first arm whose pattern
+ * statically matches the scrutinee. */
+fn evaluate_match(
+    matche: &Match,
+    expr: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let val = evaluate(&matche.0, flattened, bindings, prover_defs, field_ops, gen)?;
+    for (pat, expr2) in matche.1.iter().zip(matche.2.iter()) {
+        let res = match_pattern_expr(pat, &val, bindings, &mut HashMap::new(), prover_defs, gen)?;
+        match res {
+            Tribool::True => {
+                // Bind the already evaluated value so that the constraints it
+                // implies are not emitted twice
+                let expr = TExpr {
+                    v: Expr::LetBinding(
+                        LetBinding(pat.clone(), Box::new(val)),
+                        Box::new(expr2.clone()),
+                    ),
+                    t: expr.t.clone(),
+                    s: expr.s.clone(),
+                };
+                return evaluate(&expr, flattened, bindings, prover_defs, field_ops, gen);
+            }
+            Tribool::Indeterminate => {
+                return Err(Error::StaticMatchError {
+                    e: Box::new(val),
+                    p: Box::new(pat.clone()),
+                }
+                .locate(&pat.s))
+            }
+            Tribool::False => continue,
+        }
+    }
+    Err(Error::MatchError {
+        e1: Box::new(val),
+        e2: Box::new(expr.clone()),
+    })
+}
+
+/* Evaluate the given conditional. Constant conditions select a branch outright,
+ * whereas other conditions select between the values of both branches. Since
+ * both branches are then evaluated, neither of them may emit constraints as
+ * these would have to hold whichever branch is selected. */
+fn evaluate_if(
+    expr: &TExpr,
+    flattened: &mut Option<Module>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    let Expr::If(cond, expr1, expr2) = &expr.v else {
+        return Err(Error::UnexpectedExpression {
+            e: Box::new(expr.clone()),
+        });
+    };
+    let cond = evaluate(cond, flattened, bindings, prover_defs, field_ops, gen)?;
+    match &cond.v {
+        // Statically known conditions select a branch outright
+        Expr::Constant(c) if c.is_one() => {
+            evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)
+        }
+        Expr::Constant(c) if c.is_zero() => {
+            evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)
+        }
+        Expr::Constant(c) => Err(Error::NonBooleanCondition { c: c.clone() }),
+        _ => {
+            // Name the condition so that it is only constrained to be
+            // boolean once however many values it selects between
+            let cond = if let Expr::Variable(_) = &cond.v {
+                cond
+            } else {
+                bind_fresh_variable(cond, flattened, gen)
+            };
+            let constraint_count = flattened.as_ref().map(|flat| flat.exprs.len());
+            let expr1 = evaluate(expr1, flattened, bindings, prover_defs, field_ops, gen)?;
+            let expr2 = evaluate(expr2, flattened, bindings, prover_defs, field_ops, gen)?;
+            if flattened.as_ref().map(|flat| flat.exprs.len()) != constraint_count {
+                return Err(Error::ConditionalConstraintError);
+            }
+            select_values(&cond, expr1, expr2, flattened, gen)
         }
     }
 }
 
+/* Define a fresh variable to be equal to the given value and return the
+ * variable in its place. */
+fn bind_fresh_variable(val: TExpr, flattened: &mut Option<Module>, gen: &mut VarGen) -> TExpr {
+    let var = Variable::new(gen.generate_id());
+    let typ = val.t.clone();
+    let binding = Definition(
+        LetBinding(
+            Pat::Variable(var.clone()).type_pat(typ.clone()),
+            Box::new(val),
+        ),
+        None,
+    );
+    if let Some(flat) = flattened {
+        flat.defs.push(binding);
+    }
+    Expr::Variable(var).type_expr(typ)
+}
+
+/* Select between the two given values according to the given condition
+ * variable. Tuples and lists of the same shape are selected between
+ * elementwise. */
+fn select_values(
+    cond: &TExpr,
+    expr1: TExpr,
+    expr2: TExpr,
+    flattened: &mut Option<Module>,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    match (&expr1.v, &expr2.v) {
+        (Expr::Unit, Expr::Unit) | (Expr::Nil, Expr::Nil) => Ok(expr1),
+        (Expr::Product(expr11, expr12), Expr::Product(expr21, expr22)) => {
+            let val1 = select_values(cond, *expr11.clone(), *expr21.clone(), flattened, gen)?;
+            let val2 = select_values(cond, *expr12.clone(), *expr22.clone(), flattened, gen)?;
+            Ok(Expr::Product(Box::new(val1), Box::new(val2)).type_expr(expr1.t.clone()))
+        }
+        (Expr::Cons(expr11, expr12), Expr::Cons(expr21, expr22)) => {
+            let val1 = select_values(cond, *expr11.clone(), *expr21.clone(), flattened, gen)?;
+            let val2 = select_values(cond, *expr12.clone(), *expr22.clone(), flattened, gen)?;
+            Ok(Expr::Cons(Box::new(val1), Box::new(val2)).type_expr(expr1.t.clone()))
+        }
+        (Expr::Constant(c1), Expr::Constant(c2)) if c1 == c2 => Ok(expr1),
+        (
+            Expr::Variable(_)
+            | Expr::Constant(_)
+            | Expr::Infix(_, _, _)
+            | Expr::Negate(_)
+            | Expr::If(_, _, _),
+            Expr::Variable(_)
+            | Expr::Constant(_)
+            | Expr::Infix(_, _, _)
+            | Expr::Negate(_)
+            | Expr::If(_, _, _),
+        ) => {
+            let val = Expr::If(Box::new(cond.clone()), Box::new(expr1), Box::new(expr2));
+            Ok(bind_fresh_variable(
+                val.type_expr(Some(Type::Int)),
+                flattened,
+                gen,
+            ))
+        }
+        _ => Err(Error::ConditionalBranchError {
+            e1: Box::new(expr1.clone()),
+            e2: Box::new(expr2.clone()),
+        }),
+    }
+}
+
 /* Evaluate the given definition emitting the implied constraints. The binding
  * environment is modified as necessary. */
 fn evaluate_def(
@@ -798,7 +1120,7 @@ fn evaluate_def(
     prover_defs: &mut HashSet<VariableId>,
     field_ops: &dyn FieldOps,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     let ext = evaluate_binding(
         &def.0,
         HashMap::new(),
@@ -807,8 +1129,9 @@ fn evaluate_def(
         prover_defs,
         field_ops,
         gen,
-    );
+    )?;
     bindings.extend(ext);
+    Ok(())
 }
 
 /* Evaluate the given module emitting the constraints that it implies. */
@@ -819,16 +1142,17 @@ pub fn evaluate_module(
     prover_defs: &mut HashSet<VariableId>,
     field_ops: &dyn FieldOps,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     if let Some(flattened) = oflattened {
         flattened.pubs.extend(module.pubs.clone());
     }
     for def in &module.defs {
-        evaluate_def(def, oflattened, bindings, prover_defs, field_ops, gen);
+        evaluate_def(def, oflattened, bindings, prover_defs, field_ops, gen)?;
     }
     for expr in &module.exprs {
-        evaluate(expr, oflattened, bindings, prover_defs, field_ops, gen).unwrap();
+        evaluate(expr, oflattened, bindings, prover_defs, field_ops, gen)?;
     }
+    Ok(())
 }
 
 /* Collect all the variables occurring in the given pattern. */
@@ -850,7 +1174,7 @@ pub fn collect_pattern_variables(pat: &TPat, map: &mut HashMap<VariableId, Varia
 }
 
 /* Collect all the variables occurring in the given expression. */
-fn collect_expr_variables(expr: &TExpr, map: &mut HashMap<VariableId, Variable>) {
+pub fn collect_expr_variables(expr: &TExpr, map: &mut HashMap<VariableId, Variable>) {
     match &expr.v {
         Expr::Variable(var) => {
             map.entry(var.id).or_insert_with(|| var.clone());
@@ -875,8 +1199,13 @@ fn collect_expr_variables(expr: &TExpr, map: &mut HashMap<VariableId, Variable>)
         Expr::Negate(expr1) => {
             collect_expr_variables(expr1, map);
         }
-        Expr::Function(fun) => {
-            fun.params
+        Expr::If(cond, expr1, expr2) => {
+            collect_expr_variables(cond, map);
+            collect_expr_variables(expr1, map);
+            collect_expr_variables(expr2, map);
+        }
+        Expr::Function(fun) => {
+            fun.params
                 .iter()
                 .for_each(|param| collect_pattern_variables(param, map));
             collect_expr_variables(&fun.body, map);
@@ -936,6 +1265,7 @@ fn infix_op(op: InfixOp, e1: TExpr, e2: TExpr) -> TExpr {
         (InfixOp::Modulo, _, Expr::Constant(c)) if c.is_one() => TExpr {
             v: Expr::Constant(Zero::zero()),
             t: Some(Type::Int),
+            s: None,
         },
         (InfixOp::Add, Expr::Constant(c), _) if c.is_zero() => e2,
         (InfixOp::Add, _, Expr::Constant(c)) if c.is_zero() => e1,
@@ -943,6 +1273,7 @@ fn infix_op(op: InfixOp, e1: TExpr, e2: TExpr) -> TExpr {
         (InfixOp::Equal, _, _) => TExpr {
             v: Expr::Infix(op, Box::new(e1), Box::new(e2)),
             t: Some(Type::Unit),
+            s: None,
         },
         (
             InfixOp::Multiply
@@ -952,32 +1283,45 @@ fn infix_op(op: InfixOp, e1: TExpr, e2: TExpr) -> TExpr {
             | InfixOp::Subtract
             | InfixOp::Exponentiate
             | InfixOp::IntDivide
-            | InfixOp::Modulo,
+            | InfixOp::Modulo
+            | InfixOp::Less
+            | InfixOp::LessEqual
+            | InfixOp::NotEqual,
             _,
             _,
         ) => TExpr {
             v: Expr::Infix(op, Box::new(e1), Box::new(e2)),
             t: Some(Type::Int),
+            s: None,
         },
     }
 }
 
 /* Flatten the given binding down into the set of constraints it defines. */
-fn flatten_binding(pat: &TPat, expr: &TExpr, oflattened: &mut Option<Module>) {
+fn flatten_binding(pat: &TPat, expr: &TExpr, oflattened: &mut Option<Module>) -> Result<(), Error> {
     if let Some(flattened) = oflattened {
         match (&pat.v, &expr.v) {
             (Pat::Variable(_), Expr::Function(_) | Expr::Intrinsic(_)) => {}
             (
                 Pat::Variable(_),
-                Expr::Variable(_) | Expr::Constant(_) | Expr::Infix(_, _, _) | Expr::Negate(_),
+                Expr::Variable(_)
+                | Expr::Constant(_)
+                | Expr::Infix(_, _, _)
+                | Expr::Negate(_)
+                | Expr::If(_, _, _),
             ) => {
-                flattened
-                    .defs
-                    .push(Definition(LetBinding(pat.clone(), Box::new(expr.clone()))));
+                flattened.defs.push(Definition(
+                    LetBinding(pat.clone(), Box::new(expr.clone())),
+                    None,
+                ));
             }
             (
                 Pat::Constant(pat),
-                Expr::Variable(_) | Expr::Constant(_) | Expr::Infix(_, _, _) | Expr::Negate(_),
+                Expr::Variable(_)
+                | Expr::Constant(_)
+                | Expr::Infix(_, _, _)
+                | Expr::Negate(_)
+                | Expr::If(_, _, _),
             ) => {
                 flattened.exprs.push(
                     Expr::Infix(
@@ -989,35 +1333,53 @@ fn flatten_binding(pat: &TPat, expr: &TExpr, oflattened: &mut Option<Module>) {
                 );
             }
             (Pat::As(pat, _name), _) => {
-                flatten_binding(pat, expr, oflattened);
+                flatten_binding(pat, expr, oflattened)?;
             }
             (Pat::Unit, Expr::Unit) | (Pat::Nil, Expr::Nil) => {}
             (Pat::Product(pat1, pat2), Expr::Product(expr1, expr2)) => {
-                flatten_binding(pat1, expr1, oflattened);
-                flatten_binding(pat2, expr2, oflattened);
+                flatten_binding(pat1, expr1, oflattened)?;
+                flatten_binding(pat2, expr2, oflattened)?;
             }
             (Pat::Cons(pat1, pat2), Expr::Cons(expr1, expr2)) => {
-                flatten_binding(pat1, expr1, oflattened);
-                flatten_binding(pat2, expr2, oflattened);
+                flatten_binding(pat1, expr1, oflattened)?;
+                flatten_binding(pat2, expr2, oflattened)?;
+            }
+            _ => {
+                return Err(Error::PatternMatchError {
+                    p: Box::new(pat.clone()),
+                    e: Box::new(expr.clone()),
+                })
             }
-            _ => unreachable!("encountered unexpected binding: {} = {}", pat, expr),
         }
     }
+    Ok(())
 }
 
 /* Flatten the given equality down into the set of constraints it defines. */
-fn flatten_equals(expr1: &TExpr, expr2: &TExpr, oflattened: &mut Option<Module>) {
+fn flatten_equals(
+    expr1: &TExpr,
+    expr2: &TExpr,
+    oflattened: &mut Option<Module>,
+) -> Result<(), Error> {
     if let Some(flattened) = oflattened {
         match (&expr1.v, &expr2.v) {
             (Expr::Unit, Expr::Unit) | (Expr::Nil, Expr::Nil) => {}
             (Expr::Product(expr11, expr12), Expr::Product(expr21, expr22))
             | (Expr::Cons(expr11, expr12), Expr::Cons(expr21, expr22)) => {
-                flatten_equals(expr11, expr21, oflattened);
-                flatten_equals(expr12, expr22, oflattened);
+                flatten_equals(expr11, expr21, oflattened)?;
+                flatten_equals(expr12, expr22, oflattened)?;
             }
             (
-                Expr::Variable(_) | Expr::Negate(_) | Expr::Infix(_, _, _) | Expr::Constant(_),
-                Expr::Variable(_) | Expr::Negate(_) | Expr::Infix(_, _, _) | Expr::Constant(_),
+                Expr::Variable(_)
+                | Expr::Negate(_)
+                | Expr::Infix(_, _, _)
+                | Expr::Constant(_)
+                | Expr::If(_, _, _),
+                Expr::Variable(_)
+                | Expr::Negate(_)
+                | Expr::Infix(_, _, _)
+                | Expr::Constant(_)
+                | Expr::If(_, _, _),
             ) => {
                 flattened.exprs.push(
                     Expr::Infix(
@@ -1028,9 +1390,15 @@ fn flatten_equals(expr1: &TExpr, expr2: &TExpr, oflattened: &mut Option<Module>)
                     .type_expr(Some(Type::Unit)),
                 );
             }
-            _ => unreachable!("encountered unexpected equality: {} = {}", expr1, expr2),
+            _ => {
+                return Err(Error::EqualityError {
+                    e1: Box::new(expr1.clone()),
+                    e2: Box::new(expr2.clone()),
+                })
+            }
         }
     }
+    Ok(())
 }
 
 /* Make an equality expression to constrain the values that satisfy the circuit.
@@ -1047,7 +1415,83 @@ fn push_constraint_def(module: &mut Module, out: TPat, expr: TExpr) {
     );
     module
         .defs
-        .push(Definition(LetBinding(out, Box::new(expr))));
+        .push(Definition(LetBinding(out, Box::new(expr)), None));
+}
+
+/* Constrain the given term to be either 0 or 1 using the equality t = t*t. */
+fn push_boolean_constraint(module: &mut Module, term: &TPat) {
+    let square = infix_op(InfixOp::Multiply, term.to_expr(), term.to_expr());
+    module.exprs.push(
+        Expr::Infix(InfixOp::Equal, Box::new(term.to_expr()), Box::new(square))
+            .type_expr(Some(Type::Unit)),
+    );
+}
+
+/* Constrain the given term to fit into the given number of bits and return the
+ * terms for its bits, least significant first. The bits themselves are witness
+ * definitions computed by the prover. */
+fn flatten_bits_to_3ac(
+    term: &TPat,
+    width: usize,
+    flattened: &mut Module,
+    gen: &mut VarGen,
+) -> Vec<TPat> {
+    let mut bits = Vec::with_capacity(width);
+    let mut sum: Option<TPat> = None;
+    for i in 0..width {
+        let place = Expr::Constant(BigInt::one() << i).type_expr(Some(Type::Int));
+        let two = Expr::Constant(BigInt::from(2)).type_expr(Some(Type::Int));
+        // bit = (term \ 2^i) % 2
+        let bit = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+        let quotient = infix_op(InfixOp::IntDivide, term.to_expr(), place.clone());
+        let hint = infix_op(InfixOp::Modulo, quotient, two);
+        flattened
+            .defs
+            .push(Definition(LetBinding(bit.clone(), Box::new(hint)), None));
+        push_boolean_constraint(flattened, &bit);
+        // Accumulate 2^i*bit into the running sum
+        let scaled = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+        push_constraint_def(
+            flattened,
+            scaled.clone(),
+            infix_op(InfixOp::Multiply, place, bit.to_expr()),
+        );
+        sum = Some(match sum {
+            None => scaled,
+            Some(sum) => {
+                let acc = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+                let rhs = infix_op(InfixOp::Add, sum.to_expr(), scaled.to_expr());
+                push_constraint_def(flattened, acc.clone(), rhs);
+                acc
+            }
+        });
+        bits.push(bit);
+    }
+    let total = sum.map_or_else(
+        || Expr::Constant(BigInt::zero()).type_expr(Some(Type::Int)),
+        |sum| sum.to_expr(),
+    );
+    flattened.exprs.push(
+        Expr::Infix(InfixOp::Equal, Box::new(term.to_expr()), Box::new(total))
+            .type_expr(Some(Type::Unit)),
+    );
+    bits
+}
+
+/* Constrain the given comparison operand to fit into the given number of bits.
+ * Constants that already fit need no constraints. */
+fn flatten_operand_range_to_3ac(
+    term: &TPat,
+    width: usize,
+    flattened: &mut Module,
+    gen: &mut VarGen,
+) {
+    if let Pat::Constant(c) = &term.v {
+        if !c.is_negative() && c.bits() <= width as u64 {
+            return;
+        }
+    }
+    flatten_bits_to_3ac(term, width, flattened, gen);
 }
 
 /* Flatten the given expression down to a single term and place the definitions
@@ -1057,6 +1501,8 @@ fn flatten_expr_to_3ac(
     out: Option<TPat>,
     expr: &TExpr,
     flattened: &mut Module,
+    conditions: &mut HashSet<VariableId>,
+    bit_width: usize,
     gen: &mut VarGen,
 ) -> Result<TPat, Error> {
     match (out, &expr.v) {
@@ -1067,54 +1513,184 @@ fn flatten_expr_to_3ac(
             Ok(pat)
         }
         (out, Expr::Negate(n)) => {
-            let out1_term = flatten_expr_to_3ac(None, n, flattened, gen)?;
+            let out1_term = flatten_expr_to_3ac(None, n, flattened, conditions, bit_width, gen)?;
             let rhs = Expr::Negate(Box::new(out1_term.to_expr()));
             let out_var = Variable::new(gen.generate_id());
             let out = out.unwrap_or(Pat::Variable(out_var).type_pat(expr.t.clone()));
             push_constraint_def(flattened, out.clone(), rhs.type_expr(Some(Type::Int)));
             Ok(out)
         }
+        (out, Expr::Infix(op @ (InfixOp::Less | InfixOp::LessEqual), e1, e2)) => {
+            let out1_term = flatten_expr_to_3ac(None, e1, flattened, conditions, bit_width, gen)?;
+            let out2_term = flatten_expr_to_3ac(None, e2, flattened, conditions, bit_width, gen)?;
+            // Both operands must fit into n bits, otherwise a value that wraps
+            // around the field could flip the result
+            flatten_operand_range_to_3ac(&out1_term, bit_width, flattened, gen);
+            flatten_operand_range_to_3ac(&out2_term, bit_width, flattened, gen);
+            // a <= b exactly when bit n of b-a+2^n is set, and a < b exactly
+            // when bit n of b-a+2^n-1 is set
+            let mut offset = BigInt::one() << bit_width;
+            if *op == InfixOp::Less {
+                offset -= 1;
+            }
+            let diff = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+            let rhs = infix_op(InfixOp::Subtract, out2_term.to_expr(), out1_term.to_expr());
+            push_constraint_def(flattened, diff.clone(), rhs);
+            let shifted = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+            let offset = Expr::Constant(offset).type_expr(Some(Type::Int));
+            let rhs = infix_op(InfixOp::Add, diff.to_expr(), offset);
+            push_constraint_def(flattened, shifted.clone(), rhs);
+            let bits = flatten_bits_to_3ac(&shifted, bit_width + 1, flattened, gen);
+            let out_var = Variable::new(gen.generate_id());
+            let out = out.unwrap_or(Pat::Variable(out_var).type_pat(expr.t.clone()));
+            push_constraint_def(flattened, out.clone(), bits[bit_width].to_expr());
+            Ok(out)
+        }
+        (out, Expr::Infix(InfixOp::NotEqual, e1, e2)) => {
+            let out1_term = flatten_expr_to_3ac(None, e1, flattened, conditions, bit_width, gen)?;
+            let out2_term = flatten_expr_to_3ac(None, e2, flattened, conditions, bit_width, gen)?;
+            let diff = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+            let rhs = infix_op(InfixOp::Subtract, out1_term.to_expr(), out2_term.to_expr());
+            push_constraint_def(flattened, diff.clone(), rhs);
+            // The prover supplies the inverse of the difference, or 0 if it
+            // has none
+            let inv = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+            let one = Expr::Constant(BigInt::one()).type_expr(Some(Type::Int));
+            let hint = infix_op(InfixOp::DivideZ, one, diff.to_expr());
+            flattened
+                .defs
+                .push(Definition(LetBinding(inv.clone(), Box::new(hint)), None));
+            // out = inv*d is 0 when d is 0, and d = d*out forces it to be 1
+            // otherwise
+            let out_var = Variable::new(gen.generate_id());
+            let out = out.unwrap_or(Pat::Variable(out_var).type_pat(expr.t.clone()));
+            let rhs = infix_op(InfixOp::Multiply, inv.to_expr(), diff.to_expr());
+            push_constraint_def(flattened, out.clone(), rhs);
+            flattened.exprs.push(
+                Expr::Infix(
+                    InfixOp::Equal,
+                    Box::new(diff.to_expr()),
+                    Box::new(infix_op(InfixOp::Multiply, diff.to_expr(), out.to_expr())),
+                )
+                .type_expr(Some(Type::Unit)),
+            );
+            Ok(out)
+        }
         (out, Expr::Infix(op, e1, e2)) if *op != InfixOp::Exponentiate => {
-            let out1_term = flatten_expr_to_3ac(None, e1, flattened, gen)?;
-            let out2_term = flatten_expr_to_3ac(None, e2, flattened, gen)?;
+            let out1_term = flatten_expr_to_3ac(None, e1, flattened, conditions, bit_width, gen)?;
+            let out2_term = flatten_expr_to_3ac(None, e2, flattened, conditions, bit_width, gen)?;
             let rhs = infix_op(*op, out1_term.to_expr(), out2_term.to_expr());
             let out_var = Variable::new(gen.generate_id());
             let out = out.unwrap_or(Pat::Variable(out_var).type_pat(expr.t.clone()));
             push_constraint_def(flattened, out.clone(), rhs);
             Ok(out)
         }
-        _ => Err(Error::UnexpectedExpression { e: expr.clone() }),
+        (out, Expr::If(cond, e1, e2)) => {
+            let cond_term = flatten_expr_to_3ac(None, cond, flattened, conditions, bit_width, gen)?;
+            if let Pat::Variable(var) = &cond_term.v {
+                if conditions.insert(var.id) {
+                    push_boolean_constraint(flattened, &cond_term);
+                }
+            }
+            let out1_term = flatten_expr_to_3ac(None, e1, flattened, conditions, bit_width, gen)?;
+            let out2_term = flatten_expr_to_3ac(None, e2, flattened, conditions, bit_width, gen)?;
+            // Select using c*(a-b)+b
+            let diff = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+            let rhs = infix_op(InfixOp::Subtract, out1_term.to_expr(), out2_term.to_expr());
+            push_constraint_def(flattened, diff.clone(), rhs);
+            let prod = Pat::Variable(Variable::new(gen.generate_id())).type_pat(Some(Type::Int));
+            let rhs = infix_op(InfixOp::Multiply, cond_term.to_expr(), diff.to_expr());
+            push_constraint_def(flattened, prod.clone(), rhs);
+            let out_var = Variable::new(gen.generate_id());
+            let out = out.unwrap_or(Pat::Variable(out_var).type_pat(expr.t.clone()));
+            let rhs = infix_op(InfixOp::Add, prod.to_expr(), out2_term.to_expr());
+            push_constraint_def(flattened, out.clone(), rhs);
+            Ok(out)
+        }
+        _ => Err(Error::UnexpectedExpression {
+            e: Box::new(expr.clone()),
+        }),
     }
 }
 
 /* Flatten the given definition into three-address form. */
-fn flatten_def_to_3ac(def: &Definition, flattened: &mut Module, gen: &mut VarGen) {
-    flatten_expr_to_3ac(Some(def.0 .0.clone()), &def.0 .1, flattened, gen).unwrap();
+fn flatten_def_to_3ac(
+    def: &Definition,
+    flattened: &mut Module,
+    conditions: &mut HashSet<VariableId>,
+    bit_width: usize,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    flatten_expr_to_3ac(
+        Some(def.0 .0.clone()),
+        &def.0 .1,
+        flattened,
+        conditions,
+        bit_width,
+        gen,
+    )?;
+    Ok(())
+}
+
+/* Rewrite the conditionals occurring in the given witness expression into the
+ * arithmetic c*(a-b)+b so that provers can evaluate it directly. */
+fn lower_conditionals(expr: &mut TExpr) {
+    match &mut expr.v {
+        Expr::If(cond, expr1, expr2) => {
+            lower_conditionals(cond);
+            lower_conditionals(expr1);
+            lower_conditionals(expr2);
+            let diff = infix_op(InfixOp::Subtract, *expr1.clone(), *expr2.clone());
+            let prod = infix_op(InfixOp::Multiply, *cond.clone(), diff);
+            *expr = infix_op(InfixOp::Add, prod, *expr2.clone());
+        }
+        Expr::Infix(_, expr1, expr2) => {
+            lower_conditionals(expr1);
+            lower_conditionals(expr2);
+        }
+        Expr::Negate(expr1) => lower_conditionals(expr1),
+        _ => {}
+    }
 }
 
 /* Flatten all definitions and expressions in this module into three-address
- * form. */
+ * form. Comparisons assume that their operands fit into the given number of
+ * bits. For each equality added to the flattened module, the index of the
+ * definition that it derives from is returned, with the expressions of this
+ * module numbered after its definitions. */
 pub fn flatten_module_to_3ac(
     omodule: &Option<Module>,
     prover_defs: &HashSet<VariableId>,
     flattened: &mut Module,
+    bit_width: usize,
     gen: &mut VarGen,
-) {
+) -> Result<Vec<usize>, Error> {
+    let mut sources = Vec::new();
     if let Some(module) = omodule {
+        // The conditions that have already been constrained to be boolean
+        let mut conditions = HashSet::new();
         flattened.pubs.extend(module.pubs.clone());
-        for def in &module.defs {
+        let start = flattened.exprs.len();
+        for (idx, def) in module.defs.iter().enumerate() {
             match &def.0 .0.v {
                 Pat::Variable(var) if !prover_defs.contains(&var.id) => {
-                    flatten_def_to_3ac(def, flattened, gen)
+                    flatten_def_to_3ac(def, flattened, &mut conditions, bit_width, gen)?
                 }
                 Pat::Variable(_) => {
-                    flattened.defs.push(def.clone());
+                    let mut def = def.clone();
+                    lower_conditionals(&mut def.0 .1);
+                    flattened.defs.push(def);
                 }
                 Pat::Unit => {}
-                _ => unreachable!("encountered unexpected pattern: {}", def.0 .0),
+                _ => {
+                    return Err(Error::UnexpectedPattern {
+                        p: Box::new(def.0 .0.clone()),
+                    })
+                }
             }
+            sources.resize(flattened.exprs.len() - start, idx);
         }
-        for expr in &module.exprs {
+        for (idx, expr) in module.exprs.iter().enumerate() {
             if let Expr::Infix(InfixOp::Equal, lhs, rhs) = &expr.v {
                 // Flatten this equality constraint into a series of definitions.
                 // The last inserted definition is always an encoding of an equality
@@ -1125,23 +1701,46 @@ pub fn flatten_module_to_3ac(
                             Some(Pat::Variable(var.clone()).type_pat(ohs.t.clone())),
                             ohs,
                             flattened,
+                            &mut conditions,
+                            bit_width,
                             gen,
-                        )
-                        .unwrap();
+                        )?;
                     }
                     (_, Expr::Constant(val), ohs, _) | (ohs, _, _, Expr::Constant(val)) => {
                         flatten_expr_to_3ac(
                             Some(Pat::Constant(val.clone()).type_pat(ohs.t.clone())),
                             ohs,
                             flattened,
+                            &mut conditions,
+                            bit_width,
                             gen,
-                        )
-                        .unwrap();
+                        )?;
                     }
                     (_, _, _, _) => {
-                        let lhs = flatten_expr_to_3ac(None, lhs, flattened, gen).unwrap();
-                        let rhs = flatten_expr_to_3ac(None, rhs, flattened, gen).unwrap();
-                        flatten_expr_to_3ac(Some(lhs), &rhs.to_expr(), flattened, gen).unwrap();
+                        let lhs = flatten_expr_to_3ac(
+                            None,
+                            lhs,
+                            flattened,
+                            &mut conditions,
+                            bit_width,
+                            gen,
+                        )?;
+                        let rhs = flatten_expr_to_3ac(
+                            None,
+                            rhs,
+                            flattened,
+                            &mut conditions,
+                            bit_width,
+                            gen,
+                        )?;
+                        flatten_expr_to_3ac(
+                            Some(lhs),
+                            &rhs.to_expr(),
+                            flattened,
+                            &mut conditions,
+                            bit_width,
+                            gen,
+                        )?;
                     }
                 }
                 // Remove the last definition because it is solely an equality
@@ -1151,8 +1750,10 @@ pub fn flatten_module_to_3ac(
                     .pop()
                     .expect("a definition should have been made for the current expression");
             }
+            sources.resize(flattened.exprs.len() - start, module.defs.len() + idx);
         }
     }
+    Ok(sources)
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
@@ -1214,18 +1815,113 @@ pub fn classify_defs(omodule: &mut Option<Module>, prover_defs: &mut HashSet<Var
             }
         }
         // Now eliminate those definitions that are never used
-        module.defs.retain(|def| {
-            if let Pat::Variable(v1) = &def.0 .0.v {
-                classifier[&v1.id] != Usage::Never
-            } else if let Pat::Unit = &def.0 .0.v {
-                false
-            } else {
-                panic!("only variable patterns should be present at this stage");
-            }
+        // Other patterns are left for flatten_module_to_3ac to reject
+        module.defs.retain(|def| match &def.0 .0.v {
+            Pat::Variable(v1) => classifier[&v1.id] != Usage::Never,
+            Pat::Unit => false,
+            _ => true,
         });
     }
 }
 
+/* The standard library of gadgets that is made available to all programs. */
+pub const PRELUDE: &str = include_str!("prelude.pir");
+
+/* The prelude numbered and typed against the environment that the intrinsics
+ * registered before it produce, along with the environment that results. */
+struct TypedPrelude {
+    initial_globals: HashMap<String, VariableId>,
+    initial_id: VariableId,
+    module: Module,
+    globals: HashMap<String, VariableId>,
+    locals: HashMap<String, VariableId>,
+    global_types: HashMap<VariableId, Type>,
+    prog_types: HashMap<VariableId, Type>,
+    next_id: VariableId,
+}
+
+impl TypedPrelude {
+    fn new(
+        globals: &HashMap<String, VariableId>,
+        locals: &HashMap<String, VariableId>,
+        global_types: &HashMap<VariableId, Type>,
+        prog_types: &HashMap<VariableId, Type>,
+        gen: &VarGen,
+    ) -> Result<Self, Error> {
+        let mut module = Module::parse(PRELUDE)?;
+        let mut globals_after = globals.clone();
+        let mut locals = locals.clone();
+        let mut global_types = global_types.clone();
+        let mut prog_types = prog_types.clone();
+        let mut gen_after = VarGen(gen.0);
+        number_module_variables(&mut module, &mut globals_after, &mut gen_after, &mut locals);
+        infer_module_types(
+            &mut module,
+            &globals_after,
+            &mut global_types,
+            &mut prog_types,
+            &mut gen_after,
+        )?;
+        strip_module_types(&mut module);
+        Ok(Self {
+            initial_globals: globals.clone(),
+            initial_id: gen.0,
+            module,
+            globals: globals_after,
+            locals,
+            global_types,
+            prog_types,
+            next_id: gen_after.0,
+        })
+    }
+}
+
+/* Register the prelude definitions in the compilation environment. Must be
+ * done after the intrinsics are registered since the prelude uses them. The
+ * prelude is only parsed and typed once since every compilation registers the
+ * same intrinsics before it. */
+fn register_prelude(
+    globals: &mut HashMap<String, VariableId>,
+    locals: &mut HashMap<String, VariableId>,
+    global_types: &mut HashMap<VariableId, Type>,
+    prog_types: &mut HashMap<VariableId, Type>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    static TYPED_PRELUDE: OnceLock<TypedPrelude> = OnceLock::new();
+    let cached = match TYPED_PRELUDE.get() {
+        Some(cached) => cached,
+        None => {
+            let prelude = TypedPrelude::new(globals, locals, global_types, prog_types, gen)?;
+            TYPED_PRELUDE.get_or_init(|| prelude)
+        }
+    };
+    // Fall back to typing the prelude afresh in an environment that differs
+    // from the one the cached prelude was typed in
+    let uncached;
+    let prelude = if cached.initial_globals == *globals && cached.initial_id == gen.0 {
+        cached
+    } else {
+        uncached = TypedPrelude::new(globals, locals, global_types, prog_types, gen)?;
+        &uncached
+    };
+    globals.clone_from(&prelude.globals);
+    locals.clone_from(&prelude.locals);
+    global_types.clone_from(&prelude.global_types);
+    prog_types.clone_from(&prelude.prog_types);
+    gen.0 = prelude.next_id;
+    // The prelude only contains definitions, so no constraints are produced
+    evaluate_module(
+        &prelude.module,
+        &mut None,
+        bindings,
+        &mut HashSet::new(),
+        field_ops,
+        gen,
+    )
+}
+
 /* Fully expand out references to global variables using the available type
  * information. */
 fn expand_global_variables(
@@ -1235,7 +1931,7 @@ fn expand_global_variables(
     types: &mut HashMap<VariableId, Type>,
     bindings: &HashMap<VariableId, TExpr>,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     let mut expansions = HashMap::new();
     // Use the derived type information to figure out the form of each global
     // variable
@@ -1246,7 +1942,7 @@ fn expand_global_variables(
                 id: *id,
             })
             .type_expr(Some(vars[id].clone()));
-            expand_expr_variables(&mut expr, &mut expansions, types, gen).unwrap();
+            expand_expr_variables(&mut expr, &mut expansions, types, gen)?;
         }
     }
     // Now substitute each reference to a global variable with its inner
@@ -1257,60 +1953,238 @@ fn expand_global_variables(
     for expr in &mut module.exprs {
         copy_propagate_expr(expr, &expansions);
     }
+    Ok(())
 }
 
-/* Compile the given module down into three-address codes. */
-pub fn compile(mut module: Module, field_ops: &dyn FieldOps, config: &Config) -> Module {
-    let mut vg = VarGen::new();
+/* Prepare the given module for evaluation by registering the intrinsics and
+ * prelude, numbering its variables, and inferring its types. The bindings of
+ * the global variables are returned. */
+fn prepare_module(
+    module: &mut Module,
+    field_ops: &dyn FieldOps,
+    config: &Config,
+    vg: &mut VarGen,
+) -> Result<HashMap<VariableId, TExpr>, Error> {
+    // Comparisons decompose into bit_width+1 bits, which must not wrap around
+    let bound = BigInt::one() << (config.bit_width + 1);
+    if field_ops.canonical(bound.clone()) != bound {
+        return Err(Error::BitWidthError {
+            w: config.bit_width.into(),
+        });
+    }
     let mut globals = HashMap::new();
     let mut bindings = HashMap::new();
     let mut prog_types = HashMap::new();
     let mut global_types = HashMap::new();
-    register_fresh_intrinsic(&mut globals, &mut global_types, &mut bindings, &mut vg);
-    register_iter_intrinsic(&mut globals, &mut global_types, &mut bindings, &mut vg);
-    register_fold_intrinsic(&mut globals, &mut global_types, &mut bindings, &mut vg);
-    number_module_variables(&mut module, &mut globals, &mut vg, &mut HashMap::new());
-    infer_module_types(
-        &mut module,
-        &globals,
+    register_fresh_intrinsic(&mut globals, &mut global_types, &mut bindings, vg);
+    register_iter_intrinsic(&mut globals, &mut global_types, &mut bindings, vg);
+    register_fold_intrinsic(&mut globals, &mut global_types, &mut bindings, vg);
+    register_range_intrinsic(&mut globals, &mut global_types, &mut bindings, vg);
+    let mut locals = HashMap::new();
+    register_prelude(
+        &mut globals,
+        &mut locals,
         &mut global_types,
         &mut prog_types,
-        &mut vg,
-    );
+        &mut bindings,
+        field_ops,
+        vg,
+    )?;
+    number_module_variables(module, &mut globals, vg, &mut locals);
+    infer_module_types(module, &globals, &mut global_types, &mut prog_types, vg)?;
     qprintln!(config, "** Inferring types...");
-    print_types(&module, &prog_types, config);
+    print_types(module, &prog_types, config);
     // Global variables may have further internal structure, determine this
     // using derived type information
     expand_global_variables(
-        &mut module,
+        module,
         &globals,
         &global_types,
         &mut prog_types,
         &bindings,
-        &mut vg,
-    );
+        vg,
+    )?;
     // Type information is no longer required since we do symbolic
     // execution from now on
-    strip_module_types(&mut module);
-    let mut prover_defs = HashSet::new();
-    let mut constraints = Some(Module::default());
-    // Start generating arithmetic constraints
-    evaluate_module(
-        &module,
-        &mut constraints,
-        &mut bindings,
-        &mut prover_defs,
-        field_ops,
-        &mut vg,
-    );
+    strip_module_types(module);
+    Ok(bindings)
+}
+
+/* Lower the given constraints to three-address form and optimize them. The
+ * variables that only the prover computes are returned too, along with the
+ * origin of each resulting equality. The origins of the constraints'
+ * equalities are given, and so is a function giving the origin of each of
+ * their definitions. */
+fn lower_constraints(
+    mut constraints: Option<Module>,
+    mut prover_defs: HashSet<VariableId>,
+    expr_origins: &[usize],
+    def_origin: impl Fn(&Definition) -> usize,
+    field_ops: &dyn FieldOps,
+    config: &Config,
+    vg: &mut VarGen,
+) -> Result<(Module, HashSet<VariableId>, Vec<usize>), Error> {
     // Classify each definition that occurs in the constraints
     classify_defs(&mut constraints, &mut prover_defs);
     let mut module_3ac = Module::default();
-    flatten_module_to_3ac(&constraints, &prover_defs, &mut module_3ac, &mut vg);
+    let sources = flatten_module_to_3ac(
+        &constraints,
+        &prover_defs,
+        &mut module_3ac,
+        config.bit_width,
+        vg,
+    )?;
+    let defs = constraints.map(|module| module.defs).unwrap_or_default();
+    let mut origins: Vec<usize> = sources
+        .into_iter()
+        .map(|idx| match defs.get(idx) {
+            Some(def) => def_origin(def),
+            None => expr_origins[idx - defs.len()],
+        })
+        .collect();
+    // Each pass reports where its equalities came from
+    let mut trace = |sources: Vec<usize>| {
+        origins = sources.into_iter().map(|idx| origins[idx]).collect();
+    };
     // Start doing basic optimizations
     copy_propagate(&mut module_3ac, &prover_defs);
-    eliminate_dead_equalities(&mut module_3ac);
-    module_3ac
+    simplify_module(&mut module_3ac, &prover_defs, field_ops);
+    trace(eliminate_dead_equalities(&mut module_3ac));
+    let constraint_count = module_3ac.exprs.len();
+    trace(eliminate_common_subexpressions(&mut module_3ac));
+    qprintln!(
+        config,
+        "** Eliminating common subexpressions: {} constraints reduced to {}",
+        constraint_count,
+        module_3ac.exprs.len()
+    );
+    let constraint_count = module_3ac.exprs.len();
+    trace(fold_linear_combinations(&mut module_3ac, field_ops, vg));
+    qprintln!(
+        config,
+        "** Folding linear combinations: {} constraints reduced to {}",
+        constraint_count,
+        module_3ac.exprs.len()
+    );
+    Ok((module_3ac, prover_defs, origins))
+}
+
+/* Compile the given module down into three-address codes. */
+pub fn compile(module: Module, field_ops: &dyn FieldOps, config: &Config) -> Result<Module, Error> {
+    compile_with_provenance(module, field_ops, config).map(|(module_3ac, _)| module_3ac)
+}
+
+/* Where the parts of a compiled three-address module came from. An origin of
+ * Some(idx) stands for the top-level definition at that index, and None for
+ * the top-level constraints and public inputs of the module. */
+#[derive(Debug, Default, Clone)]
+pub struct Provenance {
+    // The variables whose definitions only the prover computes
+    pub prover_defs: HashSet<VariableId>,
+    // The origin of each equality, in the order of the module's equalities
+    pub expr_origins: Vec<Option<usize>>,
+    // The origin of each variable of the module
+    pub var_origins: HashMap<VariableId, Option<usize>>,
+}
+
+/* Compile the given module down into three-address codes, tracing each of the
+ * resulting equalities and variables back to the top-level definition whose
+ * evaluation caused it. A variable introduced during the evaluation of a
+ * definition originates there, and any other variable originates where the
+ * earliest equality that mentions it does. */
+pub fn compile_with_provenance(
+    mut module: Module,
+    field_ops: &dyn FieldOps,
+    config: &Config,
+) -> Result<(Module, Provenance), Error> {
+    let mut vg = VarGen::new();
+    let mut bindings = prepare_module(&mut module, field_ops, config, &mut vg)?;
+    let mut prover_defs = HashSet::new();
+    let mut constraints = Some(Module {
+        pubs: module.pubs.clone(),
+        ..Module::default()
+    });
+    // Origins are numbered by definition, with the top level numbered last
+    let top_level = module.defs.len();
+    let mut expr_origins = Vec::new();
+    // The first variable id allocated during each definition's evaluation
+    let mut var_starts = Vec::with_capacity(top_level + 1);
+    // Start generating arithmetic constraints
+    for (idx, def) in module.defs.iter().enumerate() {
+        var_starts.push(vg.0);
+        evaluate_def(
+            def,
+            &mut constraints,
+            &mut bindings,
+            &mut prover_defs,
+            field_ops,
+            &mut vg,
+        )?;
+        expr_origins.resize(constraints.as_ref().map_or(0, |c| c.exprs.len()), idx);
+    }
+    var_starts.push(vg.0);
+    for expr in &module.exprs {
+        evaluate(
+            expr,
+            &mut constraints,
+            &mut bindings,
+            &mut prover_defs,
+            field_ops,
+            &mut vg,
+        )?;
+    }
+    expr_origins.resize(constraints.as_ref().map_or(0, |c| c.exprs.len()), top_level);
+    let evaluated = vg.0;
+    // Variables from before the first definition, such as public inputs, and
+    // those from the top-level constraints belong to the top level
+    let evaluation_origin = |id: VariableId| match var_starts.partition_point(|start| *start <= id)
+    {
+        0 => top_level,
+        idx => idx - 1,
+    };
+    let def_origin = |def: &Definition| match &def.0 .0.v {
+        Pat::Variable(var) if var.id < evaluated => evaluation_origin(var.id),
+        _ => top_level,
+    };
+    let (module_3ac, prover_defs, origins) = lower_constraints(
+        constraints,
+        prover_defs,
+        &expr_origins,
+        def_origin,
+        field_ops,
+        config,
+        &mut vg,
+    )?;
+    // Variables made during lowering originate at their earliest equality
+    let mut var_origins = HashMap::new();
+    for (expr, origin) in module_3ac.exprs.iter().zip(&origins) {
+        let mut vars = HashMap::new();
+        collect_expr_variables(expr, &mut vars);
+        for id in vars.into_keys().filter(|id| *id >= evaluated) {
+            let entry = var_origins.entry(id).or_insert(*origin);
+            *entry = (*entry).min(*origin);
+        }
+    }
+    let mut variables = HashMap::new();
+    collect_module_variables(&module_3ac, &mut variables);
+    let as_option = |origin: usize| (origin < top_level).then_some(origin);
+    let var_origins = variables
+        .into_keys()
+        .map(|id| {
+            let origin = if id < evaluated {
+                evaluation_origin(id)
+            } else {
+                var_origins.get(&id).copied().unwrap_or(top_level)
+            };
+            (id, as_option(origin))
+        })
+        .collect();
+    let provenance = Provenance {
+        prover_defs,
+        expr_origins: origins.into_iter().map(as_option).collect(),
+        var_origins,
+    };
+    Ok((module_3ac, provenance))
 }
 
 /* Apply all the substitutions in the given map to the given expression. */
@@ -1335,6 +2209,11 @@ pub fn copy_propagate_expr(expr: &mut TExpr, substitutions: &HashMap<VariableId,
         Expr::Negate(expr1) | Expr::Function(Function { body: expr1, .. }) => {
             copy_propagate_expr(expr1, substitutions);
         }
+        Expr::If(cond, expr1, expr2) => {
+            copy_propagate_expr(cond, substitutions);
+            copy_propagate_expr(expr1, substitutions);
+            copy_propagate_expr(expr2, substitutions);
+        }
         Expr::LetBinding(binding, expr2) => {
             copy_propagate_expr(&mut binding.1, substitutions);
             copy_propagate_expr(expr2, substitutions);
@@ -1357,17 +2236,15 @@ pub fn copy_propagate_def(
     substitutions: &mut HashMap<VariableId, TExpr>,
     prover_defs: &HashSet<VariableId>,
 ) {
-    match &mut def.0 .0.v {
-        Pat::Variable(v1) => {
-            copy_propagate_expr(&mut def.0 .1, substitutions);
-            match &def.0 .1.v {
-                Expr::Variable(_) | Expr::Constant(_) if !prover_defs.contains(&v1.id) => {
-                    substitutions.insert(v1.id, *def.0 .1.clone());
-                }
-                _ => {}
+    copy_propagate_expr(&mut def.0 .1, substitutions);
+    // Three-address definitions only ever bind variables
+    if let Pat::Variable(v1) = &def.0 .0.v {
+        match &def.0 .1.v {
+            Expr::Variable(_) | Expr::Constant(_) if !prover_defs.contains(&v1.id) => {
+                substitutions.insert(v1.id, *def.0 .1.clone());
             }
+            _ => {}
         }
-        _ => panic!("only variable patterns should be present at this stage"),
     }
 }
 
@@ -1384,10 +2261,97 @@ pub fn copy_propagate(module: &mut Module, prover_defs: &HashSet<VariableId>) {
     }
 }
 
+/* Simplify the given three-address expression using algebraic identities,
+ * and evaluate it in the given field if all of its operands are constants.
+ * Division is only evaluated when the divisor is a nonzero constant. */
+fn simplify_3ac_expr(expr: &mut TExpr, field_ops: &dyn FieldOps) {
+    let constant = |e: &TExpr| match &e.v {
+        Expr::Constant(c) => Some(field_ops.canonical(c.clone())),
+        _ => None,
+    };
+    let same_variable = |a: &TExpr, b: &TExpr| match (&a.v, &b.v) {
+        (Expr::Variable(v1), Expr::Variable(v2)) => v1.id == v2.id,
+        _ => false,
+    };
+    let one = BigInt::one();
+    let minus_one = field_ops.negate(one.clone());
+    let simplified = match &expr.v {
+        Expr::Negate(a) => constant(a).map(|a| Expr::Constant(field_ops.negate(a))),
+        Expr::Infix(op, a, b) => match (op, constant(a), constant(b)) {
+            (InfixOp::Divide, Some(_), Some(d)) if d.is_zero() => None,
+            (
+                InfixOp::Add | InfixOp::Subtract | InfixOp::Multiply | InfixOp::Divide,
+                Some(c),
+                Some(d),
+            ) => Some(Expr::Constant(field_ops.infix(*op, c, d))),
+            // Identities
+            (InfixOp::Add, Some(c), _) if c.is_zero() => Some(b.v.clone()),
+            (InfixOp::Add | InfixOp::Subtract, _, Some(d)) if d.is_zero() => Some(a.v.clone()),
+            (InfixOp::Multiply, Some(c), _) if c == one => Some(b.v.clone()),
+            (InfixOp::Multiply | InfixOp::Divide, _, Some(d)) if d == one => Some(a.v.clone()),
+            // Annihilators
+            (InfixOp::Multiply, Some(c), _) if c.is_zero() => Some(Expr::Constant(c)),
+            (InfixOp::Multiply, _, Some(d)) if d.is_zero() => Some(Expr::Constant(d)),
+            (InfixOp::Subtract, _, _) if same_variable(a, b) => {
+                Some(Expr::Constant(BigInt::zero()))
+            }
+            // Negations
+            (InfixOp::Subtract, Some(c), _) if c.is_zero() => Some(Expr::Negate(b.clone())),
+            (InfixOp::Multiply, Some(c), _) if c == minus_one => Some(Expr::Negate(b.clone())),
+            (InfixOp::Multiply | InfixOp::Divide, _, Some(d)) if d == minus_one => {
+                Some(Expr::Negate(a.clone()))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(v) = simplified {
+        expr.v = v;
+    }
+}
+
+/* Simplify the three-address definitions and equalities of the given module
+ * using algebraic identities and the arithmetic of the given field. Variables
+ * whose definitions simplify down to a constant or another variable are then
+ * substituted away, as in copy propagation, so that constants propagate
+ * through the definitions that follow. */
+pub fn simplify_module(
+    module: &mut Module,
+    prover_defs: &HashSet<VariableId>,
+    field_ops: &dyn FieldOps,
+) {
+    let pubs: HashSet<VariableId> = module.pubs.iter().map(|var| var.id).collect();
+    let mut substitutions: HashMap<VariableId, TExpr> = HashMap::new();
+    for def in &mut module.defs {
+        match &def.0 .0.v {
+            Pat::Variable(var) => {
+                copy_propagate_expr(&mut def.0 .1, &substitutions);
+                simplify_3ac_expr(&mut def.0 .1, field_ops);
+                match &def.0 .1.v {
+                    Expr::Variable(_) | Expr::Constant(_)
+                        if !prover_defs.contains(&var.id) && !pubs.contains(&var.id) =>
+                    {
+                        substitutions.insert(var.id, *def.0 .1.clone());
+                    }
+                    _ => {}
+                }
+            }
+            _ => panic!("only variable patterns should be present at this stage"),
+        }
+    }
+    for expr in &mut module.exprs {
+        copy_propagate_expr(expr, &substitutions);
+        if let Expr::Infix(InfixOp::Equal, _, rhs) = &mut expr.v {
+            simplify_3ac_expr(rhs, field_ops);
+        }
+    }
+}
+
 /* Eliminate equalities that are obviously true from the constraint set. This
- * will reduce the number of gates in the circuit. */
-pub fn eliminate_dead_equalities(module: &mut Module) {
-    module.exprs.retain(|expr| match &expr.v {
+ * will reduce the number of gates in the circuit. The original index of each
+ * remaining equality is returned. */
+pub fn eliminate_dead_equalities(module: &mut Module) -> Vec<usize> {
+    let live = |expr: &TExpr| match &expr.v {
         Expr::Infix(InfixOp::Equal, expr1, expr2)
             if matches!((&expr1.v, &expr2.v), (Expr::Constant(c1), Expr::Constant(c2)) if
                          c1 == c2) =>
@@ -1401,7 +2365,426 @@ pub fn eliminate_dead_equalities(module: &mut Module) {
             false
         }
         _ => true,
+    };
+    let mut sources = Vec::with_capacity(module.exprs.len());
+    for (idx, expr) in std::mem::take(&mut module.exprs).into_iter().enumerate() {
+        if live(&expr) {
+            module.exprs.push(expr);
+            sources.push(idx);
+        }
+    }
+    sources
+}
+
+/* An operand of a three-address expression, identified by its value or by the
+ * id of its variable. */
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Operand {
+    Constant(BigInt),
+    Variable(VariableId),
+}
+
+/* The structure of a three-address expression with commutative operands put
+ * into a canonical order, so that equal structures compute equal values. */
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ExprShape {
+    Atom(Operand),
+    Negate(Operand),
+    Infix(InfixOp, Operand, Operand),
+}
+
+/* Get the operand that the given atomic expression denotes. */
+fn operand_of(expr: &TExpr) -> Option<Operand> {
+    match &expr.v {
+        Expr::Constant(c) => Some(Operand::Constant(c.clone())),
+        Expr::Variable(v) => Some(Operand::Variable(v.id)),
+        _ => None,
+    }
+}
+
+/* Get the shape of the given three-address expression, or None if it is not
+ * of three-address form. */
+fn expr_shape(expr: &TExpr) -> Option<ExprShape> {
+    match &expr.v {
+        Expr::Negate(a) => Some(ExprShape::Negate(operand_of(a)?)),
+        Expr::Infix(op @ (InfixOp::Add | InfixOp::Multiply), a, b) => {
+            let (a, b) = (operand_of(a)?, operand_of(b)?);
+            Some(ExprShape::Infix(*op, a.clone().min(b.clone()), a.max(b)))
+        }
+        Expr::Infix(op, a, b) => Some(ExprShape::Infix(*op, operand_of(a)?, operand_of(b)?)),
+        _ => Some(ExprShape::Atom(operand_of(expr)?)),
+    }
+}
+
+/* Get the shape of both sides of the given three-address equality. */
+fn equality_shape(expr: &TExpr) -> Option<(Operand, ExprShape)> {
+    match &expr.v {
+        Expr::Infix(InfixOp::Equal, lhs, rhs) => Some((operand_of(lhs)?, expr_shape(rhs)?)),
+        _ => None,
+    }
+}
+
+/* Merge the variables that are defined and constrained to equal structurally
+ * identical expressions, keeping the first of them, and then drop the
+ * equalities that have become duplicates. Addition and multiplication are
+ * treated as commutative. Public variables are never merged away, and neither
+ * are witnesses since nothing constrains them to equal their definitions. The
+ * original index of each remaining equality is returned. */
+pub fn eliminate_common_subexpressions(module: &mut Module) -> Vec<usize> {
+    let pubs: HashSet<VariableId> = module.pubs.iter().map(|var| var.id).collect();
+    // The definitions that are mirrored by equalities
+    let constrained: HashSet<(Operand, ExprShape)> =
+        module.exprs.iter().filter_map(equality_shape).collect();
+    let mut substitutions = HashMap::new();
+    let mut representatives: HashMap<ExprShape, Variable> = HashMap::new();
+    for mut def in std::mem::take(&mut module.defs) {
+        if let Pat::Variable(var) = &def.0 .0.v {
+            let mirrored = expr_shape(&def.0 .1)
+                .is_some_and(|shape| constrained.contains(&(Operand::Variable(var.id), shape)));
+            copy_propagate_expr(&mut def.0 .1, &substitutions);
+            match expr_shape(&def.0 .1) {
+                // Quotients by zero are unconstrained, so they are kept apart
+                Some(ExprShape::Infix(InfixOp::Divide, _, _)) => {}
+                Some(shape) if mirrored => match representatives.get(&shape) {
+                    Some(rep) if !pubs.contains(&var.id) => {
+                        let rep = Expr::Variable(rep.clone()).type_expr(Some(Type::Int));
+                        substitutions.insert(var.id, rep);
+                        continue;
+                    }
+                    Some(_) => {}
+                    None => {
+                        representatives.insert(shape, var.clone());
+                    }
+                },
+                _ => {}
+            }
+        }
+        module.defs.push(def);
+    }
+    let mut seen = HashSet::new();
+    let mut sources = Vec::new();
+    let exprs = std::mem::take(&mut module.exprs);
+    for (idx, mut expr) in exprs.into_iter().enumerate() {
+        copy_propagate_expr(&mut expr, &substitutions);
+        if equality_shape(&expr).is_none_or(|shape| seen.insert(shape)) {
+            module.exprs.push(expr);
+            sources.push(idx);
+        }
+    }
+    sources
+}
+
+// Number of variables that fit into a single arithmetic gate of either backend
+const GATE_WIDTH: usize = 3;
+
+/* A linear combination of variables plus a constant, which an equality
+ * requires to be zero. */
+#[derive(Clone, Default)]
+struct LinearConstraint {
+    terms: BTreeMap<VariableId, BigInt>,
+    constant: BigInt,
+}
+
+impl LinearConstraint {
+    /* Add the given multiple of the given combination to this one, dropping
+     * the terms that vanish. */
+    fn add_scaled(&mut self, other: &Self, k: &BigInt, field_ops: &dyn FieldOps) {
+        let scale = |c: &BigInt| field_ops.infix(InfixOp::Multiply, c.clone(), k.clone());
+        for (var, c) in &other.terms {
+            let sum = self.terms.remove(var).unwrap_or_default();
+            let sum = field_ops.infix(InfixOp::Add, sum, scale(c));
+            if !sum.is_zero() {
+                self.terms.insert(*var, sum);
+            }
+        }
+        self.constant =
+            field_ops.infix(InfixOp::Add, self.constant.clone(), scale(&other.constant));
+    }
+
+    /* Express the given expression as a linear combination if it is linear. */
+    fn from_expr(expr: &TExpr, field_ops: &dyn FieldOps) -> Option<Self> {
+        let one = BigInt::one();
+        let minus_one = field_ops.negate(one.clone());
+        let combine = |a: Self, b: &Self, k: &BigInt| {
+            let mut a = a;
+            a.add_scaled(b, k, field_ops);
+            a
+        };
+        match &expr.v {
+            Expr::Constant(c) => Some(Self {
+                terms: BTreeMap::new(),
+                constant: field_ops.canonical(c.clone()),
+            }),
+            Expr::Variable(var) => Some(Self {
+                terms: BTreeMap::from([(var.id, one)]),
+                constant: BigInt::zero(),
+            }),
+            Expr::Negate(a) => Some(combine(
+                Self::default(),
+                &Self::from_expr(a, field_ops)?,
+                &minus_one,
+            )),
+            Expr::Infix(InfixOp::Add, a, b) => Some(combine(
+                Self::from_expr(a, field_ops)?,
+                &Self::from_expr(b, field_ops)?,
+                &one,
+            )),
+            Expr::Infix(InfixOp::Subtract, a, b) => Some(combine(
+                Self::from_expr(a, field_ops)?,
+                &Self::from_expr(b, field_ops)?,
+                &minus_one,
+            )),
+            Expr::Infix(InfixOp::Multiply, a, b) => {
+                let (a, b) = (
+                    Self::from_expr(a, field_ops)?,
+                    Self::from_expr(b, field_ops)?,
+                );
+                match (a.terms.is_empty(), b.terms.is_empty()) {
+                    (true, _) => Some(combine(Self::default(), &b, &a.constant)),
+                    (_, true) => Some(combine(Self::default(), &a, &b.constant)),
+                    _ => None,
+                }
+            }
+            Expr::Infix(InfixOp::Divide, a, b) => {
+                let b = Self::from_expr(b, field_ops)?;
+                if !b.terms.is_empty() || b.constant.is_zero() {
+                    return None;
+                }
+                let k = field_ops.infix(InfixOp::Divide, one, b.constant);
+                Some(combine(
+                    Self::default(),
+                    &Self::from_expr(a, field_ops)?,
+                    &k,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /* Express the given equality as a linear combination if it is linear. */
+    fn from_equality(expr: &TExpr, field_ops: &dyn FieldOps) -> Option<Self> {
+        match &expr.v {
+            Expr::Infix(InfixOp::Equal, lhs, rhs) => {
+                let mut form = Self::from_expr(lhs, field_ops)?;
+                let minus_one = field_ops.negate(BigInt::one());
+                form.add_scaled(&Self::from_expr(rhs, field_ops)?, &minus_one, field_ops);
+                Some(form)
+            }
+            _ => None,
+        }
+    }
+}
+
+/* Make the weighted sum c1*v1 + ... + cn*vn + c0 of the given variables.
+ * The terms are kept even where they could be simplified, so that the sum
+ * keeps the shape that weighted_sum recognizes. */
+fn make_weighted_sum(terms: &[(BigInt, Variable)], constant: BigInt) -> TExpr {
+    let infix = |op, a, b| Expr::Infix(op, Box::new(a), Box::new(b)).type_expr(Some(Type::Int));
+    let constant = Expr::Constant(constant).type_expr(Some(Type::Int));
+    let mut terms = terms.iter().map(|(c, var)| {
+        let c = Expr::Constant(c.clone()).type_expr(Some(Type::Int));
+        let var = Expr::Variable(var.clone()).type_expr(Some(Type::Int));
+        infix(InfixOp::Multiply, c, var)
     });
+    match terms.next() {
+        None => constant,
+        Some(first) => {
+            let sum = terms.fold(first, |sum, term| infix(InfixOp::Add, sum, term));
+            infix(InfixOp::Add, sum, constant)
+        }
+    }
+}
+
+/* Recognize the weighted sums c1*v1 + ... + cn*vn + c0 that the folding of
+ * linear combinations produces, giving the weighted variables and the
+ * constant. */
+pub fn weighted_sum(expr: &TExpr) -> Option<(Vec<(BigInt, Variable)>, BigInt)> {
+    match &expr.v {
+        Expr::Constant(c) => Some((vec![], c.clone())),
+        Expr::Infix(InfixOp::Multiply, c, var) => match (&c.v, &var.v) {
+            (Expr::Constant(c), Expr::Variable(var)) => {
+                Some((vec![(c.clone(), var.clone())], BigInt::zero()))
+            }
+            _ => None,
+        },
+        Expr::Infix(InfixOp::Add, a, b) => {
+            let (mut terms, c1) = weighted_sum(a)?;
+            let (terms2, c2) = weighted_sum(b)?;
+            terms.extend(terms2);
+            Some((terms, c1 + c2))
+        }
+        _ => None,
+    }
+}
+
+/* Split the given linear combination into equalities of at most GATE_WIDTH
+ * variables, chaining them together through fresh variables that are defined
+ * to be partial sums. */
+fn split_linear_constraint(
+    form: LinearConstraint,
+    variables: &mut HashMap<VariableId, Variable>,
+    module: &mut Module,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) {
+    let mut terms: Vec<(BigInt, Variable)> = form
+        .terms
+        .into_iter()
+        .map(|(var, c)| (c, variables[&var].clone()))
+        .collect();
+    while terms.len() > GATE_WIDTH {
+        let rest = terms.split_off(2);
+        let sum = make_weighted_sum(&terms, BigInt::zero());
+        let var = Variable::new(gen.generate_id());
+        variables.insert(var.id, var.clone());
+        let pat = Pat::Variable(var.clone()).type_pat(Some(Type::Int));
+        push_constraint_def(module, pat, sum);
+        terms = vec![(BigInt::one(), var)];
+        terms.extend(rest);
+    }
+    let constraint = match terms.split_first() {
+        // Combinations without variables require their constant to be zero
+        None if form.constant.is_zero() => return,
+        None => (
+            Expr::Constant(BigInt::zero()).type_expr(Some(Type::Int)),
+            Expr::Constant(form.constant).type_expr(Some(Type::Int)),
+        ),
+        // Otherwise solve for the first variable in terms of the others
+        Some(((c1, v1), rest)) => {
+            let scale =
+                field_ops.negate(field_ops.infix(InfixOp::Divide, BigInt::one(), c1.clone()));
+            let rest: Vec<_> = rest
+                .iter()
+                .map(|(c, var)| {
+                    (
+                        field_ops.infix(InfixOp::Multiply, c.clone(), scale.clone()),
+                        var.clone(),
+                    )
+                })
+                .collect();
+            let constant = field_ops.infix(InfixOp::Multiply, form.constant, scale);
+            (
+                Expr::Variable(v1.clone()).type_expr(Some(Type::Int)),
+                make_weighted_sum(&rest, constant),
+            )
+        }
+    };
+    module.exprs.push(
+        Expr::Infix(
+            InfixOp::Equal,
+            Box::new(constraint.0),
+            Box::new(constraint.1),
+        )
+        .type_expr(Some(Type::Unit)),
+    );
+}
+
+/* Fold chains of linear equalities into wider linear combinations, and then
+ * split these back up into equalities that each fit into a single gate. A
+ * defined variable that occurs in exactly two linear equalities and nowhere
+ * else in the constraints is eliminated by adding a multiple of one equality
+ * to the other. Doing so never increases the number of gates needed, and it
+ * saves gates wherever equalities with fewer than three variables, such as the
+ * scalings in bit decompositions, are absorbed. The original index of the
+ * equality that each resulting equality derives from is returned. */
+pub fn fold_linear_combinations(
+    module: &mut Module,
+    field_ops: &dyn FieldOps,
+    gen: &mut VarGen,
+) -> Vec<usize> {
+    let defined: HashSet<VariableId> = module
+        .defs
+        .iter()
+        .filter_map(|def| match &def.0 .0.v {
+            Pat::Variable(var) => Some(var.id),
+            _ => None,
+        })
+        .collect();
+    // Public variables and those in nonlinear equalities must be kept
+    let mut pinned: HashMap<VariableId, Variable> = HashMap::new();
+    collect_public_module_variables(module, &mut pinned);
+    let mut forms: Vec<Option<LinearConstraint>> = Vec::with_capacity(module.exprs.len());
+    let mut occurrences: BTreeMap<VariableId, BTreeSet<usize>> = BTreeMap::new();
+    for (idx, expr) in module.exprs.iter().enumerate() {
+        let form = LinearConstraint::from_equality(expr, field_ops);
+        match &form {
+            Some(form) => {
+                for var in form.terms.keys() {
+                    occurrences.entry(*var).or_default().insert(idx);
+                }
+            }
+            None => collect_expr_variables(expr, &mut pinned),
+        }
+        forms.push(form);
+    }
+    let mut folded = vec![false; forms.len()];
+    let mut eliminated = HashSet::new();
+    let mut worklist: Vec<VariableId> = occurrences.keys().rev().copied().collect();
+    while let Some(var) = worklist.pop() {
+        if pinned.contains_key(&var) || !defined.contains(&var) || occurrences[&var].len() != 2 {
+            continue;
+        }
+        let mut indices = occurrences[&var].iter().copied();
+        let (target, source) = (indices.next().unwrap(), indices.next().unwrap());
+        let (old_target, source_form) =
+            (forms[target].take().unwrap(), forms[source].take().unwrap());
+        // Add the multiple of the source that cancels the variable out
+        let k = field_ops.negate(field_ops.infix(
+            InfixOp::Divide,
+            old_target.terms[&var].clone(),
+            source_form.terms[&var].clone(),
+        ));
+        let mut new_target = old_target.clone();
+        new_target.add_scaled(&source_form, &k, field_ops);
+        for affected in old_target.terms.keys().chain(source_form.terms.keys()) {
+            let indices = occurrences.get_mut(affected).unwrap();
+            indices.remove(&source);
+            indices.remove(&target);
+            if new_target.terms.contains_key(affected) {
+                indices.insert(target);
+            }
+            worklist.push(*affected);
+        }
+        forms[target] = Some(new_target);
+        folded[target] = true;
+        folded[source] = true;
+        eliminated.insert(var);
+    }
+    if eliminated.is_empty() {
+        return (0..module.exprs.len()).collect();
+    }
+    let mut variables = HashMap::new();
+    collect_module_variables(module, &mut variables);
+    let mut sources = Vec::with_capacity(module.exprs.len());
+    for (idx, expr) in std::mem::take(&mut module.exprs).into_iter().enumerate() {
+        match (folded[idx], forms[idx].take()) {
+            (false, _) => module.exprs.push(expr),
+            (true, Some(form)) => {
+                split_linear_constraint(form, &mut variables, module, field_ops, gen)
+            }
+            // This equality was folded into another one
+            (true, None) => {}
+        }
+        sources.resize(module.exprs.len(), idx);
+    }
+    // Drop the definitions of eliminated variables that nothing depends on
+    let mut needed = HashMap::new();
+    for expr in &module.exprs {
+        collect_expr_variables(expr, &mut needed);
+    }
+    let mut defs = Vec::with_capacity(module.defs.len());
+    for def in std::mem::take(&mut module.defs).into_iter().rev() {
+        if let Pat::Variable(var) = &def.0 .0.v {
+            if eliminated.contains(&var.id) && !needed.contains_key(&var.id) {
+                continue;
+            }
+        }
+        collect_expr_variables(&def.0 .1, &mut needed);
+        defs.push(def);
+    }
+    defs.reverse();
+    module.defs = defs;
+    sources
 }
 
 /* Register the fresh intrinsic in the compilation environment. */
@@ -1437,41 +2820,38 @@ fn register_fresh_intrinsic(
 /* fresh x returns a fresh unconstrained expression whose prover definition
  * equals the supplied expression. */
 fn expand_fresh_intrinsic(
-    params: &Vec<TPat>,
+    params: &[TPat],
     bindings: &HashMap<VariableId, TExpr>,
     prover_defs: &mut HashSet<VariableId>,
     gen: &mut VarGen,
 ) -> Result<TExpr, Error> {
-    match &params[..] {
-        [param]
-            if matches!(param.v, Pat::Variable(param_var) if {
-                let val = bindings[&param_var.id].clone();
-                // Make a new prover definition that is equal to the argument
-                let fresh_arg = Variable::new(gen.generate_id());
-                let mut fresh_pat = Pat::Variable(fresh_arg).type_pat(val.t.clone());
-                let mut pat_exps = HashMap::new();
-                // Expand the pattern using our knowledge of the expression's form
-                expand_pattern_variables(&mut fresh_pat, &val, &mut pat_exps, gen).expect("Pattern variable expantion shouldn't fail.");
-                // Make sure every part of expanded expression is a prover variable
-                for var in pat_exps.keys() {
-                    prover_defs.insert(*var);
-                }
-                return Ok(TExpr {
-                    t: val.t.clone(),
-                    v: Expr::LetBinding(
-                        LetBinding(
-                            fresh_pat.clone(),
-                            Box::new(val.clone()),
-                        ),
-                        Box::new(fresh_pat.to_expr())
-                    ),
-                })
-            }) =>
-        {
-            unreachable!()
+    match params {
+        [TPat {
+            v: Pat::Variable(param_var),
+            ..
+        }] => {
+            let val = bindings[&param_var.id].clone();
+            // Make a new prover definition that is equal to the argument
+            let fresh_arg = Variable::new(gen.generate_id());
+            let mut fresh_pat = Pat::Variable(fresh_arg).type_pat(val.t.clone());
+            let mut pat_exps = HashMap::new();
+            // Expand the pattern using our knowledge of the expression's form
+            expand_pattern_variables(&mut fresh_pat, &val, &mut pat_exps, gen)?;
+            // Make sure every part of expanded expression is a prover variable
+            for var in pat_exps.keys() {
+                prover_defs.insert(*var);
+            }
+            Ok(TExpr {
+                t: val.t.clone(),
+                v: Expr::LetBinding(
+                    LetBinding(fresh_pat.clone(), Box::new(val)),
+                    Box::new(fresh_pat.to_expr()),
+                ),
+                s: None,
+            })
         }
         _ => Err(Error::UnexpectedFreshParameters {
-            params: params.clone(),
+            params: params.to_vec(),
         }),
     }
 }
@@ -1497,8 +2877,10 @@ fn register_fresh_intrinsic_repl(
         body: Box::new(TExpr {
             v: Expr::Variable(fresh_arg.clone()),
             t: None,
+            s: None,
         }),
         env: HashMap::new(),
+        ret: None,
     };
     // Describe the intrinsic's type
     let imp_typ = Type::Function(Box::new(fresh_arg_type.clone()), Box::new(fresh_arg_type));
@@ -1554,12 +2936,12 @@ fn register_iter_intrinsic(
 
 /* iter x returns the Church numeral corresponding to the given integer x. */
 fn expand_iter_intrinsic(
-    params: &Vec<TPat>,
+    params: &[TPat],
     bindings: &HashMap<VariableId, TExpr>,
     _prover_defs: &mut HashSet<VariableId>,
     gen: &mut VarGen,
 ) -> Result<TExpr, Error> {
-    match &params[..] {
+    match params {
         [TPat {
             v: Pat::Variable(param_var),
             ..
@@ -1573,20 +2955,26 @@ fn expand_iter_intrinsic(
                     Box::new(iter_arg_typ.clone()),
                     Box::new(iter_arg_typ.clone()),
                 )),
+                s: None,
             };
             let mut body = TExpr {
                 v: Expr::Variable(iter_arg.clone()),
                 t: Some(iter_arg_typ.clone()),
+                s: None,
             };
             let val = if let Expr::Constant(c) = &bindings[&param_var.id].v {
                 c
             } else {
                 return Err(Error::NonConstantIterArgumentError);
             };
-            for _ in 0..val.to_i8().expect("specified iteration count is too large") {
+            let count = val
+                .to_i8()
+                .ok_or_else(|| Error::IterationCountError { c: val.clone() })?;
+            for _ in 0..count {
                 body = TExpr {
                     v: Expr::Application(Box::new(iter_func.clone()), Box::new(body.clone())),
                     t: body.t,
+                    s: None,
                 };
             }
             Ok(TExpr {
@@ -1601,7 +2989,9 @@ fn expand_iter_intrinsic(
                     ],
                     body: Box::new(body),
                     env: HashMap::new(),
+                    ret: None,
                 }),
+                s: None,
             })
         }
         _ => Err(Error::UnexpectedIterArguments {
@@ -1656,12 +3046,12 @@ fn register_fold_intrinsic(
 
 /* fold [a0, a1, ..., aN] f b = f a0 (f a1 (... (f aN b) ...)). */
 fn expand_fold_intrinsic(
-    params: &Vec<TPat>,
+    params: &[TPat],
     bindings: &HashMap<VariableId, TExpr>,
     _prover_defs: &mut HashSet<VariableId>,
     gen: &mut VarGen,
 ) -> Result<TExpr, Error> {
-    match &params[..] {
+    match params {
         [TPat {
             v: Pat::Variable(param_var),
             ..
@@ -1680,6 +3070,7 @@ fn expand_fold_intrinsic(
                     Box::new(Type::Variable(fold_elt)),
                     Box::new(fold_func_func.clone()),
                 )),
+                s: None,
             };
             let mut param_val = &bindings[&param_var.id];
             let mut param_list = Vec::new();
@@ -1696,6 +3087,7 @@ fn expand_fold_intrinsic(
             let mut body = TExpr {
                 v: Expr::Variable(fold_arg.clone()),
                 t: Some(fold_arg_typ.clone()),
+                s: None,
             };
             for param_elt in param_list.into_iter().rev() {
                 body = TExpr {
@@ -1703,10 +3095,12 @@ fn expand_fold_intrinsic(
                         Box::new(TExpr {
                             v: Expr::Application(Box::new(fold_func.clone()), param_elt),
                             t: Some(fold_func_func.clone()),
+                            s: None,
                         }),
                         Box::new(body.clone()),
                     ),
                     t: body.t,
+                    s: None,
                 };
             }
             Ok(TExpr {
@@ -1721,11 +3115,158 @@ fn expand_fold_intrinsic(
                     ],
                     body: Box::new(body),
                     env: HashMap::new(),
+                    ret: None,
                 }),
+                s: None,
             })
         }
         _ => Err(Error::UnexpectedArgumentsInFold {
-            params: params.clone(),
+            params: params.to_vec(),
+        }),
+    }
+}
+
+/* Register the range intrinsic in the compilation environment. */
+fn register_range_intrinsic(
+    globals: &mut HashMap<String, VariableId>,
+    global_types: &mut HashMap<VariableId, Type>,
+    bindings: &mut HashMap<VariableId, TExpr>,
+    gen: &mut VarGen,
+) {
+    let range_id = gen.generate_id();
+    let range_width = Variable::new(gen.generate_id());
+    let range_width_pat = Pat::Variable(range_width).type_pat(Some(Type::Int));
+    let range_arg = Variable::new(gen.generate_id());
+    let range_arg_pat = Pat::Variable(range_arg).type_pat(Some(Type::Int));
+    // Register the range function in global namespace
+    globals.insert("range".to_string(), range_id);
+    // Describe the intrinsic's type, arity, and implementation
+    let range_intrinsic =
+        Intrinsic::new(vec![range_width_pat, range_arg_pat], expand_range_intrinsic);
+    let imp_typ = Type::Function(
+        Box::new(Type::Int),
+        Box::new(Type::Function(
+            Box::new(Type::Int),
+            Box::new(Type::List(Box::new(Type::Int))),
+        )),
+    );
+    // Register the intrinsic descriptor with the global binding
+    global_types.insert(range_id, imp_typ.clone());
+    // Register the intrinsic descriptor with the global binding
+    bindings.insert(
+        range_id,
+        Expr::Intrinsic(range_intrinsic).type_expr(Some(imp_typ)),
+    );
+}
+
+/* range n x returns the little-endian list of the n bits of x. The bits are
+ * fresh prover definitions constrained to be boolean and to recombine to x. */
+fn expand_range_intrinsic(
+    params: &[TPat],
+    bindings: &HashMap<VariableId, TExpr>,
+    prover_defs: &mut HashSet<VariableId>,
+    gen: &mut VarGen,
+) -> Result<TExpr, Error> {
+    match params {
+        [TPat {
+            v: Pat::Variable(width_var),
+            ..
+        }, TPat {
+            v: Pat::Variable(arg_var),
+            ..
+        }] => {
+            let width = if let Expr::Constant(c) = &bindings[&width_var.id].v {
+                c
+            } else {
+                return Err(Error::NonConstantRangeWidthError);
+            };
+            let width = width
+                .to_usize()
+                .ok_or_else(|| Error::BitWidthError { w: width.clone() })?;
+            let int = |c: BigInt| Expr::Constant(c).type_expr(Some(Type::Int));
+            let list = Some(Type::List(Box::new(Type::Int)));
+            // Constant arguments can be decomposed immediately
+            if let Expr::Constant(c) = &bindings[&arg_var.id].v {
+                if c.is_negative() || c.bits() > width as u64 {
+                    return Err(Error::RangeError {
+                        c: c.clone(),
+                        w: width,
+                    });
+                }
+                let mut bits = Expr::Nil.type_expr(list.clone());
+                for i in (0..width).rev() {
+                    let bit = int((c >> i) & BigInt::one());
+                    bits = Expr::Cons(Box::new(bit), Box::new(bits)).type_expr(list.clone());
+                }
+                return Ok(bits);
+            }
+            let arg = Expr::Variable(arg_var.clone()).type_expr(Some(Type::Int));
+            let bit_vars: Vec<_> = (0..width)
+                .map(|_| Variable::new(gen.generate_id()))
+                .collect();
+            let bit_exprs: Vec<_> = bit_vars
+                .iter()
+                .map(|var| Expr::Variable(var.clone()).type_expr(Some(Type::Int)))
+                .collect();
+            let mut body = Vec::new();
+            // Each bit must be either 0 or 1
+            for bit in &bit_exprs {
+                let square = Expr::Infix(
+                    InfixOp::Multiply,
+                    Box::new(bit.clone()),
+                    Box::new(bit.clone()),
+                )
+                .type_expr(Some(Type::Int));
+                body.push(
+                    Expr::Infix(InfixOp::Equal, Box::new(bit.clone()), Box::new(square))
+                        .type_expr(Some(Type::Unit)),
+                );
+            }
+            // The bits must recombine into the argument
+            let mut sum = int(BigInt::zero());
+            for (i, bit) in bit_exprs.iter().enumerate() {
+                let scaled = Expr::Infix(
+                    InfixOp::Multiply,
+                    Box::new(int(BigInt::one() << i)),
+                    Box::new(bit.clone()),
+                )
+                .type_expr(Some(Type::Int));
+                sum = Expr::Infix(InfixOp::Add, Box::new(sum), Box::new(scaled))
+                    .type_expr(Some(Type::Int));
+            }
+            body.push(
+                Expr::Infix(InfixOp::Equal, Box::new(arg.clone()), Box::new(sum))
+                    .type_expr(Some(Type::Unit)),
+            );
+            let mut bits = Expr::Nil.type_expr(list.clone());
+            for bit in bit_exprs.into_iter().rev() {
+                bits = Expr::Cons(Box::new(bit), Box::new(bits)).type_expr(list.clone());
+            }
+            body.push(bits);
+            let mut expr = Expr::Sequence(body).type_expr(list.clone());
+            // Give the prover a hint for each bit: bit = (x \ 2^i) % 2
+            for (i, var) in bit_vars.into_iter().enumerate().rev() {
+                prover_defs.insert(var.id);
+                let quotient = Expr::Infix(
+                    InfixOp::IntDivide,
+                    Box::new(arg.clone()),
+                    Box::new(int(BigInt::one() << i)),
+                )
+                .type_expr(Some(Type::Int));
+                let hint = Expr::Infix(
+                    InfixOp::Modulo,
+                    Box::new(quotient),
+                    Box::new(int(BigInt::from(2))),
+                )
+                .type_expr(Some(Type::Int));
+                let pat = Pat::Variable(var).type_pat(Some(Type::Int));
+                expr = Expr::LetBinding(LetBinding(pat, Box::new(hint)), Box::new(expr))
+                    .type_expr(list.clone());
+            }
+            Ok(expr)
+        }
+        _ => Err(Error::UnexpectedRangeArguments {
+            params: params.to_vec(),
         }),
     }
 }
@@ -1740,8 +3281,18 @@ pub fn compile_repl(module: &mut Module, field_ops: &dyn FieldOps) -> Result<(),
     register_fresh_intrinsic_repl(&mut globals, &mut global_types, &mut bindings, &mut vg);
     register_iter_intrinsic(&mut globals, &mut global_types, &mut bindings, &mut vg);
     register_fold_intrinsic(&mut globals, &mut global_types, &mut bindings, &mut vg);
-
+    register_range_intrinsic(&mut globals, &mut global_types, &mut bindings, &mut vg);
     let mut locals = HashMap::new();
+    register_prelude(
+        &mut globals,
+        &mut locals,
+        &mut global_types,
+        &mut prog_types,
+        &mut bindings,
+        field_ops,
+        &mut vg,
+    )?;
+
     let mut prover_defs = HashSet::new();
     if !module.defs.is_empty() || !module.exprs.is_empty() || !module.pubs.is_empty() {
         number_module_variables(module, &mut globals, &mut vg, &mut locals);
@@ -1751,9 +3302,9 @@ pub fn compile_repl(module: &mut Module, field_ops: &dyn FieldOps) -> Result<(),
             &mut global_types,
             &mut prog_types,
             &mut vg,
-        );
+        )?;
         println!("** Inferring types...");
-        print_types(module, &prog_types, &Config { quiet: false });
+        print_types(module, &prog_types, &Config::default());
         // Global variables may have further internal structure, determine this
         // using derived type information
         expand_global_variables(
@@ -1763,7 +3314,7 @@ pub fn compile_repl(module: &mut Module, field_ops: &dyn FieldOps) -> Result<(),
             &mut prog_types,
             &bindings,
             &mut vg,
-        );
+        )?;
         // Type information is no longer required since we do symbolic
         // execution from now on
         strip_module_types(module);
@@ -1776,7 +3327,7 @@ pub fn compile_repl(module: &mut Module, field_ops: &dyn FieldOps) -> Result<(),
             &mut prover_defs,
             field_ops,
             &mut vg,
-        );
+        )?;
     }
 
     loop {
@@ -1792,98 +3343,256 @@ pub fn compile_repl(module: &mut Module, field_ops: &dyn FieldOps) -> Result<(),
             break;
         }
 
-        let mut module = Module {
-            defs: vec![],
-            exprs: vec![],
-            pubs: vec![],
-        };
+        match Module::parse(&input) {
+            Ok(mut module) => {
+                let res = (|| -> Result<(), Error> {
+                    number_module_variables(&mut module, &mut globals, &mut vg, &mut locals);
 
-        match VampirParser::parse(Rule::moduleItems, &input) {
-            Ok(mut pairs) => {
-                for pair in pairs.by_ref() {
-                    match pair.as_rule() {
-                        Rule::expr => {
-                            let expr: TExpr = TExpr::parse(pair).expect("expected expression");
-                            module.exprs.push(expr);
-                        }
-                        Rule::definition => {
-                            let definition: Definition =
-                                Definition::parse(pair).expect("expected definition");
-                            module.defs.push(definition);
-                        }
-                        Rule::declaration => {
-                            let pairs: pest::iterators::Pairs<Rule> = pair.into_inner();
-                            for pair in pairs {
-                                let var: Variable =
-                                    Variable::parse(pair).expect("expected variable");
-                                module.pubs.push(var);
-                            }
-                        }
-                        Rule::EOI => continue,
-                        _ => unreachable!(
-                            "module item should either be expression, definition, or EOI"
-                        ),
+                    // Only look at types if new definitions are added.
+                    if !module.defs.is_empty() {
+                        infer_module_types(
+                            &mut module,
+                            &globals,
+                            &mut global_types,
+                            &mut prog_types,
+                            &mut vg,
+                        )?;
+                        println!("** Inferring types...");
+                        print_types(&module, &prog_types, &Config::default());
+                        // Global variables may have further internal structure, determine this
+                        // using derived type information
+                        expand_global_variables(
+                            &mut module,
+                            &globals,
+                            &global_types,
+                            &mut prog_types,
+                            &bindings,
+                            &mut vg,
+                        )?;
+                        // Type information is no longer required since we do symbolic
+                        // execution from now on
+                        strip_module_types(&mut module);
                     }
-                }
 
-                number_module_variables(&mut module, &mut globals, &mut vg, &mut locals);
-
-                // Only look at types if new definitions are added.
-                if !module.defs.is_empty() {
-                    infer_module_types(
-                        &mut module,
-                        &globals,
-                        &mut global_types,
-                        &mut prog_types,
-                        &mut vg,
-                    );
-                    println!("** Inferring types...");
-                    print_types(&module, &prog_types, &Config { quiet: false });
-                    // Global variables may have further internal structure, determine this
-                    // using derived type information
-                    expand_global_variables(
-                        &mut module,
-                        &globals,
-                        &global_types,
-                        &mut prog_types,
-                        &bindings,
-                        &mut vg,
-                    );
-                    // Type information is no longer required since we do symbolic
-                    // execution from now on
-                    strip_module_types(&mut module);
-                }
+                    for def in &module.defs {
+                        evaluate_def(
+                            def,
+                            &mut None,
+                            &mut bindings,
+                            &mut prover_defs,
+                            field_ops,
+                            &mut vg,
+                        )?;
+                    }
+                    for expr in &module.exprs {
+                        let res = evaluate(
+                            expr,
+                            &mut None,
+                            &mut bindings,
+                            &mut prover_defs,
+                            field_ops,
+                            &mut vg,
+                        )?;
+                        println!("Out: {res}");
+                    }
+                    Ok(())
+                })();
 
-                for def in &module.defs {
-                    evaluate_def(
-                        def,
-                        &mut None,
-                        &mut bindings,
-                        &mut prover_defs,
-                        field_ops,
-                        &mut vg,
-                    );
-                }
-                for expr in &module.exprs {
-                    let res = evaluate(
-                        expr,
-                        &mut None,
-                        &mut bindings,
-                        &mut prover_defs,
-                        field_ops,
-                        &mut vg,
-                    );
-
-                    if let Err(e) = res {
-                        println!("Evaluation Error: {e:?}")
-                    } else {
-                        println!("Out: {}", res.unwrap())
-                    };
+                if let Err(e) = res {
+                    println!("Evaluation Error: {e}")
                 }
             }
-            Err(e) => eprintln!("Parse Error: {e:?}"),
+            Err(e) => eprintln!("Parse Error: {e}"),
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::halo2::synth::{make_constant, Halo2Module, PrimeFieldOps};
+    use crate::util::get_circuit_assignments;
+    use halo2_proofs::dev::MockProver;
+    use halo2_proofs::pasta::Fp;
+    use std::rc::Rc;
+
+    /* Check whether the given program is satisfied by the given inputs. */
+    fn satisfied(source: &str, inputs: &[(&str, i64)]) -> bool {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse(source).unwrap();
+        let module_3ac = compile(module, &PrimeFieldOps::<Fp>::default(), &config).unwrap();
+        let named: HashMap<String, Fp> = inputs
+            .iter()
+            .map(|(name, val)| (name.to_string(), make_constant(BigInt::from(*val))))
+            .collect();
+        let assignments = get_circuit_assignments(&module_3ac, &named).unwrap();
+        let instances = module_3ac
+            .pubs
+            .iter()
+            .map(|var| assignments[&var.id])
+            .collect();
+        let mut circuit = Halo2Module::<Fp>::new(Rc::new(module_3ac));
+        circuit.populate_variables(assignments).unwrap();
+        let prover = MockProver::run(circuit.k, &circuit, vec![instances]).unwrap();
+        prover.verify().is_ok()
+    }
+
+    #[test]
+    fn test_prelude_gadgets() {
+        // Bit decomposition only accepts values that fit
+        let range = "x = combine (range 4 x);";
+        assert!(satisfied(range, &[("x", 9)]));
+        assert!(!satisfied(range, &[("x", 16)]));
+        // Modular arithmetic wraps around at 2^4
+        let add = "z = add 4 x y;";
+        assert!(satisfied(add, &[("x", 9), ("y", 8), ("z", 1)]));
+        assert!(!satisfied(add, &[("x", 9), ("y", 8), ("z", 17)]));
+        // Bitwise logic
+        let xor = "z = xor 4 x y;";
+        assert!(satisfied(xor, &[("x", 10), ("y", 6), ("z", 12)]));
+        assert!(!satisfied(xor, &[("x", 10), ("y", 6), ("z", 13)]));
+        // Rotations
+        let ror = "z = ror 4 x;";
+        assert!(satisfied(ror, &[("x", 9), ("z", 12)]));
+        assert!(!satisfied(ror, &[("x", 9), ("z", 9)]));
+        let rol = "z = rol 4 x;";
+        assert!(satisfied(rol, &[("x", 9), ("z", 3)]));
+        assert!(!satisfied(rol, &[("x", 9), ("z", 12)]));
+        // List access
+        let nth = "z = 2 * nth (x:y:[]) 1;";
+        assert!(satisfied(nth, &[("x", 3), ("y", 5), ("z", 10)]));
+        assert!(!satisfied(nth, &[("x", 3), ("y", 5), ("z", 6)]));
+        let take = "z = combine (take 2 (x:y:w:[]));";
+        assert!(satisfied(take, &[("x", 1), ("y", 1), ("w", 1), ("z", 3)]));
+        assert!(!satisfied(take, &[("x", 1), ("y", 1), ("w", 1), ("z", 7)]));
+        // Unsigned comparisons at the ends of the range
+        let ule = "z = ule 4 x y;";
+        assert!(satisfied(ule, &[("x", 15), ("y", 15), ("z", 1)]));
+        assert!(satisfied(ule, &[("x", 15), ("y", 0), ("z", 0)]));
+        assert!(!satisfied(ule, &[("x", -1), ("y", 3), ("z", 1)]));
+        let ult = "z = ult 4 x y;";
+        assert!(satisfied(ult, &[("x", 5), ("y", 0), ("z", 0)]));
+        assert!(satisfied(ult, &[("x", 0), ("y", 15), ("z", 1)]));
+        assert!(!satisfied(ult, &[("x", 5), ("y", 0), ("z", 1)]));
+        // Signed comparisons whose difference overflows 4 bits
+        let slt = "z = slt 4 x y;";
+        assert!(satisfied(slt, &[("x", 7), ("y", 8), ("z", 0)]));
+        assert!(!satisfied(slt, &[("x", 7), ("y", 8), ("z", 1)]));
+        assert!(satisfied(slt, &[("x", 8), ("y", 7), ("z", 1)]));
+        assert!(satisfied(slt, &[("x", 15), ("y", 0), ("z", 1)]));
+        assert!(satisfied(slt, &[("x", 3), ("y", 5), ("z", 1)]));
+        assert!(satisfied(slt, &[("x", 13), ("y", 9), ("z", 0)]));
+        let sle = "z = sle 4 x y;";
+        assert!(satisfied(sle, &[("x", 7), ("y", 7), ("z", 1)]));
+        assert!(satisfied(sle, &[("x", 7), ("y", 8), ("z", 0)]));
+        assert!(!satisfied(sle, &[("x", 7), ("y", 8), ("z", 1)]));
+        assert!(satisfied(sle, &[("x", 8), ("y", 7), ("z", 1)]));
+    }
+
+    #[test]
+    fn test_intrinsic_partially_applied() {
+        // The width stays bound to range after it has been passed around
+        let partial = "def bits4 = range 4; def apply f a = f a; x = combine (apply bits4 x);";
+        assert!(satisfied(partial, &[("x", 9)]));
+        assert!(!satisfied(partial, &[("x", 16)]));
+    }
+
+    #[test]
+    fn test_comparison_operands_range_checked() {
+        let less = "(x < 10) = 1;";
+        assert!(satisfied(less, &[("x", 3)]));
+        assert!(!satisfied(less, &[("x", 12)]));
+        // p-5 would otherwise pass as less than 10
+        assert!(!satisfied(less, &[("x", -5)]));
+        let less_equal = "(x <= y) = 1;";
+        assert!(satisfied(less_equal, &[("x", 7), ("y", 7)]));
+        assert!(!satisfied(less_equal, &[("x", -1), ("y", 7)]));
+        assert!(!satisfied(less_equal, &[("x", 7), ("y", 1 << 32)]));
+        // Operands that fit still compare correctly at the top of the range
+        let max = (1i64 << 32) - 1;
+        assert!(satisfied("(x < y) = 1;", &[("x", max - 1), ("y", max)]));
+        assert!(satisfied("(x < y) = 0;", &[("x", max), ("y", 0)]));
+    }
+
+    #[test]
+    fn test_common_subexpressions_merged() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse("pub z; def f a b = a*b; f x y + f y x = z;").unwrap();
+        let module_3ac = compile(module, &PrimeFieldOps::<Fp>::default(), &config).unwrap();
+        let products = module_3ac
+            .exprs
+            .iter()
+            .filter(|expr| {
+                matches!(
+                    equality_shape(expr),
+                    Some((_, ExprShape::Infix(InfixOp::Multiply, _, _)))
+                )
+            })
+            .count();
+        assert_eq!(products, 1);
+
+        // The merged circuit is still satisfied by the same inputs
+        let named = HashMap::from([
+            ("x".to_string(), make_constant(BigInt::from(2))),
+            ("y".to_string(), make_constant(BigInt::from(3))),
+            ("z".to_string(), make_constant(BigInt::from(12))),
+        ]);
+        let assignments = get_circuit_assignments(&module_3ac, &named).unwrap();
+        let circuit = Halo2Module::<Fp>::new(Rc::new(module_3ac));
+        assert!(circuit.check_variables(assignments).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_linear_combinations_folded() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse("x + 2*y + 4*z + 8*w = v;").unwrap();
+        let module_3ac = compile(module, &PrimeFieldOps::<Fp>::default(), &config).unwrap();
+        // Five variables take three gates of three wires each
+        assert_eq!(module_3ac.exprs.len(), 3);
+
+        let circuit = Halo2Module::<Fp>::new(Rc::new(module_3ac));
+        assert_eq!(circuit.gates().len(), 3);
+        let check = |v: u64| {
+            let named = HashMap::from([
+                ("x".to_string(), make_constant(BigInt::from(1))),
+                ("y".to_string(), make_constant(BigInt::from(2))),
+                ("z".to_string(), make_constant(BigInt::from(3))),
+                ("w".to_string(), make_constant(BigInt::from(4))),
+                ("v".to_string(), make_constant(BigInt::from(v))),
+            ]);
+            let assignments = get_circuit_assignments(&circuit.module, &named).unwrap();
+            circuit.check_variables(assignments).unwrap().is_empty()
+        };
+        assert!(check(49));
+        assert!(!check(50));
+    }
+
+    #[test]
+    fn test_constants_propagated() {
+        let config = Config {
+            quiet: true,
+            ..Config::default()
+        };
+        let module = Module::parse("pub z; (x - x) * y + z = 5;").unwrap();
+        let module_3ac = compile(module, &PrimeFieldOps::<Fp>::default(), &config).unwrap();
+        // Only the constraint on the public variable remains
+        assert_eq!(module_3ac.exprs.len(), 1);
+        assert!(matches!(
+            &module_3ac.exprs[0].v,
+            Expr::Infix(InfixOp::Equal, lhs, rhs)
+                if matches!(&lhs.v, Expr::Variable(var) if var.name.as_deref() == Some("z"))
+                    && matches!(&rhs.v, Expr::Constant(c) if *c == BigInt::from(5))
+        ));
+    }
+}
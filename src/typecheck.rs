@@ -1,6 +1,6 @@
 use crate::ast::{
-    Definition, Expr, Function, InfixOp, Intrinsic, LetBinding, Module, Pat, TExpr, TPat, Variable,
-    VariableId,
+    Definition, Expr, Function, InfixOp, Intrinsic, LetBinding, Match, Module, Pat, TExpr, TPat,
+    Variable, VariableId,
 };
 use crate::error::Error;
 use crate::qprintln;
@@ -73,6 +73,11 @@ fn allocate_expr_types(expr: &mut TExpr, gen: &mut VarGen) {
         Expr::Negate(expr1) => {
             allocate_expr_types(expr1, gen);
         }
+        Expr::If(cond, expr1, expr2) => {
+            allocate_expr_types(cond, gen);
+            allocate_expr_types(expr1, gen);
+            allocate_expr_types(expr2, gen);
+        }
         Expr::Function(fun) => {
             for param in &mut fun.params {
                 allocate_pat_types(param, gen);
@@ -329,6 +334,11 @@ pub fn strip_expr_types(expr: &mut TExpr) {
         Expr::Negate(expr) => {
             strip_expr_types(expr);
         }
+        Expr::If(cond, expr1, expr2) => {
+            strip_expr_types(cond);
+            strip_expr_types(expr1);
+            strip_expr_types(expr2);
+        }
         Expr::Constant(_) | Expr::Unit | Expr::Variable(_) | Expr::Nil => {}
         Expr::Intrinsic(Intrinsic { env, .. }) => {
             for val in env.values_mut() {
@@ -374,17 +384,18 @@ fn infer_binding_types(
     vars: &mut HashMap<VariableId, Type>,
     types: &mut HashMap<VariableId, Type>,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     let expr1_var = expr_type_var(&def.1);
-    infer_expr_types(&def.1, env_ftvs, vars, types, gen);
-    infer_pat_types(&def.0, vars, types, gen);
+    infer_expr_types(&def.1, env_ftvs, vars, types, gen)?;
+    infer_pat_types(&def.0, vars, types, gen)?;
     unify_types(
         pat_type_var(&def.0),
         expr_type_var(&def.1),
         types,
         &mut None,
     )
-    .unwrap();
+    .map_err(|err| err.locate(&def.0.s))?;
+    check_pat_annotations(&def.0, types)?;
     // Compute the set of free variables occurring in RHS' TYPE that
     // do not occur in the type environment
     let mut quant_vars = HashMap::new();
@@ -407,6 +418,7 @@ fn infer_binding_types(
         let quant_expr = quant_expr.clone();
         collect_free_type_vars(&expand_type(&quant_expr, types), env_ftvs);
     }
+    Ok(())
 }
 
 /* Get or generate the type variable associated with a given pattern. */
@@ -414,6 +426,36 @@ pub fn pat_type_var(pat: &TPat) -> &Type {
     pat.t.as_ref().unwrap()
 }
 
+/* Unify the given inferred type with the given annotation. Mismatches are
+ * reported against the annotation rather than against the inferred type. */
+fn check_annotation(
+    typ: &Type,
+    annotation: &Type,
+    types: &mut HashMap<VariableId, Type>,
+) -> Result<(), Error> {
+    let inferred = expand_type(typ, types);
+    unify_types(typ, annotation, types, &mut None).map_err(|_| Error::AnnotationError {
+        a: annotation.clone(),
+        t: inferred,
+    })
+}
+
+/* Check the annotations on the given pattern and its subpatterns against the
+ * types inferred for them. */
+fn check_pat_annotations(pat: &TPat, types: &mut HashMap<VariableId, Type>) -> Result<(), Error> {
+    for annotation in &pat.a {
+        check_annotation(pat_type_var(pat), annotation, types).map_err(|err| err.locate(&pat.s))?;
+    }
+    match &pat.v {
+        Pat::Product(pat1, pat2) | Pat::Cons(pat1, pat2) => {
+            check_pat_annotations(pat1, types)?;
+            check_pat_annotations(pat2, types)
+        }
+        Pat::As(pat1, _) => check_pat_annotations(pat1, types),
+        Pat::Constant(_) | Pat::Variable(_) | Pat::Unit | Pat::Nil => Ok(()),
+    }
+}
+
 /* Assign each variable in the pattern an explicit type, even if that means
  * expanding upon parts of the given type. */
 pub fn infer_pat_types(
@@ -421,23 +463,25 @@ pub fn infer_pat_types(
     vars: &mut HashMap<VariableId, Type>,
     types: &mut HashMap<VariableId, Type>,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     match &pat.v {
         Pat::Nil => {
             let pat_var = pat_type_var(pat);
             let poly_var = Type::Variable(Variable::new(gen.generate_id()));
             // []: [a]
-            unify_types(pat_var, &Type::List(Box::new(poly_var)), types, &mut None).unwrap();
+            unify_types(pat_var, &Type::List(Box::new(poly_var)), types, &mut None)
+                .map_err(|err| err.locate(&pat.s))?;
         }
         Pat::Unit => {
             let pat_var = pat_type_var(pat);
             // (): ()
-            unify_types(pat_var, &Type::Unit, types, &mut None).unwrap();
+            unify_types(pat_var, &Type::Unit, types, &mut None)
+                .map_err(|err| err.locate(&pat.s))?;
         }
         Pat::Constant(_) => {
             let pat_var = pat_type_var(pat);
             // num: int
-            unify_types(pat_var, &Type::Int, types, &mut None).unwrap();
+            unify_types(pat_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&pat.s))?;
         }
         Pat::Variable(var) => {
             let pat_var = pat_type_var(pat);
@@ -448,8 +492,8 @@ pub fn infer_pat_types(
             let pat1_var = pat_type_var(pat1);
             let pat_var = pat_type_var(pat);
             // a1: t1 |- a1 as _: t1
-            unify_types(pat_var, pat1_var, types, &mut None).unwrap();
-            infer_pat_types(pat1, vars, types, gen);
+            unify_types(pat_var, pat1_var, types, &mut None).map_err(|err| err.locate(&pat.s))?;
+            infer_pat_types(pat1, vars, types, gen)?;
             // Map the pattern name to its type
             vars.insert(name.id, pat_var.clone());
         }
@@ -464,9 +508,9 @@ pub fn infer_pat_types(
                 types,
                 &mut None,
             )
-            .unwrap();
-            infer_pat_types(pat1, vars, types, gen);
-            infer_pat_types(pat2, vars, types, gen);
+            .map_err(|err| err.locate(&pat.s))?;
+            infer_pat_types(pat1, vars, types, gen)?;
+            infer_pat_types(pat2, vars, types, gen)?;
         }
         Pat::Cons(pat1, pat2) => {
             let pat1_var = pat_type_var(pat1);
@@ -479,51 +523,33 @@ pub fn infer_pat_types(
                 types,
                 &mut None,
             )
-            .unwrap();
-            unify_types(pat_var, pat2_var, types, &mut None).unwrap();
-            infer_pat_types(pat1, vars, types, gen);
-            infer_pat_types(pat2, vars, types, gen);
+            .map_err(|err| err.locate(&pat.s))?;
+            unify_types(pat_var, pat2_var, types, &mut None).map_err(|err| err.locate(&pat.s))?;
+            infer_pat_types(pat1, vars, types, gen)?;
+            infer_pat_types(pat2, vars, types, gen)?;
         }
     }
+    Ok(())
 }
 
 /* Recursively infer the types of expressions in the given expression tree.
  * Works by repeatedly generating and solving equations in the given typing
- * context. */
+ * context. Each kind of compound expression is handled by its own function so
+ * that the stack frame of this function, which is live throughout the
+ * recursion, stays small. */
 fn infer_expr_types(
     expr: &TExpr,
     env: &HashMap<VariableId, Variable>,
     vars: &HashMap<VariableId, Type>,
     types: &mut HashMap<VariableId, Type>,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     match &expr.v {
-        Expr::Nil => {
-            let expr_var = expr_type_var(expr);
-            let poly_var = Type::Variable(Variable::new(gen.generate_id()));
-            // []: [a]
-            unify_types(expr_var, &Type::List(Box::new(poly_var)), types, &mut None).unwrap();
-        }
-        Expr::Unit => {
-            let expr_var = expr_type_var(expr);
-            // (): ()
-            unify_types(expr_var, &Type::Unit, types, &mut None).unwrap();
-        }
-        Expr::Constant(_) => {
-            let expr_var = expr_type_var(expr);
-            // num: int
-            unify_types(expr_var, &Type::Int, types, &mut None).unwrap();
+        Expr::Nil | Expr::Unit | Expr::Constant(_) | Expr::Variable(_) => {
+            infer_atom_types(expr, vars, types, gen)
         }
         Expr::Infix(InfixOp::Equal, expr1, expr2) => {
-            let expr_var = expr_type_var(expr);
-            let expr1_var = expr_type_var(expr1);
-            let expr2_var = expr_type_var(expr2);
-            // a = b: ()
-            unify_types(expr_var, &Type::Unit, types, &mut None).unwrap();
-            // a: c |- b: c
-            unify_types(expr1_var, expr2_var, types, &mut None).unwrap();
-            infer_expr_types(expr1, env, vars, types, gen);
-            infer_expr_types(expr2, env, vars, types, gen);
+            infer_equal_types(expr, expr1, expr2, env, vars, types, gen)
         }
         Expr::Infix(
             InfixOp::Add
@@ -533,151 +559,343 @@ fn infer_expr_types(
             | InfixOp::DivideZ
             | InfixOp::Exponentiate
             | InfixOp::IntDivide
-            | InfixOp::Modulo,
+            | InfixOp::Modulo
+            | InfixOp::Less
+            | InfixOp::LessEqual
+            | InfixOp::NotEqual,
             expr1,
             expr2,
-        ) => {
-            let expr_var = expr_type_var(expr);
-            let expr1_var = expr_type_var(expr1);
-            let expr2_var = expr_type_var(expr2);
-            // a op b: int
-            unify_types(expr_var, &Type::Int, types, &mut None).unwrap();
-            // a: int
-            unify_types(expr1_var, &Type::Int, types, &mut None).unwrap();
-            // b: int
-            unify_types(expr2_var, &Type::Int, types, &mut None).unwrap();
-            infer_expr_types(expr1, env, vars, types, gen);
-            infer_expr_types(expr2, env, vars, types, gen);
-        }
-        Expr::Negate(expr1) => {
-            let expr_var = expr_type_var(expr);
-            let expr1_var = expr_type_var(expr1);
-            // (-a): int
-            unify_types(expr_var, &Type::Int, types, &mut None).unwrap();
-            // a: int
-            unify_types(expr1_var, &Type::Int, types, &mut None).unwrap();
-            infer_expr_types(expr1, env, vars, types, gen);
-        }
-        Expr::Sequence(seq) => {
-            let last_expr = seq.last().expect("encountered empty sequence");
-            let expr_var = expr_type_var(expr);
-            let last_expr_var = expr_type_var(last_expr);
-            // aN: c |- (a1; ...; aN): c
-            unify_types(expr_var, last_expr_var, types, &mut None).unwrap();
-            for expr in seq {
-                infer_expr_types(expr, env, vars, types, gen);
-            }
-        }
+        ) => infer_arithmetic_types(expr, expr1, expr2, env, vars, types, gen),
+        Expr::Negate(expr1) => infer_negate_types(expr, expr1, env, vars, types, gen),
+        Expr::If(_, _, _) => infer_if_types(expr, env, vars, types, gen),
+        Expr::Sequence(seq) => infer_sequence_types(expr, seq, env, vars, types, gen),
         Expr::Product(expr1, expr2) => {
-            let expr1_var = expr_type_var(expr1);
-            let expr2_var = expr_type_var(expr2);
-            let expr_var = expr_type_var(expr);
-            // a1: t1, a2: t2 |- (a1, a2): (t1, t2)
-            unify_types(
-                expr_var,
-                &Type::Product(Box::new(expr1_var.clone()), Box::new(expr2_var.clone())),
-                types,
-                &mut None,
-            )
-            .unwrap();
-            infer_expr_types(expr1, env, vars, types, gen);
-            infer_expr_types(expr2, env, vars, types, gen);
-        }
-        Expr::Cons(expr1, expr2) => {
-            let expr1_var = expr_type_var(expr1);
-            let expr2_var = expr_type_var(expr2);
-            let expr_var = expr_type_var(expr);
-            // a1: t1, a2: [t1] |- (a1:a2): [t1]
-            unify_types(
-                expr2_var,
-                &Type::List(Box::new(expr1_var.clone())),
-                types,
-                &mut None,
-            )
-            .unwrap();
-            unify_types(expr_var, expr2_var, types, &mut None).unwrap();
-            infer_expr_types(expr1, env, vars, types, gen);
-            infer_expr_types(expr2, env, vars, types, gen);
+            infer_product_types(expr, expr1, expr2, env, vars, types, gen)
         }
+        Expr::Cons(expr1, expr2) => infer_cons_types(expr, expr1, expr2, env, vars, types, gen),
         Expr::Application(expr1, expr2) => {
-            let expr_var = expr_type_var(expr);
-            let expr1_var = expr_type_var(expr1);
-            let expr2_var = expr_type_var(expr2);
-            // b: t, a b: u |- a: t -> u
-            unify_types(
-                expr1_var,
-                &Type::Function(Box::new(expr2_var.clone()), Box::new(expr_var.clone())),
-                types,
-                &mut None,
-            )
-            .unwrap();
-            infer_expr_types(expr1, env, vars, types, gen);
-            infer_expr_types(expr2, env, vars, types, gen);
-        }
-        Expr::Function(Function {
-            params,
-            body: expr1,
-            ..
-        }) => {
-            let expr_var = expr_type_var(expr);
-            let expr1_var = expr_type_var(expr1);
-            let mut func_var = expr1_var.clone();
-            let mut env = env.clone();
-            let mut vars = vars.clone();
-            for param in params.iter().rev() {
-                infer_pat_types(param, &mut vars, types, gen);
-                let param_type = pat_type_var(param);
-                collect_free_type_vars(&expand_type(param_type, types), &mut env);
-                func_var = Type::Function(Box::new(param_type.clone()), Box::new(func_var));
-            }
-            // a1: t1, ..., aN: tN |- b: u
-            // fun a1 ... aN -> b : t1 -> ... -> tN -> u
-            unify_types(expr_var, &func_var, types, &mut None).unwrap();
-            infer_expr_types(expr1, &env, &vars, types, gen);
-        }
-        Expr::Match(matche) => {
-            let expr_var = expr_type_var(expr);
-            let expr1_var = expr_type_var(&matche.0);
-            for (pat, expr2) in matche.1.iter().zip(matche.2.iter()) {
-                let mut vars = vars.clone();
-                let mut env = env.clone();
-                infer_pat_types(pat, &mut vars, types, gen);
-                let pat_type = pat_type_var(pat);
-                unify_types(pat_type, expr1_var, types, &mut None).unwrap();
-                let expr2_var = expr_type_var(expr2);
-                unify_types(expr_var, expr2_var, types, &mut None).unwrap();
-                collect_free_type_vars(&expand_type(pat_type, types), &mut env);
-                infer_expr_types(expr2, &env, &vars, types, gen);
-            }
-        }
-        Expr::Intrinsic(Intrinsic { params, .. }) => {
-            let expr_var = expr_type_var(expr);
-            let mut vars = vars.clone();
-            let mut func_var = Type::Variable(Variable::new(gen.generate_id()));
-            for param in params.iter().rev() {
-                infer_pat_types(param, &mut vars, types, gen);
-                let param_type = pat_type_var(param);
-                func_var = Type::Function(Box::new(param_type.clone()), Box::new(func_var));
-            }
-            unify_types(&func_var, expr_var, types, &mut None).unwrap();
+            infer_application_types(expr, expr1, expr2, env, vars, types, gen)
         }
+        Expr::Function(fun) => infer_function_types(expr, fun, env, vars, types, gen),
+        Expr::Match(matche) => infer_match_types(expr, matche, env, vars, types, gen),
+        Expr::Intrinsic(intr) => infer_intrinsic_types(expr, intr, vars, types, gen),
         Expr::LetBinding(def, expr2) => {
-            let expr_var = expr_type_var(expr);
-            let expr2_var = expr_type_var(expr2);
-            let mut env = env.clone();
-            let mut vars = vars.clone();
-            infer_binding_types(def, &mut env, &mut vars, types, gen);
-            unify_types(expr_var, expr2_var, types, &mut None).unwrap();
-            infer_expr_types(expr2, &env, &vars, types, gen);
+            infer_let_binding_types(expr, def, expr2, env, vars, types, gen)
         }
+    }
+}
+
+/* Infer the type of an expression that has no sub-expressions. */
+fn infer_atom_types(
+    expr: &TExpr,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let typ = match &expr.v {
+        // []: [a]
+        Expr::Nil => Type::List(Box::new(Type::Variable(Variable::new(gen.generate_id())))),
+        // (): ()
+        Expr::Unit => Type::Unit,
+        // num: int
+        Expr::Constant(_) => Type::Int,
         Expr::Variable(var) => {
-            let expr_var = expr_type_var(expr);
             let mut fresh = expand_type(&vars[&var.id], types);
             let mut new_map = HashMap::new();
             instantiate_type_vars(&mut fresh, &mut new_map, gen);
-            unify_types(expr_var, &fresh, types, &mut None).unwrap();
+            fresh
+        }
+        _ => {
+            return Err(Error::UnexpectedExpression {
+                e: Box::new(expr.clone()),
+            })
         }
+    };
+    unify_types(expr_var, &typ, types, &mut None).map_err(|err| err.locate(&expr.s))
+}
+
+/* Infer the types of an equality constraint and its operands. */
+fn infer_equal_types(
+    expr: &TExpr,
+    expr1: &TExpr,
+    expr2: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr1_var = expr_type_var(expr1);
+    let expr2_var = expr_type_var(expr2);
+    // a = b: ()
+    unify_types(expr_var, &Type::Unit, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    // a: c |- b: c
+    unify_types(expr1_var, expr2_var, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    infer_expr_types(expr1, env, vars, types, gen)?;
+    infer_expr_types(expr2, env, vars, types, gen)
+}
+
+/* Infer the types of an arithmetic operation and its operands. */
+fn infer_arithmetic_types(
+    expr: &TExpr,
+    expr1: &TExpr,
+    expr2: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr1_var = expr_type_var(expr1);
+    let expr2_var = expr_type_var(expr2);
+    // a op b: int
+    unify_types(expr_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    // a: int
+    unify_types(expr1_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&expr1.s))?;
+    // b: int
+    unify_types(expr2_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&expr2.s))?;
+    infer_expr_types(expr1, env, vars, types, gen)?;
+    infer_expr_types(expr2, env, vars, types, gen)
+}
+
+/* Infer the types of a negation and its operand. */
+fn infer_negate_types(
+    expr: &TExpr,
+    expr1: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr1_var = expr_type_var(expr1);
+    // (-a): int
+    unify_types(expr_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    // a: int
+    unify_types(expr1_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&expr1.s))?;
+    infer_expr_types(expr1, env, vars, types, gen)
+}
+
+/* Infer the types of a conditional, its condition, and its branches. */
+fn infer_if_types(
+    expr: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let Expr::If(cond, expr1, expr2) = &expr.v else {
+        return Err(Error::UnexpectedExpression {
+            e: Box::new(expr.clone()),
+        });
+    };
+    let expr_var = expr_type_var(expr);
+    let cond_var = expr_type_var(cond);
+    let expr1_var = expr_type_var(expr1);
+    let expr2_var = expr_type_var(expr2);
+    // c: int
+    unify_types(cond_var, &Type::Int, types, &mut None).map_err(|err| err.locate(&cond.s))?;
+    // a: t, b: t |- (if c then a else b): t
+    unify_types(expr_var, expr1_var, types, &mut None).map_err(|err| err.locate(&expr1.s))?;
+    unify_types(expr_var, expr2_var, types, &mut None).map_err(|err| err.locate(&expr2.s))?;
+    infer_expr_types(cond, env, vars, types, gen)?;
+    infer_expr_types(expr1, env, vars, types, gen)?;
+    infer_expr_types(expr2, env, vars, types, gen)
+}
+
+/* Infer the types of a sequence and each of its expressions. */
+fn infer_sequence_types(
+    expr: &TExpr,
+    seq: &[TExpr],
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let last_expr = seq.last().ok_or(Error::EmptySequenceError)?;
+    let expr_var = expr_type_var(expr);
+    let last_expr_var = expr_type_var(last_expr);
+    // aN: c |- (a1; ...; aN): c
+    unify_types(expr_var, last_expr_var, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    for expr in seq {
+        infer_expr_types(expr, env, vars, types, gen)?;
     }
+    Ok(())
+}
+
+/* Infer the types of a tuple and its components. */
+fn infer_product_types(
+    expr: &TExpr,
+    expr1: &TExpr,
+    expr2: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr1_var = expr_type_var(expr1);
+    let expr2_var = expr_type_var(expr2);
+    let expr_var = expr_type_var(expr);
+    // a1: t1, a2: t2 |- (a1, a2): (t1, t2)
+    unify_types(
+        expr_var,
+        &Type::Product(Box::new(expr1_var.clone()), Box::new(expr2_var.clone())),
+        types,
+        &mut None,
+    )
+    .map_err(|err| err.locate(&expr.s))?;
+    infer_expr_types(expr1, env, vars, types, gen)?;
+    infer_expr_types(expr2, env, vars, types, gen)
+}
+
+/* Infer the types of a list and its head and tail. */
+fn infer_cons_types(
+    expr: &TExpr,
+    expr1: &TExpr,
+    expr2: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr1_var = expr_type_var(expr1);
+    let expr2_var = expr_type_var(expr2);
+    let expr_var = expr_type_var(expr);
+    // a1: t1, a2: [t1] |- (a1:a2): [t1]
+    unify_types(
+        expr2_var,
+        &Type::List(Box::new(expr1_var.clone())),
+        types,
+        &mut None,
+    )
+    .map_err(|err| err.locate(&expr.s))?;
+    unify_types(expr_var, expr2_var, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    infer_expr_types(expr1, env, vars, types, gen)?;
+    infer_expr_types(expr2, env, vars, types, gen)
+}
+
+/* Infer the types of a function application and its operands. */
+fn infer_application_types(
+    expr: &TExpr,
+    expr1: &TExpr,
+    expr2: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr1_var = expr_type_var(expr1);
+    let expr2_var = expr_type_var(expr2);
+    // b: t, a b: u |- a: t -> u
+    unify_types(
+        expr1_var,
+        &Type::Function(Box::new(expr2_var.clone()), Box::new(expr_var.clone())),
+        types,
+        &mut None,
+    )
+    .map_err(|err| err.locate(&expr.s))?;
+    infer_expr_types(expr1, env, vars, types, gen)?;
+    infer_expr_types(expr2, env, vars, types, gen)
+}
+
+/* Infer the types of a function and its body. */
+fn infer_function_types(
+    expr: &TExpr,
+    fun: &Function,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr1_var = expr_type_var(&fun.body);
+    let mut func_var = expr1_var.clone();
+    let mut env = env.clone();
+    let mut vars = vars.clone();
+    for param in fun.params.iter().rev() {
+        infer_pat_types(param, &mut vars, types, gen)?;
+        let param_type = pat_type_var(param);
+        collect_free_type_vars(&expand_type(param_type, types), &mut env);
+        func_var = Type::Function(Box::new(param_type.clone()), Box::new(func_var));
+    }
+    // a1: t1, ..., aN: tN |- b: u
+    // fun a1 ... aN -> b : t1 -> ... -> tN -> u
+    unify_types(expr_var, &func_var, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    infer_expr_types(&fun.body, &env, &vars, types, gen)?;
+    for param in &fun.params {
+        check_pat_annotations(param, types)?;
+    }
+    if let Some(ret) = &fun.ret {
+        check_annotation(expr1_var, ret, types).map_err(|err| err.locate(&fun.body.s))?;
+    }
+    Ok(())
+}
+
+/* Infer the types of a match expression, its patterns, and its arms. */
+fn infer_match_types(
+    expr: &TExpr,
+    matche: &Match,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr1_var = expr_type_var(&matche.0);
+    infer_expr_types(&matche.0, env, vars, types, gen)?;
+    for (pat, expr2) in matche.1.iter().zip(matche.2.iter()) {
+        let mut vars = vars.clone();
+        let mut env = env.clone();
+        infer_pat_types(pat, &mut vars, types, gen)?;
+        let pat_type = pat_type_var(pat);
+        unify_types(pat_type, expr1_var, types, &mut None).map_err(|err| err.locate(&pat.s))?;
+        check_pat_annotations(pat, types)?;
+        let expr2_var = expr_type_var(expr2);
+        unify_types(expr_var, expr2_var, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+        collect_free_type_vars(&expand_type(pat_type, types), &mut env);
+        infer_expr_types(expr2, &env, &vars, types, gen)?;
+    }
+    Ok(())
+}
+
+/* Infer the type of an intrinsic from the types of its parameters. */
+fn infer_intrinsic_types(
+    expr: &TExpr,
+    intr: &Intrinsic,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let mut vars = vars.clone();
+    let mut func_var = Type::Variable(Variable::new(gen.generate_id()));
+    for param in intr.params.iter().rev() {
+        infer_pat_types(param, &mut vars, types, gen)?;
+        let param_type = pat_type_var(param);
+        func_var = Type::Function(Box::new(param_type.clone()), Box::new(func_var));
+    }
+    unify_types(&func_var, expr_var, types, &mut None).map_err(|err| err.locate(&expr.s))
+}
+
+/* Infer the types of a let binding and its body. */
+fn infer_let_binding_types(
+    expr: &TExpr,
+    def: &LetBinding,
+    expr2: &TExpr,
+    env: &HashMap<VariableId, Variable>,
+    vars: &HashMap<VariableId, Type>,
+    types: &mut HashMap<VariableId, Type>,
+    gen: &mut VarGen,
+) -> Result<(), Error> {
+    let expr_var = expr_type_var(expr);
+    let expr2_var = expr_type_var(expr2);
+    let mut env = env.clone();
+    let mut vars = vars.clone();
+    infer_binding_types(def, &mut env, &mut vars, types, gen)?;
+    unify_types(expr_var, expr2_var, types, &mut None).map_err(|err| err.locate(&expr.s))?;
+    infer_expr_types(expr2, &env, &vars, types, gen)
 }
 
 /* Infer the type of the definition bindings and its contained sub-expressions.
@@ -688,8 +906,8 @@ fn infer_def_types(
     vars: &mut HashMap<VariableId, Type>,
     types: &mut HashMap<VariableId, Type>,
     gen: &mut VarGen,
-) {
-    infer_binding_types(&def.0, env, vars, types, gen);
+) -> Result<(), Error> {
+    infer_binding_types(&def.0, env, vars, types, gen)
 }
 
 /* Type check the module using Hindley Milner. */
@@ -699,7 +917,7 @@ pub fn infer_module_types(
     vars: &mut HashMap<VariableId, Type>,
     types: &mut HashMap<VariableId, Type>,
     gen: &mut VarGen,
-) {
+) -> Result<(), Error> {
     allocate_module_types(annotated, gen);
     let mut env = HashMap::new();
     // Initialize the type environment with the types of global variables
@@ -714,11 +932,12 @@ pub fn infer_module_types(
         collect_free_type_vars(typ, &mut env);
     }
     for def in &mut annotated.defs {
-        infer_def_types(def, &mut env, vars, types, gen);
+        infer_def_types(def, &mut env, vars, types, gen)?;
     }
     for expr in &mut annotated.exprs {
-        infer_expr_types(expr, &env, vars, types, gen);
+        infer_expr_types(expr, &env, vars, types, gen)?;
     }
+    Ok(())
 }
 
 /* Expand tuple pattern variables into tuple patterns. */
@@ -801,8 +1020,8 @@ pub fn expand_pattern_variables(
             Ok(())
         }
         _ => Err(Error::PatternMatchError {
-            e: expr.clone(),
-            p: pat.clone(),
+            e: Box::new(expr.clone()),
+            p: Box::new(pat.clone()),
         }),
     }
 }
@@ -857,7 +1076,7 @@ pub fn expand_expr_variables(
         }
         (Expr::Constant(_), Type::Int) | (Expr::Unit, Type::Unit) => Ok(()),
         _ => Err(Error::ImpossibleType {
-            e: expr.clone(),
+            e: Box::new(expr.clone()),
             t: expand_type(expr_type_var(expr), types),
         }),
     }
@@ -7,11 +7,14 @@ use std::{
 
 use ark_serialize::Write;
 
+use num_bigint::BigInt;
 use num_traits::Num;
 
-use crate::ast::Variable;
+use crate::ast::{TExpr, Variable};
 use crate::error::Error;
-use crate::error::Error::{InvalidVariableAssignmentValue, MissingVariableAssignment};
+use crate::error::Error::{
+    InvalidInputsFile, InvalidVariableAssignmentValue, MissingInputsFile, MissingVariableAssignment,
+};
 use crate::{
     ast::{Module, Pat, VariableId},
     transform::collect_module_variables,
@@ -51,17 +54,63 @@ where
         .collect()
 }
 
+/* Convert the given named assignments to assignments of the variableIds of
+ * the module's undefined variables, leaving out the variables that are not
+ * named in them so that the witness solver can determine those instead. */
+pub(crate) fn get_given_circuit_assignments<T>(
+    module: &Module,
+    named_assignments: &HashMap<String, T>,
+) -> HashMap<VariableId, T>
+where
+    T: Clone,
+{
+    let mut input_variables = HashMap::new();
+    collect_module_variables(module, &mut input_variables);
+    for def in &module.defs {
+        if let Pat::Variable(var) = &def.0 .0.v {
+            input_variables.remove(&var.id);
+        }
+    }
+    input_variables
+        .into_iter()
+        .filter_map(|(id, var)| {
+            let var_name = var.name?;
+            named_assignments
+                .get(&var_name)
+                .cloned()
+                .map(|val| (id, val))
+        })
+        .collect()
+}
+
+/* An equality that does not hold under some assignment to a circuit's
+ * variables, together with the values of its sides and of the variables
+ * occurring in it. */
+pub struct UnsatisfiedEquality {
+    pub expr: TExpr,
+    pub lhs: BigInt,
+    pub rhs: BigInt,
+    pub variables: Vec<(Variable, BigInt)>,
+}
+
 /* Read satisfying inputs to the given program from a file. */
 pub fn read_inputs_from_file<F>(path_to_inputs: &PathBuf) -> Result<HashMap<String, F>, Error>
 where
     F: Clone + Num + Neg<Output = F>,
     <F as num_traits::Num>::FromStrRadixErr: std::fmt::Debug,
 {
-    let contents = fs::read_to_string(path_to_inputs).expect("Could not read inputs file");
+    let path = path_to_inputs.display().to_string();
+    let contents = fs::read_to_string(path_to_inputs).map_err(|e| MissingInputsFile {
+        path: path.clone(),
+        e: e.to_string(),
+    })?;
 
     // Read the user-supplied inputs from the file
     let named_assignments: HashMap<String, String> =
-        json5::from_str(&contents).expect("Could not parse JSON5");
+        json5::from_str(&contents).map_err(|e| InvalidInputsFile {
+            path,
+            e: e.to_string(),
+        })?;
 
     named_assignments
         .into_iter()
@@ -76,7 +125,8 @@ where
         .collect::<Result<HashMap<String, F>, Error>>()
 }
 
-/* Prompt for satisfying inputs to the given program. */
+/* Prompt for satisfying inputs to the given program. Inputs left blank are
+ * not assigned, so that the witness solver can determine them instead. */
 pub fn prompt_inputs<F>(annotated: &Module) -> HashMap<VariableId, F>
 where
     F: Num + Neg<Output = F>,
@@ -116,7 +166,11 @@ where
         std::io::stdin()
             .read_line(&mut input_line)
             .expect("failed to read input");
-        let x = parse_prefixed_num(input_line.trim()).expect("input not an integer");
+        let input_line = input_line.trim();
+        if input_line.is_empty() {
+            continue;
+        }
+        let x = parse_prefixed_num(input_line).expect("input not an integer");
         var_assignments.insert(id, x);
     }
     var_assignments
@@ -150,9 +204,29 @@ where
     Ok(if pos { magnitude } else { -magnitude })
 }
 
+// The number of bits that comparison operands are assumed to fit into when no
+// other width is configured.
+pub const DEFAULT_BIT_WIDTH: usize = 32;
+
+pub const DEFAULT_TRANSCRIPT_LABEL: &str = "vamp-ir";
+
 // Config to be shuffled around clis.
 pub struct Config {
     pub quiet: bool,
+    // Number of bits that the operands of < and <= are assumed to fit into
+    pub bit_width: usize,
+    // Fiat-Shamir transcript label recorded in compiled circuits
+    pub transcript_label: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            bit_width: DEFAULT_BIT_WIDTH,
+            transcript_label: DEFAULT_TRANSCRIPT_LABEL.to_string(),
+        }
+    }
 }
 
 // Macro for a potentially quiet print line.
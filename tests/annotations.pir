@@ -0,0 +1,22 @@
+/* The sum of the given list's elements equals x. Demonstrates type annotations
+   on patterns, definitions, and function results. Run as follows:
+   vamp-ir halo2 compile -s tests/annotations.pir -o circuit.halo2
+   vamp-ir halo2 prove -c circuit.halo2 -o proof.halo2
+   vamp-ir halo2 verify -c circuit.halo2 -p proof.halo2
+*/
+
+pub x;
+
+def add_ind (a: int) (acc: int) : int = a + acc;
+
+def sum (xs: list int) : int = fold xs add_ind 0;
+
+def scale (f: int -> int) (a, b) : (int, int) = (f a, f b);
+
+def elts : [int] = 1:2:3:[];
+
+def ((lo: int), (hi: int)) = scale (fun y { 2*y }) (1, 2);
+
+sum elts = x;
+
+lo + hi = 6;
@@ -0,0 +1,33 @@
+/* Compares values and checks ranges without hand-written bit decompositions.
+   Run as follows:
+   vamp-ir halo2 compile -s tests/comparisons.pir -o circuit.halo2
+   vamp-ir halo2 prove -c circuit.halo2 -o proof.halo2
+   vamp-ir halo2 verify -c circuit.halo2 -p proof.halo2
+*/
+
+pub x, y;
+
+// Comparisons produce 1 if they hold and 0 otherwise. Their operands are
+// constrained to fit into the configured bit width
+
+(y < x) = 1;
+
+(x <= y) = 0;
+
+(x != y) = 1;
+
+(x != x) = 0;
+
+// Comparisons can be used as conditions
+
+def max a b = if a < b then b else a;
+
+max x y = x;
+
+// The bits returned by range are little-endian
+
+def (1:1:_) = range 3 y;
+
+// Constant arguments are decomposed during compilation
+
+range 4 10 = 0:1:0:1:[];
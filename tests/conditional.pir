@@ -0,0 +1,25 @@
+/* Selects between values with conditional expressions. Run as follows:
+   vamp-ir halo2 compile -s tests/conditional.pir -o circuit.halo2
+   vamp-ir halo2 prove -c circuit.halo2 -o proof.halo2
+   vamp-ir halo2 verify -c circuit.halo2 -p proof.halo2
+*/
+
+pub x, y;
+
+// A variable condition is constrained to be 0 or 1 once and then selects
+// between both branches. Both branches are evaluated, so neither of them may
+// contain constraints
+
+def b = fresh (x % 2);
+
+def max_or_min a c = if b then a + c else a * c;
+
+def (p, q) = if b then (x, y) else (y, x);
+
+max_or_min p q = x + y;
+
+// A constant condition only evaluates the selected branch
+
+def pick n = if n then 1 else 0;
+
+pick 1 = 1 - pick 0;
@@ -1,6 +1,5 @@
-use std::fs;
 use vamp_ir;
-use vamp_ir::halo2::api::compile;
+use vamp_ir::halo2::api::compile_file;
 use vamp_ir::util::Config;
 use walkdir::{DirEntry, WalkDir};
 
@@ -22,7 +21,13 @@ fn compile_test_programs() {
     assert_ne!(files_to_test.len(), 0);
 
     for entry in files_to_test {
-        let source = fs::read_to_string(&entry.into_path()).expect("could not read file");
-        assert!(compile(source, &Config { quiet: true }).is_ok())
+        assert!(compile_file(
+            entry.into_path(),
+            &Config {
+                quiet: true,
+                ..Config::default()
+            }
+        )
+        .is_ok())
     }
 }
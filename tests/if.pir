@@ -1,4 +1,4 @@
-/* 'if' and 'if_else' functions. Run as follows:
+/* 'if_zero' and 'if_else' functions. Run as follows:
    vamp-ir setup -o params.pp
    vamp-ir compile -u params.pp -s tests/if.pir -o circuit.plonk
    vamp-ir prove -u params.pp -c circuit.plonk -o proof.plonk
@@ -23,21 +23,21 @@ nonZero 0 = 1;
 
 // If x is 0, then y must also be 0
 // If x is not 0, then y may be anything
-def if x y = (nonZero x) * y;
+def if_zero x y = if nonZero x then y else 0;
 
-// Testing if
-if 0 0 = 0;
-if 55 34 = 0;
-if 55 0 = 0;
-if 0 1 = 1;
-if 0 20 = 20;
+// Testing if_zero
+if_zero 0 0 = 0;
+if_zero 55 34 = 0;
+if_zero 55 0 = 0;
+if_zero 0 1 = 1;
+if_zero 0 20 = 20;
 
 // User input
-//if a b = 0;
+//if_zero a b = 0;
 
 // if x = 0 holds, then y must also = 0
 // but if x /= 0, then z must = 0
-def if_else x y z = if x y + x * z;
+def if_else x y z = if_zero x y + x * z;
 
 // Testing if_else
 if_else 0 0 0 = 0;
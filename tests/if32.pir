@@ -1,4 +1,4 @@
-/* 'if' and 'if_else' functions over 32 bits. Run as follows:
+/* 'if_zero' and 'if_else' functions over 32 bits. Run as follows:
    vamp-ir setup -o params.pp
    vamp-ir compile -u params.pp -s tests/if32.pir -o circuit.plonk
    vamp-ir prove -u params.pp -c circuit.plonk -o proof.plonk
@@ -70,18 +70,18 @@ def nonZero32 a = or32 (map32 (fun x { 1-x }) (range32 a));
 
 // If x is 0, then y must also be 0
 // If x is nonZero, then y may be anything
-def if x y = (nonZero32 x) * y;
+def if_zero x y = if nonZero32 x then y else 0;
 
-// Testing if
-//if 0 0 = 0;
-//if 55 34 = 0;
-//if 55 0 = 0;
-//if 0 1 = 1;
-//if 0 20 = 20;
+// Testing if_zero
+//if_zero 0 0 = 0;
+//if_zero 55 34 = 0;
+//if_zero 55 0 = 0;
+//if_zero 0 1 = 1;
+//if_zero 0 20 = 20;
 
 // if x = 0 holds, then y must also = 0
 // but if x /= 0, then z must = 0
-def if_else x y z = if x y + x * z;
+def if_else x y z = if_zero x y + x * z;
 
 // Testing if_else
 //if_else 0 0 0 = 0;
@@ -0,0 +1,17 @@
+/* Any value 0 <= x < 16 whose lowest bit is set is valid. Demonstrates that
+   definitions can be imported from other files under a namespace. Run as
+   follows:
+   vamp-ir halo2 compile -s tests/imports.pir -o circuit.halo2
+   vamp-ir halo2 prove -c circuit.halo2 -o proof.halo2
+   vamp-ir halo2 verify -c circuit.halo2 -p proof.halo2
+*/
+
+import "lib/bits.pir" as bits;
+
+pub x;
+
+// Local definitions do not clash with the imported ones
+
+def (bool, rest) = bits::range4 x;
+
+bool = 1;
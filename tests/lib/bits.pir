@@ -0,0 +1,18 @@
+/* Bit manipulation helpers shared between test programs. Import as follows:
+   import "lib/bits.pir" as bits;
+*/
+
+// Ensure that the given argument is 1 or 0, and returns it
+
+def bool x = { x*(x-1) = 0; x };
+
+// Extract the 4 bits from a number argument
+
+def range4 a = {
+    def a0 = bool (fresh ((a\1) % 2));
+    def a1 = bool (fresh ((a\2) % 2));
+    def a2 = bool (fresh ((a\4) % 2));
+    def a3 = bool (fresh ((a\8) % 2));
+    a = a0 + 2*a1 + 4*a2 + 8*a3;
+    (a0, a1, a2, a3, ())
+};
@@ -0,0 +1,35 @@
+/* The length of a list plus a constant code equals x. Demonstrates
+   match expressions over lists, constants and tuples. Run as follows:
+   vamp-ir halo2 compile -s tests/match.pir -o circuit.halo2
+   vamp-ir halo2 prove -c circuit.halo2 -o proof.halo2
+   vamp-ir halo2 verify -c circuit.halo2 -p proof.halo2
+*/
+
+pub x, y;
+
+// Count the elements of a list of at most 4 elements
+
+def length_ind length l = match l {
+    [] => 0,
+    _:t => 1 + length t,
+};
+
+def length = iter 4 length_ind (fun l { 0 });
+
+// Constant patterns are resolved at compile time
+
+def code n = match n {
+    0 => 10,
+    1 => { def c = 5; c * 4 },
+    _ => 30,
+};
+
+// Tuples are destructured by the matching arm
+
+def swap p = match p { (a, b) => (b, a) };
+
+def (u, v) = swap (2*y, length (1:2:3:[]));
+
+u + code 1 = x;
+
+v = 2*y;
@@ -0,0 +1,82 @@
+/* Exercises the gadgets provided by the standard prelude on 4 bit values. Run
+   as follows:
+   vamp-ir halo2 compile -s tests/prelude.pir -o circuit.halo2
+   vamp-ir halo2 prove -c circuit.halo2 -o proof.halo2
+   vamp-ir halo2 verify -c circuit.halo2 -p proof.halo2
+*/
+
+pub x, y;
+
+// List utilities
+
+3 = nth (1:2:3:[]) 2;
+
+(1:2:3:[]) = append (1:[]) (2:3:[]);
+
+(1:2:[]) = take 2 (1:2:3:[]);
+
+// Bit decomposition and recombination
+
+x = combine (range 4 x);
+
+// Boolean and bitwise logic
+
+12 = xor 4 10 6;
+
+2 = and 4 10 6;
+
+14 = or 4 10 6;
+
+5 = not 4 10;
+
+// Shifts and rotations
+
+4 = lshr 4 9;
+
+12 = ashr 4 9;
+
+2 = shl 4 9;
+
+12 = ror 4 9;
+
+3 = rol 4 9;
+
+6 = rotr 4 2 9;
+
+// Modular arithmetic
+
+1 = add 4 9 8;
+
+15 = sub 4 2 3;
+
+// Comparisons
+
+1 = ule 4 x (x+y);
+
+1 = ult 4 2 3;
+
+1 = slt 4 15 0;
+
+1 = sle 4 5 5;
+
+// Comparisons at the boundaries of the unsigned and signed ranges
+
+1 = ule 4 0 15;
+
+0 = ule 4 15 0;
+
+0 = ult 4 x 0;
+
+0 = ult 4 15 15;
+
+0 = slt 4 7 8;
+
+1 = slt 4 8 7;
+
+0 = slt 4 0 15;
+
+1 = sle 4 7 7;
+
+0 = sle 4 7 8;
+
+1 = sle 4 8 7;